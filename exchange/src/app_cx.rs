@@ -31,23 +31,104 @@ use uuid::Uuid;
 
 use crate::asset::{internal_asset_list, AssetKey};
 use crate::bitcoin::BitcoinRpcClient;
+use crate::fiat::{FiatDeposit, FiatProvider, FiatProviderError};
 use crate::password::Password;
 use crate::trading::{
-    CancelOrder, OrderSide, OrderUuid, PlaceOrder, PlaceOrderResult, TeResponse as Response,
-    TradeCmd, TradingEngineCmd, TradingEngineError, TradingEngineTx,
+    response_channel, CancelOrder, CancelOrderByClientId, DepthSnapshot, EngineStats, Execution,
+    ExecutionReport, LastLookRequest, MarketData, MatchEvent, OrderRecord, OrderSide, OrderUuid,
+    PlaceOrder, PlaceOrderResult, RebuildReport, ReduceOrder, ResponseRing, RestingOrdersPage,
+    SelfTradeProtection, TeResponse as Response, TradeCmd, TradingEngineCmd, TradingEngineError,
+    TradingEngineTx,
 };
 use crate::web::TradeAddOrder;
 use crate::{Asset, Configuration};
 
+mod activity;
+pub use activity::{Activity, ActivityKind, ActivityPage};
+
 mod defer_guard;
 pub use defer_guard::{defer, DeferGuard};
 
+mod fees;
+pub use fees::FillFees;
+
+mod imbalance_guard;
+pub use imbalance_guard::{ImbalanceMonitor, ImbalanceTransition};
+
+mod reserve_metrics;
+pub use reserve_metrics::{ReserveMetrics, ReserveMetricsSnapshot};
+
 mod reserve_ok;
-pub use reserve_ok::ReserveOk;
+use reserve_ok::QUOTE_CURRENCY;
+pub use reserve_ok::{ReserveGuard, ReserveOk};
+
+mod blocklist;
+pub use blocklist::{AddressBlocklist, BlockedAddresses};
+
+mod retry;
+pub use retry::{is_transient, retry_transient};
+
+mod statement;
+pub use statement::{Statement, StatementDocument, STATEMENT_VERSION};
+
+mod task_registry;
+pub use task_registry::{TaskHandle, TaskRegistry, TaskSnapshot, TaskStatus};
+
+mod ws_ticket;
+pub use ws_ticket::{WsTicketError, WsTickets};
+
+/// the longest client order id accepted, in bytes.
+const MAX_CLIENT_ORDER_ID_LEN: usize = 64;
+
+/// the longest device label a session may be given, in bytes.
+pub const MAX_DEVICE_LABEL_LEN: usize = 64;
+
+/// how often journalled trades are settled, see [`AppCx::run_settlement`].
+const SETTLEMENT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// how long settlement may go without a heartbeat before it counts as stalled.
+const SETTLEMENT_STALL_AFTER: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// how long the trading engine may go without a heartbeat before it counts as stalled, it
+/// heartbeats between commands and on every expiry poll.
+const TRADING_ENGINE_STALL_AFTER: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// how many journalled trades are read at a time.
+const SETTLEMENT_BATCH_SIZE: i64 = 100;
 
 struct Inner {
     te_state: Atomic<TradingEngineState>,
     jinja: crate::jinja::Jinja,
+    /// response slots for place-order commands, only present when the fast path is enabled.
+    place_order_ring: Option<Arc<ResponseRing<Result<PlaceOrderResult, TradingEngineError>>>>,
+    /// response slots for cancel-order commands, only present when the fast path is enabled.
+    cancel_order_ring: Option<Arc<ResponseRing<Result<(), TradingEngineError>>>>,
+    /// single-use tickets for authenticating websocket connections.
+    ws_tickets: WsTickets,
+    /// reject requests with a `503`, see [`crate::config::MaintenanceMode`].
+    maintenance_mode: std::sync::atomic::AtomicBool,
+    /// reject new orders with a `503`, see [`crate::config::LoadShedding`].
+    shedding_load: std::sync::atomic::AtomicBool,
+    /// counters for reserved funds, shared with every [`ReserveOk`] so reverts are counted too.
+    reserve_metrics: Arc<ReserveMetrics>,
+    /// liveness of the background tasks, see `GET /api/admin/tasks`.
+    tasks: Arc<TaskRegistry>,
+    /// addresses funds may not be withdrawn to or deposited on, see `blocked_addresses`.
+    blocked_addresses: AddressBlocklist,
+}
+
+/// the deadline of a trade command sent outside a web request, as long as a request is given.
+fn default_trade_deadline() -> tokio::time::Instant {
+    tokio::time::Instant::now() + crate::web::REQUEST_TIMEOUT
+}
+
+/// `true` if `err` is postgres cancelling a statement that ran past its `statement_timeout`.
+pub fn is_statement_timeout(err: &sqlx::Error) -> bool {
+    const QUERY_CANCELED: &str = "57014";
+
+    err.as_database_error()
+        .and_then(|err| err.code())
+        .is_some_and(|code| code == QUERY_CANCELED)
 }
 
 #[derive(Debug, Error)]
@@ -56,13 +137,28 @@ pub enum ReserveError {
     InsufficientFunds,
     #[error("database error")]
     Database(#[from] sqlx::Error),
+    /// the commit failed, it may or may not have been applied so it is never retried.
+    #[error("database error while committing")]
+    Commit(sqlx::Error),
+}
+
+impl ReserveError {
+    /// the underlying database error of a [`ReserveError::Database`], which may be retried.
+    fn retryable(&self) -> Option<&sqlx::Error> {
+        match self {
+            Self::Database(err) => Some(err),
+            Self::InsufficientFunds | Self::Commit(_) => None,
+        }
+    }
 }
 
 impl From<ReserveError> for PlaceOrderError {
     fn from(value: ReserveError) -> Self {
         match value {
             ReserveError::InsufficientFunds => PlaceOrderError::InsufficientFunds,
-            ReserveError::Database(_) => todo!("internal error"),
+            err @ (ReserveError::Database(_) | ReserveError::Commit(_)) => {
+                PlaceOrderError::Internal(err)
+            }
         }
     }
 }
@@ -73,6 +169,75 @@ pub enum PlaceOrderError {
     TradingEngineUnresponsive,
     #[error("insufficient funds")]
     InsufficientFunds,
+    #[error("invalid expiry: {0}")]
+    InvalidExpiry(#[from] crate::trading::ExpiryError),
+    #[error("client order ids must be between 1 and 64 bytes long")]
+    InvalidClientOrderId,
+    #[error("last look is not enabled on this exchange")]
+    LastLookDisabled,
+    #[error("{0}")]
+    NotionalTooLarge(#[from] crate::trading::NotionalOverflow),
+    #[error("only reduce-only orders are accepted while a book is one-sided")]
+    ReduceOnly,
+    #[error("the exchange is draining for maintenance, new orders are not accepted")]
+    Draining,
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+    /// the reserve could not be taken for a reason other than the funds, the order was not placed.
+    #[error("internal error: {0}")]
+    Internal(ReserveError),
+}
+
+/// Error returned when a journalled trade can not be settled.
+#[derive(Debug, Error)]
+pub enum SettlementError {
+    #[error("{0}")]
+    NotionalOverflow(#[from] crate::trading::NotionalOverflow),
+    #[error("unreadable settlement: {0}")]
+    Unreadable(serde_json::Error),
+    #[error("maker {order_uuid:?} is overdrawn by {overdrawn}, the fill was not settled")]
+    MakerOverdrawn {
+        order_uuid: OrderUuid,
+        overdrawn: i64,
+    },
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Error returned when funds would be withdrawn to a blocked address.
+#[derive(Debug, Error)]
+#[error("this address is blocked for compliance reasons")]
+pub struct BlockedAddressError {
+    /// the blocklist entry the address matched.
+    pub blocked_by: String,
+}
+
+#[derive(Debug, Error)]
+pub enum FaucetError {
+    #[error("the faucet is disabled")]
+    Disabled,
+    #[error("amount is too large")]
+    AmountTooLarge,
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum FiatDepositError {
+    #[error("fiat deposits are disabled")]
+    Disabled,
+    #[error("{0}")]
+    Provider(#[from] FiatProviderError),
+    #[error("only USD deposits are accepted")]
+    UnsupportedCurrency,
+    #[error("this transfer has already been credited")]
+    AlreadyCredited,
+    #[error("amount is too large")]
+    AmountTooLarge,
+    #[error("transfers can only be made up for the mock fiat provider")]
+    NotMock,
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
 }
 
 #[derive(Debug, Error)]
@@ -89,6 +254,93 @@ pub enum CancelOrderError {
     TradingEngineUnresponsive,
 }
 
+#[derive(Debug, Error)]
+pub enum SubscribeMatchEventsError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+}
+
+#[derive(Debug, Error)]
+pub enum SubscribeExecutionReportsError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+}
+
+#[derive(Debug, Error)]
+pub enum SubscribeMarketDataError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+}
+
+#[derive(Debug, Error)]
+pub enum SubscribeLastLookError {
+    #[error("last look is not enabled on this exchange")]
+    Disabled,
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+}
+
+#[derive(Debug, Error)]
+pub enum EngineStatsError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+}
+
+#[derive(Debug, Error)]
+pub enum RebuildBookError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+    #[error("failed to rebuild the book: {0}")]
+    TradingEngine(#[from] TradingEngineError),
+}
+
+#[derive(Debug, Error)]
+pub enum DepthSnapshotError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+}
+
+#[derive(Debug, Error)]
+pub enum RestingOrdersError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+}
+
+#[derive(Debug, Error)]
+pub enum PeekTopError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+}
+
+#[derive(Debug, Error)]
+pub enum FetchOrderError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+    #[error("order not found")]
+    NotFound,
+    #[error("order belongs to another user")]
+    Forbidden,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum ArchiveOrdersError {
+    #[error("trading engine unresponsive")]
+    TradingEngineUnresponsive,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum ReconcileDepositsError {
+    /// bitcoind refused to list the wallet's transactions for a reason other than being down.
+    #[error("failed to list transactions: {0}")]
+    ListTransactions(tonic::Status),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
 #[derive(Debug, Error)]
 pub enum CreateUserError {
     #[error("password hash error")]
@@ -133,6 +385,47 @@ pub struct UserDetails {
     portfolio: UserPortfolio,
 }
 
+/// An active session of a user, see [`AppCx::list_sessions`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionInfo {
+    /// identifies the session when revoking it, the token itself is never listed.
+    pub id: i32,
+    /// the label the session was given at login, e.g. the name of the device.
+    pub device_label: Option<String>,
+    /// the address the session was created from.
+    pub ip_address: Option<String>,
+    /// the user agent the session was created with.
+    pub user_agent: Option<String>,
+    /// when the session was created, in milliseconds since the unix epoch.
+    pub created_at: i64,
+    /// when the session was last used, in milliseconds since the unix epoch.
+    pub last_seen_at: Option<i64>,
+    /// `true` for the session the list was requested with.
+    pub current: bool,
+}
+
+/// the order deposits confirmed in, by block and position in the block, unconfirmed ones last.
+fn confirmation_order(
+    tx: &crate::bitcoin::proto::list_transactions_response::Transaction,
+) -> (i32, i32, i32) {
+    (
+        tx.blockheight.unwrap_or(i32::MAX),
+        tx.blockindex.unwrap_or(i32::MAX),
+        tx.time,
+    )
+}
+
+/// What one call of [`AppCx::update_user_accounts`] journalled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DepositSync {
+    /// deposits journalled.
+    pub inserted: usize,
+    /// new deposits left over by the per-cycle cap, journalled by the next call.
+    pub remaining: usize,
+    /// new deposits to a blocked address, quarantined instead of journalled.
+    pub quarantined: usize,
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[repr(u64)]
 enum TradingEngineState {
@@ -140,6 +433,8 @@ enum TradingEngineState {
     Suspended = 0,
     Running,
     ReduceOnly,
+    /// new orders are refused, cancels and stops that trigger still go through.
+    Draining,
 }
 
 unsafe impl bytemuck::NoUninit for TradingEngineState {}
@@ -153,6 +448,8 @@ pub struct AppCx {
     te_tx: TradingEngineTx,
     /// a client for the bitcoin core rpc.
     pub(crate) bitcoind_rpc: BitcoinRpcClient,
+    /// the bank fiat deposits are confirmed with, see [`crate::fiat`].
+    fiat: Option<Arc<dyn FiatProvider>>,
     /// a pool of connections to the database.
     db: sqlx::PgPool,
     /// Read-only data or data that has interior mutability.
@@ -168,6 +465,8 @@ impl std::fmt::Debug for Inner {
         f.debug_struct("Inner")
             .field("te_state", &self.te_state)
             .field("jinja", &"")
+            .field("place_order_ring", &self.place_order_ring)
+            .field("cancel_order_ring", &self.cancel_order_ring)
             .finish()
     }
 }
@@ -183,16 +482,39 @@ impl AppCx {
         Self {
             te_tx,
             bitcoind_rpc: btc_rpc,
+            fiat: crate::fiat::from_config(&config),
             db,
             inner_ro: Arc::new(Inner {
                 te_state: Atomic::new(TradingEngineState::Running),
                 jinja,
+                place_order_ring: config.te_response_ring_capacity.map(ResponseRing::new),
+                cancel_order_ring: config.te_response_ring_capacity.map(ResponseRing::new),
+                ws_tickets: WsTickets::new(std::time::Duration::from_secs(
+                    config.ws_ticket_ttl_secs,
+                )),
+                maintenance_mode: config.maintenance_mode.enabled.into(),
+                shedding_load: false.into(),
+                reserve_metrics: Default::default(),
+                tasks: Default::default(),
+                blocked_addresses: AddressBlocklist::new(&config.blocked_addresses),
             }),
             assets: internal_asset_list(),
             config,
         }
     }
 
+    /// confirm fiat deposits with `provider` instead of the one selected by `fiat_provider`.
+    pub fn with_fiat_provider(mut self, provider: Arc<dyn FiatProvider>) -> Self {
+        self.fiat = Some(provider);
+        self
+    }
+
+    /// `false` until a bitcoind that was unreachable at startup is connected, see
+    /// [`crate::config::BitcoindStartup`].
+    pub fn bitcoind_available(&self) -> bool {
+        self.bitcoind_rpc.is_available()
+    }
+
     pub fn config(&self) -> &Configuration {
         &self.config
     }
@@ -212,6 +534,152 @@ impl AppCx {
     pub fn set_trading_engine_state(&self, state: TradingEngineState) {
         self.inner_ro.te_state.store(state, Ordering::SeqCst)
     }
+
+    pub fn draining(&self) -> bool {
+        matches!(self.trading_engine_state(), TradingEngineState::Draining)
+    }
+
+    /// Stop or resume taking new orders, returns whether the exchange is now draining.
+    ///
+    /// A suspended engine can not be drained, and leaving drain mode always resumes normal
+    /// trading, the imbalance guard switches back to reduce-only on its next check if it must.
+    pub fn set_draining(&self, enabled: bool) -> bool {
+        let te_state = &self.inner_ro.te_state;
+        let _ = te_state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |state| {
+            match (state, enabled) {
+                (TradingEngineState::Running | TradingEngineState::ReduceOnly, true) => {
+                    Some(TradingEngineState::Draining)
+                }
+                (TradingEngineState::Draining, false) => Some(TradingEngineState::Running),
+                _ => None,
+            }
+        });
+
+        self.draining()
+    }
+
+    pub fn reserve_metrics(&self) -> &ReserveMetrics {
+        &self.inner_ro.reserve_metrics
+    }
+
+    pub fn tasks(&self) -> &Arc<TaskRegistry> {
+        &self.inner_ro.tasks
+    }
+
+    /// Register the trading engine in [`AppCx::tasks`], it is marked stopped once it exits.
+    pub async fn track_trading_engine(&self) {
+        let task = self
+            .tasks()
+            .register("trading engine", TRADING_ENGINE_STALL_AFTER);
+
+        if let Err(err) = self.te_tx.send(TradingEngineCmd::TrackLiveness(task)).await {
+            tracing::warn!(
+                ?err,
+                "failed to send track liveness command to trading engine"
+            );
+        }
+    }
+
+    pub fn blocked_addresses(&self) -> Arc<BlockedAddresses> {
+        self.inner_ro.blocked_addresses.current()
+    }
+
+    /// Replace the address blocklist without a restart, returns the number of entries in force.
+    pub fn reload_blocked_addresses(&self, entries: &[String]) -> usize {
+        let len = self.inner_ro.blocked_addresses.reload(entries);
+        tracing::warn!(
+            audit = "blocked_address",
+            entries = len,
+            "address blocklist reloaded"
+        );
+        len
+    }
+
+    /// Refuse a withdrawal by `user_id` to `address` if the address is blocked.
+    pub fn screen_withdrawal_address(
+        &self,
+        user_id: Uuid,
+        address: &str,
+    ) -> Result<(), BlockedAddressError> {
+        match self.blocked_addresses().matching(address) {
+            None => Ok(()),
+            Some(blocked_by) => {
+                tracing::warn!(
+                    audit = "blocked_address",
+                    %user_id,
+                    address,
+                    blocked_by,
+                    "withdrawal to a blocked address refused"
+                );
+                Err(BlockedAddressError { blocked_by })
+            }
+        }
+    }
+
+    pub fn ws_tickets(&self) -> &WsTickets {
+        &self.inner_ro.ws_tickets
+    }
+
+    pub fn maintenance_mode(&self) -> bool {
+        self.inner_ro.maintenance_mode.load(Ordering::Relaxed)
+    }
+
+    pub fn set_maintenance_mode(&self, enabled: bool) {
+        self.inner_ro
+            .maintenance_mode
+            .store(enabled, Ordering::SeqCst)
+    }
+
+    /// The number of commands waiting in the trading engine's channel.
+    pub fn engine_queue_depth(&self) -> usize {
+        self.te_tx.max_capacity() - self.te_tx.capacity()
+    }
+
+    /// `true` while new orders should be shed so the trading engine's queue can drain, see
+    /// [`crate::config::LoadShedding`].
+    pub fn shedding_load(&self) -> bool {
+        let config = &self.config.load_shedding;
+        if !config.enabled {
+            return false;
+        }
+
+        let depth = self.engine_queue_depth();
+        let capacity = self.te_tx.max_capacity();
+        let shedding = &self.inner_ro.shedding_load;
+
+        if depth >= config.high_water_mark(capacity) {
+            if !shedding.swap(true, Ordering::Relaxed) {
+                tracing::warn!(depth, "trading engine is backed up, shedding new orders");
+            }
+            true
+        } else if depth < config.low_water_mark(capacity) {
+            if shedding.swap(false, Ordering::Relaxed) {
+                tracing::info!(depth, "trading engine caught up, accepting new orders");
+            }
+            false
+        } else {
+            // between the marks, keep doing whatever was done last.
+            shedding.load(Ordering::Relaxed)
+        }
+    }
+
+    /// Begin a transaction whose statements are cancelled after [`Configuration::db_statement_timeout_ms`].
+    ///
+    /// Used on read-heavy paths so a runaway query fails with a timeout instead of holding a connection.
+    pub async fn begin_with_statement_timeout(
+        &self,
+    ) -> Result<sqlx::Transaction<'static, sqlx::Postgres>, sqlx::Error> {
+        let mut dtx = self.db.begin().await?;
+
+        if let Some(timeout_ms) = self.config.db_statement_timeout_ms {
+            // SET does not take bind parameters, `timeout_ms` is an integer so this is safe to format.
+            (&mut *dtx)
+                .execute(format!("SET LOCAL statement_timeout = {timeout_ms}").as_str())
+                .await?;
+        }
+
+        Ok(dtx)
+    }
 }
 
 impl AppCx {
@@ -219,6 +687,8 @@ impl AppCx {
         &self,
         user_id: uuid::Uuid,
     ) -> Result<Vec<(String, String)>, sqlx::Error> {
+        let mut dtx = self.begin_with_statement_timeout().await?;
+
         Ok(sqlx::query!(
             "SELECT address_text, currency
                 FROM user_addresses
@@ -226,7 +696,7 @@ impl AppCx {
                 AND kind = 'withdrawal';",
             user_id
         )
-        .fetch_all(&self.db)
+        .fetch_all(&mut *dtx)
         .await?
         .into_iter()
         .map(|rec| (rec.address_text, rec.currency))
@@ -237,6 +707,8 @@ impl AppCx {
         &self,
         user_id: uuid::Uuid,
     ) -> Result<Vec<(String, String)>, sqlx::Error> {
+        let mut dtx = self.begin_with_statement_timeout().await?;
+
         Ok(sqlx::query!(
             "SELECT address_text, currency
                 FROM user_addresses
@@ -244,7 +716,7 @@ impl AppCx {
                 AND kind = 'deposit';",
             user_id
         )
-        .fetch_all(&self.db)
+        .fetch_all(&mut *dtx)
         .await?
         .into_iter()
         .map(|rec| (rec.address_text, rec.currency))
@@ -307,6 +779,7 @@ impl AppCx {
         user_uuid: Uuid,
         ip_address: Option<IpAddr>,
         user_agent: Option<String>,
+        device_label: Option<String>,
     ) -> Result<String, sqlx::Error> {
         // generate a session token and store it
         let session_token = {
@@ -316,13 +789,15 @@ impl AppCx {
             hex::encode(bytes)
         };
 
+        // every login adds a session, those of the user's other devices stay valid.
         sqlx::query!(
-            "INSERT INTO session_tokens (token, max_age, user_id, ip_address, user_agent) VALUES ($1, $2, $3, $4, $5);",
+            "INSERT INTO session_tokens (token, max_age, user_id, ip_address, user_agent, device_label) VALUES ($1, $2, $3, $4, $5, $6);",
             session_token.as_bytes(),
             3600,
             user_uuid,
             ip_address.map(|ip| ip.to_string()),
-            user_agent
+            user_agent,
+            device_label
         )
         .execute(&self.db())
         .await?;
@@ -330,418 +805,4805 @@ impl AppCx {
         Ok(session_token)
     }
 
+    /// The user's sessions that have not expired, newest first, `current_token` is the session
+    /// the list is requested with.
+    pub async fn list_sessions(
+        &self,
+        user_uuid: Uuid,
+        current_token: &str,
+    ) -> Result<Vec<SessionInfo>, sqlx::Error> {
+        let recs = sqlx::query!(
+            r#"
+            SELECT id, token, device_label, ip_address, user_agent, created_at, last_accessed_at
+            FROM session_tokens
+            WHERE user_id = $1
+                AND created_at + make_interval(secs => max_age) > CURRENT_TIMESTAMP
+            ORDER BY created_at DESC, id DESC
+            "#,
+            user_uuid
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let millis = |at: sqlx::types::time::PrimitiveDateTime| {
+            (at.assume_utc().unix_timestamp_nanos() / 1_000_000) as i64
+        };
+
+        Ok(recs
+            .into_iter()
+            .map(|rec| SessionInfo {
+                id: rec.id,
+                device_label: rec.device_label,
+                ip_address: rec.ip_address,
+                user_agent: rec.user_agent,
+                created_at: millis(rec.created_at),
+                last_seen_at: rec.last_accessed_at.map(millis),
+                current: rec.token == current_token.as_bytes(),
+            })
+            .collect())
+    }
+
+    /// End the user's session `id`, returns `false` if the user has no such session.
+    pub async fn revoke_session(&self, user_uuid: Uuid, id: i32) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            "DELETE FROM session_tokens WHERE id = $1 AND user_id = $2",
+            id,
+            user_uuid
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
     pub async fn calculate_balance_from_accounting(
         &self,
         user_id: Uuid,
         currency: &str,
     ) -> Result<Option<NonZeroU64>, sqlx::Error> {
+        let mut dtx = self.begin_with_statement_timeout().await?;
+
         let rec = sqlx::query!(
             r#"
             SELECT calculate_balance($1, $2);"#,
             user_id.to_string(),
             currency
         )
-        .fetch_one(&self.db)
+        .fetch_one(&mut *dtx)
         .await?
         .calculate_balance;
         tracing::trace!(?rec, %user_id, ?currency, "balance");
         Ok(NonZeroU64::new(rec.unwrap_or_default() as u64))
     }
 
-    pub async fn update_user_accounts(&self, user_id: Uuid) {
-        async fn check_bitcoind(mut cx: AppCx, user_id: Uuid) -> Result<(), sqlx::Error> {
-            use crate::bitcoin::proto::ListTransactionsRequest;
+    /// Journal the deposits bitcoind reports for the user that have not been journalled yet.
+    ///
+    /// The deposits are journalled in a single transaction, in the order they confirmed, holding
+    /// the same lock on the user's account as [`AppCx::reconcile_wallet_deposits`]. At most
+    /// [`DepositReconciliation::max_per_cycle`] deposits are journalled per call, so a large
+    /// backlog (say after a rescan) is worked off over several calls rather than in one long
+    /// transaction. The deposits left over are counted in [`DepositSync::remaining`] and picked
+    /// up by the next call.
+    ///
+    /// [`DepositReconciliation::max_per_cycle`]: crate::config::DepositReconciliation::max_per_cycle
+    pub async fn update_user_accounts(
+        &self,
+        user_id: Uuid,
+    ) -> Result<DepositSync, ReconcileDepositsError> {
+        use crate::bitcoin::proto::ListTransactionsRequest;
 
-            let _db = cx.db();
-            let mut db = _db.begin().await?;
+        let limits = self.config.deposit_reconciliation;
+        let mut cx = self.clone();
+        let mut db = cx.begin_with_statement_timeout().await?;
 
-            let btc_account_rec = sqlx::query!(
-                r#"SELECT id FROM accounts WHERE source_type = 'crypto' AND source_id = 'bitcoin';"#
-            )
-            .fetch_one(&mut *db)
-            .await?;
+        let btc_account_rec = sqlx::query!(
+            r#"SELECT id FROM accounts WHERE source_type = 'crypto' AND source_id = 'bitcoin';"#
+        )
+        .fetch_one(&mut *db)
+        .await?;
 
-            let user_account_rec = sqlx::query!(
-                "SELECT * FROM accounts WHERE source_id = $1 AND currency = 'BTC' AND source_type = 'user';",
-                user_id.to_string()
-            )
-            .fetch_one(&mut *db)
-            .await?;
+        let user_account_rec = sqlx::query!(
+            "SELECT * FROM accounts WHERE source_id = $1 AND currency = 'BTC' AND source_type = 'user';",
+            user_id.to_string()
+        )
+        .fetch_one(&mut *db)
+        .await?;
 
-            let mut tx_journal = sqlx::query!("SELECT * FROM account_tx_journal WHERE credit_account_id = $1 AND debit_account_id = $2 AND currency = 'BTC' AND transaction_type = 'CHAIN.DEPOSIT';", user_account_rec.id, btc_account_rec.id)
-                .fetch_all(&mut *db)
-                .await?
-                .into_iter()
-                .map(|rec| (rec.txid.clone(), rec))
-                .collect::<HashMap<_, _>>();
-
-            let txs = cx
-                .bitcoind_rpc
-                .list_transactions(ListTransactionsRequest {
-                    label: Some(user_id.to_string()),
-                    count: None,
-                    skip: None,
-                    include_watch_only: None,
-                })
-                .await
-                .unwrap()
-                .into_inner();
+        let mut journalled = sqlx::query!("SELECT txid FROM account_tx_journal WHERE credit_account_id = $1 AND debit_account_id = $2 AND currency = 'BTC' AND transaction_type = 'CHAIN.DEPOSIT';", user_account_rec.id, btc_account_rec.id)
+            .fetch_all(&mut *db)
+            .await?
+            .into_iter()
+            .filter_map(|rec| rec.txid)
+            .collect::<std::collections::HashSet<_>>();
+
+        db.commit().await?;
+
+        let txs = match cx
+            .bitcoind_rpc
+            .list_transactions(ListTransactionsRequest {
+                label: Some(user_id.to_string()),
+                count: None,
+                skip: None,
+                include_watch_only: None,
+            })
+            .await
+        {
+            Ok(res) => res.into_inner(),
+            // nothing is lost, the deposits are picked up once bitcoind is back.
+            Err(status) if status.code() == tonic::Code::Unavailable => {
+                tracing::warn!(%user_id, ?status, "bitcoind unavailable, deposits not reconciled");
+                return Ok(DepositSync::default());
+            }
+            Err(status) => return Err(ReconcileDepositsError::ListTransactions(status)),
+        };
 
-            for tx in txs.transactions {
-                if tx_journal.contains_key(&tx.txid) {
-                    continue;
+        // bitcoind may list a txid more than once, e.g. one entry per output paying the user.
+        let blocked = self.blocked_addresses();
+        let mut pending = vec![];
+        let mut quarantined = 0;
+        for tx in txs
+            .transactions
+            .into_iter()
+            .filter(|tx| journalled.insert(tx.txid.clone()))
+        {
+            let address = tx.address.as_deref().unwrap_or_default();
+            match blocked.matching(address) {
+                Some(blocked_by) => {
+                    if self
+                        .quarantine_deposit(Some(user_id), &tx, address, &blocked_by)
+                        .await?
+                    {
+                        quarantined += 1;
+                    }
                 }
+                None => pending.push(tx),
+            }
+        }
+
+        // journalled oldest first, so a capped cycle leaves the newest for the next one.
+        pending.sort_by_key(confirmation_order);
+
+        let mut sync = DepositSync {
+            remaining: pending.len().saturating_sub(limits.max_per_cycle),
+            quarantined,
+            ..Default::default()
+        };
+
+        let this_cycle = &pending[..pending.len().min(limits.max_per_cycle)];
+
+        if !this_cycle.is_empty() {
+            let (amounts, txids): (Vec<i64>, Vec<String>) = this_cycle
+                .iter()
+                .map(|tx| (tx.amount as i64, tx.txid.clone()))
+                .unzip();
 
-                let res = sqlx::query!(
-                    r#"INSERT INTO account_tx_journal (
-                        credit_account_id,
-                        debit_account_id,
-                        currency,
-                        amount,
-                        transaction_type,
-                        txid
-                    ) VALUES ($1, $2, 'BTC', $3, 'CHAIN.DEPOSIT', $4)"#,
-                    user_account_rec.id,
-                    btc_account_rec.id,
-                    tx.amount as i64,
-                    tx.txid
+            let mut db = self.db.begin().await?;
+
+            // the same lock `reconcile_wallet_deposits` takes, so the two credit a user's
+            // deposits one after the other and neither journals one the other just did.
+            sqlx::query!(
+                "SELECT id FROM accounts WHERE id = $1 FOR UPDATE",
+                user_account_rec.id
+            )
+            .fetch_one(&mut *db)
+            .await?;
+
+            let res = sqlx::query!(
+                r#"
+                INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type, txid)
+                SELECT $1, $2, 'BTC', t.amount, 'CHAIN.DEPOSIT', t.txid
+                FROM UNNEST($3::int8[], $4::text[]) WITH ORDINALITY AS t(amount, txid, n)
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM account_tx_journal
+                    WHERE credit_account_id = $1 AND txid = t.txid AND transaction_type = 'CHAIN.DEPOSIT'
                 )
-                .execute(&mut *db)
-                .await?;
-            }
+                ORDER BY t.n
+                "#,
+                user_account_rec.id,
+                btc_account_rec.id,
+                &amounts,
+                &txids
+            )
+            .execute(&mut *db)
+            .await?;
 
             db.commit().await?;
 
-            Ok(())
+            sync.inserted = res.rows_affected() as usize;
         }
 
-        let check_bitcoind_fut = check_bitcoind(self.clone(), user_id.clone());
-        let (res,) = tokio::join!(check_bitcoind_fut);
-    }
+        if sync.remaining > 0 {
+            tracing::info!(
+                %user_id,
+                inserted = sync.inserted,
+                remaining = sync.remaining,
+                "deposit reconciliation capped, continuing next cycle"
+            );
+        }
 
-    pub async fn user_balance(&self, user_id: Uuid) -> Result<HashMap<String, i64>, sqlx::Error> {
-        let mut db = self.db.begin().await?;
-        let mut details = HashMap::new();
+        Ok(sync)
+    }
 
-        let vec = sqlx::query!(
-            "SELECT DISTINCT currency FROM accounts WHERE source_id = $1;",
-            user_id.to_string()
+    /// The user `address` was handed out to as a deposit address, if any.
+    ///
+    /// A user may deposit to their address any number of times, but an address handed to more
+    /// than one user can not say whose a deposit is, so it maps to no one and its deposits are
+    /// left for an operator to assign.
+    pub async fn user_for_deposit_address(
+        &self,
+        address: &str,
+    ) -> Result<Option<Uuid>, sqlx::Error> {
+        let owners = sqlx::query!(
+            "SELECT DISTINCT user_id FROM user_addresses WHERE address_text = $1 AND kind = 'deposit' LIMIT 2",
+            address
         )
-        .fetch_all(&mut *db)
+        .fetch_all(&self.db)
         .await?;
 
-        for rec in vec {
-            if let Ok(bal) = sqlx::query!(
-                r#"
-                SELECT calculate_balance($1, $2);"#,
-                user_id.to_string(),
-                rec.currency.to_string()
-            )
-            .fetch_one(&mut *db)
-            .await
-            {
-                details.insert(rec.currency, bal.calculate_balance.unwrap_or(0));
+        match owners.as_slice() {
+            [] => Ok(None),
+            [owner] => Ok(Some(owner.user_id)),
+            _ => {
+                tracing::error!(address, "deposit address belongs to more than one user");
+                Ok(None)
             }
         }
-
-        Ok(details)
     }
 
-    pub async fn reserve_by_asset(
-        &self,
-        user_uuid: Uuid,
-        quantity: std::num::NonZeroU32,
-        currency: &str,
-    ) -> Result<ReserveOk, ReserveError> {
-        let balance = self
-            .calculate_balance_from_accounting(user_uuid, currency)
-            .await?;
+    /// Journal the deposits to any user's address bitcoind reports for the whole wallet.
+    ///
+    /// Each transaction is credited to the owner of the address it paid, see
+    /// [`AppCx::user_for_deposit_address`], rather than reconciling users one at a time by
+    /// label. Deposits already journalled, by this or [`AppCx::update_user_accounts`], are
+    /// skipped. Returns the number of deposits journalled.
+    ///
+    /// A user's new deposits are journalled in a single transaction, in the order they
+    /// confirmed, holding a lock on the user's account so concurrent calls credit them one after
+    /// the other. A balance read in the meantime sees either none or all of them, never some. At
+    /// most [`DepositReconciliation::max_per_cycle`] deposits are journalled per call, counted by
+    /// whole users with the oldest deposits first, the rest are picked up by the next call.
+    ///
+    /// [`DepositReconciliation::max_per_cycle`]: crate::config::DepositReconciliation::max_per_cycle
+    pub async fn reconcile_wallet_deposits(&self) -> Result<usize, ReconcileDepositsError> {
+        use crate::bitcoin::proto::ListTransactionsRequest;
 
-        let balance = match balance {
-            Some(i) if i.get() >= quantity.get() as u64 => i,
-            _ => return Err(ReserveError::InsufficientFunds),
+        let limits = self.config.deposit_reconciliation;
+
+        let txs = match self
+            .bitcoind_rpc
+            .clone()
+            .list_transactions(ListTransactionsRequest {
+                label: None,
+                count: None,
+                skip: None,
+                include_watch_only: None,
+            })
+            .await
+        {
+            Ok(res) => res.into_inner(),
+            Err(status) if status.code() == tonic::Code::Unavailable => {
+                tracing::warn!(?status, "bitcoind unavailable, deposits not reconciled");
+                return Ok(0);
+            }
+            Err(status) => return Err(ReconcileDepositsError::ListTransactions(status)),
         };
 
-        // create a new account_tx_journal record to debit the user's account for the reserved amount.
-        let rec = sqlx::query!(
+        let txids = txs
+            .transactions
+            .iter()
+            .map(|tx| tx.txid.clone())
+            .collect::<Vec<_>>();
+        let mut journalled = sqlx::query!(
             r#"
-            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
-                (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = $3),
-                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $2),
-                $3,
-                $1,
-                'reserve asset'
-            ) RETURNING id
+            SELECT j.txid AS "txid!", a.source_id
+            FROM account_tx_journal j JOIN accounts a ON a.id = j.credit_account_id
+            WHERE j.transaction_type = 'CHAIN.DEPOSIT' AND j.txid = ANY($1::text[])
             "#,
-            quantity.get() as i64,
-            user_uuid.to_string(),
-            currency,
-        ).fetch_one(&self.db).await?;
-
-        tracing::trace!(id = ?rec.id, %user_uuid, "reserved USD fiat from user account");
-
-        let new_balance = self
-            .calculate_balance_from_accounting(user_uuid, currency)
-            .await?;
-        if let Some(nb) = new_balance {
-            assert!(nb.get() < balance.get());
-        }
+            &txids
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|rec| (rec.source_id, rec.txid))
+        .collect::<std::collections::HashSet<_>>();
 
-        Ok(ReserveOk {
-            row_id: rec.id as u32,
-            previous_balance: balance,
-            new_balance,
-        })
-    }
+        let blocked = self.blocked_addresses();
+        let mut by_user: HashMap<Uuid, Vec<_>> = HashMap::new();
 
-    pub async fn place_order(
-        &self,
-        asset: Asset,
-        user_uuid: uuid::Uuid,
-        trade_add_order: TradeAddOrder,
-    ) -> Result<(Response<PlaceOrderResult>, ReserveOk), PlaceOrderError> {
-        if !matches!(self.trading_engine_state(), TradingEngineState::Running) {
-            return Err(PlaceOrderError::TradingEngineUnresponsive);
-        }
+        for tx in txs.transactions {
+            let Some(address) = tx.address.as_deref().filter(|_| tx.category == "receive") else {
+                continue;
+            };
 
-        let TradeAddOrder {
-            side,
-            order_type,
-            stp,
-            quantity,
-            price,
-            time_in_force,
-        } = trade_add_order;
+            let owner = self.user_for_deposit_address(address).await?;
 
-        let reserve = match side {
-            OrderSide::Buy => self.reserve_by_asset(user_uuid, quantity, "USD").await?,
-            OrderSide::Sell => {
-                self.reserve_by_asset(
-                    user_uuid,
-                    quantity,
-                    match asset {
-                        Asset::Bitcoin => "BTC",
-                        Asset::Ether => "ETH",
-                    },
-                )
-                .await?
+            if let Some(blocked_by) = blocked.matching(address) {
+                self.quarantine_deposit(owner, &tx, address, &blocked_by)
+                    .await?;
+                continue;
             }
-        };
-
-        tracing::trace!(?reserve.previous_balance, ?reserve.new_balance, "marked funds as reserved");
-
-        let (place_order_tx, wait_response) = oneshot::channel();
-        let place_order = PlaceOrder::new(
-            asset,
-            user_uuid,
-            price,
-            quantity,
-            order_type,
-            stp,
-            time_in_force,
-            side,
-        );
 
-        let cmd = TradeCmd::PlaceOrder((place_order, place_order_tx));
+            let Some(user_id) = owner else {
+                tracing::debug!(address, txid = %tx.txid, "deposit to an unknown address");
+                continue;
+            };
 
-        match self.te_tx.send(TradingEngineCmd::Trade(cmd)).await {
-            Ok(()) => Ok((Response(wait_response), reserve)),
-            Err(err) => {
-                tracing::warn!(?err, "failed to send place order command to trading engine");
-                if let Err(err) = reserve.revert(&self.db).await {
-                    tracing::error!(?err, "failed to revert reserve");
-                }
-                Err(PlaceOrderError::TradingEngineUnresponsive)
+            // bitcoind may list a txid more than once, e.g. one entry per output paying the user.
+            if journalled.insert((user_id.to_string(), tx.txid.clone())) {
+                by_user.entry(user_id).or_default().push(tx);
             }
         }
-    }
 
-    pub async fn cancel_order(
-        &self,
-        user_uuid: Uuid,
-        order_uuid: Uuid,
-    ) -> Result<Response<()>, CancelOrderError> {
-        // Running and ReduceOnly are the only states where we can cancel orders.
-        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
-            return Err(CancelOrderError::TradingEngineUnresponsive);
+        let mut by_user = by_user.into_iter().collect::<Vec<_>>();
+        for (_, deposits) in by_user.iter_mut() {
+            deposits.sort_by_key(confirmation_order);
         }
+        by_user.sort_by_key(|(_, deposits)| deposits.first().map(confirmation_order));
 
-        let (cancel_order_tx, wait_response) = oneshot::channel();
-        let cancel_order = CancelOrder::new(user_uuid, OrderUuid(order_uuid));
-
-        let cmd = TradeCmd::CancelOrder((cancel_order, cancel_order_tx));
+        let mut inserted = 0;
+        let mut taken = 0;
 
-        match self.te_tx.send(TradingEngineCmd::Trade(cmd)).await {
-            Ok(()) => Ok(Response(wait_response)),
-            Err(err) => {
-                tracing::warn!(
-                    ?err,
-                    "failed to send cancel order command to trading engine"
+        for (user_id, deposits) in by_user {
+            // a user's deposits are never split, so the first one is taken whatever its size.
+            if taken > 0 && taken + deposits.len() > limits.max_per_cycle {
+                tracing::info!(
+                    inserted,
+                    "deposit reconciliation capped, continuing next cycle"
                 );
-                Err(CancelOrderError::TradingEngineUnresponsive)
+                break;
             }
+            taken += deposits.len();
+
+            let (amounts, txids): (Vec<i64>, Vec<String>) = deposits
+                .into_iter()
+                .map(|tx| (tx.amount as i64, tx.txid))
+                .unzip();
+
+            let mut db = self.db.begin().await?;
+
+            // taken before looking for the journalled deposits, so a concurrent call for the
+            // same user waits here and then sees what this one journalled.
+            let Some(account) = sqlx::query!(
+                "SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = 'BTC' FOR UPDATE",
+                user_id.to_string()
+            )
+            .fetch_optional(&mut *db)
+            .await?
+            else {
+                tracing::warn!(%user_id, "deposit to a user without a BTC account");
+                continue;
+            };
+
+            let res = sqlx::query!(
+                r#"
+                INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type, txid)
+                SELECT $1, b.id, 'BTC', t.amount, 'CHAIN.DEPOSIT', t.txid
+                FROM UNNEST($2::int8[], $3::text[]) WITH ORDINALITY AS t(amount, txid, n), accounts b
+                WHERE b.source_type = 'crypto' AND b.source_id = 'bitcoin'
+                    AND NOT EXISTS (
+                        SELECT 1 FROM account_tx_journal
+                        WHERE credit_account_id = $1 AND txid = t.txid AND transaction_type = 'CHAIN.DEPOSIT'
+                    )
+                ORDER BY t.n
+                "#,
+                account.id,
+                &amounts,
+                &txids
+            )
+            .execute(&mut *db)
+            .await?;
+
+            db.commit().await?;
+
+            inserted += res.rows_affected() as usize;
         }
+
+        Ok(inserted)
     }
 
-    pub async fn create_user(
+    /// Hold back a deposit to a blocked address instead of crediting it, returns `false` if it
+    /// was already quarantined.
+    ///
+    /// The deposit is not journalled while the address stays blocked. Taking the address off the
+    /// blocklist releases it, the next reconciliation credits it like any other deposit.
+    async fn quarantine_deposit(
         &self,
-        name: &str,
-        email: &str,
-        password_hash: PasswordHashString,
-    ) -> Result<Uuid, CreateUserError> {
-        match sqlx::query!(
+        user_id: Option<Uuid>,
+        tx: &crate::bitcoin::proto::list_transactions_response::Transaction,
+        address: &str,
+        blocked_by: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
             r#"
-            INSERT INTO users (name, email, password_hash)
-            VALUES ($1, $2, $3)
-            RETURNING id
+            INSERT INTO quarantined_deposits (txid, address_text, user_id, currency, amount, blocked_by)
+            VALUES ($1, $2, $3, 'BTC', $4, $5)
+            ON CONFLICT (txid, address_text) DO NOTHING
             "#,
-            name,
-            email,
-            password_hash.as_bytes(),
+            tx.txid,
+            address,
+            user_id,
+            tx.amount as i64,
+            blocked_by
         )
-        .fetch_one(&self.db())
-        .await
-        {
-            Ok(record) => Ok(record.id),
-            Err(err) => Err(match err {
-                sqlx::Error::Database(ref dbe) if dbe.is_unique_violation() => {
-                    CreateUserError::EmailUniqueViolation(err)
-                }
-                _ => CreateUserError::Sqlx(err),
-            }),
+        .execute(&self.db)
+        .await?;
+
+        let quarantined = res.rows_affected() > 0;
+        if quarantined {
+            tracing::warn!(
+                audit = "blocked_address",
+                ?user_id,
+                address,
+                blocked_by,
+                txid = %tx.txid,
+                "deposit to a blocked address quarantined"
+            );
         }
+
+        Ok(quarantined)
     }
 
-    pub async fn fetch_user_details(
-        &self,
-        user_id: uuid::Uuid,
-    ) -> Result<UserDetails, UserDetailsError> {
-        let mut dtx = self.db.begin().await?;
-        let _ = (*dtx).execute("SET TRANSACTION READ ONLY").await?;
+    pub async fn user_balance(&self, user_id: Uuid) -> Result<HashMap<String, i64>, sqlx::Error> {
+        let mut db = self.begin_with_statement_timeout().await?;
+        let mut details = HashMap::new();
 
-        let rec = sqlx::query!(
-            r#"SELECT id, name, role as "role: String"
-            FROM users
-            WHERE id = $1"#,
-            user_id
+        let vec = sqlx::query!(
+            "SELECT DISTINCT currency FROM accounts WHERE source_id = $1;",
+            user_id.to_string()
         )
-        .fetch_one(&mut *dtx)
+        .fetch_all(&mut *db)
         .await?;
 
-        let mut addrs = sqlx::query!(
-            r#"SELECT *
-            FROM user_addresses
-            WHERE user_id = $1"#,
-            user_id
+        for rec in vec {
+            // any error aborts the transaction, so there is no point carrying on past one.
+            let bal = sqlx::query!(
+                r#"
+                SELECT calculate_balance($1, $2);"#,
+                user_id.to_string(),
+                rec.currency.to_string()
+            )
+            .fetch_one(&mut *db)
+            .await?;
+
+            details.insert(rec.currency, bal.calculate_balance.unwrap_or(0));
+        }
+
+        Ok(details)
+    }
+
+    /// List a page of the movements of funds in and out of the user's accounts, newest first.
+    ///
+    /// Every kind of movement is a row of `account_tx_journal`, so the feed is a single keyset
+    /// paginated query ordered by `(created_at, id)`. Rows of one transaction share a timestamp,
+    /// the id breaks the tie. Pass the `next_cursor` of a page to fetch the one after it.
+    pub async fn list_activity(
+        &self,
+        user_id: Uuid,
+        cursor: Option<i32>,
+        limit: u32,
+    ) -> Result<ActivityPage, sqlx::Error> {
+        let mut dtx = self.begin_with_statement_timeout().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                j.id,
+                j.created_at,
+                j.transaction_type,
+                j.currency,
+                j.amount,
+                (c.source_type = 'user' AND c.source_id = $1) AS "incoming!"
+            FROM account_tx_journal j
+            JOIN accounts c ON c.id = j.credit_account_id
+            JOIN accounts d ON d.id = j.debit_account_id
+            WHERE ((c.source_type = 'user' AND c.source_id = $1) OR (d.source_type = 'user' AND d.source_id = $1))
+                AND ($2::INT IS NULL OR (j.created_at, j.id) < (SELECT created_at, id FROM account_tx_journal WHERE id = $2))
+            ORDER BY j.created_at DESC, j.id DESC
+            LIMIT $3
+            "#,
+            user_id.to_string(),
+            cursor,
+            i64::from(limit)
         )
         .fetch_all(&mut *dtx)
         .await?;
 
-        let mut accounts = HashMap::new();
-        for rec in addrs {
-            let entry = accounts
-                .entry(rec.currency.clone())
-                .or_insert(UserAccountDetails {
-                    amount: Default::default(),
-                    deposit_address: None,
-                    withrawal_addresses: vec![],
-                });
-
-            if rec.kind == "deposit" {
-                entry.deposit_address.replace(rec.address_text.clone());
-            } else {
-                entry.withrawal_addresses.push(UserWalletAddr {
-                    text: rec.address_text,
-                    currency: rec.currency,
-                    kind: rec.kind,
-                });
-            }
-        }
+        let activity = rows
+            .into_iter()
+            .map(|rec| Activity {
+                id: rec.id,
+                kind: ActivityKind::from_transaction_type(&rec.transaction_type),
+                transaction_type: rec.transaction_type,
+                currency: rec.currency,
+                amount: if rec.incoming {
+                    rec.amount
+                } else {
+                    -rec.amount
+                },
+                created_at: (rec.created_at.assume_utc().unix_timestamp_nanos() / 1_000_000) as i64,
+            })
+            .collect::<Vec<_>>();
 
-        let details = UserDetails {
-            name: rec.name,
-            id: rec.id,
-            role: rec.role,
-            accounts,
-            portfolio: UserPortfolio { value: 0 }
+        let next_cursor = match activity.last() {
+            Some(last) if activity.len() == limit as usize => Some(last.id),
+            _ => None,
         };
 
-        dtx.commit().await?;
-
-        Ok(details)
+        Ok(ActivityPage {
+            activity,
+            next_cursor,
+        })
     }
 
-    pub async fn fetch_user_account(
+    /// The statement of every movement of funds in or out of the user's accounts on the UTC day
+    /// `date`, see [`Statement`].
+    ///
+    /// Reserves are included, they are how an order pays for what it buys, so the totals add up
+    /// to the change in the user's balances over the day.
+    pub async fn user_statement(
         &self,
         user_id: Uuid,
-        asset: Asset,
-    ) -> Result<Option<UserAccount>, sqlx::Error> {
-        let rec = match sqlx::query!(
-            r#"SELECT id FROM accounts
-            WHERE currency = $1
-                AND source_type = 'user'
-                AND source_id = $2"#,
-            asset.to_string(),
-            user_id.to_string()
+        date: chrono::NaiveDate,
+    ) -> Result<Statement, sqlx::Error> {
+        let mut dtx = self.begin_with_statement_timeout().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                j.id,
+                j.created_at::TIMESTAMPTZ AS "created_at!",
+                j.transaction_type,
+                j.currency,
+                j.amount,
+                (c.source_type = 'user' AND c.source_id = $1) AS "incoming!"
+            FROM account_tx_journal j
+            JOIN accounts c ON c.id = j.credit_account_id
+            JOIN accounts d ON d.id = j.debit_account_id
+            WHERE ((c.source_type = 'user' AND c.source_id = $1) OR (d.source_type = 'user' AND d.source_id = $1))
+                AND j.created_at::TIMESTAMPTZ >= $2::TEXT::DATE::TIMESTAMP AT TIME ZONE 'UTC'
+                AND j.created_at::TIMESTAMPTZ < ($2::TEXT::DATE + 1)::TIMESTAMP AT TIME ZONE 'UTC'
+            ORDER BY j.id ASC
+            "#,
+            user_id.to_string(),
+            date.format("%Y-%m-%d").to_string()
         )
-        .fetch_one(&self.db)
-        .await
-        {
-            Ok(rec) => rec,
-            Err(sqlx::Error::RowNotFound) => return Ok(None),
-            Err(err) => return Err(err),
-        };
+        .fetch_all(&mut *dtx)
+        .await?;
 
-        Ok(Some(UserAccount {}))
+        let entries = rows
+            .into_iter()
+            .map(|rec| Activity {
+                id: rec.id,
+                kind: ActivityKind::from_transaction_type(&rec.transaction_type),
+                transaction_type: rec.transaction_type,
+                currency: rec.currency,
+                amount: if rec.incoming {
+                    rec.amount
+                } else {
+                    -rec.amount
+                },
+                created_at: (rec.created_at.unix_timestamp_nanos() / 1_000_000) as i64,
+            })
+            .collect();
+
+        let document = StatementDocument::new(user_id, date, entries);
+        Ok(Statement::new(
+            document,
+            self.config.statement_integrity_key.as_deref(),
+        ))
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::jinja::make_jinja_env;
-    use crate::spawn_trading_engine::spawn_trading_engine;
+    /// Credit `amount` of `currency` to the user without a real deposit, for tests and local development.
+    ///
+    /// The funds are journalled as a `FAUCET.DEPOSIT` from a dedicated faucet account, the user's
+    /// account for `currency` is opened if it doesn't exist yet. Refused unless `faucet_enabled` is set.
+    pub async fn credit_faucet(
+        &self,
+        user_id: Uuid,
+        currency: &str,
+        amount: NonZeroU64,
+    ) -> Result<(), FaucetError> {
+        if !self.config.faucet_enabled {
+            return Err(FaucetError::Disabled);
+        }
 
-    use super::*;
+        let amount = i64::try_from(amount.get()).map_err(|_| FaucetError::AmountTooLarge)?;
+        let mut dtx = self.db.begin().await?;
 
-    async fn make_app_cx_fixture(db: sqlx::PgPool) -> AppCx {
-        let config = Configuration::load_from_toml("");
-        let (te_tx, te_handle) = spawn_trading_engine(&config, db.clone())
-            .init_from_db(db.clone())
-            .await
-            .unwrap();
-        AppCx::new(
+        sqlx::query!(
+            r#"
+            INSERT INTO accounts (source_type, source_id, currency)
+            VALUES ('fiat', 'faucet', $1), ('user', $2, $1)
+            ON CONFLICT (source_id, currency) DO NOTHING;
+            "#,
+            currency,
+            user_id.to_string()
+        )
+        .execute(&mut *dtx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
+                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $2),
+                (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'faucet' AND currency = $2),
+                $2,
+                $3,
+                'FAUCET.DEPOSIT'
+            )
+            "#,
+            user_id.to_string(),
+            currency,
+            amount
+        )
+        .execute(&mut *dtx)
+        .await?;
+
+        dtx.commit().await?;
+
+        tracing::info!(%user_id, ?currency, amount, "credited faucet funds");
+        Ok(())
+    }
+
+    /// Credit the bank transfer received under `reference` to the user it was made for.
+    ///
+    /// The [`FiatProvider`] is asked what arrived and the amount is journalled as a `FIAT.DEPOSIT`
+    /// from the provider's `fiat` account, the reference is kept as the txid and a transfer is
+    /// only ever credited once. Only the quote currency is accepted.
+    pub async fn credit_fiat_deposit(
+        &self,
+        reference: &str,
+    ) -> Result<FiatDeposit, FiatDepositError> {
+        let provider = self.fiat.as_ref().ok_or(FiatDepositError::Disabled)?;
+        let deposit = provider.confirm_deposit(reference).await?;
+
+        if deposit.currency != QUOTE_CURRENCY {
+            return Err(FiatDepositError::UnsupportedCurrency);
+        }
+
+        let amount =
+            i64::try_from(deposit.amount.get()).map_err(|_| FiatDepositError::AmountTooLarge)?;
+        let mut dtx = self.db.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO accounts (source_type, source_id, currency)
+            VALUES ('fiat', $1, $3), ('user', $2, $3)
+            ON CONFLICT (source_id, currency) DO NOTHING;
+            "#,
+            provider.name(),
+            deposit.user_uuid.to_string(),
+            deposit.currency
+        )
+        .execute(&mut *dtx)
+        .await?;
+
+        let res = sqlx::query!(
+            r#"
+            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type, txid) VALUES (
+                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $2),
+                (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = $3 AND currency = $2),
+                $2,
+                $4,
+                'FIAT.DEPOSIT',
+                $5
+            )
+            ON CONFLICT (txid) WHERE transaction_type = 'FIAT.DEPOSIT' DO NOTHING
+            "#,
+            deposit.user_uuid.to_string(),
+            deposit.currency,
+            provider.name(),
+            amount,
+            reference
+        )
+        .execute(&mut *dtx)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(FiatDepositError::AlreadyCredited);
+        }
+
+        dtx.commit().await?;
+
+        tracing::info!(user_uuid = %deposit.user_uuid, reference, amount, "credited fiat deposit");
+        Ok(deposit)
+    }
+
+    /// Pretend the bank received `deposit` under `reference`, for development with the
+    /// [`MockFiatProvider`](crate::fiat::MockFiatProvider). It is credited like a real transfer
+    /// with [`AppCx::credit_fiat_deposit`].
+    pub fn receive_mock_fiat_transfer(
+        &self,
+        reference: &str,
+        deposit: FiatDeposit,
+    ) -> Result<(), FiatDepositError> {
+        let provider = self.fiat.as_ref().ok_or(FiatDepositError::Disabled)?;
+        let mock = provider.as_mock().ok_or(FiatDepositError::NotMock)?;
+
+        tracing::warn!(user_uuid = %deposit.user_uuid, reference, "made up a mock fiat transfer");
+        mock.receive(reference, deposit);
+        Ok(())
+    }
+
+    /// Journal the fees of a fill a `taker_side` taker for `asset` made against a maker.
+    ///
+    /// The taker pays `TRADE.FEE` to the exchange account. The maker either pays a `TRADE.FEE`
+    /// too or is credited a `TRADE.REBATE` out of it, see [`FillFees`]. Fees are charged in the
+    /// currency the [`FeeSchedule`] sets for the asset. A user whose balance of that currency
+    /// can not cover a fee pays it out of what the fill paid them instead, the base asset for
+    /// the buyer and the quote currency for the seller, so `dtx` must have credited the fill.
+    ///
+    /// [`FeeSchedule`]: crate::config::FeeSchedule
+    async fn charge_fill_fees(
+        &self,
+        dtx: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+        asset: Asset,
+        taker_side: OrderSide,
+        taker_uuid: Uuid,
+        execution: &Execution,
+    ) -> Result<(), sqlx::Error> {
+        use crate::config::FeeCurrency;
+
+        // what each side of a fill is paid in.
+        let (taker_proceeds, maker_proceeds) = match taker_side {
+            OrderSide::Buy => (FeeCurrency::Base, FeeCurrency::Quote),
+            OrderSide::Sell => (FeeCurrency::Quote, FeeCurrency::Base),
+        };
+
+        self.charge_fill_fee(dtx, taker_uuid, asset, execution, taker_proceeds, |fees| {
+            fees.taker_fee as i64
+        })
+        .await?;
+
+        match execution.maker_user_uuid {
+            Some(maker_uuid) => {
+                self.charge_fill_fee(dtx, maker_uuid, asset, execution, maker_proceeds, |fees| {
+                    fees.maker_fee
+                })
+                .await?
+            }
+            None => tracing::warn!(?execution, "can not settle maker fee of an unknown user"),
+        }
+
+        tracing::trace!(?execution, "settled fill fees");
+        Ok(())
+    }
+
+    /// Journal the fee `fee_of` picks out of the fees of `execution` for `user_id`, in the
+    /// configured fee currency if the user can cover it and in `proceeds` otherwise.
+    async fn charge_fill_fee(
+        &self,
+        dtx: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+        user_id: Uuid,
+        asset: Asset,
+        execution: &Execution,
+        proceeds: crate::config::FeeCurrency,
+        fee_of: impl Fn(FillFees) -> i64,
+    ) -> Result<(), sqlx::Error> {
+        let schedule = &self.config.fees;
+        let mut currency = schedule.currency(asset);
+        let mut amount = fee_of(FillFees::in_currency(schedule, execution, currency));
+
+        if amount > 0 && currency != proceeds {
+            // held until the fee is journalled, so a concurrent reserve can not spend the
+            // balance this checked.
+            let currency_code = fees::fee_currency_code(currency, asset);
+            let balance = locked_balance(dtx, user_id, &currency_code).await?;
+
+            if balance < amount {
+                tracing::debug!(%user_id, ?currency, amount, balance, "fee taken out of the fill");
+                currency = proceeds;
+                amount = fee_of(FillFees::in_currency(schedule, execution, currency));
+            }
+        }
+
+        journal_fee(
+            dtx,
+            user_id,
+            &fees::fee_currency_code(currency, asset),
+            amount,
+        )
+        .await
+    }
+
+    /// Settle what the trading engine journalled and nothing has settled yet, oldest first.
+    ///
+    /// Each settlement is applied in a transaction of its own that also marks it settled, so one
+    /// interrupted part way is applied again rather than twice. One that can never be applied is
+    /// marked with the error and left for an operator. Returns the number settled.
+    pub async fn settle_pending(&self) -> Result<usize, sqlx::Error> {
+        let mut settled = 0;
+        let mut after = 0;
+
+        loop {
+            let rows = sqlx::query!(
+                r#"SELECT id, jstr FROM trade_settlements
+                WHERE settled_at IS NULL AND error IS NULL AND id > $1
+                ORDER BY id
+                LIMIT $2"#,
+                after,
+                SETTLEMENT_BATCH_SIZE
+            )
+            .fetch_all(&self.db)
+            .await?;
+
+            let Some(last) = rows.last() else {
+                return Ok(settled);
+            };
+            after = last.id;
+
+            for row in rows {
+                let res = match serde_json::from_value(row.jstr) {
+                    Ok(settlement) => self.settle(row.id, &settlement).await,
+                    Err(err) => Err(SettlementError::Unreadable(err)),
+                };
+
+                match res {
+                    Ok(true) => settled += 1,
+                    Ok(false) => {}
+                    Err(SettlementError::Database(err)) => return Err(err),
+                    Err(err) => {
+                        tracing::error!(alert = "unsettled_trade", id = row.id, %err, "can not settle");
+                        sqlx::query!(
+                            "UPDATE trade_settlements SET error = $2 WHERE id = $1",
+                            row.id,
+                            err.to_string()
+                        )
+                        .execute(&self.db)
+                        .await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply the settlement `id` unless it has been already, returns whether it was applied.
+    async fn settle(
+        &self,
+        id: i64,
+        settlement: &crate::trading::Settlement,
+    ) -> Result<bool, SettlementError> {
+        let mut dtx = self.db.begin().await?;
+
+        // skipped if another settler holds it, it will have settled it by the time it lets go.
+        let pending = sqlx::query_scalar!(
+            "SELECT id FROM trade_settlements WHERE id = $1 AND settled_at IS NULL FOR UPDATE SKIP LOCKED",
+            id
+        )
+        .fetch_optional(&mut *dtx)
+        .await?;
+
+        if pending.is_none() {
+            return Ok(false);
+        }
+
+        // what the settlement drew from reserves, spent on the fill and released to the owner.
+        let (spent, released) = match *settlement {
+            crate::trading::Settlement::Fill {
+                asset,
+                taker_side,
+                taker,
+                maker,
+                quantity,
+                price,
+            } => {
+                let (buyer, seller) = match taker_side {
+                    OrderSide::Buy => (taker, maker),
+                    OrderSide::Sell => (maker, taker),
+                };
+                let filled = u64::from(quantity);
+
+                // the maker's reserve was taken when it was placed, so it can only be overdrawn if
+                // the ledger and the engine disagree. paying the fill out would overdraw it further.
+                let maker_currency = match taker_side {
+                    OrderSide::Buy => asset.to_string(),
+                    OrderSide::Sell => QUOTE_CURRENCY.to_owned(),
+                };
+                let balance = locked_balance(&mut dtx, maker.user_uuid, &maker_currency).await?;
+
+                if balance < 0 {
+                    let cancel = self.config.cancel_underfunded_makers;
+                    tracing::error!(
+                        alert = "underfunded_maker",
+                        ?asset,
+                        maker = ?maker.order_uuid,
+                        taker = ?taker.order_uuid,
+                        balance,
+                        cancel,
+                        "maker is overdrawn"
+                    );
+
+                    if cancel {
+                        drop(dtx);
+                        self.cancel_underfunded_maker(maker).await;
+                        return Err(SettlementError::MakerOverdrawn {
+                            order_uuid: maker.order_uuid,
+                            overdrawn: -balance,
+                        });
+                    }
+                }
+
+                // the buyer reserved at its own price and fills at the maker's, the difference is
+                // released back to it.
+                let improvement = buyer.price.get().saturating_sub(price.get());
+                let notional = crate::trading::notional(price.get(), filled)?;
+                let improvement = crate::trading::notional(improvement, filled)?;
+
+                let base_currency = asset.to_string();
+                journal_fill(
+                    &mut dtx,
+                    buyer.user_uuid,
+                    &base_currency,
+                    filled,
+                    "TRADE.FILL",
+                )
+                .await?;
+                journal_fill(
+                    &mut dtx,
+                    seller.user_uuid,
+                    QUOTE_CURRENCY,
+                    notional,
+                    "TRADE.FILL",
+                )
+                .await?;
+                journal_fill(
+                    &mut dtx,
+                    buyer.user_uuid,
+                    QUOTE_CURRENCY,
+                    improvement,
+                    "revert reserve asset",
+                )
+                .await?;
+
+                // fees fall back to the proceeds credited above, in the same transaction.
+                let execution = Execution {
+                    maker_order_uuid: Some(maker.order_uuid),
+                    maker_user_uuid: Some(maker.user_uuid),
+                    price,
+                    quantity,
+                    price_improvement: 0,
+                };
+                self.charge_fill_fees(&mut dtx, asset, taker_side, taker.user_uuid, &execution)
+                    .await?;
+
+                (
+                    vec![
+                        (QUOTE_CURRENCY.to_owned(), notional),
+                        (base_currency, filled),
+                    ],
+                    Some((QUOTE_CURRENCY.to_owned(), improvement))
+                        .filter(|(_, amount)| *amount > 0),
+                )
+            }
+            crate::trading::Settlement::Release {
+                asset,
+                side,
+                order,
+                quantity,
+            } => {
+                let (currency, amount) = match side {
+                    OrderSide::Buy => (
+                        QUOTE_CURRENCY.to_owned(),
+                        crate::trading::notional(order.price.get(), u64::from(quantity))?,
+                    ),
+                    OrderSide::Sell => (asset.to_string(), u64::from(quantity)),
+                };
+
+                journal_fill(
+                    &mut dtx,
+                    order.user_uuid,
+                    &currency,
+                    amount,
+                    "revert reserve asset",
+                )
+                .await?;
+
+                (vec![], Some((currency, amount)))
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE trade_settlements SET settled_at = CURRENT_TIMESTAMP WHERE id = $1",
+            id
+        )
+        .execute(&mut *dtx)
+        .await?;
+
+        dtx.commit().await?;
+
+        let metrics = self.reserve_metrics();
+        for (currency, amount) in spent {
+            metrics.record_settled(&currency, amount);
+        }
+        if let Some((currency, amount)) = released {
+            metrics.record_reverted(&currency, amount);
+        }
+
+        tracing::trace!(id, ?settlement, "settled");
+        Ok(true)
+    }
+
+    /// Cancel what is left of `maker` so no more fills against it are left unsettled.
+    async fn cancel_underfunded_maker(&self, maker: crate::trading::SettlementOrder) {
+        let res = match self.cancel_order(maker.user_uuid, maker.order_uuid.0).await {
+            Ok(response) => response.wait().await,
+            Err(err) => {
+                tracing::error!(?err, order_uuid = ?maker.order_uuid, "failed to cancel underfunded maker");
+                return;
+            }
+        };
+
+        // a maker that filled completely has nothing left to cancel.
+        if let Some(Err(err)) = res {
+            tracing::warn!(?err, order_uuid = ?maker.order_uuid, "underfunded maker not cancelled");
+        }
+    }
+
+    /// Settle what the trading engine journalled every [`SETTLEMENT_POLL_INTERVAL`], never returns.
+    pub async fn run_settlement(&self) {
+        let mut interval = tokio::time::interval(SETTLEMENT_POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        // a backlog is worked through in one go, allow it longer than the poll interval.
+        let task = self.tasks().register("settlement", SETTLEMENT_STALL_AFTER);
+
+        loop {
+            interval.tick().await;
+            task.heartbeat();
+
+            match self.settle_pending().await {
+                Ok(0) => {}
+                Ok(settled) => tracing::debug!(settled, "settled trades"),
+                Err(err) => tracing::warn!(?err, "failed to settle trades"),
+            }
+        }
+    }
+
+    /// Reserve `amount` of `currency` from the user's account, retrying transient database errors.
+    pub async fn reserve_by_asset(
+        &self,
+        user_uuid: Uuid,
+        amount: NonZeroU64,
+        currency: &str,
+    ) -> Result<ReserveOk, ReserveError> {
+        retry_transient(
+            || self.try_reserve_by_asset(user_uuid, amount, currency),
+            ReserveError::retryable,
+        )
+        .await
+        .inspect_err(|err| self.reserve_metrics().record_failure(err))
+    }
+
+    /// A single attempt at [`Self::reserve_by_asset`].
+    ///
+    /// The balance checks and the journal insert share one transaction, so an attempt that
+    /// fails part way is rolled back and can not leave a reserve behind for the next one.
+    async fn try_reserve_by_asset(
+        &self,
+        user_uuid: Uuid,
+        amount: NonZeroU64,
+        currency: &str,
+    ) -> Result<ReserveOk, ReserveError> {
+        let mut dtx = self.db.begin().await?;
+
+        // the same lock fees take, so a fee and a reserve never both spend one balance.
+        sqlx::query!(
+            "SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $2 FOR UPDATE",
+            user_uuid.to_string(),
+            currency
+        )
+        .fetch_optional(&mut *dtx)
+        .await?;
+
+        let balance = sqlx::query!(
+            r#"
+            SELECT calculate_balance($1, $2);"#,
+            user_uuid.to_string(),
+            currency
+        )
+        .fetch_one(&mut *dtx)
+        .await?
+        .calculate_balance;
+
+        let balance = match NonZeroU64::new(balance.unwrap_or_default() as u64) {
+            Some(i) if i.get() >= amount.get() => i,
+            _ => return Err(ReserveError::InsufficientFunds),
+        };
+
+        // only a USD account for the exchange is created by the migrations.
+        sqlx::query!(
+            r#"
+            INSERT INTO accounts (source_type, source_id, currency)
+            VALUES ('fiat', 'exchange', $1)
+            ON CONFLICT (source_id, currency) DO NOTHING;
+            "#,
+            currency
+        )
+        .execute(&mut *dtx)
+        .await?;
+
+        // create a new account_tx_journal record to debit the user's account for the reserved amount.
+        let rec = sqlx::query!(
+            r#"
+            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
+                (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = $3),
+                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $2 AND currency = $3),
+                $3,
+                $1,
+                'reserve asset'
+            ) RETURNING id
+            "#,
+            amount.get() as i64,
+            user_uuid.to_string(),
+            currency,
+        ).fetch_one(&mut *dtx).await?;
+
+        tracing::trace!(id = ?rec.id, %user_uuid, "reserved USD fiat from user account");
+
+        let new_balance = sqlx::query!(
+            r#"
+            SELECT calculate_balance($1, $2);"#,
+            user_uuid.to_string(),
+            currency
+        )
+        .fetch_one(&mut *dtx)
+        .await?
+        .calculate_balance;
+
+        let new_balance = NonZeroU64::new(new_balance.unwrap_or_default() as u64);
+        if let Some(nb) = new_balance {
+            assert!(nb.get() < balance.get());
+        }
+
+        dtx.commit().await.map_err(ReserveError::Commit)?;
+
+        let amount = amount.get();
+        self.reserve_metrics().record_created(currency, amount);
+
+        Ok(ReserveOk {
+            row_id: rec.id as u32,
+            user_uuid,
+            previous_balance: balance,
+            new_balance,
+            currency: currency.to_owned(),
+            amount,
+            metrics: self.inner_ro.reserve_metrics.clone(),
+        })
+    }
+
+    /// The self-trade protection applied to orders of `user_uuid` that do not set one, `None` if
+    /// the user has not chosen one.
+    pub async fn default_stp(
+        &self,
+        user_uuid: Uuid,
+    ) -> Result<Option<SelfTradeProtection>, sqlx::Error> {
+        let rec = sqlx::query!("SELECT default_stp FROM users WHERE id = $1", user_uuid)
+            .fetch_optional(&self.db)
+            .await?;
+
+        Ok(rec
+            .and_then(|rec| rec.default_stp)
+            .and_then(|stp| match stp.parse() {
+                Ok(stp) => Some(stp),
+                Err(err) => {
+                    tracing::warn!(?err, ?user_uuid, "ignoring invalid default stp");
+                    None
+                }
+            }))
+    }
+
+    /// Set the self-trade protection applied to orders of `user_uuid` that do not set one, `None`
+    /// to go back to the exchange default.
+    pub async fn set_default_stp(
+        &self,
+        user_uuid: Uuid,
+        stp: Option<SelfTradeProtection>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE users SET default_stp = $2 WHERE id = $1",
+            user_uuid,
+            stp.as_ref().map(SelfTradeProtection::as_str)
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn place_order(
+        &self,
+        asset: Asset,
+        user_uuid: uuid::Uuid,
+        trade_add_order: TradeAddOrder,
+    ) -> Result<Response<PlaceOrderResult>, PlaceOrderError> {
+        self.place_order_before(asset, user_uuid, trade_add_order, default_trade_deadline())
+            .await
+    }
+
+    /// Place an order the trading engine drops unprocessed once `deadline` passes, the
+    /// deadline of the request it is placed for.
+    pub async fn place_order_before(
+        &self,
+        asset: Asset,
+        user_uuid: uuid::Uuid,
+        trade_add_order: TradeAddOrder,
+        deadline: tokio::time::Instant,
+    ) -> Result<Response<PlaceOrderResult>, PlaceOrderError> {
+        let state = self.trading_engine_state();
+        if matches!(state, TradingEngineState::Suspended) {
+            return Err(PlaceOrderError::TradingEngineUnresponsive);
+        }
+
+        if matches!(state, TradingEngineState::Draining) {
+            return Err(PlaceOrderError::Draining);
+        }
+
+        let TradeAddOrder {
+            side,
+            order_type,
+            stp,
+            quantity,
+            price,
+            time_in_force,
+            reduce_only,
+            all_or_none,
+            expires_at,
+            expires_in_ms,
+            nonce,
+            trigger,
+            client_order_id,
+            last_look,
+        } = trade_add_order;
+
+        // reduce-only orders never rest, so they can not make a one-sided book any worse.
+        if matches!(state, TradingEngineState::ReduceOnly) && !reduce_only {
+            return Err(PlaceOrderError::ReduceOnly);
+        }
+
+        if last_look && !self.config.last_look.enabled {
+            return Err(PlaceOrderError::LastLookDisabled);
+        }
+
+        // the engine keeps every id it is given, so they are bounded like any other input.
+        if client_order_id
+            .as_ref()
+            .is_some_and(|id| id.is_empty() || id.len() > MAX_CLIENT_ORDER_ID_LEN)
+        {
+            return Err(PlaceOrderError::InvalidClientOrderId);
+        }
+
+        // an explicit per-order value wins over the user's default.
+        let stp = match stp {
+            Some(stp) => stp,
+            None => self.default_stp(user_uuid).await?.unwrap_or_default(),
+        };
+
+        let mut place_order = PlaceOrder::new(
+            asset,
+            user_uuid,
+            price,
+            quantity,
+            order_type,
+            stp,
+            time_in_force,
+            side,
+            reduce_only,
+            all_or_none,
+            expires_at,
+        )
+        .with_nonce(nonce)
+        .with_trigger(trigger)
+        .with_client_order_id(client_order_id)
+        .with_last_look(last_look);
+
+        // validate the order before any funds are reserved for it.
+        if let Some(expires_in_ms) = expires_in_ms {
+            place_order.expire_after(std::time::Duration::from_millis(expires_in_ms))?;
+        }
+        place_order.apply_ttl(&self.config.order_ttl)?;
+
+        // both sides are bounded, so every fill is: a fill is at most the quantity of either
+        // order at the price of the one resting, and a taker buy fills at or under its price.
+        let notional = crate::trading::checked_notional(
+            price.get(),
+            u64::from(quantity.get()),
+            self.config.max_order_notional,
+        )?;
+
+        let (reserved, currency) = match side {
+            OrderSide::Buy => {
+                // every unit may cost up to the limit price, fills at a better price release the
+                // difference when they are settled.
+                let notional = NonZeroU64::new(notional).expect("price and quantity are non-zero");
+                (notional, QUOTE_CURRENCY)
+            }
+            OrderSide::Sell => (
+                NonZeroU64::from(quantity),
+                match asset {
+                    Asset::Bitcoin => "BTC",
+                    Asset::Ether => "ETH",
+                },
+            ),
+        };
+        let reserve = self.reserve_by_asset(user_uuid, reserved, currency).await?;
+
+        tracing::trace!(?reserve.previous_balance, ?reserve.new_balance, "marked funds as reserved");
+
+        // from here on every path that does not hand the order to the trading engine must release
+        // the reserved funds. once it has the order the engine settles the reserve, what it fills
+        // and releases is journalled along with the order, see `settle_pending`.
+        let reserve_guard = reserve.defer_revert(tokio::runtime::Handle::current(), self.db());
+
+        let (place_order_tx, wait_response) =
+            response_channel(self.inner_ro.place_order_ring.as_ref());
+
+        let cmd = TradeCmd::PlaceOrder((place_order, place_order_tx));
+
+        match self
+            .te_tx
+            .send(TradingEngineCmd::trade_by(cmd, deadline))
+            .await
+        {
+            Ok(()) => {
+                reserve_guard.accept();
+                Ok(Response(wait_response))
+            }
+            Err(err) => {
+                tracing::warn!(?err, "failed to send place order command to trading engine");
+                Err(PlaceOrderError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
+    pub async fn cancel_order(
+        &self,
+        user_uuid: Uuid,
+        order_uuid: Uuid,
+    ) -> Result<Response<()>, CancelOrderError> {
+        self.cancel_order_before(user_uuid, order_uuid, default_trade_deadline())
+            .await
+    }
+
+    /// Cancel an order, unless the trading engine only gets to it after `deadline`.
+    pub async fn cancel_order_before(
+        &self,
+        user_uuid: Uuid,
+        order_uuid: Uuid,
+        deadline: tokio::time::Instant,
+    ) -> Result<Response<()>, CancelOrderError> {
+        // Running, ReduceOnly and Draining are the states where we can cancel orders.
+        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
+            return Err(CancelOrderError::TradingEngineUnresponsive);
+        }
+
+        let (cancel_order_tx, wait_response) =
+            response_channel(self.inner_ro.cancel_order_ring.as_ref());
+        let cancel_order = CancelOrder::new(user_uuid, OrderUuid(order_uuid));
+
+        let cmd = TradeCmd::CancelOrder((cancel_order, cancel_order_tx));
+
+        match self
+            .te_tx
+            .send(TradingEngineCmd::trade_by(cmd, deadline))
+            .await
+        {
+            Ok(()) => Ok(Response(wait_response)),
+            Err(err) => {
+                tracing::warn!(
+                    ?err,
+                    "failed to send cancel order command to trading engine"
+                );
+                Err(CancelOrderError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
+    /// Take `by` off the quantity of a resting order, it keeps its place in the queue. Not done
+    /// if the trading engine only gets to it after `deadline`.
+    pub async fn reduce_order(
+        &self,
+        user_uuid: Uuid,
+        order_uuid: Uuid,
+        by: std::num::NonZeroU32,
+        deadline: tokio::time::Instant,
+    ) -> Result<Response<u32>, CancelOrderError> {
+        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
+            return Err(CancelOrderError::TradingEngineUnresponsive);
+        }
+
+        let (reduce_order_tx, wait_response) = response_channel(None);
+        let reduce_order = ReduceOrder::new(user_uuid, OrderUuid(order_uuid), by);
+
+        let cmd = TradeCmd::ReduceOrder((reduce_order, reduce_order_tx));
+
+        match self
+            .te_tx
+            .send(TradingEngineCmd::trade_by(cmd, deadline))
+            .await
+        {
+            Ok(()) => Ok(Response(wait_response)),
+            Err(err) => {
+                tracing::warn!(
+                    ?err,
+                    "failed to send reduce order command to trading engine"
+                );
+                Err(CancelOrderError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
+    /// Cancel the open order `user_uuid` placed with `client_order_id`, unless the trading engine
+    /// only gets to it after `deadline`.
+    pub async fn cancel_order_by_client_id(
+        &self,
+        user_uuid: Uuid,
+        client_order_id: String,
+        deadline: tokio::time::Instant,
+    ) -> Result<Response<()>, CancelOrderError> {
+        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
+            return Err(CancelOrderError::TradingEngineUnresponsive);
+        }
+
+        let (cancel_order_tx, wait_response) =
+            response_channel(self.inner_ro.cancel_order_ring.as_ref());
+        let cancel_order = CancelOrderByClientId::new(user_uuid, client_order_id);
+
+        let cmd = TradeCmd::CancelOrderByClientId((cancel_order, cancel_order_tx));
+
+        match self
+            .te_tx
+            .send(TradingEngineCmd::trade_by(cmd, deadline))
+            .await
+        {
+            Ok(()) => Ok(Response(wait_response)),
+            Err(err) => {
+                tracing::warn!(
+                    ?err,
+                    "failed to send cancel order command to trading engine"
+                );
+                Err(CancelOrderError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
+    pub async fn depth_snapshot(
+        &self,
+        asset: Asset,
+    ) -> Result<Response<DepthSnapshot>, DepthSnapshotError> {
+        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
+            return Err(DepthSnapshotError::TradingEngineUnresponsive);
+        }
+
+        let (depth_snapshot_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::DepthSnapshot((asset, depth_snapshot_tx));
+
+        match self.te_tx.send(cmd).await {
+            Ok(()) => Ok(Response(wait_response)),
+            Err(err) => {
+                tracing::warn!(
+                    ?err,
+                    "failed to send depth snapshot command to trading engine"
+                );
+                Err(DepthSnapshotError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
+    /// Subscribe to the [`MatchEvent`]s of every book, starting with the next command the engine processes.
+    ///
+    /// A subscriber that falls too far behind misses events and is told so by
+    /// [`tokio::sync::broadcast::error::RecvError::Lagged`].
+    pub async fn subscribe_match_events(
+        &self,
+    ) -> Result<tokio::sync::broadcast::Receiver<MatchEvent>, SubscribeMatchEventsError> {
+        let (subscribe_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::SubscribeMatchEvents(subscribe_tx);
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(?err, "failed to send subscribe command to trading engine");
+            return Err(SubscribeMatchEventsError::TradingEngineUnresponsive);
+        }
+
+        match Response(wait_response).wait().await {
+            Some(Ok(subscription)) => Ok(subscription),
+            Some(Err(_)) | None => Err(SubscribeMatchEventsError::TradingEngineUnresponsive),
+        }
+    }
+
+    /// Subscribe to the [`ExecutionReport`]s of every order, starting with the next command the
+    /// engine processes. Callers filter them down to the orders they care about.
+    pub async fn subscribe_execution_reports(
+        &self,
+    ) -> Result<tokio::sync::broadcast::Receiver<ExecutionReport>, SubscribeExecutionReportsError>
+    {
+        let (subscribe_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::SubscribeExecutionReports(subscribe_tx);
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(?err, "failed to send subscribe command to trading engine");
+            return Err(SubscribeExecutionReportsError::TradingEngineUnresponsive);
+        }
+
+        match Response(wait_response).wait().await {
+            Some(Ok(subscription)) => Ok(subscription),
+            Some(Err(_)) | None => Err(SubscribeExecutionReportsError::TradingEngineUnresponsive),
+        }
+    }
+
+    /// Take a [`DepthSnapshot`] of the book for `asset` and subscribe to the [`MarketData`] of
+    /// every book from then on, the feed picks up exactly where the snapshot leaves off.
+    ///
+    /// A subscriber that falls too far behind misses frames and is told so by
+    /// [`tokio::sync::broadcast::error::RecvError::Lagged`], its copy of the book is no longer
+    /// right and it has to start over with a new snapshot.
+    pub async fn subscribe_market_data(
+        &self,
+        asset: Asset,
+    ) -> Result<
+        (DepthSnapshot, tokio::sync::broadcast::Receiver<MarketData>),
+        SubscribeMarketDataError,
+    > {
+        let (subscribe_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::SubscribeMarketData((asset, subscribe_tx));
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(?err, "failed to send subscribe command to trading engine");
+            return Err(SubscribeMarketDataError::TradingEngineUnresponsive);
+        }
+
+        match Response(wait_response).wait().await {
+            Some(Ok(subscription)) => Ok(subscription),
+            Some(Err(_)) | None => Err(SubscribeMarketDataError::TradingEngineUnresponsive),
+        }
+    }
+
+    /// Receive the last looks at matches against the last-look orders of `user_uuid`, see
+    /// [`crate::trading::last_look`].
+    pub async fn subscribe_last_look(
+        &self,
+        user_uuid: Uuid,
+    ) -> Result<tokio::sync::mpsc::Receiver<LastLookRequest>, SubscribeLastLookError> {
+        if !self.config.last_look.enabled {
+            return Err(SubscribeLastLookError::Disabled);
+        }
+
+        let (subscribe_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::SubscribeLastLook((user_uuid, subscribe_tx));
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(?err, "failed to send subscribe command to trading engine");
+            return Err(SubscribeLastLookError::TradingEngineUnresponsive);
+        }
+
+        match Response(wait_response).wait().await {
+            Some(Ok(subscription)) => Ok(subscription),
+            Some(Err(_)) | None => Err(SubscribeLastLookError::TradingEngineUnresponsive),
+        }
+    }
+
+    /// Replace the book for `asset` with one rebuilt from the journal, for operators who suspect
+    /// the book in memory no longer matches it.
+    ///
+    /// The journal is replayed here, while the engine goes on trading. The engine then only
+    /// replays what it journalled in the meantime before swapping the book, so no command is
+    /// lost and the engine is held up for no longer than that.
+    pub async fn rebuild_book(&self, asset: Asset) -> Result<RebuildReport, RebuildBookError> {
+        let mut rebuilt = Box::new(crate::trading::Assets::from_config(&self.config));
+        let through =
+            crate::spawn_trading_engine::replay_journal(&mut rebuilt, &self.db, 0).await?;
+
+        let (rebuild_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::RebuildBook((asset, rebuilt, through, rebuild_tx));
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(?err, "failed to send rebuild command to trading engine");
+            return Err(RebuildBookError::TradingEngineUnresponsive);
+        }
+
+        match Response(wait_response).wait().await {
+            Some(res) => Ok(res?),
+            None => Err(RebuildBookError::TradingEngineUnresponsive),
+        }
+    }
+
+    /// Ask the trading engine for its internal counters, for operators only.
+    pub async fn engine_stats(&self) -> Result<EngineStats, EngineStatsError> {
+        let (stats_tx, wait_response) = response_channel(None);
+
+        if let Err(err) = self.te_tx.send(TradingEngineCmd::Stats(stats_tx)).await {
+            tracing::warn!(?err, "failed to send stats command to trading engine");
+            return Err(EngineStatsError::TradingEngineUnresponsive);
+        }
+
+        match Response(wait_response).wait().await {
+            Some(Ok(stats)) => Ok(stats),
+            Some(Err(_)) | None => Err(EngineStatsError::TradingEngineUnresponsive),
+        }
+    }
+
+    /// List a page of the raw resting orders of the book for `asset`, for operators only.
+    pub async fn resting_orders(
+        &self,
+        asset: Asset,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Response<RestingOrdersPage>, RestingOrdersError> {
+        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
+            return Err(RestingOrdersError::TradingEngineUnresponsive);
+        }
+
+        let (resting_orders_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::RestingOrders((asset, offset, limit, resting_orders_tx));
+
+        match self.te_tx.send(cmd).await {
+            Ok(()) => Ok(Response(wait_response)),
+            Err(err) => {
+                tracing::warn!(
+                    ?err,
+                    "failed to send resting orders command to trading engine"
+                );
+                Err(RestingOrdersError::TradingEngineUnresponsive)
+            }
+        }
+    }
+
+    /// The price, remaining quantity and uuid of the order on `side` of the book for `asset`
+    /// that would match next, `None` if that side is empty.
+    ///
+    /// Finer grained than the best bid and offer, it names the order at the front of the queue
+    /// at the best price. Nothing in the book is changed.
+    pub async fn peek_top(
+        &self,
+        asset: Asset,
+        side: OrderSide,
+    ) -> Result<Option<(std::num::NonZeroU32, std::num::NonZeroU32, OrderUuid)>, PeekTopError> {
+        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
+            return Err(PeekTopError::TradingEngineUnresponsive);
+        }
+
+        let (peek_top_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::PeekTop((asset, side, peek_top_tx));
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(?err, "failed to send peek top command to trading engine");
+            return Err(PeekTopError::TradingEngineUnresponsive);
+        }
+
+        match Response(wait_response).wait().await {
+            Some(Ok(top)) => Ok(top),
+            Some(Err(_)) | None => Err(PeekTopError::TradingEngineUnresponsive),
+        }
+    }
+
+    /// Fetch the current state of a single order on behalf of `user_uuid`.
+    pub async fn fetch_order(
+        &self,
+        user_uuid: Uuid,
+        asset: Asset,
+        order_uuid: OrderUuid,
+    ) -> Result<OrderRecord, FetchOrderError> {
+        if matches!(self.trading_engine_state(), TradingEngineState::Suspended) {
+            return Err(FetchOrderError::TradingEngineUnresponsive);
+        }
+
+        let (fetch_order_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::FetchOrder((order_uuid, fetch_order_tx));
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(?err, "failed to send fetch order command to trading engine");
+            return Err(FetchOrderError::TradingEngineUnresponsive);
+        }
+
+        let record = match Response(wait_response).wait().await {
+            Some(Ok(Some(record))) => record,
+            // closed orders leave the engine once archived, history reads the archive.
+            Some(Ok(None)) => self
+                .fetch_archived_order(order_uuid)
+                .await?
+                .ok_or(FetchOrderError::NotFound)?,
+            Some(Err(_)) | None => return Err(FetchOrderError::TradingEngineUnresponsive),
+        };
+
+        if record.asset != asset {
+            Err(FetchOrderError::NotFound)
+        } else if record.user_uuid != user_uuid {
+            Err(FetchOrderError::Forbidden)
+        } else {
+            Ok(record)
+        }
+    }
+
+    /// Look up the record of an order in `orders_archive`.
+    async fn fetch_archived_order(
+        &self,
+        order_uuid: OrderUuid,
+    ) -> Result<Option<OrderRecord>, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"SELECT record as "record: sqlx::types::Json<OrderRecord>" FROM orders_archive WHERE order_uuid = $1"#,
+            order_uuid.0
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(rec.map(|rec| rec.record.0))
+    }
+
+    /// Move the records of the orders closed before `closed_before`, in milliseconds since the
+    /// unix epoch, out of the trading engine into `orders_archive`. Returns how many were archived.
+    ///
+    /// The records are inserted first and only the archived orders are then dropped from the
+    /// engine, if either step fails the engine keeps them and the next run archives them again.
+    pub async fn archive_closed_orders(
+        &self,
+        closed_before: i64,
+    ) -> Result<usize, ArchiveOrdersError> {
+        let (closed_tx, wait_response) = response_channel(None);
+        let cmd = TradingEngineCmd::ClosedOrders((closed_before, closed_tx));
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(
+                ?err,
+                "failed to send closed orders command to trading engine"
+            );
+            return Err(ArchiveOrdersError::TradingEngineUnresponsive);
+        }
+
+        let records = match Response(wait_response).wait().await {
+            Some(Ok(records)) => records,
+            Some(Err(_)) | None => return Err(ArchiveOrdersError::TradingEngineUnresponsive),
+        };
+
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        let mut order_uuids = Vec::with_capacity(records.len());
+        let mut user_uuids = Vec::with_capacity(records.len());
+        let mut closed_ats = Vec::with_capacity(records.len());
+        let mut jstrs = Vec::with_capacity(records.len());
+
+        for record in &records {
+            order_uuids.push(record.order_uuid.0);
+            user_uuids.push(record.user_uuid);
+            closed_ats.push(record.closed_at.unwrap_or(record.created_at));
+            jstrs.push(serde_json::to_value(record).expect("order records serialize"));
+        }
+
+        let res = sqlx::query!(
+            r#"INSERT INTO orders_archive (order_uuid, user_id, closed_at, record)
+            SELECT t.order_uuid, t.user_id, to_timestamp(t.closed_at / 1000.0), t.record
+            FROM UNNEST($1::uuid[], $2::uuid[], $3::int8[], $4::jsonb[]) AS t(order_uuid, user_id, closed_at, record)
+            ON CONFLICT (order_uuid) DO NOTHING"#,
+            &order_uuids,
+            &user_uuids,
+            &closed_ats,
+            &jstrs
+        )
+        .execute(&self.db)
+        .await;
+
+        if let Err(err) = res {
+            tracing::warn!(?err, "failed to archive closed orders");
+            return Err(err.into());
+        }
+
+        let (drop_tx, wait_response) = response_channel(None);
+        let order_uuids = order_uuids.into_iter().map(OrderUuid).collect();
+        let cmd = TradingEngineCmd::DropArchivedOrders((order_uuids, drop_tx));
+
+        if let Err(err) = self.te_tx.send(cmd).await {
+            tracing::warn!(
+                ?err,
+                "failed to send drop archived orders command to trading engine"
+            );
+            return Err(ArchiveOrdersError::TradingEngineUnresponsive);
+        }
+
+        match Response(wait_response).wait().await {
+            Some(Ok(_)) => Ok(records.len()),
+            Some(Err(_)) | None => Err(ArchiveOrdersError::TradingEngineUnresponsive),
+        }
+    }
+
+    /// Archive closed orders every [`OrderArchival::interval`](crate::config::OrderArchival::interval),
+    /// never returns.
+    pub async fn run_order_archival(&self) {
+        let archival = self.config().order_archival;
+        let mut interval = tokio::time::interval(archival.interval());
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        // a cycle may run late behind a slow one, allow a whole extra interval.
+        let task = self
+            .tasks()
+            .register("order archival", archival.interval() * 2);
+
+        loop {
+            interval.tick().await;
+            task.heartbeat();
+
+            let retention = i64::try_from(archival.retention().as_millis()).unwrap_or(i64::MAX);
+            let closed_before = chrono::Utc::now()
+                .timestamp_millis()
+                .saturating_sub(retention);
+
+            match self.archive_closed_orders(closed_before).await {
+                Ok(0) => {}
+                Ok(archived) => tracing::info!(archived, "archived closed orders"),
+                Err(err) => tracing::warn!(?err, "failed to archive closed orders"),
+            }
+        }
+    }
+
+    /// Check the imbalance of every book once, switching the engine between running and
+    /// reduce-only as books trip and recover, see [`crate::config::ImbalanceGuard`].
+    pub async fn check_imbalance(&self, monitor: &mut ImbalanceMonitor, now: tokio::time::Instant) {
+        let levels = self.config.imbalance_guard.levels;
+
+        for (_, asset) in self.assets {
+            let Ok(wait_response) = self.depth_snapshot(*asset).await else {
+                return;
+            };
+            let Some(Ok(snapshot)) = wait_response.wait().await else {
+                tracing::warn!(?asset, "failed to take depth snapshot");
+                continue;
+            };
+
+            let ticker = snapshot.ticker(levels);
+            match monitor.observe(*asset, ticker.imbalance, now) {
+                Some(ImbalanceTransition::Tripped) => tracing::error!(
+                    alert = "imbalance_guard",
+                    ?asset,
+                    imbalance = ticker.imbalance,
+                    bid_quantity = ticker.bid_quantity,
+                    ask_quantity = ticker.ask_quantity,
+                    "book is one-sided"
+                ),
+                Some(ImbalanceTransition::Recovered) => tracing::info!(
+                    ?asset,
+                    imbalance = ticker.imbalance,
+                    "book is balanced again"
+                ),
+                None => {}
+            }
+        }
+
+        // only flip between the two states the guard owns, a suspended engine stays suspended.
+        let (from, to) = if monitor.is_tripped() {
+            (TradingEngineState::Running, TradingEngineState::ReduceOnly)
+        } else {
+            (TradingEngineState::ReduceOnly, TradingEngineState::Running)
+        };
+        let te_state = &self.inner_ro.te_state;
+        if te_state
+            .compare_exchange(from, to, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            tracing::warn!(?from, ?to, "imbalance guard switched the trading engine");
+        }
+    }
+
+    /// Check the imbalance of every book every
+    /// [`ImbalanceGuard::interval`](crate::config::ImbalanceGuard::interval), never returns.
+    pub async fn run_imbalance_guard(&self) {
+        let guard = self.config().imbalance_guard;
+        let mut interval = tokio::time::interval(guard.interval());
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let task = self
+            .tasks()
+            .register("imbalance guard", guard.interval() * 2);
+        let mut monitor = ImbalanceMonitor::new(&guard);
+
+        loop {
+            let now = interval.tick().await;
+            task.heartbeat();
+            self.check_imbalance(&mut monitor, now).await;
+        }
+    }
+
+    pub async fn create_user(
+        &self,
+        name: &str,
+        email: &str,
+        password_hash: PasswordHashString,
+    ) -> Result<Uuid, CreateUserError> {
+        // never an admin, whoever signs up first with an admin's email is not known to own it.
+        match sqlx::query!(
+            r#"
+            INSERT INTO users (name, email, password_hash)
+            VALUES ($1, $2, $3)
+            RETURNING id
+            "#,
+            name,
+            email,
+            password_hash.as_bytes(),
+        )
+        .fetch_one(&self.db())
+        .await
+        {
+            Ok(record) => Ok(record.id),
+            Err(err) => Err(match err {
+                sqlx::Error::Database(ref dbe) if dbe.is_unique_violation() => {
+                    CreateUserError::EmailUniqueViolation(err)
+                }
+                _ => CreateUserError::Sqlx(err),
+            }),
+        }
+    }
+
+    /// Make the roles in the users table match `admin_emails`.
+    ///
+    /// Existing users on the list whose email has been verified are promoted to admin and, unless
+    /// the list is empty, admins not on it are demoted to user, opers are left alone. Returns the
+    /// number of users whose role changed.
+    pub async fn reconcile_admin_roles(&self) -> Result<u64, sqlx::Error> {
+        let admin_emails = self
+            .config
+            .admin_emails
+            .iter()
+            .map(|email| email.to_lowercase())
+            .collect::<Vec<_>>();
+
+        if admin_emails.is_empty() {
+            return Ok(0);
+        }
+
+        let res = sqlx::query!(
+            r#"
+            UPDATE users
+            SET role = CASE WHEN lower(email) = ANY($1) THEN 'admin' ELSE 'user' END::user_role
+            WHERE deleted_at IS NULL
+              AND (
+                (role = 'user' AND lower(email) = ANY($1) AND email_verified_at IS NOT NULL)
+                OR (role = 'admin' AND NOT lower(email) = ANY($1))
+              )
+            "#,
+            &admin_emails
+        )
+        .execute(&self.db)
+        .await?;
+
+        tracing::info!(changed = res.rows_affected(), "reconciled admin roles");
+        Ok(res.rows_affected())
+    }
+
+    /// Whether `token` is the configured `admin_signup_token` and `email` is on `admin_emails`.
+    pub fn is_admin_signup(&self, email: &str, token: &str) -> bool {
+        use sha2::{Digest as _, Sha256};
+
+        let Some(admin_signup_token) = &self.config.admin_signup_token else {
+            return false;
+        };
+
+        let email = email.to_lowercase();
+        let listed = self
+            .config
+            .admin_emails
+            .iter()
+            .any(|admin_email| admin_email.to_lowercase() == email);
+
+        // digests are compared so how long the comparison takes says nothing about the token.
+        listed && Sha256::digest(token) == Sha256::digest(admin_signup_token)
+    }
+
+    /// Verify the email of `user_uuid`, which signed up with the admin signup token, and
+    /// reconcile the roles so the user is an admin straight away.
+    pub async fn verify_admin_signup(&self, user_uuid: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE users SET email_verified_at = CURRENT_TIMESTAMP WHERE id = $1",
+            user_uuid
+        )
+        .execute(&self.db)
+        .await?;
+
+        self.reconcile_admin_roles().await.map(drop)
+    }
+
+    pub async fn fetch_user_details(
+        &self,
+        user_id: uuid::Uuid,
+    ) -> Result<UserDetails, UserDetailsError> {
+        let mut dtx = self.db.begin().await?;
+        let _ = (*dtx).execute("SET TRANSACTION READ ONLY").await?;
+
+        let rec = sqlx::query!(
+            r#"SELECT id, name, role as "role: String"
+            FROM users
+            WHERE id = $1"#,
+            user_id
+        )
+        .fetch_one(&mut *dtx)
+        .await?;
+
+        let mut addrs = sqlx::query!(
+            r#"SELECT *
+            FROM user_addresses
+            WHERE user_id = $1"#,
+            user_id
+        )
+        .fetch_all(&mut *dtx)
+        .await?;
+
+        let mut accounts = HashMap::new();
+        for rec in addrs {
+            let entry = accounts
+                .entry(rec.currency.clone())
+                .or_insert(UserAccountDetails {
+                    amount: Default::default(),
+                    deposit_address: None,
+                    withrawal_addresses: vec![],
+                });
+
+            if rec.kind == "deposit" {
+                entry.deposit_address.replace(rec.address_text.clone());
+            } else {
+                entry.withrawal_addresses.push(UserWalletAddr {
+                    text: rec.address_text,
+                    currency: rec.currency,
+                    kind: rec.kind,
+                });
+            }
+        }
+
+        let details = UserDetails {
+            name: rec.name,
+            id: rec.id,
+            role: rec.role,
+            accounts,
+            portfolio: UserPortfolio { value: 0 },
+        };
+
+        dtx.commit().await?;
+
+        Ok(details)
+    }
+
+    pub async fn fetch_user_account(
+        &self,
+        user_id: Uuid,
+        asset: Asset,
+    ) -> Result<Option<UserAccount>, sqlx::Error> {
+        let rec = match sqlx::query!(
+            r#"SELECT id FROM accounts
+            WHERE currency = $1
+                AND source_type = 'user'
+                AND source_id = $2"#,
+            asset.to_string(),
+            user_id.to_string()
+        )
+        .fetch_one(&self.db)
+        .await
+        {
+            Ok(rec) => rec,
+            Err(sqlx::Error::RowNotFound) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Some(UserAccount {}))
+    }
+}
+
+/// The user's balance of `currency`, with their account locked until `dtx` ends.
+async fn locked_balance(
+    dtx: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+    user_id: Uuid,
+    currency: &str,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query!(
+        "SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $2 FOR UPDATE",
+        user_id.to_string(),
+        currency
+    )
+    .fetch_optional(&mut **dtx)
+    .await?;
+
+    let balance = sqlx::query!(
+        "SELECT calculate_balance($1, $2);",
+        user_id.to_string(),
+        currency
+    )
+    .fetch_one(&mut **dtx)
+    .await?
+    .calculate_balance
+    .unwrap_or_default();
+
+    Ok(balance)
+}
+
+/// Credit `amount` of `currency` to the user out of the exchange account holding the reserves.
+async fn journal_fill(
+    dtx: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+    user_id: Uuid,
+    currency: &str,
+    amount: u64,
+    transaction_type: &str,
+) -> Result<(), sqlx::Error> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    // a buyer receiving the asset for the first time has no account for it yet.
+    sqlx::query!(
+        r#"
+        INSERT INTO accounts (source_type, source_id, currency)
+        VALUES ('fiat', 'exchange', $2), ('user', $1, $2)
+        ON CONFLICT (source_id, currency) DO NOTHING;
+        "#,
+        user_id.to_string(),
+        currency
+    )
+    .execute(&mut **dtx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
+            (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $2),
+            (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = $2),
+            $2,
+            $3,
+            $4
+        )
+        "#,
+        user_id.to_string(),
+        currency,
+        amount as i64,
+        transaction_type
+    )
+    .execute(&mut **dtx)
+    .await?;
+
+    Ok(())
+}
+
+/// Move `amount` of `currency` from the user to the exchange account, or from the exchange to the user when negative.
+async fn journal_fee(
+    dtx: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+    user_id: Uuid,
+    currency: &str,
+    amount: i64,
+) -> Result<(), sqlx::Error> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    // a maker selling the base asset may not hold USD yet, nor the exchange the base asset.
+    sqlx::query!(
+        r#"
+        INSERT INTO accounts (source_type, source_id, currency)
+        VALUES ('user', $1, $2), ('fiat', 'exchange', $2)
+        ON CONFLICT (source_id, currency) DO NOTHING;
+        "#,
+        user_id.to_string(),
+        currency
+    )
+    .execute(&mut **dtx)
+    .await?;
+
+    if amount > 0 {
+        sqlx::query!(
+            r#"
+            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
+                (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = $3),
+                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $3),
+                $3,
+                $2,
+                'TRADE.FEE'
+            )
+            "#,
+            user_id.to_string(),
+            amount,
+            currency
+        )
+        .execute(&mut **dtx)
+        .await?;
+    } else {
+        sqlx::query!(
+            r#"
+            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
+                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = $3),
+                (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = $3),
+                $3,
+                $2,
+                'TRADE.REBATE'
+            )
+            "#,
+            user_id.to_string(),
+            -amount,
+            currency
+        )
+        .execute(&mut **dtx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::jinja::make_jinja_env;
+    use crate::spawn_trading_engine::spawn_trading_engine;
+
+    use super::*;
+
+    async fn make_app_cx_fixture(db: sqlx::PgPool) -> AppCx {
+        make_app_cx_fixture_with_config(db, Configuration::defaults_for_test()).await
+    }
+
+    async fn make_app_cx_fixture_with_config(db: sqlx::PgPool, config: Configuration) -> AppCx {
+        let (te_tx, te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        AppCx::new(
+            te_tx,
+            BitcoinRpcClient::new_mock(),
+            db,
+            make_jinja_env(&config),
+            config,
+        )
+    }
+
+    fn faucet_config() -> Configuration {
+        let mut config = Configuration::defaults_for_test();
+        config.faucet_enabled = true;
+        config
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_faucet_credits_balance(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            app_cx
+                .credit_faucet(user_uuid, "BTC", NonZeroU64::new(250).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let balance = app_cx
+            .calculate_balance_from_accounting(user_uuid, "BTC")
+            .await
+            .unwrap();
+        assert_eq!(balance, NonZeroU64::new(500));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_activity_feed(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture_with_config(db.clone(), faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let amount = NonZeroU64::new(100).unwrap();
+        let reserve = app_cx
+            .reserve_by_asset(user_uuid, amount, "USD")
+            .await
+            .unwrap();
+        reserve.revert(&db).await.unwrap();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type)
+            VALUES (1, (SELECT id FROM accounts WHERE source_id = $1 AND currency = 'USD'), 'USD', 50, 'CHAIN.WITHDRAWAL');
+            "#,
+            user_uuid.to_string()
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let first = app_cx.list_activity(user_uuid, None, 3).await.unwrap();
+        let rest = app_cx
+            .list_activity(user_uuid, first.next_cursor, 3)
+            .await
+            .unwrap();
+        assert_eq!(rest.next_cursor, None);
+
+        let feed = first
+            .activity
+            .iter()
+            .chain(&rest.activity)
+            .map(|activity| (activity.kind, activity.amount))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            feed,
+            vec![
+                (ActivityKind::Withdrawal, -50),
+                (ActivityKind::ReserveRevert, 100),
+                (ActivityKind::Reserve, -100),
+                (ActivityKind::Deposit, 1000),
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_maker_rebate_is_credited_on_fill(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let mut config = faucet_config();
+        config.fees = crate::config::FeeSchedule {
+            maker_bps: -1,
+            taker_bps: 2,
+            ..Default::default()
+        };
+        let app_cx = make_app_cx_fixture_with_config(db, config).await;
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let maker_uuid = app_cx
+            .create_user("maker", "maker@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let taker_uuid = app_cx
+            .create_user("taker", "taker@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(maker_uuid, "BTC", NonZeroU64::new(500).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(taker_uuid, "USD", NonZeroU64::new(51_000).unwrap())
+            .await
+            .unwrap();
+
+        let order = |side| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(500).unwrap(),
+            price: std::num::NonZeroU32::new(100).unwrap(),
+            time_in_force: TimeInForce::GoodTilCanceled,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        // a notional of 50_000, 2bps to the taker and a 1bps rebate to the maker.
+        for (user_uuid, side) in [(maker_uuid, OrderSide::Sell), (taker_uuid, OrderSide::Buy)] {
+            app_cx
+                .place_order(Asset::Bitcoin, user_uuid, order(side))
+                .await
+                .unwrap()
+                .wait()
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        app_cx.settle_pending().await.unwrap();
+
+        let balance = |user_uuid| app_cx.calculate_balance_from_accounting(user_uuid, "USD");
+        assert_eq!(balance(maker_uuid).await.unwrap(), NonZeroU64::new(50_005));
+        assert_eq!(balance(taker_uuid).await.unwrap(), NonZeroU64::new(990));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fees_charged_in_the_base_asset(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let mut config = faucet_config();
+        config.fees = crate::config::FeeSchedule {
+            maker_bps: 10,
+            taker_bps: 20,
+            currency: [(Asset::Bitcoin, crate::config::FeeCurrency::Base)].into(),
+        };
+        let app_cx = make_app_cx_fixture_with_config(db.clone(), config).await;
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let seller_uuid = app_cx
+            .create_user("seller", "seller@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let buyer_uuid = app_cx
+            .create_user("buyer", "buyer@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(seller_uuid, "BTC", NonZeroU64::new(10_000).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(buyer_uuid, "USD", NonZeroU64::new(10_000).unwrap())
+            .await
+            .unwrap();
+
+        let order = |side| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(10_000).unwrap(),
+            price: std::num::NonZeroU32::new(1).unwrap(),
+            time_in_force: TimeInForce::GoodTilCanceled,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        // the buying taker pays 20bps of the quantity in BTC. the selling maker sold all of its
+        // BTC, it can not cover 10bps of it and pays 10bps of the notional out of its USD
+        // proceeds instead.
+        for (user_uuid, side) in [(seller_uuid, OrderSide::Sell), (buyer_uuid, OrderSide::Buy)] {
+            app_cx
+                .place_order(Asset::Bitcoin, user_uuid, order(side))
+                .await
+                .unwrap()
+                .wait()
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        app_cx.settle_pending().await.unwrap();
+
+        let balance =
+            |user_uuid, currency| app_cx.calculate_balance_from_accounting(user_uuid, currency);
+        assert_eq!(
+            balance(buyer_uuid, "BTC").await.unwrap(),
+            NonZeroU64::new(9_980)
+        );
+        assert_eq!(balance(buyer_uuid, "USD").await.unwrap(), None);
+        assert_eq!(balance(seller_uuid, "BTC").await.unwrap(), None);
+        assert_eq!(
+            balance(seller_uuid, "USD").await.unwrap(),
+            NonZeroU64::new(9_990)
+        );
+
+        let fees = sqlx::query!(
+            "SELECT currency, amount FROM account_tx_journal WHERE transaction_type = 'TRADE.FEE' ORDER BY id"
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|rec| (rec.currency, rec.amount))
+        .collect::<Vec<_>>();
+        assert_eq!(fees, vec![("BTC".to_owned(), 20), ("USD".to_owned(), 10)]);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_user_statement_digest_is_stable(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture_with_config(db.clone(), faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(user_uuid, "BTC", NonZeroU64::new(3).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .reserve_by_asset(user_uuid, NonZeroU64::new(400).unwrap(), "USD")
+            .await
+            .unwrap();
+
+        // the day the journal rows were written, as the database sees it.
+        let day = sqlx::query_scalar::<_, String>(
+            "SELECT MIN(created_at)::DATE::TEXT FROM account_tx_journal",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        let date = chrono::NaiveDate::parse_from_str(&day, "%Y-%m-%d").unwrap();
+
+        let statement = app_cx.user_statement(user_uuid, date).await.unwrap();
+        let kinds = statement
+            .document
+            .entries
+            .iter()
+            .map(|entry| (entry.kind, entry.currency.as_str(), entry.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (ActivityKind::Deposit, "USD", 1000),
+                (ActivityKind::Deposit, "BTC", 3),
+                (ActivityKind::Reserve, "USD", -400),
+            ]
+        );
+        assert_eq!(
+            statement.document.totals.into_iter().collect::<Vec<_>>(),
+            vec![("BTC".to_owned(), 3), ("USD".to_owned(), 600)]
+        );
+
+        // both sides compute the same digest however often they ask.
+        for _ in 0..3 {
+            let again = app_cx.user_statement(user_uuid, date).await.unwrap();
+            assert_eq!(again.digest, statement.digest);
+        }
+
+        let other_day = app_cx
+            .user_statement(user_uuid, date.pred_opt().unwrap())
+            .await
+            .unwrap();
+        assert!(other_day.document.entries.is_empty());
+        assert_ne!(other_day.digest, statement.digest);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_faucet_refused_when_disabled(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture(db).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        let res = app_cx
+            .credit_faucet(user_uuid, "BTC", NonZeroU64::new(250).unwrap())
+            .await;
+        assert!(matches!(res, Err(FaucetError::Disabled)));
+
+        let balance = app_cx
+            .calculate_balance_from_accounting(user_uuid, "BTC")
+            .await
+            .unwrap();
+        assert_eq!(balance, None);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_duplicate_user_email(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture(db.clone()).await;
+        let password_hash = Password("letmein".into());
+
+        let user_uuid = app_cx
+            .create_user(
+                "foo",
+                "foo@example.com",
+                password_hash.argon2_hash_password().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        if let Err(err) = app_cx
+            .create_user(
+                "foo",
+                "foo@example.com",
+                password_hash.argon2_hash_password().unwrap(),
+            )
+            .await
+        {
+            assert!(matches!(err, CreateUserError::EmailUniqueViolation(err)));
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_place_order_releases_reserve_on_early_return(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        // shut the trading engine down so handing the order over fails after funds are reserved.
+        let config = faucet_config();
+        let te = spawn_trading_engine(&config, db.clone());
+        te.input.send(TradingEngineCmd::Shutdown).await.unwrap();
+        te.handle.await.unwrap();
+
+        let app_cx = AppCx::new(
+            te.input,
+            BitcoinRpcClient::new_mock(),
+            db.clone(),
+            make_jinja_env(&config),
+            config,
+        );
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let order = TradeAddOrder {
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(100).unwrap(),
+            price: std::num::NonZeroU32::new(1).unwrap(),
+            time_in_force: TimeInForce::default(),
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        let res = app_cx.place_order(Asset::Bitcoin, user_uuid, order).await;
+        assert!(matches!(
+            res,
+            Err(PlaceOrderError::TradingEngineUnresponsive)
+        ));
+
+        // the guard spawns the revert onto the runtime, give it a moment to land.
+        let mut balance = None;
+        for _ in 0..50 {
+            balance = app_cx
+                .calculate_balance_from_accounting(user_uuid, "USD")
+                .await
+                .unwrap();
+
+            if balance == NonZeroU64::new(1000) && app_cx.reserve_metrics().snapshot().reverted == 1
+            {
+                break;
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(
+            balance,
+            NonZeroU64::new(1000),
+            "reserved funds were not released"
+        );
+
+        let metrics = app_cx.reserve_metrics().snapshot();
+        assert_eq!(
+            (metrics.created, metrics.reverted, metrics.settled),
+            (1, 1, 0)
+        );
+        assert_eq!(metrics.outstanding.get("USD"), Some(&0));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_order_notional_too_large_is_rejected_before_reserving(db: sqlx::PgPool) {
+        use crate::trading::{NotionalOverflow, OrderType, TimeInForce};
+
+        let mut config = faucet_config();
+        config.max_order_notional = 1_000_000;
+        let app_cx = make_app_cx_fixture_with_config(db, config).await;
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let order_on = |side, quantity: u32, price: u32| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(quantity).unwrap(),
+            price: std::num::NonZeroU32::new(price).unwrap(),
+            time_in_force: TimeInForce::default(),
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        let order = |quantity, price| order_on(OrderSide::Buy, quantity, price);
+
+        // a wrapped notional would have reserved next to nothing.
+        let res = app_cx
+            .place_order(Asset::Bitcoin, user_uuid, order(u32::MAX, u32::MAX))
+            .await;
+        assert!(
+            matches!(
+                res,
+                Err(PlaceOrderError::NotionalTooLarge(NotionalOverflow {
+                    max: 1_000_000,
+                    ..
+                }))
+            ),
+            "{res:?}"
+        );
+
+        let res = app_cx
+            .place_order(Asset::Bitcoin, user_uuid, order(1001, 1000))
+            .await;
+        assert!(matches!(res, Err(PlaceOrderError::NotionalTooLarge(_))));
+
+        // a sell reserves only its quantity, but a buyer filling it pays the notional.
+        let res = app_cx
+            .place_order(
+                Asset::Bitcoin,
+                user_uuid,
+                order_on(OrderSide::Sell, 1001, 1000),
+            )
+            .await;
+        assert!(matches!(res, Err(PlaceOrderError::NotionalTooLarge(_))));
+
+        assert_eq!(app_cx.reserve_metrics().snapshot().created, 0);
+        assert_eq!(
+            app_cx
+                .calculate_balance_from_accounting(user_uuid, "USD")
+                .await
+                .unwrap(),
+            NonZeroU64::new(1000)
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_reserve_metrics_after_fill_and_cancel(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let buyer_uuid = app_cx
+            .create_user("buyer", "buyer@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let seller_uuid = app_cx
+            .create_user("seller", "seller@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(buyer_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(seller_uuid, "BTC", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let outstanding = |app_cx: &AppCx, currency: &str| {
+            let metrics = app_cx.reserve_metrics().snapshot();
+            metrics
+                .outstanding
+                .get(currency)
+                .copied()
+                .unwrap_or_default()
+        };
+        let before = (outstanding(&app_cx, "USD"), outstanding(&app_cx, "BTC"));
+
+        let order = |side, quantity| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(quantity).unwrap(),
+            price: std::num::NonZeroU32::new(1).unwrap(),
+            time_in_force: TimeInForce::default(),
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        let placed = app_cx
+            .place_order(Asset::Bitcoin, buyer_uuid, order(OrderSide::Buy, 100))
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+
+        app_cx
+            .place_order(Asset::Bitcoin, seller_uuid, order(OrderSide::Sell, 40))
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+
+        app_cx
+            .cancel_order(buyer_uuid, placed.order_uuid.0)
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+
+        // accepted by the engine, but nothing is spent or released before it settles.
+        let metrics = app_cx.reserve_metrics().snapshot();
+        assert_eq!(
+            (metrics.created, metrics.settled, metrics.reverted),
+            (2, 0, 0)
+        );
+        assert_eq!(
+            (outstanding(&app_cx, "USD"), outstanding(&app_cx, "BTC")),
+            (before.0 + 100, before.1 + 40)
+        );
+
+        // the fill and the release of the cancelled remainder.
+        assert_eq!(app_cx.settle_pending().await.unwrap(), 2);
+
+        // the fill spends from both reserves, the cancel releases the rest of the buy.
+        let metrics = app_cx.reserve_metrics().snapshot();
+        assert_eq!(
+            (metrics.created, metrics.settled, metrics.reverted),
+            (2, 2, 1)
+        );
+        assert_eq!(
+            (outstanding(&app_cx, "USD"), outstanding(&app_cx, "BTC")),
+            before
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_ioc_partial_fill_refunds_unfilled_reserve(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let maker_uuid = app_cx
+            .create_user("maker", "maker@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let taker_uuid = app_cx
+            .create_user("taker", "taker@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(maker_uuid, "BTC", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(taker_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let order = |side, quantity, time_in_force| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(quantity).unwrap(),
+            price: std::num::NonZeroU32::new(1).unwrap(),
+            time_in_force,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        let response = app_cx
+            .place_order(
+                Asset::Bitcoin,
+                maker_uuid,
+                order(OrderSide::Sell, 50, TimeInForce::GoodTilCanceled),
+            )
+            .await
+            .unwrap();
+        response.wait().await.unwrap().unwrap();
+
+        let response = app_cx
+            .place_order(
+                Asset::Bitcoin,
+                taker_uuid,
+                order(OrderSide::Buy, 100, TimeInForce::ImmediateOrCancel),
+            )
+            .await
+            .unwrap();
+        let placed = response.wait().await.unwrap().unwrap();
+        assert_eq!(
+            (placed.quantity_filled, placed.quantity_cancelled),
+            (50, 50)
+        );
+
+        // the fill and the release of the cancelled remainder.
+        assert_eq!(app_cx.settle_pending().await.unwrap(), 2);
+        assert_eq!(app_cx.settle_pending().await.unwrap(), 0);
+
+        let balance = app_cx
+            .calculate_balance_from_accounting(taker_uuid, "USD")
+            .await
+            .unwrap();
+        assert_eq!(balance, NonZeroU64::new(950));
+        assert_eq!(
+            app_cx
+                .calculate_balance_from_accounting(taker_uuid, "BTC")
+                .await
+                .unwrap(),
+            NonZeroU64::new(50)
+        );
+        assert_eq!(
+            app_cx.reserve_metrics().snapshot().outstanding.get("USD"),
+            Some(&0)
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fills_settle_after_the_request_is_dropped(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let maker_uuid = app_cx
+            .create_user("maker", "maker@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let taker_uuid = app_cx
+            .create_user("taker", "taker@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(maker_uuid, "BTC", NonZeroU64::new(10).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(taker_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let order = |side, order_type, price| TradeAddOrder {
+            side,
+            order_type,
+            quantity: std::num::NonZeroU32::new(10).unwrap(),
+            price: std::num::NonZeroU32::new(price).unwrap(),
+            time_in_force: TimeInForce::GoodTilCanceled,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        app_cx
+            .place_order(
+                Asset::Bitcoin,
+                maker_uuid,
+                order(OrderSide::Sell, OrderType::Limit, 50),
+            )
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the taker disconnects before the engine answers, a market order worst at 100.
+        drop(
+            app_cx
+                .place_order(
+                    Asset::Bitcoin,
+                    taker_uuid,
+                    order(OrderSide::Buy, OrderType::Market, 100),
+                )
+                .await
+                .unwrap(),
+        );
+
+        // the market buy reserved 1000 and filled at 50, half of it comes back.
+        let mut settled = 0;
+        for _ in 0..50 {
+            settled += app_cx.settle_pending().await.unwrap();
+            if settled == 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(settled, 1);
+
+        let balance = |user_uuid, currency| {
+            let app_cx = app_cx.clone();
+            async move {
+                app_cx
+                    .calculate_balance_from_accounting(user_uuid, currency)
+                    .await
+                    .unwrap()
+                    .map_or(0, NonZeroU64::get)
+            }
+        };
+        assert_eq!(balance(taker_uuid, "USD").await, 500);
+        assert_eq!(balance(taker_uuid, "BTC").await, 10);
+        assert_eq!(balance(maker_uuid, "USD").await, 500);
+        assert_eq!(balance(maker_uuid, "BTC").await, 0);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_overdrawn_maker_is_cancelled_and_its_fill_left_unsettled(db: sqlx::PgPool) {
+        use crate::trading::{OrderStatus, OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db.clone(), faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let maker_uuid = app_cx
+            .create_user("maker", "maker@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let taker_uuid = app_cx
+            .create_user("taker", "taker@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(maker_uuid, "BTC", NonZeroU64::new(10).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(taker_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let order = |side, quantity| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(quantity).unwrap(),
+            price: std::num::NonZeroU32::new(100).unwrap(),
+            time_in_force: TimeInForce::GoodTilCanceled,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        let maker = app_cx
+            .place_order(Asset::Bitcoin, maker_uuid, order(OrderSide::Sell, 10))
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the ledger and the engine disagree, the maker spent 5 BTC the engine thinks it reserved.
+        sqlx::query!(
+            r#"
+            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type) VALUES (
+                (SELECT id FROM accounts WHERE source_type = 'fiat' AND source_id = 'exchange' AND currency = 'BTC'),
+                (SELECT id FROM accounts WHERE source_type = 'user' AND source_id = $1 AND currency = 'BTC'),
+                'BTC',
+                5,
+                'TEST'
+            )
+            "#,
+            maker_uuid.to_string()
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        app_cx
+            .place_order(Asset::Bitcoin, taker_uuid, order(OrderSide::Buy, 4))
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+
+        // only the release of the maker's cancelled remainder settles.
+        assert_eq!(app_cx.settle_pending().await.unwrap(), 1);
+
+        let record = app_cx
+            .fetch_order(maker_uuid, Asset::Bitcoin, maker.order_uuid)
+            .await
+            .unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+
+        let unsettled = sqlx::query_scalar!(
+            "SELECT count(*) FROM trade_settlements WHERE error IS NOT NULL AND settled_at IS NULL"
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(unsettled, Some(1));
+
+        let balance = |user_uuid, currency| {
+            let app_cx = app_cx.clone();
+            async move {
+                app_cx
+                    .calculate_balance_from_accounting(user_uuid, currency)
+                    .await
+                    .unwrap()
+                    .map_or(0, NonZeroU64::get)
+            }
+        };
+        assert_eq!(balance(taker_uuid, "BTC").await, 0);
+        assert_eq!(balance(maker_uuid, "USD").await, 0);
+        assert_eq!(balance(maker_uuid, "BTC").await, 1);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_released_stop_orders_settle(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce, Trigger, TriggerKind};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let seller_uuid = app_cx
+            .create_user("seller", "seller@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let buyer_uuid = app_cx
+            .create_user("buyer", "buyer@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let stopper_uuid = app_cx
+            .create_user("stopper", "stopper@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(seller_uuid, "BTC", NonZeroU64::new(10).unwrap())
+            .await
+            .unwrap();
+        for user_uuid in [buyer_uuid, stopper_uuid] {
+            app_cx
+                .credit_faucet(user_uuid, "USD", NonZeroU64::new(1000).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let order = |side, order_type, quantity, price, trigger| TradeAddOrder {
+            side,
+            order_type,
+            quantity: std::num::NonZeroU32::new(quantity).unwrap(),
+            price: std::num::NonZeroU32::new(price).unwrap(),
+            time_in_force: TimeInForce::GoodTilCanceled,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger,
+            client_order_id: None,
+            last_look: false,
+        };
+        let place = |user_uuid, order| {
+            let app_cx = app_cx.clone();
+            async move {
+                app_cx
+                    .place_order(Asset::Bitcoin, user_uuid, order)
+                    .await
+                    .unwrap()
+                    .wait()
+                    .await
+                    .unwrap()
+                    .unwrap()
+            }
+        };
+
+        place(
+            seller_uuid,
+            order(OrderSide::Sell, OrderType::Limit, 1, 100, None),
+        )
+        .await;
+        place(
+            seller_uuid,
+            order(OrderSide::Sell, OrderType::Limit, 2, 105, None),
+        )
+        .await;
+
+        // two stop buys of 2 worst at 110, each reserving 220. only one of them finds liquidity.
+        let stop = Some(Trigger {
+            kind: TriggerKind::Stop,
+            price: std::num::NonZeroU32::new(100).unwrap(),
+        });
+        for _ in 0..2 {
+            place(
+                stopper_uuid,
+                order(OrderSide::Buy, OrderType::Market, 2, 110, stop),
+            )
+            .await;
+        }
+
+        // a trade at 100 releases both.
+        place(
+            buyer_uuid,
+            order(OrderSide::Buy, OrderType::Limit, 1, 100, None),
+        )
+        .await;
+        app_cx.settle_pending().await.unwrap();
+
+        let balance = |user_uuid, currency| {
+            let app_cx = app_cx.clone();
+            async move {
+                app_cx
+                    .calculate_balance_from_accounting(user_uuid, currency)
+                    .await
+                    .unwrap()
+                    .map_or(0, NonZeroU64::get)
+            }
+        };
+
+        // one filled 2 at 105 and got back 10 of its reserve, the other got back all of it.
+        assert_eq!(balance(stopper_uuid, "BTC").await, 2);
+        assert_eq!(balance(stopper_uuid, "USD").await, 1000 - 210);
+        assert_eq!(balance(buyer_uuid, "BTC").await, 1);
+        assert_eq!(balance(buyer_uuid, "USD").await, 900);
+        assert_eq!(balance(seller_uuid, "BTC").await, 7);
+        assert_eq!(balance(seller_uuid, "USD").await, 100 + 210);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_crossing_orders_settle_both_ledgers(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let mut config = faucet_config();
+        config.fees = crate::config::FeeSchedule {
+            maker_bps: 10,
+            taker_bps: 20,
+            ..Default::default()
+        };
+        let app_cx = make_app_cx_fixture_with_config(db.clone(), config).await;
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let seller_uuid = app_cx
+            .create_user("seller", "seller@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let buyer_uuid = app_cx
+            .create_user("buyer", "buyer@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(seller_uuid, "BTC", NonZeroU64::new(10).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(buyer_uuid, "USD", NonZeroU64::new(2000).unwrap())
+            .await
+            .unwrap();
+
+        let order = |side, price| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(10).unwrap(),
+            price: std::num::NonZeroU32::new(price).unwrap(),
+            time_in_force: TimeInForce::GoodTilCanceled,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        // what the web handler and the settlement task do with an order, from reserving funds
+        // to settling the fills and their fees.
+        let place = |user_uuid, order| {
+            let app_cx = app_cx.clone();
+            async move {
+                let response = app_cx
+                    .place_order(Asset::Bitcoin, user_uuid, order)
+                    .await
+                    .unwrap();
+                let placed = response.wait().await.unwrap().unwrap();
+                app_cx.settle_pending().await.unwrap();
+                placed
+            }
+        };
+
+        // the seller rests 10 at 100, the buyer crosses it bidding up to 105.
+        place(seller_uuid, order(OrderSide::Sell, 100)).await;
+        let taker = place(buyer_uuid, order(OrderSide::Buy, 105)).await;
+        assert_eq!(taker.quantity_filled, 10);
+        assert_eq!(taker.order_index, None);
+
+        // a notional of 1000: 2 in taker fees and 1 in maker fees, the buyer reserved 1050 and
+        // gets the 50 of price improvement back.
+        let balance = |user_uuid, currency| {
+            let app_cx = app_cx.clone();
+            async move {
+                app_cx
+                    .calculate_balance_from_accounting(user_uuid, currency)
+                    .await
+                    .unwrap()
+                    .map_or(0, NonZeroU64::get)
+            }
+        };
+        assert_eq!(balance(buyer_uuid, "BTC").await, 10);
+        assert_eq!(balance(buyer_uuid, "USD").await, 2000 - 1000 - 2);
+        assert_eq!(balance(seller_uuid, "BTC").await, 0);
+        assert_eq!(balance(seller_uuid, "USD").await, 1000 - 1);
+
+        // the exchange holds its fees and nothing else, no reserve is left behind.
+        let exchange_balance = |currency: &'static str| {
+            sqlx::query_scalar::<_, i64>(
+                r#"
+                SELECT COALESCE(SUM(CASE WHEN j.credit_account_id = a.id THEN j.amount ELSE -j.amount END), 0)::int8
+                FROM accounts a
+                JOIN account_tx_journal j ON a.id IN (j.credit_account_id, j.debit_account_id)
+                WHERE a.source_type = 'fiat' AND a.source_id = 'exchange' AND a.currency = $1
+                "#,
+            )
+            .bind(currency)
+            .fetch_one(&db)
+        };
+        assert_eq!(exchange_balance("USD").await.unwrap(), 3);
+        assert_eq!(exchange_balance("BTC").await.unwrap(), 0);
+
+        let metrics = app_cx.reserve_metrics().snapshot();
+        assert_eq!(metrics.created, 2);
+        assert_eq!(metrics.outstanding.get("USD"), Some(&0));
+        assert_eq!(metrics.outstanding.get("BTC"), Some(&0));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fiat_deposit_funds_a_settled_buy(db: sqlx::PgPool) {
+        use crate::fiat::FiatProviderKind;
+        use crate::trading::{OrderType, TimeInForce};
+
+        let mut config = faucet_config();
+        config.fiat_provider = FiatProviderKind::Mock;
+        let app_cx = make_app_cx_fixture_with_config(db, config).await;
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let seller_uuid = app_cx
+            .create_user("seller", "seller@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let buyer_uuid = app_cx
+            .create_user("buyer", "buyer@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(seller_uuid, "BTC", NonZeroU64::new(10).unwrap())
+            .await
+            .unwrap();
+
+        // the buyer wires 1000 cents, nothing is credited until the bank has it.
+        let res = app_cx.credit_fiat_deposit("WIRE-1").await;
+        assert!(matches!(
+            res,
+            Err(FiatDepositError::Provider(
+                FiatProviderError::UnknownReference
+            ))
+        ));
+
+        app_cx
+            .receive_mock_fiat_transfer(
+                "WIRE-1",
+                FiatDeposit {
+                    user_uuid: buyer_uuid,
+                    currency: "USD".into(),
+                    amount: NonZeroU64::new(1000).unwrap(),
+                },
+            )
+            .unwrap();
+        app_cx.credit_fiat_deposit("WIRE-1").await.unwrap();
+
+        let res = app_cx.credit_fiat_deposit("WIRE-1").await;
+        assert!(matches!(res, Err(FiatDepositError::AlreadyCredited)));
+
+        let order = |side| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(10).unwrap(),
+            price: std::num::NonZeroU32::new(100).unwrap(),
+            time_in_force: TimeInForce::GoodTilCanceled,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        for (user_uuid, side) in [(seller_uuid, OrderSide::Sell), (buyer_uuid, OrderSide::Buy)] {
+            let response = app_cx
+                .place_order(Asset::Bitcoin, user_uuid, order(side))
+                .await
+                .unwrap();
+            response.wait().await.unwrap().unwrap();
+        }
+        app_cx.settle_pending().await.unwrap();
+
+        let balance = |user_uuid, currency| {
+            let app_cx = app_cx.clone();
+            async move {
+                app_cx
+                    .calculate_balance_from_accounting(user_uuid, currency)
+                    .await
+                    .unwrap()
+                    .map_or(0, NonZeroU64::get)
+            }
+        };
+        assert_eq!(balance(buyer_uuid, "BTC").await, 10);
+        assert_eq!(balance(buyer_uuid, "USD").await, 0);
+        assert_eq!(balance(seller_uuid, "USD").await, 1000);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_closed_orders_move_to_the_archive(db: sqlx::PgPool) {
+        use crate::trading::{OrderStatus, OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("archie", "archie@example.com", password_hash)
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(2000).unwrap())
+            .await
+            .unwrap();
+
+        let place = |price| {
+            let app_cx = app_cx.clone();
+            async move {
+                let order = TradeAddOrder {
+                    side: OrderSide::Buy,
+                    order_type: OrderType::Limit,
+                    quantity: std::num::NonZeroU32::new(1).unwrap(),
+                    price: std::num::NonZeroU32::new(price).unwrap(),
+                    time_in_force: TimeInForce::GoodTilCanceled,
+                    stp: None,
+                    reduce_only: false,
+                    all_or_none: false,
+                    expires_at: None,
+                    expires_in_ms: None,
+                    nonce: None,
+                    trigger: None,
+                    client_order_id: None,
+                    last_look: false,
+                };
+                let response = app_cx
+                    .place_order(Asset::Bitcoin, user_uuid, order)
+                    .await
+                    .unwrap();
+                let placed = response.wait().await.unwrap().unwrap();
+                placed.order_uuid
+            }
+        };
+
+        let closed = place(100).await;
+        let open = place(101).await;
+
+        app_cx
+            .cancel_order(user_uuid, closed.0)
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+
+        // nothing has aged past the retention yet.
+        assert_eq!(app_cx.archive_closed_orders(0).await.unwrap(), 0);
+
+        let aged = chrono::Utc::now().timestamp_millis() + 1;
+        assert_eq!(app_cx.archive_closed_orders(aged).await.unwrap(), 1);
+        assert_eq!(app_cx.archive_closed_orders(aged).await.unwrap(), 0);
+
+        // history still finds the closed order, now from the archive.
+        let record = app_cx
+            .fetch_order(user_uuid, Asset::Bitcoin, closed)
+            .await
+            .unwrap();
+        assert_eq!(record.status, OrderStatus::Cancelled);
+        assert!(record.closed_at.is_some());
+
+        let record = app_cx
+            .fetch_order(user_uuid, Asset::Bitcoin, open)
+            .await
+            .unwrap();
+        assert_eq!(record.status, OrderStatus::Open);
+
+        // and only the open order is on the book.
+        let page = app_cx
+            .resting_orders(Asset::Bitcoin, 0, 10)
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+        let resting = page
+            .orders
+            .iter()
+            .filter_map(|order| order.order_uuid)
+            .collect::<Vec<_>>();
+        assert_eq!(resting, vec![open]);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_orders_without_stp_use_the_user_default(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("maker", "maker@example.com", password_hash)
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let place = |stp| {
+            let app_cx = app_cx.clone();
+            async move {
+                let order = TradeAddOrder {
+                    side: OrderSide::Buy,
+                    order_type: OrderType::Limit,
+                    quantity: std::num::NonZeroU32::new(1).unwrap(),
+                    price: std::num::NonZeroU32::new(100).unwrap(),
+                    time_in_force: TimeInForce::GoodTilCanceled,
+                    stp,
+                    reduce_only: false,
+                    all_or_none: false,
+                    expires_at: None,
+                    expires_in_ms: None,
+                    nonce: None,
+                    trigger: None,
+                    client_order_id: None,
+                    last_look: false,
+                };
+                let response = app_cx
+                    .place_order(Asset::Bitcoin, user_uuid, order)
+                    .await
+                    .unwrap();
+                let placed = response.wait().await.unwrap().unwrap();
+                placed.stp
+            }
+        };
+
+        // without a default the exchange default applies.
+        assert_eq!(place(None).await, SelfTradeProtection::default());
+
+        app_cx
+            .set_default_stp(user_uuid, Some(SelfTradeProtection::CancelBoth))
+            .await
+            .unwrap();
+        assert_eq!(
+            app_cx.default_stp(user_uuid).await.unwrap(),
+            Some(SelfTradeProtection::CancelBoth)
+        );
+
+        assert_eq!(place(None).await, SelfTradeProtection::CancelBoth);
+        assert_eq!(
+            place(Some(SelfTradeProtection::CancelOldest)).await,
+            SelfTradeProtection::CancelOldest
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_reserve_retries_transient_error_once(db: sqlx::PgPool) {
+        use std::sync::atomic::AtomicU32;
+
+        let app_cx = make_app_cx_fixture_with_config(db.clone(), faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let amount = NonZeroU64::new(100).unwrap();
+        let attempts = AtomicU32::new(0);
+
+        // the first attempt fails as if the connection dropped, the second goes through.
+        let reserve = retry_transient(
+            || async {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    let reset = std::io::ErrorKind::ConnectionReset.into();
+                    return Err(ReserveError::Database(sqlx::Error::Io(reset)));
+                }
+
+                app_cx.try_reserve_by_asset(user_uuid, amount, "USD").await
+            },
+            ReserveError::retryable,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(reserve.new_balance, NonZeroU64::new(900));
+
+        let reserves = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM account_tx_journal WHERE transaction_type = 'reserve asset'",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(reserves, 1, "the reserve was inserted more than once");
+    }
+
+    #[test]
+    fn test_reserve_database_errors_are_internal_place_order_errors() {
+        let reset = || sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into());
+
+        assert!(matches!(
+            PlaceOrderError::from(ReserveError::InsufficientFunds),
+            PlaceOrderError::InsufficientFunds
+        ));
+        assert!(matches!(
+            PlaceOrderError::from(ReserveError::Database(reset())),
+            PlaceOrderError::Internal(ReserveError::Database(_))
+        ));
+        assert!(matches!(
+            PlaceOrderError::from(ReserveError::Commit(reset())),
+            PlaceOrderError::Internal(ReserveError::Commit(_))
+        ));
+    }
+
+    /// hand an order straight to the trading engine, skipping the reserve.
+    async fn place_resting_order(app_cx: &AppCx, user_uuid: Uuid) -> OrderUuid {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let order = PlaceOrder::new(
+            Asset::Bitcoin,
+            user_uuid,
+            std::num::NonZeroU32::new(100).unwrap(),
+            std::num::NonZeroU32::new(5).unwrap(),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilCanceled,
+            OrderSide::Buy,
+            false,
+            false,
+            None,
+        );
+
+        let (tx, rx) = response_channel(None);
+        app_cx
+            .te_tx
+            .send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((order, tx))))
+            .await
+            .unwrap();
+
+        rx.recv().await.unwrap().unwrap().order_uuid
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_peek_top_follows_price_time_priority(db: sqlx::PgPool) {
+        use std::num::NonZeroU32;
+
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture(db).await;
+
+        let place = |side, price, quantity| {
+            let app_cx = app_cx.clone();
+            async move {
+                let order = PlaceOrder::new(
+                    Asset::Bitcoin,
+                    Uuid::new_v4(),
+                    NonZeroU32::new(price).unwrap(),
+                    NonZeroU32::new(quantity).unwrap(),
+                    OrderType::Limit,
+                    SelfTradeProtection::default(),
+                    TimeInForce::GoodTilCanceled,
+                    side,
+                    false,
+                    false,
+                    None,
+                );
+
+                let (tx, rx) = response_channel(None);
+                app_cx
+                    .te_tx
+                    .send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((order, tx))))
+                    .await
+                    .unwrap();
+                rx.recv().await.unwrap().unwrap().order_uuid
+            }
+        };
+        let top = |side| {
+            let app_cx = app_cx.clone();
+            async move {
+                app_cx
+                    .peek_top(Asset::Bitcoin, side)
+                    .await
+                    .unwrap()
+                    .map(|(price, quantity, uuid)| (price.get(), quantity.get(), uuid))
+            }
+        };
+
+        assert_eq!(top(OrderSide::Buy).await, None);
+
+        let first_at_100 = place(OrderSide::Buy, 100, 5).await;
+        place(OrderSide::Buy, 100, 3).await;
+        let best_bid = place(OrderSide::Buy, 101, 2).await;
+        place(OrderSide::Sell, 105, 1).await;
+        let best_ask = place(OrderSide::Sell, 104, 7).await;
+
+        assert_eq!(top(OrderSide::Buy).await, Some((101, 2, best_bid)));
+        assert_eq!(top(OrderSide::Sell).await, Some((104, 7, best_ask)));
+
+        // the best bid is filled, the oldest order at the next price is up.
+        place(OrderSide::Sell, 101, 2).await;
+        assert_eq!(top(OrderSide::Buy).await, Some((100, 5, first_at_100)));
+
+        // partly filled, it keeps its place with what is left.
+        place(OrderSide::Sell, 100, 2).await;
+        assert_eq!(top(OrderSide::Buy).await, Some((100, 3, first_at_100)));
+        assert_eq!(top(OrderSide::Sell).await, Some((104, 7, best_ask)));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_imbalance_guard_switches_to_reduce_only_and_back(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let mut config = faucet_config();
+        config.imbalance_guard = crate::config::ImbalanceGuard {
+            enabled: true,
+            trip_percent: 90,
+            recover_percent: 60,
+            sustain_ms: 1_000,
+            ..Default::default()
+        };
+        let app_cx = make_app_cx_fixture_with_config(db, config).await;
+        let mut monitor = ImbalanceMonitor::new(&app_cx.config().imbalance_guard);
+        let t0 = tokio::time::Instant::now();
+        let at = |ms: u64| t0 + std::time::Duration::from_millis(ms);
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(10_000).unwrap())
+            .await
+            .unwrap();
+
+        // bids and no asks, as one-sided as a book gets.
+        place_resting_order(&app_cx, user_uuid).await;
+
+        app_cx.check_imbalance(&mut monitor, at(0)).await;
+        assert_eq!(app_cx.trading_engine_state(), TradingEngineState::Running);
+
+        app_cx.check_imbalance(&mut monitor, at(1_000)).await;
+        assert_eq!(
+            app_cx.trading_engine_state(),
+            TradingEngineState::ReduceOnly
+        );
+
+        let order = |reduce_only: bool| TradeAddOrder {
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(1).unwrap(),
+            price: std::num::NonZeroU32::new(100).unwrap(),
+            time_in_force: TimeInForce::default(),
+            stp: None,
+            reduce_only,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+        let res = app_cx
+            .place_order(Asset::Bitcoin, user_uuid, order(false))
+            .await;
+        assert!(matches!(res, Err(PlaceOrderError::ReduceOnly)));
+        let res = app_cx
+            .place_order(Asset::Bitcoin, user_uuid, order(true))
+            .await;
+        assert!(res.is_ok());
+
+        // asks arrive and balance the book.
+        let ask = PlaceOrder::new(
+            Asset::Bitcoin,
+            Uuid::new_v4(),
+            std::num::NonZeroU32::new(200).unwrap(),
+            std::num::NonZeroU32::new(5).unwrap(),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilCanceled,
+            OrderSide::Sell,
+            false,
+            false,
+            None,
+        );
+        let (tx, rx) = response_channel(None);
+        app_cx
+            .te_tx
+            .send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((ask, tx))))
+            .await
+            .unwrap();
+        rx.recv().await.unwrap().unwrap();
+
+        app_cx.check_imbalance(&mut monitor, at(1_500)).await;
+        assert_eq!(app_cx.trading_engine_state(), TradingEngineState::Running);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_drain_mode_refuses_new_orders_but_not_cancels(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(10_000).unwrap())
+            .await
+            .unwrap();
+
+        let resting = place_resting_order(&app_cx, user_uuid).await;
+
+        assert!(app_cx.set_draining(true));
+        assert_eq!(app_cx.trading_engine_state(), TradingEngineState::Draining);
+
+        let order = TradeAddOrder {
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(1).unwrap(),
+            price: std::num::NonZeroU32::new(100).unwrap(),
+            time_in_force: TimeInForce::default(),
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+        let res = app_cx
+            .place_order(Asset::Bitcoin, user_uuid, order.clone())
+            .await;
+        assert!(matches!(res, Err(PlaceOrderError::Draining)));
+
+        let cancelled = app_cx
+            .cancel_order(user_uuid, resting.0)
+            .await
+            .unwrap()
+            .wait()
+            .await;
+        assert!(matches!(cancelled, Some(Ok(()))));
+
+        assert!(!app_cx.set_draining(false));
+        assert_eq!(app_cx.trading_engine_state(), TradingEngineState::Running);
+        let res = app_cx.place_order(Asset::Bitcoin, user_uuid, order).await;
+        assert!(res.is_ok());
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_trade_cmd_past_its_deadline_is_skipped(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture(db.clone()).await;
+
+        let order = PlaceOrder::new(
+            Asset::Bitcoin,
+            Uuid::new_v4(),
+            std::num::NonZeroU32::new(100).unwrap(),
+            std::num::NonZeroU32::new(5).unwrap(),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilCanceled,
+            OrderSide::Buy,
+            false,
+            false,
+            None,
+        );
+
+        let (tx, rx) = response_channel(None);
+        let deadline = tokio::time::Instant::now() - std::time::Duration::from_millis(1);
+        app_cx
+            .te_tx
+            .send(TradingEngineCmd::trade_by(
+                TradeCmd::PlaceOrder((order, tx)),
+                deadline,
+            ))
+            .await
+            .unwrap();
+
+        let res = rx.recv().await.unwrap();
+        assert!(matches!(res, Err(TradingEngineError::DeadlineExceeded)));
+
+        // nothing was journalled, matched or rested.
+        let journalled = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM trading_event_source")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(journalled, 0);
+
+        let stats = app_cx.engine_stats().await.unwrap();
+        assert_eq!(stats.commands_processed, 0);
+        assert_eq!(stats.orders_tracked, 0);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_order_past_its_request_deadline_releases_its_reserve(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(user_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let order = TradeAddOrder {
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(10).unwrap(),
+            price: std::num::NonZeroU32::new(10).unwrap(),
+            time_in_force: TimeInForce::default(),
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        // the request timed out while the order was being reserved.
+        let deadline = tokio::time::Instant::now() - std::time::Duration::from_millis(1);
+        let res = app_cx
+            .place_order_before(Asset::Bitcoin, user_uuid, order, deadline)
+            .await
+            .unwrap()
+            .wait()
+            .await;
+        assert!(matches!(
+            res,
+            Some(Err(TradingEngineError::DeadlineExceeded))
+        ));
+
+        assert_eq!(app_cx.settle_pending().await.unwrap(), 1);
+        assert_eq!(
+            app_cx
+                .calculate_balance_from_accounting(user_uuid, "USD")
+                .await
+                .unwrap(),
+            NonZeroU64::new(1000)
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_expired_order_does_not_match_before_the_poll(db: sqlx::PgPool) {
+        use crate::trading::{OrderType, TimeInForce};
+
+        let app_cx = make_app_cx_fixture_with_config(db, faucet_config()).await;
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let seller_uuid = app_cx
+            .create_user("seller", "seller@example.com", password_hash.clone())
+            .await
+            .unwrap();
+        let buyer_uuid = app_cx
+            .create_user("buyer", "buyer@example.com", password_hash)
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(seller_uuid, "BTC", NonZeroU64::new(10).unwrap())
+            .await
+            .unwrap();
+        app_cx
+            .credit_faucet(buyer_uuid, "USD", NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+
+        let order = |side, time_in_force, expires_in_ms| TradeAddOrder {
+            side,
+            order_type: OrderType::Limit,
+            quantity: std::num::NonZeroU32::new(10).unwrap(),
+            price: std::num::NonZeroU32::new(10).unwrap(),
+            time_in_force,
+            stp: None,
+            reduce_only: false,
+            all_or_none: false,
+            expires_at: None,
+            expires_in_ms,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+        };
+
+        app_cx
+            .place_order(
+                Asset::Bitcoin,
+                seller_uuid,
+                order(OrderSide::Sell, TimeInForce::GoodTilDate, Some(5)),
+            )
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+
+        // well short of the poll interval, the buy is the next thing the engine sees.
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        let placed = app_cx
+            .place_order(
+                Asset::Bitcoin,
+                buyer_uuid,
+                order(OrderSide::Buy, TimeInForce::GoodTilCanceled, None),
+            )
+            .await
+            .unwrap()
+            .wait()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(placed.quantity_filled, 0);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_engine_stats_count_resting_orders(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture(db).await;
+
+        let stats = app_cx.engine_stats().await.unwrap();
+        assert_eq!(stats.commands_processed, 0);
+        assert_eq!(stats.last_command_latency_us, None);
+
+        for _ in 0..3 {
+            place_resting_order(&app_cx, Uuid::new_v4()).await;
+        }
+
+        let stats = app_cx.engine_stats().await.unwrap();
+        let books = stats
+            .books
+            .iter()
+            .map(|book| (book.asset, book.resting_bids, book.resting_asks, book.held))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            books,
+            vec![(Asset::Bitcoin, 3, 0, 0), (Asset::Ether, 0, 0, 0)]
+        );
+        assert_eq!(stats.orders_tracked, 3);
+        assert_eq!(stats.commands_processed, 3);
+        assert!(stats.last_command_latency_us.is_some());
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fetch_order_as_owner(db: sqlx::PgPool) {
+        use crate::trading::OrderStatus;
+
+        let app_cx = make_app_cx_fixture(db).await;
+        let user_uuid = Uuid::new_v4();
+        let order_uuid = place_resting_order(&app_cx, user_uuid).await;
+
+        let record = app_cx
+            .fetch_order(user_uuid, Asset::Bitcoin, order_uuid)
+            .await
+            .unwrap();
+
+        assert_eq!(record.order_uuid, order_uuid);
+        assert_eq!(record.status, OrderStatus::Open);
+        assert_eq!(record.quantity.get(), 5);
+        assert_eq!(record.quantity_remaining(), 5);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fetch_order_not_found(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture(db).await;
+        let user_uuid = Uuid::new_v4();
+
+        let res = app_cx
+            .fetch_order(user_uuid, Asset::Bitcoin, OrderUuid(Uuid::new_v4()))
+            .await;
+        assert!(matches!(res, Err(FetchOrderError::NotFound)));
+
+        // an order is also not found when asked for under the wrong asset.
+        let order_uuid = place_resting_order(&app_cx, user_uuid).await;
+        let res = app_cx
+            .fetch_order(user_uuid, Asset::Ether, order_uuid)
+            .await;
+        assert!(matches!(res, Err(FetchOrderError::NotFound)));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fetch_order_of_another_user(db: sqlx::PgPool) {
+        let app_cx = make_app_cx_fixture(db).await;
+        let order_uuid = place_resting_order(&app_cx, Uuid::new_v4()).await;
+
+        let res = app_cx
+            .fetch_order(Uuid::new_v4(), Asset::Bitcoin, order_uuid)
+            .await;
+        assert!(matches!(res, Err(FetchOrderError::Forbidden)));
+    }
+
+    /// collects everything the log subscriber writes so tests can assert on it.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_engine_logs_carry_request_id(db: sqlx::PgPool) {
+        use tracing::Instrument as _;
+
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::fmt()
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .finish(),
+        );
+
+        let app_cx = make_app_cx_fixture(db).await;
+
+        let request = axum::http::Request::builder()
+            .header("x-request-id", "test-request-id")
+            .body(())
+            .unwrap();
+        let span = crate::web::make_request_span(&request);
+        place_resting_order(&app_cx, Uuid::new_v4())
+            .instrument(span)
+            .await;
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            output
+                .lines()
+                .any(|line| line.contains("processing place order")
+                    && line.contains("request_id=test-request-id")),
+            "engine log did not carry the request id:\n{output}"
+        );
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_statement_timeout_cancels_slow_query(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.db_statement_timeout_ms = Some(50);
+        let app_cx = make_app_cx_fixture_with_config(db, config).await;
+
+        let started = std::time::Instant::now();
+        let mut dtx = app_cx.begin_with_statement_timeout().await.unwrap();
+        let err = sqlx::query("SELECT pg_sleep(10)")
+            .execute(&mut *dtx)
+            .await
+            .unwrap_err();
+
+        assert!(is_statement_timeout(&err), "{err:?}");
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_deposit_through_confirmations(db: sqlx::PgPool) {
+        use crate::bitcoin::proto::list_transactions_response::Transaction;
+        use crate::bitcoin::proto::ListTransactionsResponse;
+
+        let config = Configuration::defaults_for_test();
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        let (bitcoind_rpc, script) = BitcoinRpcClient::new_scripted();
+        let app_cx = AppCx::new(
             te_tx,
-            BitcoinRpcClient::new_mock(),
-            db,
+            bitcoind_rpc,
+            db.clone(),
             make_jinja_env(&config),
             config,
+        );
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO accounts (source_type, source_id, currency)
+            VALUES ('user', $1, 'BTC');
+            "#,
+            user_uuid.to_string()
         )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        // bitcoind reports the same transaction with more confirmations on every poll.
+        for confirmations in [0, 1, 6] {
+            script.push_list_transactions(Ok(ListTransactionsResponse {
+                transactions: vec![Transaction {
+                    confirmations,
+                    txid: "deadbeef".into(),
+                    category: "receive".into(),
+                    amount: 2.0,
+                    ..Default::default()
+                }],
+            }));
+        }
+
+        for _ in 0..3 {
+            app_cx.update_user_accounts(user_uuid).await.unwrap();
+
+            let balance = app_cx
+                .calculate_balance_from_accounting(user_uuid, "BTC")
+                .await
+                .unwrap();
+            assert_eq!(
+                balance,
+                NonZeroU64::new(2),
+                "deposit credited more than once"
+            );
+        }
     }
 
     #[sqlx::test(migrations = "../migrations")]
-    async fn test_duplicate_user_email(db: sqlx::PgPool) {
-        let app_cx = make_app_cx_fixture(db.clone()).await;
-        let password_hash = Password("letmein".into());
+    async fn test_deposit_reconciliation_is_capped(db: sqlx::PgPool) {
+        use crate::bitcoin::proto::list_transactions_response::Transaction;
+        use crate::bitcoin::proto::ListTransactionsResponse;
+
+        let mut config = Configuration::defaults_for_test();
+        config.deposit_reconciliation.max_per_cycle = 200;
+
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        let (bitcoind_rpc, script) = BitcoinRpcClient::new_scripted();
+        let app_cx = AppCx::new(
+            te_tx,
+            bitcoind_rpc,
+            db.clone(),
+            make_jinja_env(&config),
+            config,
+        );
 
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
         let user_uuid = app_cx
-            .create_user(
-                "foo",
-                "foo@example.com",
-                password_hash.argon2_hash_password().unwrap(),
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO accounts (source_type, source_id, currency)
+            VALUES ('user', $1, 'BTC');
+            "#,
+            user_uuid.to_string()
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let transactions = (0..250)
+            .map(|n| Transaction {
+                confirmations: 6,
+                txid: format!("{n:064x}"),
+                category: "receive".into(),
+                amount: 1.0,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..3 {
+            script.push_list_transactions(Ok(ListTransactionsResponse {
+                transactions: transactions.clone(),
+            }));
+        }
+
+        let first = app_cx.update_user_accounts(user_uuid).await.unwrap();
+        assert_eq!(
+            first,
+            DepositSync {
+                inserted: 200,
+                remaining: 50,
+                ..Default::default()
+            }
+        );
+
+        let balance = app_cx
+            .calculate_balance_from_accounting(user_uuid, "BTC")
+            .await
+            .unwrap();
+        assert_eq!(balance, NonZeroU64::new(200));
+
+        let second = app_cx.update_user_accounts(user_uuid).await.unwrap();
+        assert_eq!(
+            second,
+            DepositSync {
+                inserted: 50,
+                remaining: 0,
+                ..Default::default()
+            }
+        );
+
+        // nothing new once the backlog is worked off.
+        let third = app_cx.update_user_accounts(user_uuid).await.unwrap();
+        assert_eq!(third, DepositSync::default());
+
+        let balance = app_cx
+            .calculate_balance_from_accounting(user_uuid, "BTC")
+            .await
+            .unwrap();
+        assert_eq!(balance, NonZeroU64::new(250));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_wallet_deposit_credits_the_address_owner(db: sqlx::PgPool) {
+        use crate::bitcoin::proto::list_transactions_response::Transaction;
+        use crate::bitcoin::proto::ListTransactionsResponse;
+
+        let config = Configuration::defaults_for_test();
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        let (bitcoind_rpc, script) = BitcoinRpcClient::new_scripted();
+        let app_cx = AppCx::new(
+            te_tx,
+            bitcoind_rpc,
+            db.clone(),
+            make_jinja_env(&config),
+            config,
+        );
+
+        let mut users = vec![];
+        for name in ["foo", "bar"] {
+            let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+            let user_uuid = app_cx
+                .create_user(name, &format!("{name}@example.com"), password_hash)
+                .await
+                .unwrap();
+
+            sqlx::query!(
+                "INSERT INTO accounts (source_type, source_id, currency) VALUES ('user', $1, 'BTC')",
+                user_uuid.to_string()
             )
+            .execute(&db)
             .await
             .unwrap();
 
-        if let Err(err) = app_cx
-            .create_user(
-                "foo",
-                "foo@example.com",
-                password_hash.argon2_hash_password().unwrap(),
+            users.push(user_uuid);
+        }
+
+        // each user has an address of their own, and the same address was handed to both.
+        for (user_uuid, address) in [
+            (users[0], "bcrt1qfoo"),
+            (users[1], "bcrt1qbar"),
+            (users[0], "bcrt1qreused"),
+            (users[1], "bcrt1qreused"),
+        ] {
+            sqlx::query!(
+                "INSERT INTO user_addresses (user_id, address_text, kind, currency) VALUES ($1, $2, 'deposit', 'BTC')",
+                user_uuid,
+                address
             )
+            .execute(&db)
             .await
-        {
-            assert!(matches!(err, CreateUserError::EmailUniqueViolation(err)));
+            .unwrap();
+        }
+
+        assert_eq!(
+            app_cx.user_for_deposit_address("bcrt1qbar").await.unwrap(),
+            Some(users[1])
+        );
+        assert_eq!(
+            app_cx
+                .user_for_deposit_address("bcrt1qreused")
+                .await
+                .unwrap(),
+            None
+        );
+
+        let deposit = |txid: &str, address: &str| Transaction {
+            confirmations: 1,
+            txid: txid.into(),
+            address: Some(address.into()),
+            category: "receive".into(),
+            amount: 2.0,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            script.push_list_transactions(Ok(ListTransactionsResponse {
+                transactions: vec![
+                    deposit("aa", "bcrt1qbar"),
+                    deposit("bb", "bcrt1qunknown"),
+                    deposit("cc", "bcrt1qreused"),
+                ],
+            }));
+        }
+
+        assert_eq!(app_cx.reconcile_wallet_deposits().await.unwrap(), 1);
+        assert_eq!(app_cx.reconcile_wallet_deposits().await.unwrap(), 0);
+
+        let balance = |user_uuid| app_cx.calculate_balance_from_accounting(user_uuid, "BTC");
+        assert_eq!(balance(users[1]).await.unwrap(), NonZeroU64::new(2));
+        assert_eq!(balance(users[0]).await.unwrap(), None);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_concurrent_deposit_crediting_is_all_or_nothing_per_user(db: sqlx::PgPool) {
+        use std::sync::atomic::AtomicBool;
+
+        use crate::bitcoin::proto::list_transactions_response::Transaction;
+        use crate::bitcoin::proto::ListTransactionsResponse;
+
+        let config = Configuration::defaults_for_test();
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        let (bitcoind_rpc, script) = BitcoinRpcClient::new_scripted();
+        let app_cx = AppCx::new(
+            te_tx,
+            bitcoind_rpc,
+            db.clone(),
+            make_jinja_env(&config),
+            config,
+        );
+
+        let mut users = vec![];
+        let mut deposits = vec![];
+        for (name, prefix) in [("foo", "a"), ("bar", "b")] {
+            let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+            let user_uuid = app_cx
+                .create_user(name, &format!("{name}@example.com"), password_hash)
+                .await
+                .unwrap();
+            let address = format!("bcrt1q{name}");
+            sqlx::query!(
+                "INSERT INTO accounts (source_type, source_id, currency) VALUES ('user', $1, 'BTC')",
+                user_uuid.to_string()
+            )
+            .execute(&db)
+            .await
+            .unwrap();
+            sqlx::query!(
+                "INSERT INTO user_addresses (user_id, address_text, kind, currency) VALUES ($1, $2, 'deposit', 'BTC')",
+                user_uuid,
+                address
+            )
+            .execute(&db)
+            .await
+            .unwrap();
+
+            // listed newest first, the journal has them in the order they confirmed.
+            for height in (1..=5).rev() {
+                deposits.push(Transaction {
+                    confirmations: 6 - height,
+                    blockheight: Some(height),
+                    txid: format!("{prefix}{height}"),
+                    address: Some(address.clone()),
+                    category: "receive".into(),
+                    amount: height as f64,
+                    ..Default::default()
+                });
+            }
+            users.push(user_uuid);
+        }
+
+        const CALLS: usize = 4;
+        for _ in 0..CALLS {
+            script.push_list_transactions(Ok(ListTransactionsResponse {
+                transactions: deposits.clone(),
+            }));
+        }
+
+        // read the balances for as long as the deposits are being credited.
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = tokio::spawn({
+            let app_cx = app_cx.clone();
+            let users = users.clone();
+            let done = done.clone();
+            async move {
+                let mut seen = vec![];
+                while !done.load(Ordering::SeqCst) {
+                    for user_uuid in &users {
+                        let balance = app_cx
+                            .calculate_balance_from_accounting(*user_uuid, "BTC")
+                            .await
+                            .unwrap();
+                        seen.push(balance);
+                    }
+                    tokio::task::yield_now().await;
+                }
+                seen
+            }
+        });
+
+        let inserted = futures::future::join_all(
+            (0..CALLS).map(|_| async { app_cx.reconcile_wallet_deposits().await.unwrap() }),
+        )
+        .await;
+        done.store(true, Ordering::SeqCst);
+        let seen = reader.await.unwrap();
+
+        // every deposit is credited once, however the calls interleave.
+        assert_eq!(inserted.iter().sum::<usize>(), 10);
+
+        // each user is credited 1 + 2 + 3 + 4 + 5 in one go, or not yet at all.
+        for balance in seen {
+            assert!(
+                matches!(balance.map(NonZeroU64::get), None | Some(15)),
+                "saw a partially credited balance: {balance:?}"
+            );
+        }
+
+        for (user_uuid, prefix) in users.iter().zip(["a", "b"]) {
+            assert_eq!(
+                app_cx
+                    .calculate_balance_from_accounting(*user_uuid, "BTC")
+                    .await
+                    .unwrap(),
+                NonZeroU64::new(15)
+            );
+
+            let txids = sqlx::query!(
+                r#"
+                SELECT j.txid AS "txid!" FROM account_tx_journal j JOIN accounts a ON a.id = j.credit_account_id
+                WHERE a.source_id = $1 AND j.transaction_type = 'CHAIN.DEPOSIT'
+                ORDER BY j.id
+                "#,
+                user_uuid.to_string()
+            )
+            .fetch_all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|rec| rec.txid)
+            .collect::<Vec<_>>();
+            let in_order = (1..=5)
+                .map(|height| format!("{prefix}{height}"))
+                .collect::<Vec<_>>();
+            assert_eq!(txids, in_order);
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_deposit_paying_a_blocked_wallet_address_is_quarantined(db: sqlx::PgPool) {
+        use crate::bitcoin::proto::list_transactions_response::Transaction;
+        use crate::bitcoin::proto::ListTransactionsResponse;
+
+        let mut config = Configuration::defaults_for_test();
+        config.blocked_addresses = vec!["bcrt1qtainted*".into()];
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        let (bitcoind_rpc, script) = BitcoinRpcClient::new_scripted();
+        let app_cx = AppCx::new(
+            te_tx,
+            bitcoind_rpc,
+            db.clone(),
+            make_jinja_env(&config),
+            config,
+        );
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        sqlx::query!(
+            "INSERT INTO accounts (source_type, source_id, currency) VALUES ('user', $1, 'BTC')",
+            user_uuid.to_string()
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        for address in ["bcrt1qclean", "bcrt1qtainted0"] {
+            sqlx::query!(
+                "INSERT INTO user_addresses (user_id, address_text, kind, currency) VALUES ($1, $2, 'deposit', 'BTC')",
+                user_uuid,
+                address
+            )
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        let deposit = |txid: &str, address: &str| Transaction {
+            confirmations: 1,
+            txid: txid.into(),
+            address: Some(address.into()),
+            category: "receive".into(),
+            amount: 2.0,
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            script.push_list_transactions(Ok(ListTransactionsResponse {
+                transactions: vec![
+                    deposit("aa", "bcrt1qclean"),
+                    deposit("bb", "bcrt1qtainted0"),
+                ],
+            }));
+        }
+
+        assert_eq!(app_cx.reconcile_wallet_deposits().await.unwrap(), 1);
+        assert_eq!(app_cx.reconcile_wallet_deposits().await.unwrap(), 0);
+
+        let balance = || app_cx.calculate_balance_from_accounting(user_uuid, "BTC");
+        assert_eq!(balance().await.unwrap(), NonZeroU64::new(2));
+
+        let quarantined =
+            sqlx::query!("SELECT txid, user_id, amount, blocked_by FROM quarantined_deposits")
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].txid, "bb");
+        assert_eq!(quarantined[0].user_id, Some(user_uuid));
+        assert_eq!(quarantined[0].amount, 2);
+        assert_eq!(quarantined[0].blocked_by, "bcrt1qtainted*");
+
+        // taking the address off the list releases the deposit.
+        assert_eq!(app_cx.reload_blocked_addresses(&[]), 0);
+        assert_eq!(app_cx.reconcile_wallet_deposits().await.unwrap(), 1);
+        assert_eq!(balance().await.unwrap(), NonZeroU64::new(4));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_trading_engine_is_tracked_until_it_stops(db: sqlx::PgPool) {
+        let config = Configuration::defaults_for_test();
+        let (te_tx, te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        let app_cx = AppCx::new(
+            te_tx.clone(),
+            BitcoinRpcClient::new_mock(),
+            db,
+            make_jinja_env(&config),
+            config,
+        );
+
+        app_cx.track_trading_engine().await;
+        // a round trip through the engine, so it has picked up its handle.
+        app_cx.engine_stats().await.unwrap();
+
+        let engine = || {
+            app_cx
+                .tasks()
+                .snapshot()
+                .into_iter()
+                .find(|task| task.name == "trading engine")
+                .unwrap()
+        };
+        assert_eq!(engine().status, TaskStatus::Running);
+        assert!(engine().last_heartbeat.is_some());
+
+        te_tx.send(TradingEngineCmd::Shutdown).await.unwrap();
+        te_handle.await.unwrap();
+        assert_eq!(engine().status, TaskStatus::Stopped);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_list_transactions_failure_is_an_error(db: sqlx::PgPool) {
+        let config = Configuration::defaults_for_test();
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        let (bitcoind_rpc, script) = BitcoinRpcClient::new_scripted();
+        let app_cx = AppCx::new(
+            te_tx,
+            bitcoind_rpc,
+            db.clone(),
+            make_jinja_env(&config),
+            config,
+        );
+
+        let password_hash = Password("letmein".into()).argon2_hash_password().unwrap();
+        let user_uuid = app_cx
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        sqlx::query!(
+            "INSERT INTO accounts (source_type, source_id, currency) VALUES ('user', $1, 'BTC')",
+            user_uuid.to_string()
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        // bitcoind being down is not an error, the deposits are picked up once it is back.
+        script.push_list_transactions(Err(tonic::Status::unavailable("bitcoind is down")));
+        assert_eq!(app_cx.reconcile_wallet_deposits().await.unwrap(), 0);
+
+        for _ in 0..2 {
+            script.push_list_transactions(Err(tonic::Status::internal("wallet is locked")));
         }
+        assert!(matches!(
+            app_cx.reconcile_wallet_deposits().await,
+            Err(ReconcileDepositsError::ListTransactions(_))
+        ));
+        assert!(matches!(
+            app_cx.update_user_accounts(user_uuid).await,
+            Err(ReconcileDepositsError::ListTransactions(_))
+        ));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_withdrawal_to_a_blocked_address_is_refused(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.blocked_addresses = vec!["bc1qsanctioned".into()];
+        let app_cx = make_app_cx_fixture_with_config(db, config).await;
+        let user_uuid = Uuid::new_v4();
+
+        let res = app_cx.screen_withdrawal_address(user_uuid, "bc1qsanctioned");
+        assert!(matches!(
+            res,
+            Err(BlockedAddressError { blocked_by }) if blocked_by == "bc1qsanctioned"
+        ));
+        assert!(app_cx
+            .screen_withdrawal_address(user_uuid, "bc1qelsewhere")
+            .is_ok());
+
+        // a reload takes effect on the next withdrawal.
+        app_cx.reload_blocked_addresses(&["bc1qelse*".into()]);
+        assert!(app_cx
+            .screen_withdrawal_address(user_uuid, "bc1qsanctioned")
+            .is_ok());
+        assert!(app_cx
+            .screen_withdrawal_address(user_uuid, "bc1qelsewhere")
+            .is_err());
     }
 
     #[sqlx::test(migrations = "../migrations")]
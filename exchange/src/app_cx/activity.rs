@@ -0,0 +1,83 @@
+use serde::Serialize;
+
+/// What an [`Activity`] entry is, derived from the `transaction_type` of its journal row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    /// funds arriving from outside the exchange, on-chain or from the faucet.
+    Deposit,
+    /// funds leaving the exchange.
+    Withdrawal,
+    /// funds set aside for an order.
+    Reserve,
+    /// reserved funds given back because the order they were set aside for was never placed.
+    ReserveRevert,
+    /// the proceeds of a match.
+    Trade,
+    /// any other movement of funds.
+    Other,
+}
+
+impl ActivityKind {
+    /// classify a `transaction_type` of `account_tx_journal`.
+    pub fn from_transaction_type(transaction_type: &str) -> Self {
+        match transaction_type {
+            "reserve asset" => Self::Reserve,
+            "revert reserve asset" => Self::ReserveRevert,
+            t if t.ends_with(".DEPOSIT") => Self::Deposit,
+            t if t.ends_with(".WITHDRAWAL") => Self::Withdrawal,
+            t if t.starts_with("TRADE.") => Self::Trade,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A single movement of funds in or out of one of a user's accounts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Activity {
+    /// the id of the journal row, also the cursor to continue after this entry.
+    pub id: i32,
+    /// what the movement is.
+    pub kind: ActivityKind,
+    /// the `transaction_type` as recorded in the journal.
+    pub transaction_type: String,
+    /// the currency moved.
+    pub currency: String,
+    /// the amount moved, positive into the user's account and negative out of it.
+    pub amount: i64,
+    /// when the movement was recorded, in milliseconds since the unix epoch.
+    pub created_at: i64,
+}
+
+/// A page of a user's activity, newest first.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityPage {
+    /// the entries of this page.
+    pub activity: Vec<Activity>,
+    /// pass as the cursor to fetch the next page, `None` once there are no older entries.
+    pub next_cursor: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_types_are_classified() {
+        for (transaction_type, kind) in [
+            ("CHAIN.DEPOSIT", ActivityKind::Deposit),
+            ("FAUCET.DEPOSIT", ActivityKind::Deposit),
+            ("CHAIN.WITHDRAWAL", ActivityKind::Withdrawal),
+            ("reserve asset", ActivityKind::Reserve),
+            ("revert reserve asset", ActivityKind::ReserveRevert),
+            ("TRADE.FILL", ActivityKind::Trade),
+            ("test deposit", ActivityKind::Other),
+        ] {
+            assert_eq!(
+                ActivityKind::from_transaction_type(transaction_type),
+                kind,
+                "{transaction_type}"
+            );
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::{Arc, RwLock};
+
+/// Addresses the exchange must not withdraw to or take deposits on, for compliance.
+///
+/// A deposit is matched on the wallet address it paid, never on where it came from.
+///
+/// An entry is either an exact address or a prefix ending in `*`, which blocks every address
+/// starting with it. The list is seeded from `blocked_addresses` and may be swapped out while
+/// running with [`AddressBlocklist::reload`], callers holding the old list keep using it.
+#[derive(Debug, Default)]
+pub struct AddressBlocklist {
+    current: RwLock<Arc<BlockedAddresses>>,
+}
+
+impl AddressBlocklist {
+    pub fn new(entries: &[String]) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(BlockedAddresses::new(entries))),
+        }
+    }
+
+    /// the list in force right now.
+    pub fn current(&self) -> Arc<BlockedAddresses> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// replace the list with `entries`, returns the number of entries now in force.
+    pub fn reload(&self, entries: &[String]) -> usize {
+        let blocked = Arc::new(BlockedAddresses::new(entries));
+        let len = blocked.len();
+        *self.current.write().unwrap() = blocked;
+        len
+    }
+}
+
+/// One version of the [`AddressBlocklist`].
+#[derive(Debug, Default)]
+pub struct BlockedAddresses {
+    exact: ahash::AHashSet<String>,
+    prefixes: Vec<String>,
+}
+
+impl BlockedAddresses {
+    fn new(entries: &[String]) -> Self {
+        let mut blocked = Self::default();
+        for entry in entries.iter().map(|entry| entry.trim()) {
+            match entry.strip_suffix('*') {
+                // a bare `*` would block everything, which is not what anyone means by it.
+                Some("") => tracing::warn!("ignoring blocklist entry that matches every address"),
+                Some(prefix) => blocked.prefixes.push(prefix.to_owned()),
+                None if entry.is_empty() => (),
+                None => {
+                    blocked.exact.insert(entry.to_owned());
+                }
+            }
+        }
+        blocked
+    }
+
+    pub fn len(&self) -> usize {
+        self.exact.len() + self.prefixes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the entry blocking `address`, if any.
+    pub fn matching(&self, address: &str) -> Option<String> {
+        if self.exact.contains(address) {
+            return Some(address.to_owned());
+        }
+
+        self.prefixes
+            .iter()
+            .find(|prefix| address.starts_with(prefix.as_str()))
+            .map(|prefix| format!("{prefix}*"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_and_prefix_entries() {
+        let blocklist = AddressBlocklist::new(&[
+            "bc1qexact".into(),
+            "bc1qbad*".into(),
+            "*".into(),
+            "  ".into(),
+        ]);
+        let blocked = blocklist.current();
+        assert_eq!(blocked.len(), 2);
+        assert_eq!(blocked.matching("bc1qexact").as_deref(), Some("bc1qexact"));
+        assert_eq!(blocked.matching("bc1qexactly"), None);
+        assert_eq!(blocked.matching("bc1qbadf00d").as_deref(), Some("bc1qbad*"));
+        assert_eq!(blocked.matching("bc1qgood"), None);
+
+        assert_eq!(blocklist.reload(&["bc1qgood".into()]), 1);
+        assert_eq!(blocklist.current().matching("bc1qexact"), None);
+        assert!(blocklist.current().matching("bc1qgood").is_some());
+        // the list taken before the reload is unchanged.
+        assert!(blocked.matching("bc1qexact").is_some());
+    }
+}
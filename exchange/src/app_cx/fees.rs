@@ -0,0 +1,123 @@
+use super::QUOTE_CURRENCY;
+use crate::config::{FeeCurrency, FeeSchedule};
+use crate::trading::{wide_notional, Execution};
+use crate::Asset;
+
+/// basis points in a whole.
+const BPS: u128 = 10_000;
+
+/// the code of the account currency `currency` is for fills of `asset`.
+pub(super) fn fee_currency_code(currency: FeeCurrency, asset: Asset) -> String {
+    match currency {
+        FeeCurrency::Quote => QUOTE_CURRENCY.to_owned(),
+        FeeCurrency::Base => asset.to_string(),
+    }
+}
+
+/// The fees owed on a single [`Execution`], in the currency they were computed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FillFees {
+    /// paid by the taker to the exchange.
+    pub taker_fee: u64,
+    /// paid by the maker to the exchange, negative when the exchange pays the maker a rebate.
+    pub maker_fee: i64,
+}
+
+impl FillFees {
+    /// compute the fees of `execution` in the quote currency, see [`FillFees::in_currency`].
+    pub fn new(schedule: &FeeSchedule, execution: &Execution) -> Self {
+        Self::in_currency(schedule, execution, FeeCurrency::Quote)
+    }
+
+    /// compute the fees of `execution` in `currency`, rounding down in the payer's favour.
+    ///
+    /// The fees are a share of the notional in the quote currency and of the quantity in the
+    /// base currency. A rebate is capped at the taker fee of the same fill, so even a schedule
+    /// that skipped config validation can not make a fill cost the exchange money.
+    pub fn in_currency(
+        schedule: &FeeSchedule,
+        execution: &Execution,
+        currency: FeeCurrency,
+    ) -> Self {
+        let amount = match currency {
+            FeeCurrency::Quote => {
+                wide_notional(execution.price.get(), u64::from(execution.quantity))
+            }
+            FeeCurrency::Base => u128::from(execution.quantity),
+        };
+        let of_amount = |bps: u32| {
+            let fee = amount * u128::from(bps) / BPS;
+            i64::try_from(fee).unwrap_or(i64::MAX)
+        };
+
+        let taker_fee = of_amount(schedule.taker_bps);
+        let maker_fee = if schedule.maker_bps < 0 {
+            -of_amount(schedule.maker_bps.unsigned_abs()).min(taker_fee)
+        } else {
+            of_amount(schedule.maker_bps.unsigned_abs())
+        };
+
+        Self {
+            taker_fee: taker_fee as u64,
+            maker_fee,
+        }
+    }
+
+    /// what the exchange keeps from the fill.
+    pub fn net(&self) -> i64 {
+        (self.taker_fee as i64).saturating_add(self.maker_fee)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    fn execution(price: u32, quantity: u32) -> Execution {
+        Execution {
+            maker_order_uuid: None,
+            maker_user_uuid: None,
+            price: NonZeroU32::new(price).unwrap(),
+            quantity,
+            price_improvement: 0,
+        }
+    }
+
+    #[test]
+    fn test_rebate_never_exceeds_taker_fee() {
+        let schedule = FeeSchedule {
+            maker_bps: -1,
+            taker_bps: 2,
+            ..Default::default()
+        };
+        let fees = FillFees::new(&schedule, &execution(100, 500));
+        assert_eq!((fees.taker_fee, fees.maker_fee, fees.net()), (10, -5, 5));
+
+        // an unvalidated schedule has its rebate capped.
+        let schedule = FeeSchedule {
+            maker_bps: -5,
+            taker_bps: 2,
+            ..Default::default()
+        };
+        let fees = FillFees::new(&schedule, &execution(100, 500));
+        assert_eq!((fees.taker_fee, fees.maker_fee, fees.net()), (10, -10, 0));
+    }
+
+    #[test]
+    fn test_base_fees_are_a_share_of_the_quantity() {
+        let schedule = FeeSchedule {
+            maker_bps: 10,
+            taker_bps: 20,
+            ..Default::default()
+        };
+        let execution = execution(100, 10_000);
+
+        let quote = FillFees::in_currency(&schedule, &execution, FeeCurrency::Quote);
+        assert_eq!((quote.taker_fee, quote.maker_fee), (2_000, 1_000));
+
+        let base = FillFees::in_currency(&schedule, &execution, FeeCurrency::Base);
+        assert_eq!((base.taker_fee, base.maker_fee), (20, 10));
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::ImbalanceGuard;
+use crate::Asset;
+
+/// A change in whether a book trips the [`ImbalanceGuard`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImbalanceTransition {
+    /// the book stayed one-sided for the whole sustain period.
+    Tripped,
+    /// the book fell back below `recover_percent`.
+    Recovered,
+}
+
+#[derive(Debug, Default)]
+struct BookImbalance {
+    /// when the book went at or above `trip_percent`, `None` while below it.
+    breached_since: Option<Instant>,
+    tripped: bool,
+}
+
+/// Tracks the imbalance of every book across the cycles of the [`ImbalanceGuard`].
+#[derive(Debug)]
+pub struct ImbalanceMonitor {
+    trip_percent: f64,
+    recover_percent: f64,
+    sustain: Duration,
+    books: ahash::AHashMap<Asset, BookImbalance>,
+}
+
+impl ImbalanceMonitor {
+    pub fn new(config: &ImbalanceGuard) -> Self {
+        Self {
+            trip_percent: f64::from(config.trip_percent),
+            recover_percent: f64::from(config.recover_percent),
+            sustain: config.sustain(),
+            books: Default::default(),
+        }
+    }
+
+    /// record the `imbalance` of `asset` seen at `now`, `None` for an empty book.
+    pub fn observe(
+        &mut self,
+        asset: Asset,
+        imbalance: Option<f64>,
+        now: Instant,
+    ) -> Option<ImbalanceTransition> {
+        let percent = imbalance.map_or(0.0, |imbalance| imbalance.abs() * 100.0);
+        let book = self.books.entry(asset).or_default();
+
+        if book.tripped {
+            if percent < self.recover_percent {
+                *book = BookImbalance::default();
+                return Some(ImbalanceTransition::Recovered);
+            }
+            return None;
+        }
+
+        if percent < self.trip_percent {
+            book.breached_since = None;
+            return None;
+        }
+
+        let since = *book.breached_since.get_or_insert(now);
+        if now.duration_since(since) >= self.sustain {
+            book.tripped = true;
+            return Some(ImbalanceTransition::Tripped);
+        }
+
+        None
+    }
+
+    /// `true` while any book trips the guard.
+    pub fn is_tripped(&self) -> bool {
+        self.books.values().any(|book| book.tripped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trips_after_sustain_and_recovers_with_hysteresis() {
+        let config = ImbalanceGuard {
+            enabled: true,
+            trip_percent: 90,
+            recover_percent: 60,
+            sustain_ms: 1_000,
+            ..Default::default()
+        };
+        let mut monitor = ImbalanceMonitor::new(&config);
+        let t0 = Instant::now();
+        let at = |ms: u64| t0 + Duration::from_millis(ms);
+
+        // a breach that does not last the sustain period is forgotten.
+        assert_eq!(monitor.observe(Asset::Bitcoin, Some(0.95), at(0)), None);
+        assert_eq!(monitor.observe(Asset::Bitcoin, Some(0.5), at(500)), None);
+        assert_eq!(monitor.observe(Asset::Bitcoin, Some(-1.0), at(1_000)), None);
+        assert!(!monitor.is_tripped());
+
+        assert_eq!(
+            monitor.observe(Asset::Bitcoin, Some(-1.0), at(2_000)),
+            Some(ImbalanceTransition::Tripped)
+        );
+        assert!(monitor.is_tripped());
+
+        // between the two percents the book stays tripped.
+        assert_eq!(monitor.observe(Asset::Bitcoin, Some(0.7), at(2_500)), None);
+        assert_eq!(monitor.observe(Asset::Ether, Some(0.0), at(2_500)), None);
+        assert!(monitor.is_tripped());
+
+        assert_eq!(
+            monitor.observe(Asset::Bitcoin, None, at(3_000)),
+            Some(ImbalanceTransition::Recovered)
+        );
+        assert!(!monitor.is_tripped());
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use super::ReserveError;
+
+/// Counters for the lifecycle of reserved funds.
+///
+/// A reserve is outstanding from the moment it is made until it is either settled, spent on the
+/// fills of the order it was made for, or reverted. A reserve that fills in parts is settled once
+/// per fill. An outstanding amount that only ever grows points at reserves that are stuck,
+/// neither used nor given back.
+#[derive(Debug, Default)]
+pub struct ReserveMetrics {
+    created: AtomicU64,
+    reverted: AtomicU64,
+    settled: AtomicU64,
+    insufficient_funds: AtomicU64,
+    database_errors: AtomicU64,
+    outstanding: Mutex<HashMap<String, u64>>,
+}
+
+/// A point-in-time copy of [`ReserveMetrics`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReserveMetricsSnapshot {
+    /// reserves made.
+    pub created: u64,
+    /// reserves given back to the user.
+    pub reverted: u64,
+    /// fills paid for out of a reserve, once settled in the ledger.
+    pub settled: u64,
+    /// reserves refused because the user's balance was too low.
+    pub insufficient_funds: u64,
+    /// reserves that failed on a database error, after retries.
+    pub database_errors: u64,
+    /// the amount reserved but neither settled nor reverted, per currency.
+    pub outstanding: HashMap<String, u64>,
+}
+
+impl ReserveMetrics {
+    pub(crate) fn record_created(&self, currency: &str, amount: u64) {
+        self.created.fetch_add(1, Ordering::Relaxed);
+        self.adjust_outstanding(currency, |outstanding| outstanding.saturating_add(amount));
+    }
+
+    pub(crate) fn record_reverted(&self, currency: &str, amount: u64) {
+        self.reverted.fetch_add(1, Ordering::Relaxed);
+        self.adjust_outstanding(currency, |outstanding| outstanding.saturating_sub(amount));
+    }
+
+    pub(crate) fn record_settled(&self, currency: &str, amount: u64) {
+        self.settled.fetch_add(1, Ordering::Relaxed);
+        self.adjust_outstanding(currency, |outstanding| outstanding.saturating_sub(amount));
+    }
+
+    pub(crate) fn record_failure(&self, err: &ReserveError) {
+        let counter = match err {
+            ReserveError::InsufficientFunds => &self.insufficient_funds,
+            ReserveError::Database(_) | ReserveError::Commit(_) => &self.database_errors,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn adjust_outstanding(&self, currency: &str, f: impl FnOnce(u64) -> u64) {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        let amount = outstanding.entry(currency.to_owned()).or_default();
+        *amount = f(*amount);
+
+        tracing::debug!(?currency, outstanding = *amount, "outstanding reserves");
+    }
+
+    /// copy the current values of every counter.
+    pub fn snapshot(&self) -> ReserveMetricsSnapshot {
+        ReserveMetricsSnapshot {
+            created: self.created.load(Ordering::Relaxed),
+            reverted: self.reverted.load(Ordering::Relaxed),
+            settled: self.settled.load(Ordering::Relaxed),
+            insufficient_funds: self.insufficient_funds.load(Ordering::Relaxed),
+            database_errors: self.database_errors.load(Ordering::Relaxed),
+            outstanding: self.outstanding.lock().unwrap().clone(),
+        }
+    }
+}
@@ -1,14 +1,37 @@
 use std::num::NonZeroU64;
+use std::sync::Arc;
 
 use futures::TryFutureExt as _;
 
-use super::{defer, DeferGuard};
+use super::{defer, retry_transient, DeferGuard, ReserveMetrics};
+
+/// the currency assets are priced in, reserved by buys and received by sells.
+pub(crate) const QUOTE_CURRENCY: &str = "USD";
 
 #[derive(Debug, Clone)]
 pub struct ReserveOk {
     pub row_id: u32,
+    pub user_uuid: uuid::Uuid,
     pub previous_balance: NonZeroU64,
     pub new_balance: Option<NonZeroU64>,
+    pub currency: String,
+    pub amount: u64,
+    pub(crate) metrics: Arc<ReserveMetrics>,
+}
+
+/// Reverts a reserve when dropped, unless the order it was made for is accepted first.
+#[must_use]
+pub struct ReserveGuard<F: FnMut()> {
+    guard: DeferGuard<F>,
+}
+
+impl<F: FnMut()> ReserveGuard<F> {
+    /// the trading engine accepted the order, keep the funds reserved for it.
+    ///
+    /// The reserve stays outstanding until the settlements of the order spend or release it.
+    pub fn accept(self) {
+        self.guard.cancel();
+    }
 }
 
 impl ReserveOk {
@@ -16,19 +39,27 @@ impl ReserveOk {
         self,
         handle: tokio::runtime::Handle,
         db: sqlx::PgPool,
-    ) -> DeferGuard<impl FnMut()> {
-        defer(move || {
+    ) -> ReserveGuard<impl FnMut()> {
+        let guard = defer(move || {
             let this = self.clone();
             let db = db.clone();
 
+            // the order never reached the engine, nothing else will release what it reserved.
             handle.spawn(async move {
-                let fut = this.revert(&db);
+                let res = retry_transient(|| this.clone().revert(&db), |err| Some(err)).await;
 
-                if let Err(err) = fut.await {
-                    tracing::warn!(?err, "failed to revert reserved funds");
+                if let Err(err) = res {
+                    tracing::error!(
+                        alert = "unreverted_reserve",
+                        row_id = this.row_id,
+                        ?err,
+                        "failed to revert reserved funds"
+                    );
                 }
             });
-        })
+        });
+
+        ReserveGuard { guard }
     }
 
     pub fn revert(
@@ -53,5 +84,28 @@ impl ReserveOk {
     )
     .fetch_one(db)
     .map_ok(|rec| rec.id)
+    .inspect_ok(move |_| self.metrics.record_reverted(&self.currency, self.amount))
+    }
+
+    /// release `amount` of the reserve, leaving the rest reserved.
+    pub fn revert_amount(
+        &self,
+        amount: u64,
+        db: &sqlx::PgPool,
+    ) -> impl std::future::Future<Output = Result<i32, sqlx::Error>> + '_ {
+        sqlx::query!(
+            r#"
+            INSERT INTO account_tx_journal (credit_account_id, debit_account_id, currency, amount, transaction_type)
+            SELECT debit_account_id, credit_account_id, currency, $2, 'revert reserve asset'
+            FROM account_tx_journal
+            WHERE id = $1
+            RETURNING id
+            "#,
+            self.row_id as i32,
+            amount as i64
+        )
+        .fetch_one(db)
+        .map_ok(|rec| rec.id)
+        .inspect_ok(move |_| self.metrics.record_reverted(&self.currency, amount))
     }
 }
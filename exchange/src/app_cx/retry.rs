@@ -0,0 +1,118 @@
+use std::future::Future;
+use std::time::Duration;
+
+/// How many times an operation is attempted before a transient error is given up on.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// The delay before the first retry, doubled for every retry after it.
+const BASE_BACKOFF: Duration = Duration::from_millis(20);
+
+/// `true` if `err` is worth retrying: the same statements could succeed if run again.
+///
+/// That covers postgres aborting a transaction to resolve a conflict with a concurrent one
+/// (serialization failures and deadlocks) and the connection to the database going away.
+/// Everything else, constraint violations, missing rows, bad SQL, will fail the same way again.
+pub fn is_transient(err: &sqlx::Error) -> bool {
+    const SERIALIZATION_FAILURE: &str = "40001";
+    const DEADLOCK_DETECTED: &str = "40P01";
+
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(err) => err
+            .code()
+            .is_some_and(|code| code == SERIALIZATION_FAILURE || code == DEADLOCK_DETECTED),
+        _ => false,
+    }
+}
+
+/// Run `op` until it succeeds, fails with an error that isn't transient, or runs out of attempts.
+///
+/// `op` must be safe to run again after a transient failure, i.e. it does all of its
+/// writes in a single transaction that is rolled back when it fails.
+pub async fn retry_transient<T, E, F, Fut>(
+    mut op: F,
+    as_sqlx: impl Fn(&E) -> Option<&sqlx::Error>,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        match op().await {
+            Err(err) if attempt < MAX_ATTEMPTS && as_sqlx(&err).is_some_and(is_transient) => {
+                let backoff = BASE_BACKOFF * 2u32.pow(attempt - 1);
+                tracing::warn!(err = ?as_sqlx(&err), attempt, ?backoff, "transient database error, retrying");
+
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn connection_reset() -> sqlx::Error {
+        sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into())
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_are_retried() {
+        let calls = AtomicU32::new(0);
+
+        let res = retry_transient(
+            || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(connection_reset()),
+                    _ => Ok(()),
+                }
+            },
+            |err| Some(err),
+        )
+        .await;
+
+        assert!(res.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fatal_errors_are_not_retried() {
+        let calls = AtomicU32::new(0);
+
+        let res: Result<(), _> = retry_transient(
+            || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(sqlx::Error::RowNotFound)
+            },
+            |err| Some(err),
+        )
+        .await;
+
+        assert!(matches!(res, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_are_bounded() {
+        let calls = AtomicU32::new(0);
+
+        let res: Result<(), _> = retry_transient(
+            || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(connection_reset())
+            },
+            |err| Some(err),
+        )
+        .await;
+
+        assert!(matches!(res, Err(sqlx::Error::Io(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), MAX_ATTEMPTS);
+    }
+}
@@ -0,0 +1,135 @@
+//! Daily statements of a user's account movements, for back-office reconciliation.
+//!
+//! A statement is a canonical JSON document hashed with SHA-256 so the exchange and the user can
+//! each compute the digest and compare. The canonical form is:
+//!
+//! - compact JSON, no whitespace, fields in the order they are declared here.
+//! - entries in journal order, by ascending id.
+//! - amounts as integers in the smallest unit of their currency, times as milliseconds since
+//!   the unix epoch and the day as `YYYY-MM-DD`, all UTC.
+//! - totals keyed by currency in ascending order.
+//!
+//! Changing any of this changes every digest, bump [`STATEMENT_VERSION`] when it does.
+//!
+//! The digest only catches accidental changes. With `statement_integrity_key` set a statement
+//! also carries an HMAC integrity tag, which the exchange can check to show it issued the
+//! statement unaltered. The key is secret, so users can not verify the tag, it is not a signature.
+
+use std::collections::BTreeMap;
+
+use hmac::{Hmac, Mac as _};
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+use uuid::Uuid;
+
+use super::Activity;
+
+/// the version of the canonical form, part of every document.
+pub const STATEMENT_VERSION: u32 = 1;
+
+/// The canonical document a statement digest is computed over.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatementDocument {
+    /// the version of the canonical form.
+    pub version: u32,
+    /// the user the statement is for.
+    pub user_uuid: Uuid,
+    /// the UTC day covered, `YYYY-MM-DD`.
+    pub date: String,
+    /// every movement of funds in or out of the user's accounts that day, oldest first.
+    pub entries: Vec<Activity>,
+    /// the net movement of each currency over the day.
+    pub totals: BTreeMap<String, i64>,
+}
+
+impl StatementDocument {
+    /// the statement of `entries`, which must already be in journal order.
+    pub fn new(user_uuid: Uuid, date: chrono::NaiveDate, entries: Vec<Activity>) -> Self {
+        let mut totals = BTreeMap::new();
+        for entry in &entries {
+            *totals.entry(entry.currency.clone()).or_insert(0i64) += entry.amount;
+        }
+
+        Self {
+            version: STATEMENT_VERSION,
+            user_uuid,
+            date: date.format("%Y-%m-%d").to_string(),
+            entries,
+            totals,
+        }
+    }
+
+    /// the bytes the digest and integrity tag are computed over.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("a statement always serializes")
+    }
+}
+
+/// A statement with its digest, and integrity tag if the exchange has an integrity key.
+#[derive(Debug, Clone, Serialize)]
+pub struct Statement {
+    /// the canonical document.
+    pub document: StatementDocument,
+    /// the hex SHA-256 of the canonical bytes of `document`.
+    pub digest: String,
+    /// the hex HMAC-SHA256 of the canonical bytes under `statement_integrity_key`, if set.
+    pub integrity_tag: Option<String>,
+}
+
+impl Statement {
+    /// digest `document` and tag it under `integrity_key`, if any.
+    pub fn new(document: StatementDocument, integrity_key: Option<&str>) -> Self {
+        let bytes = document.canonical_bytes();
+        let digest = hex::encode(Sha256::digest(&bytes));
+        let integrity_tag = integrity_key.map(|key| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+                .expect("hmac accepts keys of any length");
+            mac.update(&bytes);
+            hex::encode(mac.finalize().into_bytes())
+        });
+
+        Self {
+            document,
+            digest,
+            integrity_tag,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_cx::ActivityKind;
+
+    #[test]
+    fn test_canonical_form_is_fixed() {
+        let entry = Activity {
+            id: 7,
+            kind: ActivityKind::Deposit,
+            transaction_type: "FAUCET.DEPOSIT".into(),
+            currency: "USD".into(),
+            amount: 1000,
+            created_at: 1_700_000_000_000,
+        };
+        let date = chrono::NaiveDate::from_ymd_opt(2023, 11, 14).unwrap();
+        let document = StatementDocument::new(Uuid::nil(), date, vec![entry]);
+
+        assert_eq!(
+            String::from_utf8(document.canonical_bytes()).unwrap(),
+            concat!(
+                r#"{"version":1,"user_uuid":"00000000-0000-0000-0000-000000000000","date":"2023-11-14","#,
+                r#""entries":[{"id":7,"kind":"deposit","transaction_type":"FAUCET.DEPOSIT","#,
+                r#""currency":"USD","amount":1000,"created_at":1700000000000}],"totals":{"USD":1000}}"#,
+            )
+        );
+
+        let tagged = Statement::new(document.clone(), Some("key"));
+        let untagged = Statement::new(document, None);
+        assert_eq!(tagged.digest, untagged.digest);
+        assert_eq!(tagged.digest.len(), 64);
+        assert!(tagged
+            .integrity_tag
+            .is_some_and(|integrity_tag| integrity_tag.len() == 64));
+        assert_eq!(untagged.integrity_tag, None);
+    }
+}
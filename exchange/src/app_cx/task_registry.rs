@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+
+/// Whether a registered background task is still running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    /// the task holds its [`TaskHandle`].
+    Running,
+    /// the task dropped its [`TaskHandle`], it returned, panicked or was cancelled.
+    Stopped,
+}
+
+#[derive(Debug)]
+struct TaskEntry {
+    status: TaskStatus,
+    expected_interval: Duration,
+    registered_at: SystemTime,
+    last_heartbeat: Option<SystemTime>,
+}
+
+/// The liveness of the long-running background tasks, e.g. the order archival.
+///
+/// A task registers itself under a name with the interval it is expected to heartbeat within,
+/// and heartbeats through the returned [`TaskHandle`] each time it does its work. A running task
+/// that has not heartbeat within its interval is flagged as stalled.
+#[derive(Debug, Default)]
+pub struct TaskRegistry {
+    tasks: Mutex<BTreeMap<&'static str, TaskEntry>>,
+}
+
+/// A point-in-time view of one registered task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskSnapshot {
+    /// the name the task registered under.
+    pub name: &'static str,
+    /// whether the task is still running.
+    pub status: TaskStatus,
+    /// the last heartbeat in milliseconds since the unix epoch, `None` if it never heartbeat.
+    pub last_heartbeat: Option<i64>,
+    /// how often the task is expected to heartbeat, in seconds.
+    pub expected_interval_secs: u64,
+    /// `true` if the task is running but has not heartbeat within its expected interval.
+    pub stalled: bool,
+}
+
+/// Held by a registered task for as long as it runs, dropping it marks the task stopped.
+#[derive(Debug)]
+pub struct TaskHandle {
+    registry: Arc<TaskRegistry>,
+    name: &'static str,
+}
+
+impl TaskRegistry {
+    /// register the task `name`, expected to heartbeat at least once every `expected_interval`.
+    ///
+    /// registering a name again replaces the previous entry, e.g. when a task is restarted.
+    pub fn register(
+        self: &Arc<Self>,
+        name: &'static str,
+        expected_interval: Duration,
+    ) -> TaskHandle {
+        self.tasks.lock().unwrap().insert(
+            name,
+            TaskEntry {
+                status: TaskStatus::Running,
+                expected_interval,
+                registered_at: SystemTime::now(),
+                last_heartbeat: None,
+            },
+        );
+
+        TaskHandle {
+            registry: Arc::clone(self),
+            name,
+        }
+    }
+
+    /// the registered tasks, by name.
+    pub fn snapshot(&self) -> Vec<TaskSnapshot> {
+        self.snapshot_at(SystemTime::now())
+    }
+
+    fn snapshot_at(&self, now: SystemTime) -> Vec<TaskSnapshot> {
+        let unix_millis = |at: SystemTime| {
+            at.duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as i64)
+        };
+
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&name, entry)| {
+                let since = entry.last_heartbeat.unwrap_or(entry.registered_at);
+                let silent_for = now.duration_since(since).unwrap_or_default();
+
+                TaskSnapshot {
+                    name,
+                    status: entry.status,
+                    last_heartbeat: entry.last_heartbeat.map(unix_millis),
+                    expected_interval_secs: entry.expected_interval.as_secs(),
+                    stalled: entry.status == TaskStatus::Running
+                        && silent_for > entry.expected_interval,
+                }
+            })
+            .collect()
+    }
+
+    fn update(&self, name: &'static str, f: impl FnOnce(&mut TaskEntry)) {
+        if let Some(entry) = self.tasks.lock().unwrap().get_mut(name) {
+            f(entry);
+        }
+    }
+}
+
+impl TaskHandle {
+    /// record that the task is alive and just did its work.
+    pub fn heartbeat(&self) {
+        self.registry.update(self.name, |entry| {
+            entry.last_heartbeat = Some(SystemTime::now());
+        });
+    }
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        tracing::info!(task = self.name, "background task stopped");
+        self.registry.update(self.name, |entry| {
+            entry.status = TaskStatus::Stopped;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeats_are_recorded_and_stalled_tasks_flagged() {
+        let registry = Arc::new(TaskRegistry::default());
+        let interval = Duration::from_secs(60);
+
+        let task = registry.register("order archival", interval);
+        let [snapshot] = registry.snapshot().try_into().unwrap();
+        assert_eq!(snapshot.status, TaskStatus::Running);
+        assert_eq!(snapshot.last_heartbeat, None);
+        assert!(!snapshot.stalled);
+
+        task.heartbeat();
+        let [snapshot] = registry.snapshot().try_into().unwrap();
+        assert!(snapshot.last_heartbeat.is_some());
+        assert!(!snapshot.stalled);
+
+        // nothing heard from the task for longer than its interval.
+        let later = SystemTime::now() + interval * 2;
+        let [snapshot] = registry.snapshot_at(later).try_into().unwrap();
+        assert!(snapshot.stalled);
+
+        // a stopped task is reported as such, not as stalled.
+        drop(task);
+        let [snapshot] = registry.snapshot_at(later).try_into().unwrap();
+        assert_eq!(snapshot.status, TaskStatus::Stopped);
+        assert!(!snapshot.stalled);
+    }
+}
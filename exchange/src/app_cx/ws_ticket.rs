@@ -0,0 +1,102 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WsTicketError {
+    #[error("unknown or already used ticket")]
+    Invalid,
+    #[error("ticket has expired")]
+    Expired,
+}
+
+/// Single-use, short-lived tickets used to authenticate websocket connections.
+///
+/// Browsers can't set headers on a websocket handshake so the credential has to
+/// go in the URL, where it ends up in access logs. A ticket is only good for one
+/// connection and only for a few seconds, so a logged ticket is useless.
+#[derive(Debug)]
+pub struct WsTickets {
+    ttl: Duration,
+    tickets: Mutex<ahash::AHashMap<String, (Uuid, Instant)>>,
+}
+
+impl WsTickets {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            tickets: Mutex::default(),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// issue a new ticket for `user_uuid`.
+    pub fn issue(&self, user_uuid: Uuid) -> String {
+        let ticket = {
+            let mut rng = rand::thread_rng();
+            let mut bytes = [0u8; 32];
+            rand::Rng::fill(&mut rng, &mut bytes[..]);
+            hex::encode(bytes)
+        };
+
+        let now = Instant::now();
+        let mut tickets = self.tickets.lock().unwrap();
+
+        // drop tickets that were never redeemed so the map doesn't grow forever.
+        tickets.retain(|_, (_, expires)| *expires > now);
+        tickets.insert(ticket.clone(), (user_uuid, now + self.ttl));
+
+        ticket
+    }
+
+    /// consume `ticket`, returning the user it was issued to.
+    pub fn redeem(&self, ticket: &str) -> Result<Uuid, WsTicketError> {
+        let (user_uuid, expires) = self
+            .tickets
+            .lock()
+            .unwrap()
+            .remove(ticket)
+            .ok_or(WsTicketError::Invalid)?;
+
+        if Instant::now() >= expires {
+            return Err(WsTicketError::Expired);
+        }
+
+        Ok(user_uuid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticket_is_single_use() {
+        let tickets = WsTickets::new(Duration::from_secs(30));
+        let user_uuid = Uuid::new_v4();
+        let ticket = tickets.issue(user_uuid);
+
+        assert_eq!(tickets.redeem(&ticket), Ok(user_uuid));
+        assert_eq!(tickets.redeem(&ticket), Err(WsTicketError::Invalid));
+    }
+
+    #[test]
+    fn test_expired_ticket_is_rejected() {
+        let tickets = WsTickets::new(Duration::ZERO);
+        let ticket = tickets.issue(Uuid::new_v4());
+
+        assert_eq!(tickets.redeem(&ticket), Err(WsTicketError::Expired));
+    }
+
+    #[test]
+    fn test_unknown_ticket_is_rejected() {
+        let tickets = WsTickets::new(Duration::from_secs(30));
+
+        assert_eq!(tickets.redeem("not-a-ticket"), Err(WsTicketError::Invalid));
+    }
+}
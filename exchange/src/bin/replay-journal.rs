@@ -0,0 +1,147 @@
+//! Replay an export of the `trading_event_source` journal through the matching engine, offline.
+//!
+//! Every command is applied, in order, to an in-memory engine exactly as the trading engine
+//! does when it bootstraps from the database, and a JSON line describing the outcome is
+//! printed per command, followed by the final books and every execution. Orders carry their
+//! uuids and timestamps in the journal so the output is deterministic and can be diffed
+//! against what production did.
+//!
+//! Export the journal with one JSON command per line, in the order it was written:
+//!
+//! ```text
+//! psql "$DATABASE_URL" -Atc "SELECT jstr FROM trading_event_source ORDER BY id" > journal.jsonl
+//! replay-journal journal.jsonl --config exchange.toml
+//! ```
+#![allow(warnings)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use clap::Parser;
+use exchange::trading::{self, Assets, TradeCmdPayload};
+use exchange::Asset;
+use serde_json::json;
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// the journal export, one JSON command per line, `-` for stdin
+    journal: PathBuf,
+    /// the exchange config, so the replay enforces the same matching limits as production
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// print the depth of every book after each command
+    #[arg(long)]
+    depth: bool,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let Args {
+        journal,
+        config,
+        depth,
+    } = Args::parse();
+
+    let mut assets = match config {
+        Some(path) => Assets::from_config(&exchange::Configuration::load_from_path(&path)?),
+        None => Assets::new(),
+    };
+
+    let journal: Box<dyn BufRead> = if journal.as_os_str() == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(std::fs::File::open(&journal)?))
+    };
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let mut executions = vec![];
+
+    for (line_no, line) in journal.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let cmd: TradeCmdPayload =
+            serde_json::from_str(&line).map_err(|err| format!("line {}: {err}", line_no + 1))?;
+        let command = serde_json::to_value(&cmd)?;
+
+        let outcome = match cmd {
+            TradeCmdPayload::PlaceOrder(place_order) => {
+                match trading::do_place_order(&mut assets, place_order) {
+                    Ok(result) => {
+                        for execution in &result.executions {
+                            executions.push(json!({
+                                "line": line_no + 1,
+                                "taker_order_uuid": result.order_uuid,
+                                "side": result.side,
+                                "execution": execution,
+                            }));
+                        }
+
+                        json!({
+                            "order_uuid": result.order_uuid,
+                            "quantity_filled": result.quantity_filled,
+                            "quantity_remaining": result.quantity_remaining,
+                            "resting": result.order_index.is_some(),
+                            "executions": result.executions,
+                        })
+                    }
+                    Err(err) => json!({ "error": err.to_string() }),
+                }
+            }
+            TradeCmdPayload::CancelOrder(cancel_order) => {
+                match trading::do_cancel_order(&mut assets, cancel_order) {
+                    Ok(()) => json!({ "cancelled": true }),
+                    Err(err) => json!({ "error": err.to_string() }),
+                }
+            }
+            TradeCmdPayload::CancelOrderByClientId(cancel_order) => {
+                match trading::do_cancel_order_by_client_id(&mut assets, cancel_order) {
+                    Ok(()) => json!({ "cancelled": true }),
+                    Err(err) => json!({ "error": err.to_string() }),
+                }
+            }
+            TradeCmdPayload::ReduceOrder(reduce_order) => {
+                match trading::do_reduce_order(&mut assets, reduce_order) {
+                    Ok(quantity_remaining) => json!({ "quantity_remaining": quantity_remaining }),
+                    Err(err) => json!({ "error": err.to_string() }),
+                }
+            }
+        };
+
+        let mut step = json!({
+            "line": line_no + 1,
+            "command": command,
+            "outcome": outcome,
+        });
+
+        if depth {
+            step["depth"] = json!(books(&assets));
+        }
+
+        serde_json::to_writer(&mut out, &step)?;
+        writeln!(out)?;
+    }
+
+    let resting = [Asset::Bitcoin, Asset::Ether]
+        .map(|asset| trading::do_resting_orders(&assets, asset, 0, usize::MAX));
+
+    serde_json::to_writer(
+        &mut out,
+        &json!({
+            "final": {
+                "depth": books(&assets),
+                "resting": resting,
+                "executions": executions,
+            }
+        }),
+    )?;
+    writeln!(out)?;
+
+    Ok(())
+}
+
+fn books(assets: &Assets) -> [trading::DepthSnapshot; 2] {
+    [Asset::Bitcoin, Asset::Ether].map(|asset| trading::do_depth_snapshot(assets, asset))
+}
@@ -1,7 +1,12 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+
 use tonic::transport::Endpoint;
 
 use super::proto::bitcoin_core_rpc_client::BitcoinCoreRpcClient;
-use super::proto::GetNewAddressRequest;
+use super::proto::{
+    GetNewAddressRequest, GetNewAddressResponse, ListTransactionsRequest, ListTransactionsResponse,
+};
 
 // async fn bitcoind_rpc_client(
 //     config: &Config,
@@ -43,7 +48,56 @@ pub struct BitcoinRpcClient(Inner);
 #[derive(Debug, Clone)]
 enum Inner {
     Grpc(BitcoinCoreRpcClient<tonic::transport::Channel>),
-    Mock,
+    Mock(Arc<MockScript>),
+    /// a client connected in the background, `None` until the connection is made.
+    Deferred(Arc<RwLock<Option<Inner>>>),
+}
+
+/// Completes a client made with [`BitcoinRpcClient::new_deferred`] once bitcoind is reachable.
+#[derive(Debug, Clone)]
+pub struct DeferredConnection(Arc<RwLock<Option<Inner>>>);
+
+impl DeferredConnection {
+    /// route the calls of the deferred client, and every clone of it, through `client` from now on.
+    pub fn connected(&self, client: BitcoinRpcClient) {
+        let inner = match client.0 {
+            Inner::Deferred(slot) => slot.read().unwrap().clone(),
+            inner => Some(inner),
+        };
+        *self.0.write().unwrap() = inner;
+    }
+}
+
+/// Responses queued up for a mock [`BitcoinRpcClient`], each call pops the next one for its method.
+///
+/// Tests script what bitcoind would answer, e.g. a transaction whose confirmations increase
+/// from one call to the next, or an error. A call with nothing queued panics.
+#[derive(Debug, Default)]
+pub struct MockScript {
+    get_new_address: Mutex<VecDeque<Result<GetNewAddressResponse, tonic::Status>>>,
+    list_transactions: Mutex<VecDeque<Result<ListTransactionsResponse, tonic::Status>>>,
+}
+
+impl MockScript {
+    /// queue the response to the next `get_new_address` call.
+    pub fn push_get_new_address(&self, res: Result<GetNewAddressResponse, tonic::Status>) {
+        self.get_new_address.lock().unwrap().push_back(res);
+    }
+
+    /// queue the response to the next `list_transactions` call.
+    pub fn push_list_transactions(&self, res: Result<ListTransactionsResponse, tonic::Status>) {
+        self.list_transactions.lock().unwrap().push_back(res);
+    }
+
+    fn pop<T>(
+        queue: &Mutex<VecDeque<Result<T, tonic::Status>>>,
+        method: &str,
+    ) -> Result<tonic::Response<T>, tonic::Status> {
+        match queue.lock().unwrap().pop_front() {
+            Some(res) => res.map(tonic::Response::new),
+            None => panic!("no scripted response left for `{method}`"),
+        }
+    }
 }
 
 #[allow(missing_docs)]
@@ -59,9 +113,42 @@ impl BitcoinRpcClient {
         }
     }
 
+    /// A client that is not connected yet, every call fails with an `unavailable` status until
+    /// the returned [`DeferredConnection`] is completed.
+    pub fn new_deferred() -> (Self, DeferredConnection) {
+        let slot = Arc::new(RwLock::new(None));
+        (
+            Self(Inner::Deferred(slot.clone())),
+            DeferredConnection(slot),
+        )
+    }
+
+    /// `false` while a deferred client is still waiting for its connection.
+    pub fn is_available(&self) -> bool {
+        self.connected().is_ok()
+    }
+
+    /// the client calls go through, resolving a deferred client.
+    fn connected(&self) -> Result<Inner, tonic::Status> {
+        match &self.0 {
+            Inner::Deferred(slot) => slot
+                .read()
+                .unwrap()
+                .clone()
+                .ok_or_else(|| tonic::Status::unavailable("not connected to bitcoind yet")),
+            inner => Ok(inner.clone()),
+        }
+    }
+
     /// Create a dummy client used for testing
     pub fn new_mock() -> Self {
-        Self(Inner::Mock)
+        Self::new_scripted().0
+    }
+
+    /// Create a dummy client used for testing that answers from the returned [`MockScript`]
+    pub fn new_scripted() -> (Self, Arc<MockScript>) {
+        let script = Arc::new(MockScript::default());
+        (Self(Inner::Mock(script.clone())), script)
     }
 
     /// Generate a new wallet address
@@ -69,19 +156,76 @@ impl BitcoinRpcClient {
         &mut self,
         request: GetNewAddressRequest,
     ) -> Result<tonic::Response<super::proto::GetNewAddressResponse>, tonic::Status> {
-        match &mut self.0 {
-            Inner::Grpc(grpc) => grpc.get_new_address(request).await,
-            Inner::Mock => panic!(),
+        match self.connected()? {
+            Inner::Grpc(mut grpc) => grpc.get_new_address(request).await,
+            Inner::Mock(script) => MockScript::pop(&script.get_new_address, "get_new_address"),
+            Inner::Deferred(_) => unreachable!("a deferred client resolves to a connected one"),
         }
     }
 
     pub async fn list_transactions(
         &mut self,
-        request: super::proto::ListTransactionsRequest,
-    ) -> Result<tonic::Response<super::proto::ListTransactionsResponse>, tonic::Status> {
-        match &mut self.0 {
-            Inner::Grpc(grpc) => grpc.list_transactions(request).await,
-            Inner::Mock => panic!(),
+        request: ListTransactionsRequest,
+    ) -> Result<tonic::Response<ListTransactionsResponse>, tonic::Status> {
+        match self.connected()? {
+            Inner::Grpc(mut grpc) => grpc.list_transactions(request).await,
+            Inner::Mock(script) => MockScript::pop(&script.list_transactions, "list_transactions"),
+            Inner::Deferred(_) => unreachable!("a deferred client resolves to a connected one"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_transactions_request() -> ListTransactionsRequest {
+        ListTransactionsRequest {
+            label: None,
+            count: None,
+            skip: None,
+            include_watch_only: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scripted_responses_are_returned_in_order() {
+        let (mut client, script) = BitcoinRpcClient::new_scripted();
+
+        script.push_get_new_address(Ok(GetNewAddressResponse {
+            address: "bcrt1qexample".into(),
+        }));
+        script.push_list_transactions(Err(tonic::Status::unavailable("bitcoind is syncing")));
+        script.push_list_transactions(Ok(ListTransactionsResponse {
+            transactions: vec![],
+        }));
+
+        let res = client
+            .get_new_address(GetNewAddressRequest {
+                label: None,
+                address_type: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.into_inner().address, "bcrt1qexample");
+
+        let err = client
+            .list_transactions(list_transactions_request())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+
+        let res = client
+            .list_transactions(list_transactions_request())
+            .await
+            .unwrap();
+        assert!(res.into_inner().transactions.is_empty());
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "no scripted response left for `list_transactions`")]
+    async fn test_unscripted_call_panics() {
+        let mut client = BitcoinRpcClient::new_mock();
+        let _ = client.list_transactions(list_transactions_request()).await;
+    }
+}
@@ -1,13 +1,12 @@
 //! Support for bitcoin core rpc.
 
 use std::convert::Infallible;
-use std::time::Duration;
 
 use futures::future::BoxFuture;
 use futures::FutureExt;
 
 mod client;
-pub use client::BitcoinRpcClient;
+pub use client::{BitcoinRpcClient, DeferredConnection, MockScript};
 
 pub mod rpc;
 use rpc::AddressType;
@@ -194,16 +193,10 @@ impl proto::bitcoin_core_rpc_server::BitcoinCoreRpc for BitcoinCoreRpcImpl {
     }
 }
 
-/// Start gRPC server with [`tonic_reflection::server::ServerReflectionServer`] and [`BitcoinCoreRpcImpl`]
-pub async fn start_grpc_proxy(
-    config: Configuration,
-    signals: Signals,
-) -> Result<(), tonic::transport::Error> {
+/// Build the gRPC server with [`tonic_reflection::server::ServerReflectionServer`] and [`BitcoinCoreRpcImpl`]
+fn grpc_proxy_router(config: Configuration, signals: Signals) -> tonic::transport::server::Router {
     use proto::bitcoin_core_rpc_server::BitcoinCoreRpcServer;
 
-    let addr = config.bitcoin_grpc_bind_addr.clone();
-    tracing::info!(%addr, "starting grpc proxy");
-
     let svc = BitcoinCoreRpcServer::new(BitcoinCoreRpcImpl { config, signals });
 
     let reflection: tonic_reflection::server::ServerReflectionServer<_> =
@@ -215,14 +208,59 @@ pub async fn start_grpc_proxy(
     tonic::transport::Server::builder()
         .add_service(reflection)
         .add_service(svc)
-        .serve_with_shutdown(addr, async move {
-            let _ = signals.ctrl_c().await;
-            tracing::warn!("SIGINT received");
-            tokio::spawn(async move {
-                tokio::time::sleep(Duration::from_secs(3)).await;
-                std::process::exit(1);
-            });
-        })
+}
+
+/// Start gRPC server with [`tonic_reflection::server::ServerReflectionServer`] and [`BitcoinCoreRpcImpl`]
+///
+/// Stops on SIGINT or SIGTERM, open connections get [`Configuration::shutdown_deadline`] to
+/// finish before they are dropped.
+pub async fn start_grpc_proxy(
+    config: Configuration,
+    signals: Signals,
+) -> Result<(), tonic::transport::Error> {
+    let addr = config.bitcoin_grpc_bind_addr.clone();
+    let deadline = config.shutdown_deadline();
+    tracing::info!(%addr, "starting grpc proxy");
+
+    let (stopping_tx, stopping_rx) = tokio::sync::oneshot::channel();
+
+    let server = grpc_proxy_router(config, signals).serve_with_shutdown(addr, async move {
+        let signal = signals.shutdown().await;
+        tracing::warn!(?signal, "shutdown signal received");
+        let _ = stopping_tx.send(());
+    });
+
+    tokio::pin!(server);
+
+    tokio::select! {
+        res = &mut server => res,
+        () = async move {
+            let _ = stopping_rx.await;
+            tokio::time::sleep(deadline).await;
+        } => {
+            tracing::warn!(?deadline, "grpc proxy connections still open at the shutdown deadline, dropping them");
+            Ok(())
+        }
+    }
+}
+
+/// Serve the gRPC proxy on an already bound `listener` until `shutdown` resolves.
+///
+/// Used to run the proxy inside another process, e.g. [`crate::start_fullstack`], which owns the
+/// shutdown. Binding first lets clients connect as soon as this is spawned.
+pub async fn serve_grpc_proxy(
+    listener: tokio::net::TcpListener,
+    config: Configuration,
+    signals: Signals,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<(), tonic::transport::Error> {
+    let incoming = futures::stream::unfold(listener, |listener| async move {
+        let conn = listener.accept().await.map(|(stream, _)| stream);
+        Some((conn, listener))
+    });
+
+    grpc_proxy_router(config, signals)
+        .serve_with_incoming_shutdown(incoming, shutdown)
         .await
 }
 
@@ -240,3 +278,27 @@ pub async fn connect_bitcoin_rpc(
         BitcoinRpcClient::new_grpc(config.bitcoin_grpc_endpoint.clone()).await?;
     Ok(bitcoin_rpc_client)
 }
+
+/// Keep trying to connect to the bitcoin grpc service, every `retry_interval`, until it is
+/// reachable and `connection` is completed. `task` is heartbeat on every attempt.
+pub async fn connect_bitcoin_rpc_in_background(
+    config: Configuration,
+    connection: DeferredConnection,
+    retry_interval: std::time::Duration,
+    task: crate::app_cx::TaskHandle,
+) {
+    loop {
+        task.heartbeat();
+
+        match connect_bitcoin_rpc(&config).await {
+            Ok(client) => {
+                connection.connected(client);
+                return;
+            }
+            Err(err) => {
+                tracing::warn!(?err, ?retry_interval, "bitcoind unavailable, retrying");
+                tokio::time::sleep(retry_interval).await;
+            }
+        }
+    }
+}
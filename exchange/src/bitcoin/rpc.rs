@@ -254,6 +254,31 @@ fn opt_result<T: for<'a> serde::de::Deserialize<'a>>(
     }
 }
 
+/// Run the CPU-heavy `parse` on tokio's blocking pool and wait for it.
+async fn parse_blocking<T, F>(parse: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(parse).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(Error::Io(err.into())),
+    }
+}
+
+/// Decode a hex encoded consensus object, such as a raw transaction, off the async runtime.
+async fn deserialize_hex_blocking<T>(hex: String) -> Result<T, Error>
+where
+    T: bitcoin::consensus::Decodable + Send + 'static,
+{
+    parse_blocking(move || {
+        let bytes: Vec<u8> = FromHex::from_hex(&hex)?;
+        Ok(bitcoin::consensus::encode::deserialize(&bytes)?)
+    })
+    .await
+}
+
 /// Used to pass raw txs into the API.
 pub trait RawTx: Sized + Clone {
     fn raw_hex(self) -> String;
@@ -357,6 +382,28 @@ impl BitcoinCoreRpcHttp {
         Ok(resp?.result()?)
     }
 
+    /// Like [`Self::send_request`] but the response is deserialized on the blocking pool.
+    ///
+    /// Use this for calls whose responses can be megabytes of JSON (`listtransactions`,
+    /// `scantxoutset`, ...) so parsing them doesn't stall the other tasks on the runtime.
+    async fn send_request_parse_blocking<T>(
+        &self,
+        cmd: &str,
+        args: &[serde_json::Value],
+    ) -> Result<T, Error>
+    where
+        T: for<'a> serde::de::Deserialize<'a> + Send + 'static,
+    {
+        let v_args: Vec<_> = args
+            .iter()
+            .map(serde_json::value::to_raw_value)
+            .collect::<std::result::Result<_, serde_json::Error>>()?;
+        let req = self.client.build_request(cmd, &v_args[..]);
+
+        let resp = self.client.send_request(req).await.map_err(Error::from)?;
+        parse_blocking(move || Ok(resp.result()?)).await
+    }
+
     pub async fn get_network_info(&self) -> Result<GetNetworkInfoResult, Error> {
         self.send_request("getnetworkinfo", &[]).await
     }
@@ -595,8 +642,7 @@ impl BitcoinCoreRpcHttp {
         let hex: String = self
             .send_request("getrawtransaction", handle_defaults(&mut args, &[null()]))
             .await?;
-        let bytes: Vec<u8> = FromHex::from_hex(&hex)?;
-        Ok(bitcoin::consensus::encode::deserialize(&bytes)?)
+        deserialize_hex_blocking(hex).await
     }
 
     pub async fn get_raw_transaction_hex(
@@ -696,7 +742,7 @@ impl BitcoinCoreRpcHttp {
             opt_into_json(skip)?,
             opt_into_json(include_watchonly)?,
         ];
-        self.send_request(
+        self.send_request_parse_blocking(
             "listtransactions",
             handle_defaults(&mut args, &[10.into(), 0.into(), null()]),
         )
@@ -716,7 +762,7 @@ impl BitcoinCoreRpcHttp {
             opt_into_json(include_watchonly)?,
             opt_into_json(include_removed)?,
         ];
-        self.send_request("listsinceblock", handle_defaults(&mut args, &[null()]))
+        self.send_request_parse_blocking("listsinceblock", handle_defaults(&mut args, &[null()]))
             .await
     }
 
@@ -870,7 +916,7 @@ impl BitcoinCoreRpcHttp {
             into_json(true)?,
             null(),
         ];
-        self.send_request("listunspent", handle_defaults(&mut args, &defaults))
+        self.send_request_parse_blocking("listunspent", handle_defaults(&mut args, &defaults))
             .await
     }
 
@@ -1362,7 +1408,43 @@ impl BitcoinCoreRpcHttp {
         &self,
         descriptors: &[ScanTxOutRequest],
     ) -> Result<ScanTxOutResult, Error> {
-        self.send_request("scantxoutset", &["start".into(), into_json(descriptors)?])
+        self.send_request_parse_blocking("scantxoutset", &["start".into(), into_json(descriptors)?])
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_runtime_stays_responsive_during_large_parse() {
+        let raw = serde_json::to_string(
+            &(0..200_000)
+                .map(|n| serde_json::json!({ "txid": format!("{n:064x}"), "vout": n }))
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+
+        // the parse can't finish until another task on this (single threaded) runtime
+        // has run, which would deadlock if the parse were done inline.
+        let (tick_tx, tick_rx) = std::sync::mpsc::channel();
+        let ticker = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            tick_tx.send(()).unwrap();
+        });
+
+        let parse = parse_blocking(move || {
+            tick_rx.recv().unwrap();
+            Ok(serde_json::from_str::<Vec<serde_json::Value>>(&raw)?)
+        });
+
+        let parsed = tokio::time::timeout(std::time::Duration::from_secs(10), parse)
             .await
+            .expect("parse should not block the runtime")
+            .unwrap();
+
+        assert_eq!(parsed.len(), 200_000);
+        ticker.await.unwrap();
     }
 }
@@ -12,10 +12,12 @@
 //! struct. The fields are all public the struct is plain-ol-data (POD).
 //!
 
+use std::collections::HashMap;
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 /// The string key used to check the environment variable for the webserver address.
 pub const WEBSERVER_ADDRESS: &str = "WEBSERVER_ADDRESS";
@@ -55,6 +57,11 @@ pub fn database_url() -> String {
     })
 }
 
+/// get the database url from the environment or an empty string, checked by [`Configuration::validate`].
+fn database_url_or_empty() -> String {
+    std::env::var(DATABASE_URL).unwrap_or_default()
+}
+
 /// The string key used to check the environment variable for the config file path.
 pub const CONFIG_FILE_PATH: &str = "CONFIG_FILE_PATH";
 
@@ -68,14 +75,131 @@ const fn default_te_channel_capacity() -> usize {
     1024
 }
 
+/// The default number of seconds a websocket ticket stays valid for.
+const fn default_ws_ticket_ttl_secs() -> u64 {
+    30
+}
+
+/// The default number of seconds to wait for tasks to finish on shutdown before aborting them.
+const fn default_shutdown_deadline_secs() -> u64 {
+    10
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+/// The default `Retry-After` of maintenance responses, five minutes.
+const fn default_maintenance_retry_after_secs() -> u64 {
+    300
+}
+
+/// The default `Path` of the session cookie, the whole site.
+fn default_session_cookie_path() -> String {
+    "/".to_owned()
+}
+
+/// The default idle time before TCP keepalive probes, a minute.
+const fn default_tcp_keepalive_secs() -> Option<u64> {
+    Some(60)
+}
+
+/// The default interval between TCP keepalive probes.
+const fn default_tcp_keepalive_interval_secs() -> u64 {
+    15
+}
+
+/// The default expiry of a good-til-date order, one day.
+const fn default_order_ttl_default_secs() -> u64 {
+    60 * 60 * 24
+}
+
+/// The default maximum expiry of a good-til-date order, thirty days.
+const fn default_order_ttl_max_secs() -> u64 {
+    60 * 60 * 24 * 30
+}
+
+const fn default_order_archive_retention_secs() -> u64 {
+    7 * 24 * 60 * 60 // 7 days
+}
+
+const fn default_order_archive_interval_secs() -> u64 {
+    60 * 60 // 1 hour
+}
+
+const fn default_load_shedding_high_water_percent() -> u8 {
+    75
+}
+
+const fn default_load_shedding_low_water_percent() -> u8 {
+    50
+}
+
+const fn default_last_look_window_ms() -> u64 {
+    20
+}
+
+const fn default_last_look_total_ms() -> u64 {
+    100
+}
+
+const fn default_bitcoind_retry_interval_ms() -> u64 {
+    5_000
+}
+
+const fn default_imbalance_guard_levels() -> usize {
+    5
+}
+
+const fn default_imbalance_guard_trip_percent() -> u8 {
+    90
+}
+
+const fn default_imbalance_guard_recover_percent() -> u8 {
+    60
+}
+
+const fn default_imbalance_guard_sustain_ms() -> u64 {
+    30_000
+}
+
+const fn default_imbalance_guard_interval_ms() -> u64 {
+    1_000
+}
+
+const fn default_automatic_shutdown_after_secs() -> u64 {
+    300
+}
+
+const fn default_max_order_notional() -> u64 {
+    crate::trading::notional::MAX_NOTIONAL
+}
+
+/// the longest a maker may hold up the trading engine on a single last look.
+pub const MAX_LAST_LOOK_WINDOW_MS: u64 = 250;
+
+/// the longest the makers of every order a taker matches may hold up the trading engine together.
+pub const MAX_LAST_LOOK_TOTAL_MS: u64 = 1_000;
+
+const fn default_max_integer_digits() -> usize {
+    20 // u64::MAX
+}
+
+const fn default_deposit_max_per_cycle() -> usize {
+    1_000
+}
+
+/// The default trading engine response ring capacity, `None` disables the ring.
+const fn default_te_response_ring_capacity() -> Option<usize> {
+    None
+}
+
 /// The string key used to check the environment variable for the bitcoin rpc url.
 pub const BITCOIN_RPC_URL: &str = "BITCOIN_RPC_URL";
 
-/// get the bitcoin rpc url from the environment or panic.
-fn bitcoin_rpc_url() -> String {
-    std::env::var(BITCOIN_RPC_URL).ok().unwrap_or_else(|| {
-        panic!("BITCOIN_RPC_URL env var not set");
-    })
+/// get the bitcoin rpc url from the environment or an empty string, checked by [`Configuration::validate`].
+fn bitcoin_rpc_url_or_empty() -> String {
+    std::env::var(BITCOIN_RPC_URL).unwrap_or_default()
 }
 
 /// the default bitcoin grpc endpoint.
@@ -85,13 +209,6 @@ fn default_bitcoin_grpc_endpoint() -> tonic::transport::Endpoint {
 /// The string key used to check the environment variable for the bitcoin **grpc** url.
 pub const BITCOIN_GRPC_ENDPOINT: &str = "BITCOIN_GRPC_ENDPOINT";
 
-/// get the bitcoin grpc url from the environment or panic.
-fn bitcoin_grpc_url() -> String {
-    std::env::var(BITCOIN_RPC_URL).ok().unwrap_or_else(|| {
-        panic!("BITCOIN_RPC_URL env var not set");
-    })
-}
-
 /// The string key used to check the environment variable for the bitcoin grpc bind address.
 pub const BITCOIN_GRPC_BIND_ADDR: &str = "BITCOIN_GRPC_BIND_ADDR";
 
@@ -128,6 +245,566 @@ pub const JINJA_TEMPLATE_DIR: &str = "JINJA_TEMPLATE_DIR";
 /// The string key used to check the environment variable for the `/www` dir that stores all frontend (FE) files
 pub const FE_WEB_DIR: &str = "FE_WEB_DIR";
 
+/// Global (not per-client) limits on the number of in-flight requests for each group of routes.
+///
+/// Unset groups are unlimited, requests beyond the limit are rejected with a `503 Service Unavailable`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConcurrencyLimits {
+    /// limit for the `/api/trade` routes
+    pub trade: Option<usize>,
+    /// limit for the `/api/user` routes
+    pub user: Option<usize>,
+    /// limit for the `/api/session` routes
+    pub session: Option<usize>,
+    /// limit for the `/api/deposit` routes
+    pub deposit: Option<usize>,
+    /// limit for the `/api/withdrawal` routes
+    pub withdrawal: Option<usize>,
+    /// limit for the `/api/public` routes
+    pub public: Option<usize>,
+    /// limit for the `/api/ws` routes
+    pub ws: Option<usize>,
+    /// limit for the `/api/admin` routes
+    pub admin: Option<usize>,
+}
+
+/// Coarse liquidity requirements the opposite side of a book must meet before a market order is matched.
+///
+/// Unset requirements are not checked, market orders failing a check are rejected without touching the book.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarketOrderLiquidity {
+    /// the minimum total quantity resting on the opposite side
+    pub min_quantity: Option<u64>,
+    /// the minimum number of price levels on the opposite side
+    pub min_levels: Option<usize>,
+}
+
+/// Space reserved up front in the book of an asset.
+///
+/// Books start empty and grow as orders arrive, reallocating as they do. Reserving room for the
+/// expected size avoids that under load, at the cost of holding the memory even while idle.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrderbookCapacity {
+    /// price levels reserved on each side of the book
+    #[serde(default)]
+    pub price_levels: usize,
+    /// resting orders the book can track before its index grows
+    #[serde(default)]
+    pub orders: usize,
+}
+
+/// Bounds on the expiry of good-til-date orders.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrderTtl {
+    /// seconds until expiry for good-til-date orders placed without one
+    #[serde(default = "default_order_ttl_default_secs")]
+    pub default_secs: u64,
+    /// the furthest in the future, in seconds, a good-til-date order may expire
+    #[serde(default = "default_order_ttl_max_secs")]
+    pub max_secs: u64,
+    /// clamp expiries beyond `max_secs` to it instead of rejecting the order
+    #[serde(default)]
+    pub clamp_to_max: bool,
+}
+
+impl Default for OrderTtl {
+    fn default() -> Self {
+        Self {
+            default_secs: default_order_ttl_default_secs(),
+            max_secs: default_order_ttl_max_secs(),
+            clamp_to_max: false,
+        }
+    }
+}
+
+/// Fees charged on every fill, in basis points of the fill's notional.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeSchedule {
+    /// charged to the owner of the resting order, a negative value pays makers a rebate
+    #[serde(default)]
+    pub maker_bps: i32,
+    /// charged to the owner of the incoming order
+    #[serde(default)]
+    pub taker_bps: u32,
+    /// The currency fees are charged in for each asset, the quote currency for unlisted assets
+    #[serde(default)]
+    pub currency: HashMap<crate::Asset, FeeCurrency>,
+}
+
+impl FeeSchedule {
+    /// The currency the fees of fills of `asset` are charged in.
+    pub fn currency(&self, asset: crate::Asset) -> FeeCurrency {
+        self.currency.get(&asset).copied().unwrap_or_default()
+    }
+}
+
+/// The currency the fees of a fill are charged in.
+///
+/// Only the two currencies of the fill itself are supported. A separate fee token would need a
+/// price to convert the fill's notional at, which the exchange does not have, so it is left out
+/// rather than charged at a made up rate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeCurrency {
+    /// the quote currency, the fee is a share of the fill's notional.
+    #[default]
+    Quote,
+    /// the asset traded, the fee is a share of the fill's quantity.
+    Base,
+}
+
+/// Bounds on how many deposits a single reconciliation with bitcoind journals.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DepositReconciliation {
+    /// deposits journalled per reconciliation, the rest are left to the next one
+    #[serde(default = "default_deposit_max_per_cycle")]
+    pub max_per_cycle: usize,
+}
+
+impl Default for DepositReconciliation {
+    fn default() -> Self {
+        Self {
+            max_per_cycle: default_deposit_max_per_cycle(),
+        }
+    }
+}
+
+/// When the records of closed orders move out of the trading engine into `orders_archive`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrderArchival {
+    /// how long a filled or cancelled order stays in the engine before it is archived
+    #[serde(default = "default_order_archive_retention_secs")]
+    pub retention_secs: u64,
+    /// how often to look for orders to archive
+    #[serde(default = "default_order_archive_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for OrderArchival {
+    fn default() -> Self {
+        Self {
+            retention_secs: default_order_archive_retention_secs(),
+            interval_secs: default_order_archive_interval_secs(),
+        }
+    }
+}
+
+impl OrderArchival {
+    /// see [`OrderArchival::retention_secs`]
+    pub fn retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.retention_secs)
+    }
+
+    /// see [`OrderArchival::interval_secs`]
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// Opt-in "last look" for market makers, off by default.
+///
+/// Last look lets the maker of a resting order reject a match before it executes. It is
+/// controversial since it hands makers a free option against takers, so it takes two opt-ins: the
+/// exchange enables it here and the maker places each order with `last_look` set.
+///
+/// When a taker would match such an order the engine pauses on that match, sends the maker the
+/// details over their websocket and waits up to `window_ms` for an answer. A rejected order is
+/// skipped, keeping its place in the book, and the taker moves on to the next resting order. An
+/// accepted order, a maker that is not connected and a maker that does not answer in time all
+/// fill as usual.
+///
+/// The engine processes one command at a time, every other order waits while a maker decides, so
+/// the window is kept small and capped at [`MAX_LAST_LOOK_WINDOW_MS`]. A taker sweeping many
+/// last-look orders waits on their makers for `total_ms` at most, the orders left once that is
+/// spent fill without asking. Only orders matched when they are placed get a last look, held
+/// orders released by a trigger fill without one.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LastLook {
+    /// accept orders placed with `last_look` and ask their makers before filling them
+    #[serde(default)]
+    pub enabled: bool,
+    /// how long the engine waits on a maker's answer before filling the order anyway
+    #[serde(default = "default_last_look_window_ms")]
+    pub window_ms: u64,
+    /// how long the engine waits on makers in total for one taker, at least `window_ms`
+    #[serde(default = "default_last_look_total_ms")]
+    pub total_ms: u64,
+}
+
+impl Default for LastLook {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_ms: default_last_look_window_ms(),
+            total_ms: default_last_look_total_ms(),
+        }
+    }
+}
+
+impl LastLook {
+    /// see [`LastLook::window_ms`], `None` unless last look is enabled.
+    pub fn window(&self) -> Option<std::time::Duration> {
+        self.enabled
+            .then(|| std::time::Duration::from_millis(self.window_ms))
+    }
+
+    /// see [`LastLook::total_ms`].
+    pub fn total(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.total_ms)
+    }
+}
+
+/// What startup does when bitcoind can not be reached.
+///
+/// Trading does not need bitcoind, only deposits and withdrawals do. Unless bitcoind is
+/// `required` the exchange starts without it, answers deposit and withdrawal routes with a `503`
+/// and reports itself degraded on `/ready` until a background retry connects.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BitcoindStartup {
+    /// refuse to start while bitcoind is unreachable
+    #[serde(default = "default_true")]
+    pub required: bool,
+    /// how long to wait between attempts to connect when not `required`
+    #[serde(default = "default_bitcoind_retry_interval_ms")]
+    pub retry_interval_ms: u64,
+}
+
+impl Default for BitcoindStartup {
+    fn default() -> Self {
+        Self {
+            required: true,
+            retry_interval_ms: default_bitcoind_retry_interval_ms(),
+        }
+    }
+}
+
+impl BitcoindStartup {
+    /// see [`BitcoindStartup::retry_interval_ms`].
+    pub fn retry_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.retry_interval_ms)
+    }
+}
+
+/// Bounds on the digits of an amount string, checked while parsing before the value is converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AmountDigits {
+    /// digits allowed before the decimal point, leading zeros included
+    #[serde(default = "default_max_integer_digits")]
+    pub max_integer_digits: usize,
+    /// digits allowed after the decimal point, amounts are whole minor units so none by default
+    #[serde(default)]
+    pub max_fractional_digits: usize,
+}
+
+impl Default for AmountDigits {
+    fn default() -> Self {
+        Self {
+            max_integer_digits: default_max_integer_digits(),
+            max_fractional_digits: 0,
+        }
+    }
+}
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// human readable lines, for development
+    Pretty,
+    /// one JSON object per line, for log ingestion
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            Self::Pretty
+        } else {
+            Self::Json
+        }
+    }
+}
+
+/// The `SameSite` attribute of the session cookie.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    /// only sent on requests originating from the site itself
+    Strict,
+    /// also sent on top-level navigations from other sites
+    #[default]
+    Lax,
+    /// sent on all requests, browsers require `secure` as well
+    None,
+}
+
+/// Attributes of the `session-token` cookie.
+///
+/// The defaults suit production, local development over plain http needs `secure = false`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionCookie {
+    /// only send the cookie over https
+    #[serde(default = "default_true")]
+    pub secure: bool,
+    /// hide the cookie from scripts
+    #[serde(default = "default_true")]
+    pub http_only: bool,
+    /// the `SameSite` attribute
+    #[serde(default)]
+    pub same_site: CookieSameSite,
+    /// the `Domain` attribute, the cookie is host-only if unset
+    pub domain: Option<String>,
+    /// the `Path` attribute
+    #[serde(default = "default_session_cookie_path")]
+    pub path: String,
+}
+
+impl Default for SessionCookie {
+    fn default() -> Self {
+        Self {
+            secure: true,
+            http_only: true,
+            same_site: CookieSameSite::default(),
+            domain: None,
+            path: default_session_cookie_path(),
+        }
+    }
+}
+
+/// Connection-level settings of the webserver, applied to every accepted connection.
+///
+/// Long-lived streaming clients behind proxies need keepalive probes, otherwise idle connections
+/// are silently dropped by middleboxes along the way.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebserverConnection {
+    /// accept HTTP/2 alongside HTTP/1.1, from a TLS terminating proxy or as cleartext with prior knowledge
+    #[serde(default = "default_true")]
+    pub http2: bool,
+    /// set `TCP_NODELAY` so small streamed frames aren't held back
+    #[serde(default = "default_true")]
+    pub tcp_nodelay: bool,
+    /// seconds a connection may be idle before keepalive probes are sent, keepalive is off if unset
+    #[serde(default = "default_tcp_keepalive_secs")]
+    pub tcp_keepalive_secs: Option<u64>,
+    /// seconds between keepalive probes
+    #[serde(default = "default_tcp_keepalive_interval_secs")]
+    pub tcp_keepalive_interval_secs: u64,
+}
+
+impl Default for WebserverConnection {
+    fn default() -> Self {
+        Self {
+            http2: true,
+            tcp_nodelay: true,
+            tcp_keepalive_secs: default_tcp_keepalive_secs(),
+            tcp_keepalive_interval_secs: default_tcp_keepalive_interval_secs(),
+        }
+    }
+}
+
+/// Rejecting requests with a `503 Service Unavailable` while the exchange is being worked on.
+///
+/// `/health` is always exempt so orchestrators don't restart the exchange mid-deploy.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceMode {
+    /// start in maintenance mode, it can be toggled at runtime with `PUT /api/admin/maintenance`
+    #[serde(default)]
+    pub enabled: bool,
+    /// assets whose trade and public routes are always in maintenance, whether or not `enabled` is set
+    #[serde(default)]
+    pub assets: Vec<crate::Asset>,
+    /// the `Retry-After` sent with maintenance responses, in seconds
+    #[serde(default = "default_maintenance_retry_after_secs")]
+    pub retry_after_secs: u64,
+    /// keep the admin routes up during maintenance
+    #[serde(default = "default_true")]
+    pub exempt_admin: bool,
+}
+
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            assets: vec![],
+            retry_after_secs: default_maintenance_retry_after_secs(),
+            exempt_admin: true,
+        }
+    }
+}
+
+/// Shedding new orders with a `503 Service Unavailable` while the trading engine is backed up.
+///
+/// Once the commands waiting in the engine's channel reach `high_water_percent` of
+/// `te_channel_capacity`, order placements and edits are rejected straight away rather than
+/// queueing behind the backlog until they time out. Cancels are always let through. Shedding
+/// stops when the queue falls below `low_water_percent`, the gap between the two marks keeps it
+/// from flapping while the queue hovers around one of them.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoadShedding {
+    /// shed orders while the engine is backed up
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// start shedding at this share of the engine's channel capacity, as a percentage
+    #[serde(default = "default_load_shedding_high_water_percent")]
+    pub high_water_percent: u8,
+    /// stop shedding below this share of the engine's channel capacity, as a percentage
+    #[serde(default = "default_load_shedding_low_water_percent")]
+    pub low_water_percent: u8,
+}
+
+impl Default for LoadShedding {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            high_water_percent: default_load_shedding_high_water_percent(),
+            low_water_percent: default_load_shedding_low_water_percent(),
+        }
+    }
+}
+
+impl LoadShedding {
+    /// see [`LoadShedding::high_water_percent`], in commands for a channel of `capacity`.
+    pub fn high_water_mark(&self, capacity: usize) -> usize {
+        capacity * usize::from(self.high_water_percent) / 100
+    }
+
+    /// see [`LoadShedding::low_water_percent`], in commands for a channel of `capacity`.
+    pub fn low_water_mark(&self, capacity: usize) -> usize {
+        capacity * usize::from(self.low_water_percent) / 100
+    }
+}
+
+/// Switch the trading engine to reduce-only while a book is dangerously one-sided, off by default.
+///
+/// A book trips the guard when the absolute imbalance of its best `levels` price levels, see
+/// [`crate::trading::Ticker::imbalance`], stays at or above `trip_percent` for `sustain_ms`.
+/// While tripped only `reduce_only` orders, which never rest, are accepted. The engine goes back
+/// to running once every tripped book is below `recover_percent`, the gap between the two keeps
+/// it from flapping while a book hovers around the threshold.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImbalanceGuard {
+    /// watch the books for one-sided liquidity
+    #[serde(default)]
+    pub enabled: bool,
+    /// how many price levels of each side the imbalance is computed from
+    #[serde(default = "default_imbalance_guard_levels")]
+    pub levels: usize,
+    /// the absolute imbalance that trips the guard, as a percentage
+    #[serde(default = "default_imbalance_guard_trip_percent")]
+    pub trip_percent: u8,
+    /// the absolute imbalance a tripped book must fall below to recover, as a percentage
+    #[serde(default = "default_imbalance_guard_recover_percent")]
+    pub recover_percent: u8,
+    /// how long a book must stay at or above `trip_percent` before the guard trips
+    #[serde(default = "default_imbalance_guard_sustain_ms")]
+    pub sustain_ms: u64,
+    /// how often the books are checked
+    #[serde(default = "default_imbalance_guard_interval_ms")]
+    pub interval_ms: u64,
+}
+
+impl Default for ImbalanceGuard {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            levels: default_imbalance_guard_levels(),
+            trip_percent: default_imbalance_guard_trip_percent(),
+            recover_percent: default_imbalance_guard_recover_percent(),
+            sustain_ms: default_imbalance_guard_sustain_ms(),
+            interval_ms: default_imbalance_guard_interval_ms(),
+        }
+    }
+}
+
+impl ImbalanceGuard {
+    /// see [`ImbalanceGuard::sustain_ms`]
+    pub fn sustain(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.sustain_ms)
+    }
+
+    /// see [`ImbalanceGuard::interval_ms`]
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.interval_ms)
+    }
+}
+
+/// Stop a debug build on its own after a while, so a forgotten development server does not run
+/// overnight. Release builds never stop on their own, whatever is set here.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutomaticShutdown {
+    /// stop debug builds after `after_secs`
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// how long a debug build runs before it stops
+    #[serde(default = "default_automatic_shutdown_after_secs")]
+    pub after_secs: u64,
+}
+
+impl Default for AutomaticShutdown {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            after_secs: default_automatic_shutdown_after_secs(),
+        }
+    }
+}
+
+impl AutomaticShutdown {
+    /// see [`AutomaticShutdown::after_secs`]
+    pub fn after(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.after_secs)
+    }
+}
+
+/// Error returned when loading a [`Configuration`].
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// the config file could not be read.
+    #[error("failed to read config file {path:?}: {source}")]
+    Io {
+        /// the path of the config file.
+        path: PathBuf,
+        /// the underlying io error.
+        #[source]
+        source: std::io::Error,
+    },
+    /// the config is not valid TOML or does not match the expected schema.
+    #[error("failed to parse config: {0}")]
+    Parse(#[from] toml::de::Error),
+    /// a required setting was neither set in the config nor in the environment.
+    #[error("missing required setting `{field}`, set it in the config or via the `{env}` env var")]
+    MissingField {
+        /// the name of the field in the config.
+        field: &'static str,
+        /// the environment variable used as a fallback.
+        env: &'static str,
+    },
+    /// two or more settings conflict with each other.
+    #[error("invalid setting `{field}`: {reason}")]
+    Invalid {
+        /// the name of the field in the config.
+        field: &'static str,
+        /// why the value was rejected.
+        reason: &'static str,
+    },
+}
+
 /// application "configuration" loaded from a config file, unspecified values may use the environent variables as fallback.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Configuration {
@@ -135,14 +812,17 @@ pub struct Configuration {
     #[serde(default = "webserver_address")]
     pub webserver_bind_addr: SocketAddr,
     /// Specifies the database url (with credentials) to use
-    #[serde(default = "database_url")]
+    #[serde(default = "database_url_or_empty")]
     pub database_url: String,
     /// Configure the message channel capacity of the trading engine
     #[serde(default = "default_te_channel_capacity")]
     pub te_channel_capacity: usize,
+    /// Pre-allocate this many response slots for trading engine commands instead of a `oneshot` per command
+    #[serde(default = "default_te_response_ring_capacity")]
+    pub te_response_ring_capacity: Option<usize>,
     /// Mnemonic for the exchange Ether wallet
     pub eth_wallet_mnemonic: Option<String>,
-    #[serde(default = "bitcoin_rpc_url")]
+    #[serde(default = "bitcoin_rpc_url_or_empty")]
     /// Specifies the URL for the bitcoin-rpc service to connect to
     pub bitcoin_rpc_url: String,
     /// The username for auth
@@ -168,22 +848,343 @@ pub struct Configuration {
     pub jinja_template_dir: Option<PathBuf>,
     /// the directory that stores all frontend (FE) files like CSS, HTML fragments, robots.txt, fonts
     pub fe_web_dir: Option<PathBuf>,
+    /// Global concurrency limits for each group of API routes
+    #[serde(default)]
+    pub route_concurrency_limits: RouteConcurrencyLimits,
+    /// Write prices, quantities and notionals as JSON strings instead of numbers, see [`crate::json_amount`]
+    #[serde(default)]
+    pub json_amounts_as_strings: bool,
+    /// Reject amount strings with more digits than this before parsing them, see [`crate::json_amount`]
+    #[serde(default)]
+    pub json_amount_digits: AmountDigits,
+    /// The digit limits of amount strings sent for each asset, `json_amount_digits` for unlisted assets
+    #[serde(default)]
+    pub asset_json_amount_digits: HashMap<crate::Asset, AmountDigits>,
+    /// Minimum book liquidity required before market orders are accepted
+    #[serde(default)]
+    pub market_order_liquidity: MarketOrderLiquidity,
+    /// The maximum number of orders that may rest at a single price of a book, unlimited if unset
+    #[serde(default)]
+    pub max_orders_per_price_level: Option<usize>,
+    /// Cancel a resting order whose owner is overdrawn when a fill against it is settled and leave
+    /// the fill for an operator, otherwise only alert and settle it anyway
+    #[serde(default = "default_true")]
+    pub cancel_underfunded_makers: bool,
+    /// Space reserved up front in the book of each asset, books of unlisted assets start empty
+    #[serde(default)]
+    pub orderbook_capacity: HashMap<crate::Asset, OrderbookCapacity>,
+    /// The order types accepted for each asset, every type is accepted for unlisted assets
+    #[serde(default)]
+    pub allowed_order_types: HashMap<crate::Asset, Vec<crate::trading::OrderType>>,
+    /// How takers are shared out at a price level of each asset, price-time for unlisted assets
+    #[serde(default)]
+    pub matching_policy: HashMap<crate::Asset, crate::trading::MatchingPolicy>,
+    /// Cancel statements on read-heavy paths (balances, listings, reconciliation) that run longer than this, unlimited if unset
+    #[serde(default)]
+    pub db_statement_timeout_ms: Option<u64>,
+    /// Default and maximum expiry of good-til-date orders
+    #[serde(default)]
+    pub order_ttl: OrderTtl,
+    /// How many seconds a websocket ticket stays valid for after it is issued
+    #[serde(default = "default_ws_ticket_ttl_secs")]
+    pub ws_ticket_ttl_secs: u64,
+    /// How many seconds shutdown waits for the trading engine and background tasks before aborting them
+    #[serde(default = "default_shutdown_deadline_secs")]
+    pub shutdown_deadline_secs: u64,
+    /// HTTP/2 and TCP keepalive/nodelay settings of webserver connections
+    #[serde(default)]
+    pub webserver_connection: WebserverConnection,
+    /// The webserver is reached over https, directly or through a TLS terminating proxy
+    #[serde(default)]
+    pub webserver_tls: bool,
+    /// Attributes of the session cookie set on sign-in and sign-up
+    #[serde(default)]
+    pub session_cookie: SessionCookie,
+    /// Reject requests with a `503` during deploys, for every route or per asset
+    #[serde(default)]
+    pub maintenance_mode: MaintenanceMode,
+    /// Apply pending database migrations at startup instead of refusing to start with an outdated schema
+    #[serde(default)]
+    pub auto_migrate: bool,
+    /// Tag daily statements with an HMAC-SHA256 under this secret key, statements are only digested if unset
+    ///
+    /// Only the exchange holds the key, so only the exchange can check a tag. It shows a statement
+    /// the exchange issued has not been altered, users can not verify it on their own.
+    #[serde(default)]
+    pub statement_integrity_key: Option<String>,
+    /// Users with these emails are admins, the list is reconciled with the users table on startup
+    ///
+    /// Only users whose `email_verified_at` is set are promoted, which signing up with
+    /// `admin_signup_token` does, so registering an admin's email first grants nothing. An empty
+    /// list leaves the roles in the database alone, otherwise admins not on it lose the role.
+    #[serde(default)]
+    pub admin_emails: Vec<String>,
+    /// A secret handed to the people on `admin_emails`, signing up with it verifies their email
+    ///
+    /// A signup carrying the token is refused unless its email is on the list. Unset, no signup
+    /// is verified and nobody new becomes an admin.
+    #[serde(default)]
+    pub admin_signup_token: Option<String>,
+    /// Addresses withdrawals may not go to, deposits paying one are quarantined, for compliance
+    ///
+    /// Deposits are matched on the wallet address they paid, bitcoind does not report the sending
+    /// address, so this does not screen where a deposit came from.
+    ///
+    /// An entry ending in `*` blocks every address starting with it. Replaced at runtime with
+    /// `PUT /api/admin/blocklist`, a restart goes back to this list.
+    #[serde(default)]
+    pub blocked_addresses: Vec<String>,
+    /// Allow admins to credit users with test funds out of thin air, refused in release builds
+    #[serde(default)]
+    pub faucet_enabled: bool,
+    /// The bank fiat deposits are confirmed with, fiat deposits are refused by default
+    #[serde(default)]
+    pub fiat_provider: crate::fiat::FiatProviderKind,
+    /// Maker and taker fees, makers may be paid a rebate of up to the taker fee
+    #[serde(default)]
+    pub fees: FeeSchedule,
+    /// `pretty` or `json` logs, pretty by default in debug builds and json in release builds
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Batch size and per-cycle cap of the deposits journalled when reconciling with bitcoind
+    #[serde(default)]
+    pub deposit_reconciliation: DepositReconciliation,
+    /// Run the bitcoin-grpc-proxy inside the fullstack process, bound to `bitcoin_grpc_bind_addr`
+    /// and stopped by the same shutdown as the webserver and trading engine
+    #[serde(default)]
+    pub fullstack_grpc_proxy: bool,
+    /// Retention and interval of moving closed orders out of the trading engine into the archive
+    #[serde(default)]
+    pub order_archival: OrderArchival,
+    /// Let makers opt their orders into a brief window to reject a match, off by default, see [`LastLook`]
+    #[serde(default)]
+    pub last_look: LastLook,
+    /// Reject new orders while the trading engine's queue is backed up, see [`LoadShedding`]
+    #[serde(default)]
+    pub load_shedding: LoadShedding,
+    /// Start without bitcoind and connect in the background, see [`BitcoindStartup`]
+    #[serde(default)]
+    pub bitcoind_startup: BitcoindStartup,
+    /// Reject orders whose `price * quantity` exceeds this, in the smallest unit of the quote
+    /// currency, at most what the ledger can hold, see [`crate::trading::notional`]
+    #[serde(default = "default_max_order_notional")]
+    pub max_order_notional: u64,
+    /// Switch to reduce-only while a book is one-sided, see [`ImbalanceGuard`]
+    #[serde(default)]
+    pub imbalance_guard: ImbalanceGuard,
+    /// Stop debug builds after a while, see [`AutomaticShutdown`]
+    #[serde(default)]
+    pub automatic_shutdown: AutomaticShutdown,
 }
 
 impl Configuration {
-    /// load directly from a string, should only be used for tests.
-    #[track_caller]
-    pub fn load_from_toml(st: &str) -> Self {
-        toml::from_str(st).expect("Failed to parse config file")
+    /// parse the configuration from a TOML string.
+    pub fn load_from_toml(st: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(st)?;
+        config.validate()?;
+        Ok(config)
     }
 
     /// read the TOML from the file at `path`
+    pub fn load_from_path(path: &Path) -> Result<Self, ConfigError> {
+        let st = path
+            .canonicalize()
+            .and_then(std::fs::read_to_string)
+            .map_err(|source| ConfigError::Io {
+                path: path.to_owned(),
+                source,
+            })?;
+
+        Self::load_from_toml(&st)
+    }
+
+    /// a configuration with placeholder values for the required settings, only built for tests.
+    #[cfg(test)]
     #[track_caller]
-    pub fn load_from_path(path: &Path) -> std::io::Result<Self> {
-        let config_file_path = path.canonicalize()?;
-        let st = std::fs::read_to_string(config_file_path)?;
-        Ok(toml::from_str(&st)
-            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?)
+    pub fn defaults_for_test() -> Self {
+        Self::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange_test"
+            bitcoin_rpc_url = "http://localhost:8332"
+            "#,
+        )
+        .expect("test configuration should always be valid")
+    }
+
+    /// check that settings without a usable default have been provided.
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.database_url.is_empty() {
+            return Err(ConfigError::MissingField {
+                field: "database_url",
+                env: DATABASE_URL,
+            });
+        }
+
+        if self.bitcoin_rpc_url.is_empty() {
+            return Err(ConfigError::MissingField {
+                field: "bitcoin_rpc_url",
+                env: BITCOIN_RPC_URL,
+            });
+        }
+
+        if self.webserver_tls && !self.session_cookie.secure {
+            return Err(ConfigError::Invalid {
+                field: "session_cookie.secure",
+                reason: "must be set when `webserver_tls` is enabled",
+            });
+        }
+
+        if self.session_cookie.same_site == CookieSameSite::None && !self.session_cookie.secure {
+            return Err(ConfigError::Invalid {
+                field: "session_cookie.secure",
+                reason: "must be set when `same_site` is \"none\"",
+            });
+        }
+
+        if self
+            .admin_signup_token
+            .as_deref()
+            .is_some_and(str::is_empty)
+        {
+            return Err(ConfigError::Invalid {
+                field: "admin_signup_token",
+                reason: "must not be empty, leave it unset to turn admin signups off",
+            });
+        }
+
+        if self.fees.taker_bps > 10_000 || self.fees.maker_bps > 10_000 {
+            return Err(ConfigError::Invalid {
+                field: "fees",
+                reason: "fees can not exceed 10000 bps",
+            });
+        }
+
+        if self.fees.maker_bps < 0 && self.fees.maker_bps.unsigned_abs() > self.fees.taker_bps {
+            return Err(ConfigError::Invalid {
+                field: "fees.maker_bps",
+                reason: "a maker rebate can not exceed the taker fee",
+            });
+        }
+
+        if self.deposit_reconciliation.max_per_cycle == 0 {
+            return Err(ConfigError::Invalid {
+                field: "deposit_reconciliation.max_per_cycle",
+                reason: "must be at least 1",
+            });
+        }
+
+        if self.json_amount_digits.max_integer_digits == 0 {
+            return Err(ConfigError::Invalid {
+                field: "json_amount_digits.max_integer_digits",
+                reason: "must be at least 1",
+            });
+        }
+
+        if self
+            .asset_json_amount_digits
+            .values()
+            .any(|digits| digits.max_integer_digits == 0)
+        {
+            return Err(ConfigError::Invalid {
+                field: "asset_json_amount_digits.max_integer_digits",
+                reason: "must be at least 1",
+            });
+        }
+
+        if self.order_archival.interval_secs == 0 {
+            return Err(ConfigError::Invalid {
+                field: "order_archival.interval_secs",
+                reason: "must be at least 1",
+            });
+        }
+
+        if self.last_look.window_ms == 0 || self.last_look.window_ms > MAX_LAST_LOOK_WINDOW_MS {
+            return Err(ConfigError::Invalid {
+                field: "last_look.window_ms",
+                reason: "must be between 1 and 250",
+            });
+        }
+
+        if self.last_look.total_ms < self.last_look.window_ms
+            || self.last_look.total_ms > MAX_LAST_LOOK_TOTAL_MS
+        {
+            return Err(ConfigError::Invalid {
+                field: "last_look.total_ms",
+                reason: "must be between `window_ms` and 1000",
+            });
+        }
+
+        let ImbalanceGuard {
+            levels,
+            trip_percent,
+            recover_percent,
+            interval_ms,
+            ..
+        } = self.imbalance_guard;
+        if levels == 0 || interval_ms == 0 {
+            return Err(ConfigError::Invalid {
+                field: "imbalance_guard",
+                reason: "`levels` and `interval_ms` must be at least 1",
+            });
+        }
+
+        if trip_percent == 0 || trip_percent > 100 || recover_percent >= trip_percent {
+            return Err(ConfigError::Invalid {
+                field: "imbalance_guard",
+                reason:
+                    "`recover_percent` must be below `trip_percent`, which is between 1 and 100",
+            });
+        }
+
+        if self.max_order_notional == 0
+            || self.max_order_notional > crate::trading::notional::MAX_NOTIONAL
+        {
+            return Err(ConfigError::Invalid {
+                field: "max_order_notional",
+                reason: "must be between 1 and 9223372036854775807",
+            });
+        }
+
+        let LoadShedding {
+            high_water_percent,
+            low_water_percent,
+            ..
+        } = self.load_shedding;
+        if high_water_percent > 100 || low_water_percent >= high_water_percent {
+            return Err(ConfigError::Invalid {
+                field: "load_shedding",
+                reason:
+                    "`low_water_percent` must be below `high_water_percent`, which is at most 100",
+            });
+        }
+
+        // a low water mark of zero commands is never gone below, shedding would never stop.
+        let low_water_mark = self.load_shedding.low_water_mark(self.te_channel_capacity);
+        let high_water_mark = self.load_shedding.high_water_mark(self.te_channel_capacity);
+        if self.load_shedding.enabled && (low_water_mark == 0 || low_water_mark >= high_water_mark)
+        {
+            return Err(ConfigError::Invalid {
+                field: "load_shedding",
+                reason:
+                    "each water mark must be at least one command, the low one below the high one",
+            });
+        }
+
+        if self.faucet_enabled && !cfg!(debug_assertions) {
+            return Err(ConfigError::Invalid {
+                field: "faucet_enabled",
+                reason: "the faucet is only available in debug builds",
+            });
+        }
+
+        if self.fiat_provider == crate::fiat::FiatProviderKind::Mock && !cfg!(debug_assertions) {
+            return Err(ConfigError::Invalid {
+                field: "fiat_provider",
+                reason: "the mock fiat provider is only available in debug builds",
+            });
+        }
+
+        Ok(())
     }
 
     /// A tuple of the user and password for bitcoin-rpc auth
@@ -193,6 +1194,34 @@ impl Configuration {
         (user, password)
     }
 
+    /// The order types accepted for `asset`, see [`Configuration::allowed_order_types`].
+    pub fn allowed_order_types(&self, asset: crate::Asset) -> &[crate::trading::OrderType] {
+        self.allowed_order_types
+            .get(&asset)
+            .map_or(crate::trading::OrderType::ALL.as_slice(), Vec::as_slice)
+    }
+
+    /// The digit limits of amount strings sent for `asset`, see [`Configuration::asset_json_amount_digits`].
+    pub fn json_amount_digits(&self, asset: Option<crate::Asset>) -> AmountDigits {
+        asset
+            .and_then(|asset| self.asset_json_amount_digits.get(&asset))
+            .copied()
+            .unwrap_or(self.json_amount_digits)
+    }
+
+    /// How takers are shared out among the orders at a price level of `asset`.
+    pub fn matching_policy(&self, asset: crate::Asset) -> crate::trading::MatchingPolicy {
+        self.matching_policy
+            .get(&asset)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// How long shutdown waits for tasks to finish before aborting them.
+    pub fn shutdown_deadline(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_deadline_secs)
+    }
+
     /// Get the path to the template directory for [`minijinja`] or "$CWD/templates/" if not set.
     pub fn jinja_template_dir(&self) -> PathBuf {
         self.jinja_template_dir
@@ -207,3 +1236,221 @@ impl Configuration {
             .unwrap_or_else(|| PathBuf::from(std::env::var(FE_WEB_DIR).unwrap()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_toml_is_an_error() {
+        let err = Configuration::load_from_toml("te_channel_capacity = [").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(_)), "{err:?}");
+        assert!(
+            err.to_string().starts_with("failed to parse config"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_wrong_type_is_an_error() {
+        let err = Configuration::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange"
+            bitcoin_rpc_url = "http://localhost:8332"
+            te_channel_capacity = "lots"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("te_channel_capacity"), "{err}");
+    }
+
+    #[test]
+    fn test_minimal_toml_parses() {
+        let config = Configuration::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange"
+            bitcoin_rpc_url = "http://localhost:8332"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.database_url, "postgres://localhost/exchange");
+        assert_eq!(config.te_channel_capacity, default_te_channel_capacity());
+    }
+
+    #[test]
+    fn test_insecure_session_cookie_with_tls_is_an_error() {
+        let err = Configuration::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange"
+            bitcoin_rpc_url = "http://localhost:8332"
+            webserver_tls = true
+
+            [session_cookie]
+            secure = false
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ConfigError::Invalid {
+                    field: "session_cookie.secure",
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_rebate_larger_than_taker_fee_is_an_error() {
+        let err = Configuration::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange"
+            bitcoin_rpc_url = "http://localhost:8332"
+
+            [fees]
+            maker_bps = -3
+            taker_bps = 2
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ConfigError::Invalid {
+                    field: "fees.maker_bps",
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_fee_token_currency_is_an_error() {
+        let err = Configuration::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange"
+            bitcoin_rpc_url = "http://localhost:8332"
+
+            [fees.currency]
+            Bitcoin = "token"
+            "#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(_)), "{err:?}");
+    }
+
+    #[test]
+    fn test_zero_max_order_notional_is_an_error() {
+        let err = Configuration::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange"
+            bitcoin_rpc_url = "http://localhost:8332"
+            max_order_notional = 0
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ConfigError::Invalid {
+                    field: "max_order_notional",
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_empty_admin_signup_token_is_an_error() {
+        let err = Configuration::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange"
+            bitcoin_rpc_url = "http://localhost:8332"
+            admin_signup_token = ""
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ConfigError::Invalid {
+                    field: "admin_signup_token",
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_load_shedding_water_marks_must_be_apart_and_nonzero() {
+        let load = |low_water_percent: u8| {
+            Configuration::load_from_toml(&format!(
+                r#"
+                database_url = "postgres://localhost/exchange"
+                bitcoin_rpc_url = "http://localhost:8332"
+                te_channel_capacity = 10
+
+                [load_shedding]
+                high_water_percent = 25
+                low_water_percent = {low_water_percent}
+                "#
+            ))
+        };
+
+        assert!(load(10).is_ok());
+
+        // 5% of 10 commands rounds down to none.
+        let err = load(5).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ConfigError::Invalid {
+                    field: "load_shedding",
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+
+        // 20% and 25% of 10 commands are both two commands.
+        assert!(load(20).is_err());
+    }
+
+    #[test]
+    fn test_json_amount_digits_are_per_asset() {
+        let config = Configuration::load_from_toml(
+            r#"
+            database_url = "postgres://localhost/exchange"
+            bitcoin_rpc_url = "http://localhost:8332"
+
+            [json_amount_digits]
+            max_integer_digits = 12
+
+            [asset_json_amount_digits.Bitcoin]
+            max_integer_digits = 8
+            max_fractional_digits = 2
+            "#,
+        )
+        .unwrap();
+
+        let bitcoin = config.json_amount_digits(Some(crate::Asset::Bitcoin));
+        assert_eq!(bitcoin.max_integer_digits, 8);
+        assert_eq!(bitcoin.max_fractional_digits, 2);
+        assert_eq!(
+            config.json_amount_digits(Some(crate::Asset::Ether)),
+            config.json_amount_digits
+        );
+        assert_eq!(config.json_amount_digits(None).max_integer_digits, 12);
+    }
+
+    #[test]
+    fn test_defaults_for_test() {
+        let _ = Configuration::defaults_for_test();
+    }
+}
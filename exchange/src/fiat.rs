@@ -0,0 +1,120 @@
+//! Fiat (USD) deposits.
+//!
+//! Users fund their USD balance with a bank transfer quoting a reference the exchange handed
+//! them. Once the money lands the transfer is credited with [`crate::app_cx::AppCx::credit_fiat_deposit`],
+//! which asks the configured [`FiatProvider`] what it received under that reference and journals a
+//! `FIAT.DEPOSIT` from the provider's `fiat` account into the user's account. From then on the
+//! funds are an ordinary entry in `account_tx_journal` and reserve and settle like any other.
+//!
+//! **Production integration point:** there is no real banking provider yet, only
+//! [`MockFiatProvider`] which is refused in release builds. A bank or payments processor is
+//! plugged in by implementing [`FiatProvider`] and constructing it in [`from_config`].
+//!
+//! Transfers to the mock provider are made up with `POST /api/admin/fiat/mock-transfers`.
+//!
+//! In development USD can also be handed out with the faucet, see `faucet_enabled`.
+
+use std::fmt::Debug;
+use std::num::NonZeroU64;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::Configuration;
+
+/// A transfer the provider received.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FiatDeposit {
+    /// the user the transfer was made for.
+    pub user_uuid: Uuid,
+    /// the ISO 4217 code of the transfer, only `USD` is credited.
+    pub currency: String,
+    /// the amount received in the smallest unit of the currency, e.g. cents.
+    #[serde(with = "crate::json_amount")]
+    pub amount: NonZeroU64,
+}
+
+/// Error returned by a [`FiatProvider`].
+#[derive(Debug, Error)]
+pub enum FiatProviderError {
+    /// the provider has not received a transfer with this reference.
+    #[error("no transfer with this reference")]
+    UnknownReference,
+    /// the provider could not be reached or failed to answer.
+    #[error("fiat provider error: {0}")]
+    Provider(String),
+}
+
+/// The bank or payments processor holding the exchange's fiat.
+pub trait FiatProvider: Send + Sync + Debug {
+    /// the `source_id` of the `fiat` account deposits are debited from.
+    fn name(&self) -> &'static str;
+
+    /// the transfer received under `reference`, if any.
+    fn confirm_deposit<'a>(
+        &'a self,
+        reference: &'a str,
+    ) -> BoxFuture<'a, Result<FiatDeposit, FiatProviderError>>;
+
+    /// the provider as a [`MockFiatProvider`], `None` for a real one.
+    fn as_mock(&self) -> Option<&MockFiatProvider> {
+        None
+    }
+}
+
+/// Which [`FiatProvider`] fiat deposits are confirmed with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FiatProviderKind {
+    /// fiat deposits are refused.
+    #[default]
+    Disabled,
+    /// an in-memory provider for development and tests, refused in release builds.
+    Mock,
+}
+
+/// the provider selected by `fiat_provider`, `None` if fiat deposits are disabled.
+pub fn from_config(config: &Configuration) -> Option<Arc<dyn FiatProvider>> {
+    match config.fiat_provider {
+        FiatProviderKind::Disabled => None,
+        FiatProviderKind::Mock => Some(Arc::new(MockFiatProvider::default())),
+    }
+}
+
+/// An in-memory [`FiatProvider`], transfers are made up with [`MockFiatProvider::receive`].
+#[derive(Debug, Default)]
+pub struct MockFiatProvider {
+    transfers: Mutex<ahash::AHashMap<String, FiatDeposit>>,
+}
+
+impl MockFiatProvider {
+    /// pretend a transfer of `deposit` arrived under `reference`.
+    pub fn receive(&self, reference: impl Into<String>, deposit: FiatDeposit) {
+        self.transfers
+            .lock()
+            .unwrap()
+            .insert(reference.into(), deposit);
+    }
+}
+
+impl FiatProvider for MockFiatProvider {
+    fn name(&self) -> &'static str {
+        "mock-bank"
+    }
+
+    fn confirm_deposit<'a>(
+        &'a self,
+        reference: &'a str,
+    ) -> BoxFuture<'a, Result<FiatDeposit, FiatProviderError>> {
+        let deposit = self.transfers.lock().unwrap().get(reference).cloned();
+        async move { deposit.ok_or(FiatProviderError::UnknownReference) }.boxed()
+    }
+
+    fn as_mock(&self) -> Option<&MockFiatProvider> {
+        Some(self)
+    }
+}
@@ -1,5 +1,5 @@
 //! Utilities for configuring the use of [`minijinja`]
-use minijinja::Environment;
+use minijinja::{AutoEscape, Environment};
 use minijinja_autoreload::AutoReloader;
 
 use crate::Configuration;
@@ -14,7 +14,39 @@ pub fn make_jinja_env(config: &Configuration) -> Jinja {
         tracing::warn!("JINJA TEMPALTE RELOAD");
         let mut env = Environment::new();
         env.set_loader(minijinja::path_loader(&path));
+        env.set_auto_escape_callback(auto_escape);
         notif.watch_path(&path, true);
         Ok(env)
     })
 }
+
+/// minijinja only looks at the final extension (`.jinja` here) when deciding
+/// whether to escape, so strip it first to catch our `*.html.jinja` templates.
+fn auto_escape(name: &str) -> AutoEscape {
+    let name = name.strip_suffix(".jinja").unwrap_or(name);
+    minijinja::default_auto_escape_callback(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_templates_escape_user_content() {
+        let mut env = Environment::new();
+        env.set_loader(minijinja::path_loader(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../frontend/templates"
+        )));
+        env.set_auto_escape_callback(auto_escape);
+
+        let html = env
+            .get_template("consumer/home.html.jinja")
+            .unwrap()
+            .render(minijinja::context! { user => minijinja::context! { name => "<script>alert(1)</script>" } })
+            .unwrap();
+
+        assert!(html.contains("Good morning, &lt;script&gt;alert(1)"));
+        assert!(!html.contains("<script>alert(1)</script>"));
+    }
+}
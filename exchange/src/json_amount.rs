@@ -0,0 +1,268 @@
+//! serde helpers for amounts (prices, quantities, notionals) sent over JSON.
+//!
+//! JSON numbers are read as doubles by most clients, so amounts above 2^53 lose
+//! precision silently. When [`Configuration::json_amounts_as_strings`](crate::Configuration::json_amounts_as_strings)
+//! is set amounts are written as strings instead, which is the common exchange
+//! convention. Either form is always accepted on input.
+//!
+//! Amount strings with more digits than [`Configuration::json_amount_digits`](crate::Configuration::json_amount_digits)
+//! allows for the asset of the request are rejected with a descriptive error before they are parsed.
+//!
+//! Use with `#[serde(with = "crate::json_amount")]`, or `crate::json_amount::option` for `Option` fields.
+//!
+//! The settings in force are those of the enclosing [`scope`], each request runs in one set from
+//! the configuration. Outside of a scope, e.g. in the trading engine, [`JsonAmounts::default`]
+//! applies, so what is written to the journal never depends on the webserver configuration.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::config::AmountDigits;
+
+/// How amounts are written and read within a [`scope`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonAmounts {
+    /// write amounts as JSON strings (`true`) or JSON numbers (`false`, the default).
+    pub as_strings: bool,
+    /// reject amount strings with more digits than this.
+    pub digits: AmountDigits,
+}
+
+tokio::task_local! {
+    static JSON_AMOUNTS: JsonAmounts;
+}
+
+/// run `f` with `amounts` in force for every amount it writes or reads.
+pub async fn scope<F: Future>(amounts: JsonAmounts, f: F) -> F::Output {
+    JSON_AMOUNTS.scope(amounts, f).await
+}
+
+/// like [`scope`] but for a synchronous `f`.
+pub fn sync_scope<R>(amounts: JsonAmounts, f: impl FnOnce() -> R) -> R {
+    JSON_AMOUNTS.sync_scope(amounts, f)
+}
+
+/// the settings of the enclosing [`scope`], the defaults outside of one.
+pub fn current() -> JsonAmounts {
+    JSON_AMOUNTS
+        .try_with(|amounts| *amounts)
+        .unwrap_or_default()
+}
+
+/// check the digits of an amount string against `limits` without converting it.
+fn check_digits(st: &str, limits: AmountDigits) -> Result<(), String> {
+    let (integer, fractional) = st.split_once('.').unwrap_or((st, ""));
+    let integer = integer.strip_prefix(['+', '-']).unwrap_or(integer);
+
+    if integer.len() > limits.max_integer_digits {
+        return Err(format!(
+            "amount has {} integer digits, at most {} are allowed",
+            integer.len(),
+            limits.max_integer_digits
+        ));
+    }
+
+    if fractional.len() > limits.max_fractional_digits {
+        return Err(format!(
+            "amount has {} fractional digits, at most {} are allowed",
+            fractional.len(),
+            limits.max_fractional_digits
+        ));
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrNumber<T> {
+    String(String),
+    Number(T),
+}
+
+impl<T> StringOrNumber<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    fn into_value<E: serde::de::Error>(self) -> Result<T, E> {
+        match self {
+            Self::String(st) => {
+                let st = st.trim();
+                check_digits(st, current().digits).map_err(E::custom)?;
+                st.parse().map_err(E::custom)
+            }
+            Self::Number(value) => Ok(value),
+        }
+    }
+}
+
+/// serialize an amount as a string or a number depending on [`JsonAmounts::as_strings`].
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Display + Serialize,
+    S: Serializer,
+{
+    if current().as_strings {
+        serializer.collect_str(value)
+    } else {
+        value.serialize(serializer)
+    }
+}
+
+/// deserialize an amount from either a string or a number.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr + Deserialize<'de>,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    StringOrNumber::deserialize(deserializer)?.into_value()
+}
+
+/// the same as the parent module but for `Option` amounts, `None` is always `null`.
+pub mod option {
+    use super::*;
+
+    /// serialize an optional amount as a string or a number depending on [`JsonAmounts::as_strings`].
+    pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Display + Serialize,
+        S: Serializer,
+    {
+        match value {
+            Some(value) => super::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// deserialize an optional amount from either a string, a number or `null`.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr + Deserialize<'de>,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<StringOrNumber<T>>::deserialize(deserializer)?
+            .map(StringOrNumber::into_value)
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Amounts {
+        #[serde(with = "crate::json_amount")]
+        quantity: u64,
+        #[serde(with = "crate::json_amount")]
+        price: NonZeroU32,
+        #[serde(with = "crate::json_amount::option")]
+        worst_price: Option<u32>,
+    }
+
+    #[test]
+    fn test_accepts_strings_and_numbers() {
+        let from_numbers: Amounts =
+            serde_json::from_str(r#"{"quantity": 10, "price": 5, "worst_price": null}"#).unwrap();
+        let from_strings: Amounts =
+            serde_json::from_str(r#"{"quantity": "10", "price": "5", "worst_price": null}"#)
+                .unwrap();
+
+        assert_eq!(from_numbers, from_strings);
+        assert!(
+            serde_json::from_str::<Amounts>(
+                r#"{"quantity": "10", "price": "0", "worst_price": null}"#
+            )
+            .is_err(),
+            "a zero price should still be rejected"
+        );
+    }
+
+    #[test]
+    fn test_rejects_over_precise_and_over_large_strings() {
+        let parse = |quantity: &str| {
+            serde_json::from_str::<Amounts>(&format!(
+                r#"{{"quantity": "{quantity}", "price": "5", "worst_price": null}}"#
+            ))
+            .unwrap_err()
+            .to_string()
+        };
+
+        let err = parse("1.125");
+        assert!(
+            err.contains("amount has 3 fractional digits, at most 0 are allowed"),
+            "{err}"
+        );
+
+        let err = parse(&"9".repeat(50));
+        assert!(
+            err.contains("amount has 50 integer digits, at most 20 are allowed"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_check_digits_uses_the_given_limits() {
+        let limits = AmountDigits {
+            max_integer_digits: 4,
+            max_fractional_digits: 2,
+        };
+
+        assert_eq!(check_digits("1234.56", limits), Ok(()));
+        assert_eq!(check_digits("-1234", limits), Ok(()));
+        assert!(check_digits("12345", limits).is_err());
+        assert!(check_digits("1.234", limits).is_err());
+    }
+
+    #[test]
+    fn test_settings_only_apply_within_their_scope() {
+        let amounts = JsonAmounts {
+            as_strings: true,
+            digits: AmountDigits {
+                max_integer_digits: 2,
+                max_fractional_digits: 0,
+            },
+        };
+        let parse = || {
+            serde_json::from_str::<Amounts>(
+                r#"{"quantity": "100", "price": "5", "worst_price": null}"#,
+            )
+        };
+
+        assert!(sync_scope(amounts, parse).is_err());
+        assert_eq!(sync_scope(amounts, current), amounts);
+
+        assert!(parse().is_ok());
+        assert_eq!(current(), JsonAmounts::default());
+    }
+
+    #[test]
+    fn test_large_quantity_round_trips_as_string() {
+        // 2^53 + 1 is the first integer a double can not represent.
+        let amounts = Amounts {
+            quantity: 9_007_199_254_740_993,
+            price: NonZeroU32::MAX,
+            worst_price: Some(u32::MAX),
+        };
+
+        let as_strings = JsonAmounts {
+            as_strings: true,
+            ..Default::default()
+        };
+        let json = sync_scope(as_strings, || serde_json::to_string(&amounts)).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"quantity":"9007199254740993","price":"4294967295","worst_price":"4294967295"}"#
+        );
+        assert_eq!(serde_json::from_str::<Amounts>(&json).unwrap(), amounts);
+    }
+}
@@ -12,6 +12,8 @@
 //! - [`bitcoin`] - the bitcoin rpc client
 //! - [`signal`] - the signal handler
 //! - [`config`] - the configuration
+//! - [`logging`] - the tracing subscriber
+//! - [`schema`] - the startup check of the database migrations
 //!
 //! The exchange can be started in fullstack mode using the `start_everything` function.
 //!
@@ -29,7 +31,11 @@ use tracing::Instrument;
 pub mod asset;
 pub mod bitcoin;
 pub mod config;
+pub mod fiat;
 pub mod jinja;
+pub mod json_amount;
+pub mod logging;
+pub mod schema;
 pub mod signal;
 pub mod test;
 pub mod trading;
@@ -50,14 +56,21 @@ pub enum StartFullstackError {
     /// Error returned by the database.
     #[error("database error")]
     Database(#[from] sqlx::Error),
+    /// The database schema does not match this build.
+    #[error("database schema: {0}")]
+    Schema(#[from] schema::SchemaError),
     /// Error returned by the bitcoin rpc client.
     #[error("bitcoin rpc error: {0}")]
     BitcoinRpc(tonic::transport::Error),
+    /// The in-process gRPC proxy could not bind its address.
+    #[error("grpc proxy error: {0}")]
+    GrpcProxy(std::io::Error),
     /// The exchange was interrupted.
     #[error("interrupted")]
     Interrupted,
 }
 
+mod shutdown;
 mod spawn_trading_engine;
 
 /// Starts the exchange in fullstack mode i.e. all components are ran.
@@ -65,29 +78,10 @@ pub fn start_fullstack(
     config: config::Configuration,
     signals: signal::Signals,
 ) -> impl Future<Output = Result<(), StartFullstackError>> {
-    /// create a future that, depending on the build profile, will either:
-    ///
-    /// - wait for 5 minutes and then resolve (debug)
-    /// - never resolve (release)
-    ///
-    /// This has no real purpose, I just have a habit of forgetting to stop
-    /// exchange when I'm done developing and I don't want to leave it running
-    /// overnight on my laptop.
-    ///
-    fn automatic_shutdown() -> impl std::future::Future<Output = ()> {
-        #[cfg(debug_assertions)]
-        return {
-            const AUTOMATIC_SHUTDOWN_AFTER_DUR: std::time::Duration =
-                std::time::Duration::from_secs(300); // 5 minutes
-
-            tokio::time::sleep(AUTOMATIC_SHUTDOWN_AFTER_DUR)
-        };
-
-        #[cfg(not(debug_assertions))]
-        return std::future::pending();
-    }
-
     async move {
+        // registered up front so a signal during startup still stops everything below.
+        let shutdown_signal = signals.shutdown();
+
         tracing::debug!(
             config = ?config,
             "starting exchange in fullstack mode"
@@ -101,65 +95,286 @@ pub fn start_fullstack(
             .connect(&config.database_url)
             .await?;
 
-        tracing::info!("preparing trading engine");
+        let automatic_shutdown = automatic_shutdown(config.automatic_shutdown);
+        run_fullstack(config, signals, db, shutdown_signal, automatic_shutdown).await
+    }
+}
+
+/// create a future that, depending on the build profile, will either:
+///
+/// - wait for `automatic_shutdown.after_secs` and then resolve, unless disabled (debug)
+/// - never resolve (release)
+///
+/// This has no real purpose, I just have a habit of forgetting to stop
+/// exchange when I'm done developing and I don't want to leave it running
+/// overnight on my laptop.
+///
+fn automatic_shutdown(
+    automatic_shutdown: config::AutomaticShutdown,
+) -> impl std::future::Future<Output = ()> {
+    #[cfg(debug_assertions)]
+    return async move {
+        if automatic_shutdown.enabled {
+            tokio::time::sleep(automatic_shutdown.after()).await
+        } else {
+            std::future::pending().await
+        }
+    };
+
+    #[cfg(not(debug_assertions))]
+    return {
+        let _ = automatic_shutdown;
+        std::future::pending()
+    };
+}
+
+/// everything [`start_fullstack`] runs once connected to the database, until `shutdown_signal`
+/// or `automatic_shutdown` resolves.
+async fn run_fullstack(
+    mut config: config::Configuration,
+    signals: signal::Signals,
+    db: sqlx::PgPool,
+    shutdown_signal: impl Future<Output = Result<signal::ShutdownSignal, ()>>,
+    automatic_shutdown: impl Future<Output = ()>,
+) -> Result<(), StartFullstackError> {
+    schema::ensure_migrated(&db, config.auto_migrate).await?;
+
+    let (grpc_proxy_tx, mut grpc_proxy_handle) = if config.fullstack_grpc_proxy {
+        let listener = tokio::net::TcpListener::bind(config.bitcoin_grpc_bind_addr)
+            .await
+            .map_err(StartFullstackError::GrpcProxy)?;
+        let addr = listener
+            .local_addr()
+            .map_err(StartFullstackError::GrpcProxy)?;
+
+        tracing::info!(%addr, "starting grpc proxy");
 
+        // the proxy is ours, connect to where it is actually listening.
+        config.bitcoin_grpc_endpoint = format!("http://{addr}")
+            .parse()
+            .expect("a socket address is a valid endpoint");
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(bitcoin::serve_grpc_proxy(
+            listener,
+            config.clone(),
+            signals,
+            async move {
+                let _ = rx.await;
+            },
+        ));
+
+        (Some(tx), Some(handle))
+    } else {
+        (None, None)
+    };
+
+    tracing::info!("preparing trading engine");
+
+    let bitcoind_span = tracing::info_span!(
+        "bitcoind_rpc_client",
+        rpcurl = ?config.bitcoin_rpc_url,
+        wallet = ?config.bitcoin_wallet_name,
+    );
+
+    // without bitcoind only deposits and withdrawals are unavailable, trading can go ahead.
+    let (btc_rpc, bitcoind_connection) = if config.bitcoind_startup.required {
         let btc_rpc = bitcoin::connect_bitcoin_rpc(&config)
-            .instrument(tracing::info_span!(
-                "bitcoind_rpc_client",
-                rpcurl = ?config.bitcoin_rpc_url,
-                wallet = ?config.bitcoin_wallet_name,
-            ))
+            .instrument(bitcoind_span.clone())
             .await
             .map_err(|err| StartFullstackError::BitcoinRpc(err))?;
+        (btc_rpc, None)
+    } else {
+        let (btc_rpc, connection) = bitcoin::BitcoinRpcClient::new_deferred();
+        (btc_rpc, Some(connection))
+    };
+
+    let (te_tx, mut te_handle) = spawn_trading_engine::spawn_trading_engine(&config, db.clone())
+        .init_from_db(db.clone())
+        .await?;
 
-        let (te_tx, mut te_handle) =
-            spawn_trading_engine::spawn_trading_engine(&config, db.clone())
-                .init_from_db(db.clone())
-                .await?;
+    let state = AppCx::new(
+        te_tx.clone(),
+        btc_rpc,
+        db,
+        crate::jinja::make_jinja_env(&config),
+        config.clone(),
+    );
 
-        let state = AppCx::new(
-            te_tx.clone(),
-            btc_rpc,
-            db,
-            crate::jinja::make_jinja_env(&config),
+    state.track_trading_engine().await;
+
+    let bitcoind_reconnect = bitcoind_connection.map(|connection| {
+        let retry_interval = config.bitcoind_startup.retry_interval();
+        let task = state
+            .tasks()
+            .register("bitcoind reconnect", retry_interval * 2);
+        let reconnect = bitcoin::connect_bitcoin_rpc_in_background(
             config.clone(),
+            connection,
+            retry_interval,
+            task,
         );
+        tokio::spawn(reconnect.instrument(bitcoind_span))
+    });
 
-        tracing::info!("launching webserver and waiting for stop signal");
-
-        let res = tokio::select! {
-            res = web::serve(config.webserver_bind_addr, state) => res.map_err(StartFullstackError::Webserver),
-            res = &mut te_handle => match res {
-                Ok(()) => {
-                    tracing::info!("trading engine shutdown");
-                    Ok(())
-                },
-                Err(err) => {
-                    tracing::error!(?err, "trading engine panicked");
-                    Err(StartFullstackError::Interrupted)
-                }
-            },
-            () = automatic_shutdown() => {
-                tracing::info!("auto-shutdown triggered");
+    state.reconcile_admin_roles().await?;
+
+    tracing::info!("launching webserver and waiting for stop signal");
+
+    let order_archival = state.clone();
+    let imbalance_guard = state.clone();
+    let settlement = state.clone();
+
+    let res = tokio::select! {
+        res = web::serve(config.webserver_bind_addr, state) => res.map_err(StartFullstackError::Webserver),
+        res = &mut te_handle => match res {
+            Ok(()) => {
+                tracing::info!("trading engine shutdown");
                 Ok(())
             },
-            _ = signals.ctrl_c() => {
-                tracing::info!("SIGINT received");
+            Err(err) => {
+                tracing::error!(?err, "trading engine panicked");
                 Err(StartFullstackError::Interrupted)
-            },
-        };
+            }
+        },
+        res = async { grpc_proxy_handle.as_mut().unwrap().await }, if grpc_proxy_handle.is_some() => {
+            tracing::error!(?res, "grpc proxy stopped");
+            Err(StartFullstackError::Interrupted)
+        },
+        () = order_archival.run_order_archival() => {
+            tracing::error!("order archival stopped");
+            Err(StartFullstackError::Interrupted)
+        },
+        () = settlement.run_settlement() => {
+            tracing::error!("settlement stopped");
+            Err(StartFullstackError::Interrupted)
+        },
+        () = imbalance_guard.run_imbalance_guard(), if config.imbalance_guard.enabled => {
+            tracing::error!("imbalance guard stopped");
+            Err(StartFullstackError::Interrupted)
+        },
+        () = automatic_shutdown => {
+            tracing::info!("auto-shutdown triggered");
+            Ok(())
+        },
+        signal = shutdown_signal => {
+            tracing::info!(?signal, "shutdown signal received");
+            Err(StartFullstackError::Interrupted)
+        },
+    };
+
+    // attempt to shutdown gracefully
+    tracing::info!("shutting down gracefully");
+
+    if let Some(reconnect) = bitcoind_reconnect {
+        reconnect.abort();
+    }
 
-        // attempt to shutdown gracefully
-        tracing::info!("shutting down gracefully");
+    if !te_handle.is_finished() {
+        let _ = te_tx.send(trading::TradingEngineCmd::Shutdown).await;
 
-        if !te_handle.is_finished() {
-            let _ = te_tx.send(trading::TradingEngineCmd::Shutdown).await;
+        match shutdown::join_or_abort("trading engine", te_handle, config.shutdown_deadline()).await
+        {
+            Some(Ok(())) => {}
+            Some(Err(err)) => tracing::error!(?err, "trading engine shutdown panicked"),
+            None => tracing::warn!("trading engine was aborted, it did not shut down in time"),
+        }
+    }
 
-            if let Err(err) = te_handle.await {
-                tracing::error!(?err, "trading engine shutdown panicked");
+    if let (Some(tx), Some(handle)) = (grpc_proxy_tx, grpc_proxy_handle) {
+        let _ = tx.send(());
+
+        if !handle.is_finished() {
+            match shutdown::join_or_abort("grpc proxy", handle, config.shutdown_deadline()).await {
+                Some(Ok(Ok(()))) => tracing::info!("grpc proxy shutdown"),
+                Some(Ok(Err(err))) => tracing::error!(?err, "grpc proxy error"),
+                Some(Err(err)) => tracing::error!(?err, "grpc proxy shutdown panicked"),
+                None => tracing::warn!("grpc proxy was aborted, it did not shut down in time"),
             }
         }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn free_local_addr() -> std::net::SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_automatic_shutdown_can_be_disabled() {
+        let disabled = config::AutomaticShutdown {
+            enabled: false,
+            after_secs: 0,
+        };
+        let res = tokio::time::timeout(Duration::from_millis(50), automatic_shutdown(disabled));
+        assert!(res.await.is_err(), "a disabled automatic shutdown resolved");
 
-        res
+        let enabled = config::AutomaticShutdown {
+            enabled: true,
+            after_secs: 0,
+        };
+        let res = tokio::time::timeout(Duration::from_millis(50), automatic_shutdown(enabled));
+        assert_eq!(res.await.is_ok(), cfg!(debug_assertions));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_one_signal_stops_webserver_engine_and_grpc_proxy(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.webserver_bind_addr = free_local_addr();
+        config.bitcoin_grpc_bind_addr = free_local_addr();
+        config.fullstack_grpc_proxy = true;
+        config.shutdown_deadline_secs = 2;
+
+        let grpc_addr = config.bitcoin_grpc_bind_addr;
+        let deadline = config.shutdown_deadline();
+        let signals = signal::from_host_os();
+
+        // stands in for SIGTERM, a real signal would reach every test in the process.
+        let (terminate_tx, terminate_rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown_signal = async move {
+            terminate_rx
+                .await
+                .map(|()| signal::ShutdownSignal::Terminate)
+                .map_err(drop)
+        };
+
+        let fullstack = tokio::spawn(async move {
+            run_fullstack(config, signals, db, shutdown_signal, std::future::pending()).await
+        });
+
+        // wait for the proxy to come up, it is the first component started.
+        let started = tokio::time::timeout(Duration::from_secs(5), async {
+            while tokio::net::TcpStream::connect(grpc_addr).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await;
+        assert!(started.is_ok(), "grpc proxy never started listening");
+
+        terminate_tx.send(()).unwrap();
+
+        // the engine and the proxy each get the deadline, anything longer is a hang.
+        let res = tokio::time::timeout(deadline * 2 + Duration::from_secs(1), fullstack)
+            .await
+            .expect("fullstack did not stop within the shutdown deadline")
+            .unwrap();
+
+        assert!(
+            matches!(res, Err(StartFullstackError::Interrupted)),
+            "{res:?}"
+        );
+        assert!(
+            tokio::net::TcpStream::connect(grpc_addr).await.is_err(),
+            "grpc proxy still listening after shutdown"
+        );
     }
 }
@@ -0,0 +1,107 @@
+//! Setup of the [`tracing`] subscriber.
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LogFormat;
+
+/// build a subscriber that writes `format` lines to `writer`.
+///
+/// In [`LogFormat::Json`] the event's fields are top level keys and the fields of the current
+/// span, such as the `request_id` and `user_id` of a request, are under `span`.
+pub fn subscriber<W>(
+    format: LogFormat,
+    writer: W,
+    filter: EnvFilter,
+) -> Box<dyn tracing::Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_file(true)
+        .with_thread_ids(true)
+        .with_line_number(true)
+        .with_env_filter(filter);
+
+    match format {
+        LogFormat::Pretty => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(
+            builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .finish(),
+        ),
+    }
+}
+
+/// install a subscriber writing `format` lines to stdout, filtered by `RUST_LOG`.
+pub fn init(format: LogFormat) {
+    tracing::subscriber::set_global_default(subscriber(
+        format,
+        std::io::stdout,
+        EnvFilter::from_default_env(),
+    ))
+    .expect("a global tracing subscriber was already set");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_carry_request_and_user_ids() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = subscriber(
+            LogFormat::Json,
+            move || writer.clone(),
+            EnvFilter::new("info"),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = axum::http::Request::builder()
+                .uri("/api/order")
+                .header("x-request-id", "test-request-id")
+                .body(())
+                .unwrap();
+
+            let span = crate::web::make_request_span(&request);
+            let _enter = span.enter();
+            span.record("user_id", "test-user-id");
+
+            tracing::info!(order_uuid = "test-order-uuid", "order placed");
+        });
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let lines = logs
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 1, "{logs}");
+        let line = &lines[0];
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "order placed");
+        assert_eq!(line["order_uuid"], "test-order-uuid");
+        assert_eq!(line["span"]["request_id"], "test-request-id");
+        assert_eq!(line["span"]["user_id"], "test-user-id");
+    }
+}
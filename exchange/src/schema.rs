@@ -0,0 +1,113 @@
+//! Check the database schema at startup, or bring it up to date.
+//!
+//! Running against a database that is missing migrations otherwise fails deep inside the first
+//! query that touches a missing table or column. [`ensure_migrated`] fails fast instead, or
+//! applies the pending migrations when `auto_migrate` is set.
+
+use sqlx::migrate::{Migrate as _, MigrateError, Migrator};
+use sqlx::PgPool;
+use thiserror::Error;
+
+/// The migrations this build expects, embedded from the `migrations/` directory.
+pub static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+/// The database schema does not match the migrations of this build.
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    /// a migration has not been applied.
+    #[error(
+        "the database schema is behind, migration {version} ({description}) has not been applied. \
+        apply the migrations or set `auto_migrate`"
+    )]
+    Behind {
+        /// the version of the first missing migration.
+        version: i64,
+        /// the description of the first missing migration.
+        description: String,
+    },
+    /// a migration was applied with different contents than this build has.
+    #[error("migration {version} was changed after it was applied to the database")]
+    Modified {
+        /// the version of the changed migration.
+        version: i64,
+    },
+    /// a migration failed part way through.
+    #[error("migration {version} was only partially applied, the database needs fixing by hand")]
+    Dirty {
+        /// the version of the partially applied migration.
+        version: i64,
+    },
+    /// reading or applying the migrations failed.
+    #[error("migrate: {0}")]
+    Migrate(#[from] MigrateError),
+}
+
+/// apply the pending migrations when `auto_migrate` is set, otherwise check none are pending.
+///
+/// Both are idempotent, an up-to-date database is left as it is.
+pub async fn ensure_migrated(db: &PgPool, auto_migrate: bool) -> Result<(), SchemaError> {
+    if auto_migrate {
+        tracing::info!("applying pending database migrations");
+        MIGRATOR.run(db).await?;
+        return Ok(());
+    }
+
+    check_migrated(db).await
+}
+
+/// check every migration of this build has been applied, unchanged.
+pub async fn check_migrated(db: &PgPool) -> Result<(), SchemaError> {
+    let mut conn = db.acquire().await.map_err(MigrateError::from)?;
+
+    // creates the empty bookkeeping table of a fresh database, the schema itself is untouched.
+    conn.ensure_migrations_table().await?;
+
+    if let Some(version) = conn.dirty_version().await? {
+        return Err(SchemaError::Dirty { version });
+    }
+
+    let applied = conn.list_applied_migrations().await?;
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+    {
+        match applied.iter().find(|a| a.version == migration.version) {
+            Some(a) if a.checksum != migration.checksum => {
+                return Err(SchemaError::Modified {
+                    version: migration.version,
+                });
+            }
+            Some(_) => {}
+            None => {
+                return Err(SchemaError::Behind {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = false)]
+    async fn test_startup_migrates_a_fresh_database(db: PgPool) {
+        let err = ensure_migrated(&db, false).await.unwrap_err();
+        assert!(
+            matches!(err, SchemaError::Behind { version: 0, .. }),
+            "{err:?}"
+        );
+
+        ensure_migrated(&db, true).await.unwrap();
+        ensure_migrated(&db, false).await.unwrap();
+
+        // applying again is a no-op.
+        ensure_migrated(&db, true).await.unwrap();
+        check_migrated(&db).await.unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+//! Bounded waits for tasks during shutdown.
+//!
+//! A wedged task must not keep the process alive forever, orchestrators will
+//! escalate to SIGKILL and we lose the chance to log what was stuck.
+
+use std::time::Duration;
+
+use tokio::task::{JoinError, JoinHandle};
+
+/// Wait up to `deadline` for the task `name` to finish, aborting it if it doesn't.
+///
+/// Returns `None` if the task was aborted. The aborted task is not waited on, a
+/// task that never yields can not observe the abort.
+pub(crate) async fn join_or_abort<T>(
+    name: &'static str,
+    mut handle: JoinHandle<T>,
+    deadline: Duration,
+) -> Option<Result<T, JoinError>> {
+    match tokio::time::timeout(deadline, &mut handle).await {
+        Ok(res) => Some(res),
+        Err(_elapsed) => {
+            tracing::warn!(
+                task = name,
+                ?deadline,
+                "task did not finish before the shutdown deadline, aborting"
+            );
+            handle.abort();
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wedged_task_is_aborted_at_deadline() {
+        let deadline = Duration::from_millis(50);
+        let engine = tokio::spawn(std::future::pending::<()>());
+        let started = std::time::Instant::now();
+
+        let res = join_or_abort("trading engine", engine, deadline).await;
+
+        assert!(
+            res.is_none(),
+            "a task that never finishes should be aborted"
+        );
+        assert!(started.elapsed() >= deadline);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_finished_task_is_joined() {
+        let task = tokio::spawn(async { 42 });
+
+        let res = join_or_abort("task", task, Duration::from_secs(5)).await;
+
+        assert_eq!(res.unwrap().unwrap(), 42);
+    }
+}
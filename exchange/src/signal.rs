@@ -16,11 +16,22 @@ enum SignalSource {
     Tokio,
 }
 
+/// The signal that asked the application to shut down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownSignal {
+    /// SIGINT, usually an interactive ctrl-c.
+    Interrupt,
+    /// SIGTERM, sent by orchestrators such as k8s and systemd.
+    Terminate,
+}
+
 /// A abstraction for signal handling.
 #[derive(Debug, Clone, Copy)]
 pub struct Signals {
     /// SIGINT signal source.
     ctrl_c: SignalSource,
+    /// SIGTERM signal source.
+    terminate: SignalSource,
 }
 
 impl Signals {
@@ -43,11 +54,74 @@ impl Signals {
             }
         }
     }
+
+    /// Returns a future that resolves when a SIGTERM is received.
+    ///
+    /// There is no SIGTERM outside of unix, the future never resolves there.
+    #[track_caller]
+    #[must_use]
+    pub fn terminate(&self) -> impl Future<Output = Result<(), ()>> + '_ {
+        match self.terminate {
+            #[cfg(unix)]
+            SignalSource::Tokio => {
+                let mut signal =
+                    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                        .expect("failed to register signal handler");
+
+                futures::future::Either::Left(async move {
+                    match signal.recv().await {
+                        Some(()) => Ok(()),
+                        None => Err(()),
+                    }
+                })
+            }
+            #[cfg(not(unix))]
+            SignalSource::Tokio => futures::future::Either::Right(std::future::pending()),
+        }
+    }
+
+    /// Returns a future that resolves with whichever of SIGINT or SIGTERM is received first.
+    ///
+    /// Both handlers are registered before this returns, not when the future is first polled.
+    #[track_caller]
+    #[must_use]
+    pub fn shutdown(&self) -> impl Future<Output = Result<ShutdownSignal, ()>> + '_ {
+        first_of(self.ctrl_c(), self.terminate())
+    }
+}
+
+/// resolves with the [`ShutdownSignal`] of whichever of `ctrl_c` or `terminate` resolves first.
+async fn first_of(
+    ctrl_c: impl Future<Output = Result<(), ()>>,
+    terminate: impl Future<Output = Result<(), ()>>,
+) -> Result<ShutdownSignal, ()> {
+    tokio::select! {
+        res = ctrl_c => res.map(|()| ShutdownSignal::Interrupt),
+        res = terminate => res.map(|()| ShutdownSignal::Terminate),
+    }
 }
 
 /// Returns a [`Signals`] instance that uses the host OS's signal handling.
 pub fn from_host_os() -> Signals {
     Signals {
         ctrl_c: SignalSource::Tokio,
+        terminate: SignalSource::Tokio,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{pending, ready};
+
+    use super::*;
+
+    // the signals are stood in for, a real one would reach every test in the process.
+    #[tokio::test]
+    async fn test_sigterm_resolves_shutdown_like_sigint() {
+        let terminate = first_of(pending(), ready(Ok(()))).await;
+        assert_eq!(terminate, Ok(ShutdownSignal::Terminate));
+
+        let interrupt = first_of(ready(Ok(())), pending()).await;
+        assert_eq!(interrupt, Ok(ShutdownSignal::Interrupt));
     }
 }
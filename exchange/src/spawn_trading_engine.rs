@@ -1,8 +1,15 @@
 use futures::StreamExt;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
+use tracing::Instrument as _;
 
 use crate::trading::{self, TradeCmd};
-use crate::{Asset, Configuration};
+use crate::Configuration;
+
+/// how many [`trading::MatchEvent`]s a subscriber may fall behind by before it misses some.
+const MATCH_EVENTS_CAPACITY: usize = 4096;
+
+/// how often the engine looks for good-til-date orders that have expired.
+const EXPIRY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
 
 pub struct SpawnTradingEngine {
     pub input: trading::TradingEngineTx,
@@ -33,28 +40,152 @@ impl SpawnTradingEngine {
     }
 }
 
+/// replay the journal entries after `after` into `assets`, returns the id of the last one replayed,
+/// or `after` if there were none.
+///
+/// the rows are streamed, so replaying the whole journal does not hold all of it in memory.
+pub(crate) async fn replay_journal(
+    assets: &mut trading::Assets,
+    db: &sqlx::PgPool,
+    after: i64,
+) -> Result<i64, trading::TradingEngineError> {
+    let mut stream = sqlx::query!(
+        "SELECT id, jstr FROM trading_event_source WHERE id > $1 ORDER BY id",
+        after
+    )
+    .fetch(db);
+
+    let mut last = after;
+    while let Some(row) = stream.next().await {
+        let row = row?;
+        let payload = serde_json::from_value(row.jstr)
+            .map_err(|_| trading::TradingEngineError::JournalUnreadable(row.id))?;
+        trading::do_replay(assets, payload);
+        last = row.id;
+    }
+
+    Ok(last)
+}
+
+/// journal a trade command along with the settlements it left, in one transaction so a command is
+/// never replayed without the ledger moves it made, nor the other way around.
+async fn journal(
+    db: &sqlx::PgPool,
+    jstr: serde_json::Value,
+    settlements: &[trading::Settlement],
+) -> Result<(), sqlx::Error> {
+    let mut dtx = db.begin().await?;
+
+    sqlx::query!("INSERT INTO trading_event_source (jstr) VALUES ($1)", jstr)
+        .execute(&mut *dtx)
+        .await?;
+    insert_settlements(&mut dtx, settlements).await?;
+
+    dtx.commit().await
+}
+
+/// queue `settlements` to be settled, in the order they are given.
+async fn insert_settlements(
+    dtx: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+    settlements: &[trading::Settlement],
+) -> Result<(), sqlx::Error> {
+    if settlements.is_empty() {
+        return Ok(());
+    }
+
+    let jstrs = settlements
+        .iter()
+        .map(|settlement| serde_json::to_value(settlement).expect("settlements serialize"))
+        .collect::<Vec<_>>();
+
+    sqlx::query!(
+        r#"INSERT INTO trade_settlements (jstr)
+        SELECT t.jstr FROM UNNEST($1::jsonb[]) WITH ORDINALITY AS t(jstr, n)
+        ORDER BY t.n"#,
+        &jstrs
+    )
+    .execute(&mut **dtx)
+    .await?;
+
+    Ok(())
+}
+
+/// release the reserve of an order the engine drops without processing, it is never journalled.
+async fn release_unprocessed(db: &sqlx::PgPool, cmd: &trading::TradingEngineCmd) {
+    let trading::TradingEngineCmd::Trade(TradeCmd::PlaceOrder((place_order, _)), ..) = cmd else {
+        return;
+    };
+
+    let release = trading::Settlement::release_rejected(place_order);
+    let res = async {
+        let mut dtx = db.begin().await?;
+        insert_settlements(&mut dtx, &[release]).await?;
+        dtx.commit().await
+    }
+    .await;
+
+    if let Err(err) = res {
+        tracing::error!(
+            ?err,
+            ?release,
+            "failed to release the reserve of a dropped order"
+        );
+    }
+}
+
 pub fn spawn_trading_engine(config: &Configuration, db: sqlx::PgPool) -> SpawnTradingEngine {
     use trading::TradingEngineCmd as T;
 
-    async fn trading_engine_supervisor(mut rx: mpsc::Receiver<T>, db: sqlx::PgPool) {
-        use trading::{AssetBook, Assets, TradeCmdPayload as P};
+    async fn trading_engine_supervisor(
+        mut rx: mpsc::Receiver<T>,
+        db: sqlx::PgPool,
+        config: Configuration,
+    ) {
+        let mut assets = trading::Assets::from_config(&config);
+        let last_look_window = config.last_look.window();
+        let last_look_total = config.last_look.total();
+
+        let (match_events, _) = broadcast::channel(MATCH_EVENTS_CAPACITY);
+        let (execution_reports, _) = broadcast::channel(MATCH_EVENTS_CAPACITY);
+        let (market_data, _) = broadcast::channel(MATCH_EVENTS_CAPACITY);
 
-        let mut assets = Assets {
-            order_uuids: Default::default(),
-            eth: AssetBook::new(Asset::Ether),
-            btc: AssetBook::new(Asset::Bitcoin),
+        // hand the events of the last command to the subscribers, if there are any.
+        let publish = |assets: &mut trading::Assets| {
+            let events = assets.drain_match_events().collect::<Vec<_>>();
+
+            // the reports and the feed are only worked out for someone listening to them.
+            if execution_reports.receiver_count() > 0 {
+                for report in assets.execution_reports(&events) {
+                    let _ = execution_reports.send(report);
+                }
+            }
+            if market_data.receiver_count() > 0 {
+                for frame in assets.market_data(&events) {
+                    let _ = market_data.send(frame);
+                }
+            }
+
+            for event in events {
+                let _ = match_events.send(event);
+            }
         };
 
         macro_rules! try_event_log {
             ($input:expr, $e:expr) => {
+                try_event_log!($input, $e, None)
+            };
+            // `$rejected` is settled instead if the command fails, the reserve of a rejected order.
+            ($input:expr, $e:expr, $rejected:expr) => {
                 if let Ok(jstr) = ::serde_json::to_value(&$input) {
                     let res: Result<_, trading::TradingEngineError> = $e;
 
-                    match sqlx::query!("INSERT INTO trading_event_source (jstr) VALUES ($1)", jstr)
-                        .execute(&db)
-                        .await
-                    {
-                        Ok(_) => res,
+                    let mut settlements = assets.settlements(assets.match_events());
+                    if res.is_err() {
+                        settlements.extend($rejected);
+                    }
+
+                    match journal(&db, jstr, &settlements).await {
+                        Ok(()) => res,
                         Err(e) => Err(trading::TradingEngineError::Database(e)),
                     }
                 } else {
@@ -62,12 +193,74 @@ pub fn spawn_trading_engine(config: &Configuration, db: sqlx::PgPool) -> SpawnTr
                 }
             };
         }
+        let mut last_look_desks = trading::LastLookDesks::default();
         let mut running = true;
-        while let Some(cmd) = rx.recv().await {
+        let mut commands_processed: u64 = 0;
+        let mut last_command_latency = None;
+        let mut liveness: Option<crate::app_cx::TaskHandle> = None;
+
+        // cancel the good-til-date orders that have expired by now, journalled like any other cancel.
+        macro_rules! cancel_expired {
+            () => {
+                let now = chrono::Utc::now().timestamp_millis();
+                for cancel_order in trading::do_poll_expired(&mut assets, now) {
+                    tracing::info!(?cancel_order, "cancelling expired good-til-date order");
+                    if let Err(err) = try_event_log!(
+                        cancel_order,
+                        trading::do_cancel_order(&mut assets, cancel_order)
+                    ) {
+                        tracing::warn!(?err, "failed to cancel expired order");
+                    }
+                    // each cancel journals the settlements of the events since the last publish.
+                    publish(&mut assets);
+                }
+            };
+        }
+
+        let mut expiry_poll = tokio::time::interval(EXPIRY_POLL_INTERVAL);
+        expiry_poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            // commands go first, expiries are only polled for here while there are none waiting.
+            let cmd = tokio::select! {
+                biased;
+                cmd = rx.recv() => match cmd {
+                    Some(cmd) => cmd,
+                    None => break,
+                },
+                _ = expiry_poll.tick() => {
+                    if let Some(task) = &liveness {
+                        task.heartbeat();
+                    }
+                    if running {
+                        cancel_expired!();
+                    }
+                    continue;
+                }
+            };
+
             if !running {
+                release_unprocessed(&db, &cmd).await;
                 continue;
             }
 
+            // the caller already gave up on it, doing the work now would only leave side effects.
+            if cmd.is_past_deadline(tokio::time::Instant::now()) {
+                tracing::warn!("skipping a trade command past its deadline");
+                release_unprocessed(&db, &cmd).await;
+                cmd.consume_respond_with_error(trading::TradingEngineError::DeadlineExceeded);
+                continue;
+            }
+
+            // a steady stream of commands starves the poll above, an expired order must still
+            // be gone before the next trade can match it.
+            if matches!(cmd, T::Trade(..)) {
+                cancel_expired!();
+            }
+
+            let started = std::time::Instant::now();
+            let is_trade = matches!(cmd, T::Trade(..) | T::Bootstrap(_));
+
             match cmd {
                 T::Suspend => {
                     running = false;
@@ -76,36 +269,154 @@ pub fn spawn_trading_engine(config: &Configuration, db: sqlx::PgPool) -> SpawnTr
                     running = true;
                 }
                 T::Shutdown => break,
-                T::Trade(TradeCmd::PlaceOrder((place_order, response))) => {
-                    let t = try_event_log!(
-                        place_order,
-                        trading::do_place_order(&mut assets, place_order)
-                    );
+                T::TrackLiveness(task) => {
+                    task.heartbeat();
+                    liveness = Some(task);
+                }
+                T::Trade(TradeCmd::PlaceOrder((mut place_order, response)), span, _) => {
+                    let t = async {
+                        tracing::info!("processing place order");
+                        // logged with the order, so a replay matches it the same way.
+                        assets.record_matching_policy(&mut place_order);
+                        // ask first so the rejections are logged along with the order.
+                        if let Some(window) = last_look_window {
+                            last_look_desks
+                                .review(&mut assets, &mut place_order, window, last_look_total)
+                                .await;
+                        }
+                        let rejected = trading::Settlement::release_rejected(&place_order);
+                        try_event_log!(
+                            place_order,
+                            trading::do_place_order(&mut assets, place_order),
+                            Some(rejected)
+                        )
+                    }
+                    .instrument(span)
+                    .await;
 
+                    publish(&mut assets);
                     let _ = response.send(t);
                 }
-                T::Trade(TradeCmd::CancelOrder((cancel_order, response))) => {
-                    let t = try_event_log!(
-                        cancel_order,
-                        trading::do_cancel_order(&mut assets, cancel_order)
-                    );
+                T::Trade(TradeCmd::CancelOrder((cancel_order, response)), span, _) => {
+                    let t = async {
+                        tracing::info!("processing cancel order");
+                        try_event_log!(
+                            cancel_order,
+                            trading::do_cancel_order(&mut assets, cancel_order)
+                        )
+                    }
+                    .instrument(span)
+                    .await;
 
+                    publish(&mut assets);
                     let _ = response.send(t);
                 }
-                T::Bootstrap(P::PlaceOrder(place_order)) => {
-                    let _ = trading::do_place_order(&mut assets, place_order);
+                T::Trade(TradeCmd::CancelOrderByClientId((cancel_order, response)), span, _) => {
+                    let t = async {
+                        tracing::info!("processing cancel order by client order id");
+                        try_event_log!(
+                            cancel_order,
+                            trading::do_cancel_order_by_client_id(&mut assets, cancel_order)
+                        )
+                    }
+                    .instrument(span)
+                    .await;
+
+                    publish(&mut assets);
+                    let _ = response.send(t);
                 }
-                T::Bootstrap(P::CancelOrder(cancel_order)) => {
-                    let _ = trading::do_cancel_order(&mut assets, cancel_order);
+                T::Trade(TradeCmd::ReduceOrder((reduce_order, response)), span, _) => {
+                    let t = async {
+                        tracing::info!("processing reduce order");
+                        try_event_log!(
+                            reduce_order,
+                            trading::do_reduce_order(&mut assets, reduce_order)
+                        )
+                    }
+                    .instrument(span)
+                    .await;
+
+                    publish(&mut assets);
+                    let _ = response.send(t);
+                }
+                T::Bootstrap(payload) => trading::do_replay(&mut assets, payload),
+                T::DepthSnapshot((asset, response)) => {
+                    let _ = response.send(Ok(trading::do_depth_snapshot(&assets, asset)));
+                }
+                T::FetchOrder((order_uuid, response)) => {
+                    let _ = response.send(Ok(trading::do_fetch_order(&assets, order_uuid)));
+                }
+                T::PeekTop((asset, side, response)) => {
+                    let _ = response.send(Ok(trading::do_peek_top(&assets, asset, side)));
+                }
+                T::RestingOrders((asset, offset, limit, response)) => {
+                    let page = trading::do_resting_orders(&assets, asset, offset, limit);
+                    let _ = response.send(Ok(page));
+                }
+                T::SubscribeMatchEvents(response) => {
+                    let _ = response.send(Ok(match_events.subscribe()));
+                }
+                T::SubscribeExecutionReports(response) => {
+                    let _ = response.send(Ok(execution_reports.subscribe()));
+                }
+                T::SubscribeMarketData((asset, response)) => {
+                    // taken together, so the feed starts right where the snapshot leaves off.
+                    let snapshot = trading::do_depth_snapshot(&assets, asset);
+                    let _ = response.send(Ok((snapshot, market_data.subscribe())));
+                }
+                T::ClosedOrders((closed_before, response)) => {
+                    let records = trading::do_closed_orders(&assets, closed_before);
+                    let _ = response.send(Ok(records));
+                }
+                T::DropArchivedOrders((order_uuids, response)) => {
+                    let dropped = trading::do_drop_archived_orders(&mut assets, &order_uuids);
+                    let _ = response.send(Ok(dropped));
+                }
+                T::Stats(response) => {
+                    let stats = trading::EngineStats {
+                        commands_processed,
+                        last_command_latency_us: last_command_latency,
+                        ..trading::do_engine_stats(&assets)
+                    };
+                    let _ = response.send(Ok(stats));
+                }
+                T::RebuildBook((asset, mut rebuilt, through, response)) => {
+                    // the journal up to `through` was replayed off the loop, only what was
+                    // journalled since is left, and nothing is journalled while this runs.
+                    let t = async {
+                        replay_journal(&mut rebuilt, &db, through).await?;
+                        Ok(trading::do_rebuild_book(&mut assets, *rebuilt, asset))
+                    }
+                    .await;
+
+                    if let Ok(report) = &t {
+                        tracing::warn!(?report, "rebuilt book from the journal");
+                    }
+                    let _ = response.send(t);
+                }
+                T::SubscribeLastLook((user_uuid, response)) => {
+                    let _ = response.send(Ok(last_look_desks.subscribe(user_uuid)));
                 }
             }
+
+            if is_trade {
+                commands_processed += 1;
+                last_command_latency = Some(started.elapsed().as_micros() as u64);
+            }
+        }
+
+        // the orders still queued were reserved for but will never be processed.
+        rx.close();
+        while let Ok(cmd) = rx.try_recv() {
+            release_unprocessed(&db, &cmd).await;
         }
 
         tracing::warn!("trading engine supervisor finished");
     }
 
     let (input, output) = mpsc::channel(config.te_channel_capacity);
-    let handle = tokio::spawn(trading_engine_supervisor(output, db));
+
+    let handle = tokio::spawn(trading_engine_supervisor(output, db, config.clone()));
 
     SpawnTradingEngine { input, handle }
 }
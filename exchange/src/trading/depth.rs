@@ -0,0 +1,278 @@
+//! Aggregated views of the [`Orderbook`] by price level.
+
+use std::num::NonZeroU32;
+
+use serde::Serialize;
+
+use super::*;
+
+/// The resting quantity at a single price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DepthLevel {
+    /// the price of the level.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub price: u32,
+    /// the total quantity resting at this price.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub quantity: u64,
+    /// the number of orders resting at this price.
+    pub orders: usize,
+}
+
+/// A point-in-time copy of the price levels of an [`Orderbook`], best price first on each side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DepthSnapshot {
+    /// the asset of the book.
+    pub asset: Asset,
+    /// the bids, highest price first.
+    pub bids: Vec<DepthLevel>,
+    /// the asks, lowest price first.
+    pub asks: Vec<DepthLevel>,
+}
+
+/// The estimated outcome of a market order of a given size.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quote {
+    /// the side of the hypothetical order.
+    pub side: OrderSide,
+    /// the quantity that was asked for.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub quantity: u32,
+    /// the quantity the book could fill, at most `quantity`.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub quantity_available: u64,
+    /// the volume weighted average price of the fill, `None` if the book side is empty.
+    #[serde(serialize_with = "crate::json_amount::option::serialize")]
+    pub average_price: Option<f64>,
+    /// the worst price the fill would touch, `None` if the book side is empty.
+    #[serde(serialize_with = "crate::json_amount::option::serialize")]
+    pub worst_price: Option<u32>,
+    /// `true` if the book had enough depth to fill all of `quantity`.
+    pub sufficient_depth: bool,
+}
+
+/// Top of book and market-quality metrics computed from the best levels of each side.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Ticker {
+    /// the asset of the book.
+    pub asset: Asset,
+    /// the highest bid, `None` if there are no bids.
+    #[serde(serialize_with = "crate::json_amount::option::serialize")]
+    pub best_bid: Option<u32>,
+    /// the lowest ask, `None` if there are no asks.
+    #[serde(serialize_with = "crate::json_amount::option::serialize")]
+    pub best_ask: Option<u32>,
+    /// the quantity resting in the top levels of the bids.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub bid_quantity: u64,
+    /// the quantity resting in the top levels of the asks.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub ask_quantity: u64,
+    /// the mid price weighted by the quantity on the opposite side, `None` if the book is one-sided.
+    #[serde(serialize_with = "crate::json_amount::option::serialize")]
+    pub microprice: Option<f64>,
+    /// `(bid_quantity - ask_quantity) / (bid_quantity + ask_quantity)`, from -1 (only asks) to 1 (only bids), `None` if the book is empty.
+    pub imbalance: Option<f64>,
+    /// `true` if at least one side of the book is empty.
+    pub one_sided: bool,
+}
+
+impl DepthSnapshot {
+    /// take a snapshot of the price levels in `orderbook`.
+    pub fn from_orderbook(asset: Asset, orderbook: &Orderbook) -> Self {
+        Self {
+            asset,
+            bids: orderbook.depth(OrderSide::Buy),
+            asks: orderbook.depth(OrderSide::Sell),
+        }
+    }
+
+    /// estimate the fill of a market order on `side` for `quantity` by walking the opposite side of the book.
+    pub fn quote(&self, side: OrderSide, quantity: NonZeroU32) -> Quote {
+        let levels = match side {
+            OrderSide::Buy => &self.asks,
+            OrderSide::Sell => &self.bids,
+        };
+
+        let wanted = u64::from(quantity.get());
+        let mut filled = 0u64;
+        let mut notional = 0u128;
+        let mut worst_price = None;
+
+        for level in levels {
+            if filled == wanted {
+                break;
+            }
+
+            let take = std::cmp::min(level.quantity, wanted - filled);
+            filled += take;
+            notional += super::wide_notional(level.price, take);
+            worst_price = Some(level.price);
+        }
+
+        Quote {
+            side,
+            quantity: quantity.get(),
+            quantity_available: filled,
+            average_price: (filled > 0).then(|| notional as f64 / filled as f64),
+            worst_price,
+            sufficient_depth: filled == wanted,
+        }
+    }
+
+    /// keep only the best `depth` price levels of each side.
+    pub fn truncate(&mut self, depth: usize) {
+        self.bids.truncate(depth);
+        self.asks.truncate(depth);
+    }
+
+    /// compute the microprice and imbalance from the best `levels` price levels of each side.
+    pub fn ticker(&self, levels: usize) -> Ticker {
+        let quantity = |side: &[DepthLevel]| -> u64 {
+            side.iter().take(levels).map(|level| level.quantity).sum()
+        };
+
+        let best_bid = self.bids.first().map(|level| level.price);
+        let best_ask = self.asks.first().map(|level| level.price);
+        let bid_quantity = quantity(&self.bids);
+        let ask_quantity = quantity(&self.asks);
+        let total = (bid_quantity + ask_quantity) as f64;
+
+        let microprice = match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) if bid_quantity + ask_quantity > 0 => Some(
+                (f64::from(bid) * ask_quantity as f64 + f64::from(ask) * bid_quantity as f64)
+                    / total,
+            ),
+            _ => None,
+        };
+
+        Ticker {
+            asset: self.asset,
+            best_bid,
+            best_ask,
+            bid_quantity,
+            ask_quantity,
+            microprice,
+            imbalance: (bid_quantity + ask_quantity > 0)
+                .then(|| (bid_quantity as f64 - ask_quantity as f64) / total),
+            one_sided: best_bid.is_none() || best_ask.is_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! nz {
+        ($e:literal) => {
+            ::std::num::NonZeroU32::new($e).unwrap()
+        };
+    }
+
+    fn order(price: NonZeroU32, quantity: NonZeroU32) -> Order {
+        Order {
+            memo: 0,
+            quantity,
+            price,
+            all_or_none: false,
+        }
+    }
+
+    fn fixed_book() -> Orderbook {
+        let mut orderbook = Orderbook::new();
+        orderbook.push_ask(order(nz!(100), nz!(2)));
+        orderbook.push_ask(order(nz!(100), nz!(1)));
+        orderbook.push_ask(order(nz!(110), nz!(4)));
+        orderbook.push_bid(order(nz!(90), nz!(5)));
+        orderbook
+    }
+
+    #[test]
+    fn test_depth_levels_are_aggregated() {
+        let snapshot = DepthSnapshot::from_orderbook(Asset::Bitcoin, &fixed_book());
+
+        assert_eq!(
+            snapshot.asks,
+            vec![
+                DepthLevel {
+                    price: 100,
+                    quantity: 3,
+                    orders: 2
+                },
+                DepthLevel {
+                    price: 110,
+                    quantity: 4,
+                    orders: 1
+                },
+            ]
+        );
+        assert_eq!(snapshot.bids.len(), 1);
+    }
+
+    #[test]
+    fn test_quote_average_price() {
+        let snapshot = DepthSnapshot::from_orderbook(Asset::Bitcoin, &fixed_book());
+        let quote = snapshot.quote(OrderSide::Buy, nz!(5));
+
+        // 3 @ 100 + 2 @ 110 = 520 over 5 units
+        assert_eq!(quote.average_price, Some(104.0));
+        assert_eq!(quote.worst_price, Some(110));
+        assert_eq!(quote.quantity_available, 5);
+        assert!(quote.sufficient_depth);
+    }
+
+    #[test]
+    fn test_quote_insufficient_depth() {
+        let snapshot = DepthSnapshot::from_orderbook(Asset::Bitcoin, &fixed_book());
+        let quote = snapshot.quote(OrderSide::Sell, nz!(8));
+
+        assert_eq!(quote.average_price, Some(90.0));
+        assert_eq!(quote.worst_price, Some(90));
+        assert_eq!(quote.quantity_available, 5);
+        assert!(!quote.sufficient_depth);
+    }
+
+    #[test]
+    fn test_ticker_microprice_and_imbalance() {
+        let snapshot = DepthSnapshot::from_orderbook(Asset::Bitcoin, &fixed_book());
+
+        // best bid 90 x 5, best ask 100 x 3
+        let ticker = snapshot.ticker(1);
+        assert_eq!(ticker.microprice, Some((90.0 * 3.0 + 100.0 * 5.0) / 8.0));
+        assert_eq!(ticker.microprice, Some(96.25));
+        assert_eq!(ticker.imbalance, Some(0.25));
+        assert!(!ticker.one_sided);
+
+        // the second ask level adds 4, the bids have no second level
+        let ticker = snapshot.ticker(2);
+        assert_eq!((ticker.bid_quantity, ticker.ask_quantity), (5, 7));
+        assert_eq!(ticker.microprice, Some((90.0 * 7.0 + 100.0 * 5.0) / 12.0));
+        assert_eq!(ticker.imbalance, Some(-2.0 / 12.0));
+    }
+
+    #[test]
+    fn test_ticker_one_sided_book() {
+        let mut orderbook = Orderbook::new();
+        orderbook.push_ask(order(nz!(100), nz!(2)));
+
+        let ticker = DepthSnapshot::from_orderbook(Asset::Bitcoin, &orderbook).ticker(5);
+        assert_eq!(ticker.microprice, None);
+        assert_eq!(ticker.imbalance, Some(-1.0));
+        assert!(ticker.one_sided);
+
+        let ticker = DepthSnapshot::from_orderbook(Asset::Bitcoin, &Orderbook::new()).ticker(5);
+        assert_eq!(ticker.microprice, None);
+        assert_eq!(ticker.imbalance, None);
+    }
+
+    #[test]
+    fn test_quote_empty_side() {
+        let snapshot = DepthSnapshot::from_orderbook(Asset::Bitcoin, &Orderbook::new());
+        let quote = snapshot.quote(OrderSide::Buy, nz!(1));
+
+        assert_eq!(quote.average_price, None);
+        assert_eq!(quote.worst_price, None);
+        assert!(!quote.sufficient_depth);
+    }
+}
@@ -0,0 +1,430 @@
+//! Execution reports, the per-order view of the [`MatchEvent`]s.
+//!
+//! A [`MatchEvent`] describes a change to a book, an [`ExecutionReport`] describes what that
+//! change did to one order: how much of it traded and at what price, how much is left and how
+//! much has been filled in total. A fill emits a report for the taker and one for the maker.
+//!
+//! Reports are published in two formats, selected per subscription with
+//! [`ExecutionReportFormat`]. The native format is the [`ExecutionReport`] as is, the
+//! [`ExecutionReportFormat::Fix`] format names the fields and values after the FIX 4.4
+//! `ExecutionReport<8>` message so existing trading infrastructure can consume them as they are:
+//!
+//! | FIX          | native            |
+//! |--------------|-------------------|
+//! | `ClOrdID`    | `client_order_id` |
+//! | `OrderID`    | `order_uuid`      |
+//! | `Account`    | `user_uuid`       |
+//! | `Symbol`     | `asset`           |
+//! | `Side`       | `side`            |
+//! | `ExecType`   | `exec_type`       |
+//! | `OrdStatus`  | `order_status`    |
+//! | `LastQty`    | `last_quantity`   |
+//! | `LastPx`     | `last_price`      |
+//! | `LeavesQty`  | `leaves_quantity` |
+//! | `CumQty`     | `cum_quantity`    |
+
+use std::num::NonZeroU32;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// What happened to the order, FIX `ExecType<150>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecType {
+    /// the order came to rest on the book.
+    New,
+    /// some of the order traded.
+    Trade,
+    /// the rest of the order was cancelled.
+    Canceled,
+    /// the quantity of the resting order was reduced without trading.
+    Restated,
+}
+
+impl ExecType {
+    /// the name of the value in FIX.
+    pub fn as_fix(&self) -> &'static str {
+        match self {
+            Self::New => "New",
+            Self::Trade => "Trade",
+            Self::Canceled => "Canceled",
+            Self::Restated => "Restated",
+        }
+    }
+}
+
+/// The state of the order after the execution, FIX `OrdStatus<39>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrdStatus {
+    /// nothing has traded yet.
+    New,
+    /// some has traded and some is left.
+    PartiallyFilled,
+    /// all of it has traded.
+    Filled,
+    /// the order is closed with some of it untraded.
+    Canceled,
+}
+
+impl OrdStatus {
+    /// the name of the value in FIX.
+    pub fn as_fix(&self) -> &'static str {
+        match self {
+            Self::New => "New",
+            Self::PartiallyFilled => "PartiallyFilled",
+            Self::Filled => "Filled",
+            Self::Canceled => "Canceled",
+        }
+    }
+}
+
+/// What a single [`MatchEvent`] did to one order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExecutionReport {
+    /// the order.
+    pub order_uuid: OrderUuid,
+    /// the id the user placed the order with, if it is still open under it.
+    pub client_order_id: Option<String>,
+    /// the owner of the order.
+    pub user_uuid: uuid::Uuid,
+    /// the book of the order.
+    pub asset: Asset,
+    /// the side of the order.
+    pub side: OrderSide,
+    /// what happened to the order.
+    pub exec_type: ExecType,
+    /// the state of the order afterwards.
+    pub order_status: OrdStatus,
+    /// the quantity traded, zero unless `exec_type` is [`ExecType::Trade`].
+    pub last_quantity: u32,
+    /// the price traded at, `None` unless `exec_type` is [`ExecType::Trade`].
+    pub last_price: Option<NonZeroU32>,
+    /// the quantity still open, zero once the order is closed.
+    pub leaves_quantity: u32,
+    /// the quantity filled in total.
+    pub cum_quantity: u32,
+}
+
+/// An [`ExecutionReport`] under the FIX names, see the [module docs](self).
+#[derive(Debug, Serialize)]
+pub struct FixExecutionReport<'a> {
+    #[serde(rename = "ClOrdID")]
+    cl_ord_id: Option<&'a str>,
+    #[serde(rename = "OrderID")]
+    order_id: OrderUuid,
+    #[serde(rename = "Account")]
+    account: uuid::Uuid,
+    #[serde(rename = "Symbol")]
+    symbol: Asset,
+    #[serde(rename = "Side")]
+    side: &'static str,
+    #[serde(rename = "ExecType")]
+    exec_type: &'static str,
+    #[serde(rename = "OrdStatus")]
+    ord_status: &'static str,
+    #[serde(rename = "LastQty")]
+    last_qty: u32,
+    #[serde(rename = "LastPx")]
+    last_px: Option<NonZeroU32>,
+    #[serde(rename = "LeavesQty")]
+    leaves_qty: u32,
+    #[serde(rename = "CumQty")]
+    cum_qty: u32,
+}
+
+impl ExecutionReport {
+    /// the report under the FIX names.
+    pub fn to_fix(&self) -> FixExecutionReport<'_> {
+        FixExecutionReport {
+            cl_ord_id: self.client_order_id.as_deref(),
+            order_id: self.order_uuid,
+            account: self.user_uuid,
+            symbol: self.asset,
+            side: match self.side {
+                OrderSide::Buy => "Buy",
+                OrderSide::Sell => "Sell",
+            },
+            exec_type: self.exec_type.as_fix(),
+            ord_status: self.order_status.as_fix(),
+            last_qty: self.last_quantity,
+            last_px: self.last_price,
+            leaves_qty: self.leaves_quantity,
+            cum_qty: self.cum_quantity,
+        }
+    }
+}
+
+/// The format execution reports are delivered in, chosen per subscription.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionReportFormat {
+    /// the [`ExecutionReport`] as is.
+    #[default]
+    Native,
+    /// the [`FixExecutionReport`].
+    Fix,
+}
+
+impl ExecutionReportFormat {
+    /// `report` in this format.
+    pub fn to_json(&self, report: &ExecutionReport) -> serde_json::Value {
+        let value = match self {
+            Self::Native => serde_json::to_value(report),
+            Self::Fix => serde_json::to_value(report.to_fix()),
+        };
+        value.expect("an execution report always serializes")
+    }
+}
+
+/// the filled and the open quantity of an order.
+#[derive(Debug, Clone, Copy)]
+struct Quantities {
+    cum: u32,
+    leaves: u32,
+}
+
+impl Assets {
+    /// The execution reports of `events`, the events of the last command, in the same order.
+    ///
+    /// Only the state of an order after the command is kept, so it is walked back from there
+    /// through the events to find the state after each one.
+    pub fn execution_reports(&self, events: &[MatchEvent]) -> Vec<ExecutionReport> {
+        let mut quantities = ahash::AHashMap::<OrderUuid, Quantities>::new();
+        let mut reports = Vec::with_capacity(events.len());
+
+        let mut report = |order_uuid: OrderUuid,
+                          exec_type: ExecType,
+                          last: Option<(u32, NonZeroU32)>,
+                          leaves: Option<u32>,
+                          before: &dyn Fn(Quantities) -> Quantities| {
+            let Some(record) = self.orders.get(&order_uuid) else {
+                return;
+            };
+
+            let mut now = *quantities.entry(order_uuid).or_insert_with(|| Quantities {
+                cum: record.quantity_filled,
+                leaves: if record.is_closed() {
+                    0
+                } else {
+                    record.quantity_remaining()
+                },
+            });
+            if let Some(leaves) = leaves {
+                now.leaves = leaves;
+            }
+
+            let order_status = match exec_type {
+                ExecType::Canceled => OrdStatus::Canceled,
+                _ if now.leaves == 0 && now.cum > 0 => OrdStatus::Filled,
+                _ if now.leaves == 0 => OrdStatus::Canceled,
+                _ if now.cum > 0 => OrdStatus::PartiallyFilled,
+                _ => OrdStatus::New,
+            };
+
+            reports.push(ExecutionReport {
+                order_uuid,
+                client_order_id: record.client_order_id.clone(),
+                user_uuid: record.user_uuid,
+                asset: record.asset,
+                side: record.side,
+                exec_type,
+                order_status,
+                last_quantity: last.map_or(0, |(quantity, _)| quantity),
+                last_price: last.map(|(_, price)| price),
+                leaves_quantity: now.leaves,
+                cum_quantity: now.cum,
+            });
+
+            quantities.insert(order_uuid, before(now));
+        };
+
+        for (ix, event) in events.iter().enumerate().rev() {
+            match *event {
+                MatchEvent::Fill {
+                    maker,
+                    taker,
+                    quantity,
+                    price,
+                    ..
+                } => {
+                    let before = |now: Quantities| Quantities {
+                        cum: now.cum - quantity,
+                        leaves: now.leaves + quantity,
+                    };
+                    // walking back, the maker's report is pushed first so it ends up second.
+                    if let Some(maker) = maker {
+                        report(
+                            maker,
+                            ExecType::Trade,
+                            Some((quantity, price)),
+                            None,
+                            &before,
+                        );
+                    }
+                    report(
+                        taker,
+                        ExecType::Trade,
+                        Some((quantity, price)),
+                        None,
+                        &before,
+                    );
+                }
+                MatchEvent::AggressorResting { order_uuid, .. } => {
+                    report(order_uuid, ExecType::New, None, None, &|now| now);
+                }
+                MatchEvent::Cancel {
+                    order_uuid,
+                    quantity,
+                    ..
+                } => {
+                    report(order_uuid, ExecType::Canceled, None, Some(0), &|now| {
+                        Quantities {
+                            leaves: now.leaves + quantity,
+                            ..now
+                        }
+                    });
+                }
+                MatchEvent::Update {
+                    order_uuid,
+                    quantity_remaining,
+                    ..
+                } => {
+                    // a maker partially filled is updated right after the fill, which reports it.
+                    let after_fill = ix.checked_sub(1).is_some_and(|prev| {
+                        matches!(events[prev], MatchEvent::Fill { maker: Some(maker), .. } if maker == order_uuid)
+                    });
+                    if !after_fill {
+                        let restated = Some(quantity_remaining);
+                        report(order_uuid, ExecType::Restated, None, restated, &|now| now);
+                    }
+                }
+            }
+        }
+
+        reports.reverse();
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(side: OrderSide, price: u32, quantity: u32) -> PlaceOrder {
+        PlaceOrder::new(
+            Asset::Bitcoin,
+            uuid::Uuid::new_v4(),
+            NonZeroU32::new(price).unwrap(),
+            NonZeroU32::new(quantity).unwrap(),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilCanceled,
+            side,
+            false,
+            false,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_partial_fill_of_a_maker() {
+        let mut assets = Assets::new();
+        let maker = do_place_order(&mut assets, order(OrderSide::Sell, 100, 10))
+            .unwrap()
+            .order_uuid;
+        assets.drain_match_events().for_each(drop);
+
+        let taker = do_place_order(&mut assets, order(OrderSide::Buy, 100, 4))
+            .unwrap()
+            .order_uuid;
+        let events = assets.drain_match_events().collect::<Vec<_>>();
+        let reports = assets.execution_reports(&events);
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].order_uuid, taker);
+        assert_eq!(reports[0].order_status, OrdStatus::Filled);
+
+        let fix = ExecutionReportFormat::Fix.to_json(&reports[1]);
+        assert_eq!(fix["OrderID"], serde_json::json!(maker));
+        assert_eq!(fix["ExecType"], "Trade");
+        assert_eq!(fix["OrdStatus"], "PartiallyFilled");
+        assert_eq!(fix["LastQty"], 4);
+        assert_eq!(fix["LastPx"], 100);
+        assert_eq!(fix["LeavesQty"], 6);
+        assert_eq!(fix["CumQty"], 4);
+
+        // the native format is untouched.
+        let native = ExecutionReportFormat::Native.to_json(&reports[1]);
+        assert_eq!(native["exec_type"], "trade");
+        assert_eq!(native["order_status"], "partially_filled");
+        assert_eq!(native["leaves_quantity"], 6);
+    }
+
+    #[test]
+    fn test_taker_quantities_accumulate_over_a_sweep() {
+        let mut assets = Assets::new();
+        do_place_order(&mut assets, order(OrderSide::Sell, 100, 2)).unwrap();
+        do_place_order(&mut assets, order(OrderSide::Sell, 101, 3)).unwrap();
+        assets.drain_match_events().for_each(drop);
+
+        let taker = do_place_order(&mut assets, order(OrderSide::Buy, 101, 7))
+            .unwrap()
+            .order_uuid;
+        let events = assets.drain_match_events().collect::<Vec<_>>();
+        let reports = assets
+            .execution_reports(&events)
+            .into_iter()
+            .filter(|report| report.order_uuid == taker)
+            .map(|report| {
+                (
+                    report.exec_type,
+                    report.order_status,
+                    report.cum_quantity,
+                    report.leaves_quantity,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            reports,
+            vec![
+                (ExecType::Trade, OrdStatus::PartiallyFilled, 2, 5),
+                (ExecType::Trade, OrdStatus::PartiallyFilled, 5, 2),
+                (ExecType::New, OrdStatus::PartiallyFilled, 5, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_client_order_id_is_reported_once_the_order_closes() {
+        let mut assets = Assets::new();
+        let with_id = |place_order: PlaceOrder, id: &str| {
+            place_order.with_client_order_id(Some(id.to_owned()))
+        };
+
+        let maker =
+            do_place_order(&mut assets, with_id(order(OrderSide::Sell, 100, 10), "m")).unwrap();
+        assets.drain_match_events().for_each(drop);
+
+        do_place_order(&mut assets, with_id(order(OrderSide::Buy, 100, 4), "t")).unwrap();
+        let events = assets.drain_match_events().collect::<Vec<_>>();
+        let reports = assets.execution_reports(&events);
+        assert_eq!(reports[0].order_status, OrdStatus::Filled);
+        assert_eq!(reports[0].client_order_id.as_deref(), Some("t"));
+
+        do_cancel_order(
+            &mut assets,
+            CancelOrder::new(maker.user_uuid, maker.order_uuid),
+        )
+        .unwrap();
+        let events = assets.drain_match_events().collect::<Vec<_>>();
+        let reports = assets.execution_reports(&events);
+        assert_eq!(reports[0].order_status, OrdStatus::Canceled);
+
+        let fix = ExecutionReportFormat::Fix.to_json(&reports[0]);
+        assert_eq!(fix["ClOrdID"], "m");
+    }
+}
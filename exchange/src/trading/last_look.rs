@@ -0,0 +1,208 @@
+//! Opt-in "last look" for market makers.
+//!
+//! Last look lets the maker of a resting order reject a match before it executes. It hands the
+//! maker a free option against the taker, which is why it is off unless the exchange enables it
+//! with [`crate::config::LastLook`] and, on top of that, the maker places the order with
+//! `last_look` set. Orders without it are never held up.
+//!
+//! Before a placed order is matched the engine walks the resting orders it would fill, in the
+//! usual price-time order. For each last-look order whose maker is listening on their websocket
+//! the engine stops and sends a [`LastLookRequest`], then waits up to the configured window:
+//!
+//! - a rejection skips that resting order for this taker, it keeps its place in the book, and
+//!   the walk carries on to the next resting order, possibly at the next price level.
+//! - an acceptance, no answer within the window, or a maker that is not connected leaves the
+//!   match to execute as usual.
+//!
+//! The rejections are logged with the place-order command, so replaying the journal skips the
+//! same orders without asking anyone. The engine handles one command at a time and every other
+//! order waits while a maker decides, keep the window small.
+//!
+//! Only orders matched when they are placed get a last look, held orders released by a trigger
+//! are matched without one.
+
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+
+use super::{
+    try_fill_orders_skipping, Assets, MakerScreen, Order, OrderSide, OrderUuid, PlaceOrder,
+    ResponseTx, TradingEngineError,
+};
+use crate::Asset;
+
+/// how many requests may queue up for a maker's websocket before the maker loses their say.
+const REQUESTS_CAPACITY: usize = 16;
+
+/// The match a maker is asked to accept or reject.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LastLookQuote {
+    /// identifies the request in the maker's answer.
+    pub request_id: uuid::Uuid,
+    /// the maker's resting order.
+    pub order_uuid: OrderUuid,
+    /// the asset traded.
+    pub asset: Asset,
+    /// the side of the maker's order.
+    pub side: OrderSide,
+    /// the price the match would execute at, the maker's price.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub price: NonZeroU32,
+    /// the quantity the match would exchange.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub quantity: u32,
+}
+
+/// A last look handed to a maker, answered by sending `true` to accept or `false` to reject.
+#[derive(Debug)]
+pub struct LastLookRequest {
+    /// the match waiting on the maker.
+    pub quote: LastLookQuote,
+    /// where the maker's answer goes, dropping it accepts the match.
+    pub decision: oneshot::Sender<bool>,
+}
+
+/// type-alias for a [`ResponseTx`] that sends the [LastLookRequest]s of a maker.
+pub type SubscribeLastLookTx =
+    ResponseTx<Result<mpsc::Receiver<LastLookRequest>, TradingEngineError>>;
+
+/// The makers listening for last looks, kept by the trading engine.
+#[derive(Debug, Default)]
+pub struct LastLookDesks {
+    makers: ahash::AHashMap<uuid::Uuid, mpsc::Sender<LastLookRequest>>,
+}
+
+impl LastLookDesks {
+    /// send the last looks of `user_uuid` to the returned receiver, replacing any earlier one.
+    pub fn subscribe(&mut self, user_uuid: uuid::Uuid) -> mpsc::Receiver<LastLookRequest> {
+        let (tx, rx) = mpsc::channel(REQUESTS_CAPACITY);
+        self.makers.insert(user_uuid, tx);
+        rx
+    }
+
+    /// ask the makers of the last-look orders `place_order` would match, one at a time, and
+    /// record the orders they reject on `place_order`.
+    ///
+    /// each maker gets `window` to answer, all of them together get `total`. the orders not asked
+    /// by then fill as if their makers had not answered.
+    pub async fn review(
+        &mut self,
+        assets: &mut Assets,
+        place_order: &mut PlaceOrder,
+        window: Duration,
+        total: Duration,
+    ) {
+        let mut asked = Vec::new();
+        let deadline = tokio::time::Instant::now() + total;
+
+        loop {
+            self.makers.retain(|_, tx| !tx.is_closed());
+            if self.makers.is_empty() {
+                break;
+            }
+
+            let makers = &self.makers;
+            let next = next_last_look(assets, place_order, |order_uuid, maker_uuid| {
+                !asked.contains(order_uuid) && makers.contains_key(maker_uuid)
+            });
+            let Some((maker_uuid, quote)) = next else {
+                break;
+            };
+
+            let left = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if left.is_zero() {
+                tracing::info!(asked = asked.len(), "last looks cut short, out of time");
+                break;
+            }
+
+            let order_uuid = quote.order_uuid;
+            asked.push(order_uuid);
+
+            if !self.ask(maker_uuid, quote, window.min(left)).await {
+                tracing::info!(?order_uuid, "maker rejected the match on a last look");
+                place_order.last_look_rejections.push(order_uuid);
+            }
+        }
+    }
+
+    /// `false` only if the maker rejects the match within `window`.
+    async fn ask(&self, maker_uuid: uuid::Uuid, quote: LastLookQuote, window: Duration) -> bool {
+        let Some(tx) = self.makers.get(&maker_uuid) else {
+            return true;
+        };
+
+        // a maker too far behind to take the request gets no say, like one that does not answer.
+        let (decision, rx) = oneshot::channel();
+        if tx.try_send(LastLookRequest { quote, decision }).is_err() {
+            return true;
+        }
+
+        !matches!(tokio::time::timeout(window, rx).await, Ok(Ok(false)))
+    }
+}
+
+/// the first resting order `place_order` would match that is a last-look order `ask` returns
+/// `true` for, with its maker.
+fn next_last_look(
+    assets: &mut Assets,
+    place_order: &PlaceOrder,
+    ask: impl Fn(&OrderUuid, &uuid::Uuid) -> bool,
+) -> Option<(uuid::Uuid, LastLookQuote)> {
+    if place_order.trigger.is_some() || assets.last_look_orders.is_empty() {
+        return None;
+    }
+
+    let rejected = assets.last_look_rejected(&place_order.last_look_rejections);
+    let taker = Order {
+        memo: u32::MAX,
+        quantity: place_order.quantity,
+        price: place_order.price,
+        all_or_none: place_order.all_or_none,
+    };
+
+    // a dry run of the match, aborted so the book is left as it was. a match that fails here
+    // fails again when the order is placed, which reports the error.
+    let asset_book = assets.match_asset_mut(place_order.asset);
+    let matching_policy = place_order
+        .matching_policy
+        .unwrap_or(asset_book.matching_policy);
+    let pending_fill = try_fill_orders_skipping(
+        asset_book.orderbook_mut(),
+        taker,
+        place_order.side,
+        place_order.order_type,
+        matching_policy,
+        |oix| {
+            if rejected.contains(&oix) {
+                MakerScreen::Skip
+            } else {
+                MakerScreen::Match
+            }
+        },
+    )
+    .ok()?;
+    let maker_fills = pending_fill.maker_fills.clone();
+    pending_fill.abort();
+
+    let resting = &assets.match_asset(place_order.asset).resting;
+    maker_fills.into_iter().find_map(|fill| {
+        let order_uuid = *resting.get(&fill.oix)?;
+        let maker_uuid = assets.orders.get(&order_uuid)?.user_uuid;
+
+        if !assets.last_look_orders.contains(&order_uuid) || !ask(&order_uuid, &maker_uuid) {
+            return None;
+        }
+
+        let quote = LastLookQuote {
+            request_id: uuid::Uuid::new_v4(),
+            order_uuid,
+            asset: place_order.asset,
+            side: place_order.side.opposite(),
+            price: fill.maker.price,
+            quantity: fill.fill_amount,
+        };
+        Some((maker_uuid, quote))
+    })
+}
@@ -0,0 +1,185 @@
+//! The public feed of a book: its trades and the changes to its price levels.
+//!
+//! Unlike [`MatchEvent`]s the feed names no orders or users, anyone may read it. A level update
+//! carries the new totals of the level rather than the change, so a client that applies one
+//! twice, or after a [`DepthSnapshot`] that already includes it, ends up with the same book.
+
+use std::num::NonZeroU32;
+
+use serde::Serialize;
+
+use super::*;
+
+/// One frame of the market data feed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MarketData {
+    /// an incoming order traded with a resting one.
+    Trade {
+        /// the book the trade happened in.
+        asset: Asset,
+        /// the price of the trade.
+        #[serde(serialize_with = "crate::json_amount::serialize")]
+        price: u32,
+        /// the quantity exchanged.
+        #[serde(serialize_with = "crate::json_amount::serialize")]
+        quantity: u32,
+        /// the side of the incoming order.
+        taker_side: OrderSide,
+    },
+    /// a price level changed, its totals after the change.
+    Level {
+        /// the book of the level.
+        asset: Asset,
+        /// the side of the book the level is on.
+        side: OrderSide,
+        /// the price of the level.
+        #[serde(serialize_with = "crate::json_amount::serialize")]
+        price: u32,
+        /// the total quantity resting at the price, zero once the level is gone.
+        #[serde(serialize_with = "crate::json_amount::serialize")]
+        quantity: u64,
+        /// the number of orders resting at the price.
+        orders: usize,
+    },
+}
+
+impl MarketData {
+    /// the book the frame is about.
+    pub fn asset(&self) -> Asset {
+        match self {
+            Self::Trade { asset, .. } | Self::Level { asset, .. } => *asset,
+        }
+    }
+}
+
+impl Assets {
+    /// The market data of `events`, the events of the last command: its trades in the order they
+    /// happened, then the levels they changed as they are now.
+    pub fn market_data(&self, events: &[MatchEvent]) -> Vec<MarketData> {
+        let mut frames = vec![];
+        let mut levels = vec![];
+
+        let mut touch = |order_uuid: &OrderUuid| {
+            if let Some(record) = self.orders.get(order_uuid) {
+                let level = (record.asset, record.side, record.price);
+                if !levels.contains(&level) {
+                    levels.push(level);
+                }
+            }
+        };
+
+        for event in events {
+            match event {
+                MatchEvent::Fill {
+                    asset,
+                    maker,
+                    taker,
+                    quantity,
+                    price,
+                } => {
+                    let Some(taker_side) = self.orders.get(taker).map(|record| record.side) else {
+                        continue;
+                    };
+                    frames.push(MarketData::Trade {
+                        asset: *asset,
+                        price: price.get(),
+                        quantity: *quantity,
+                        taker_side,
+                    });
+                    if let Some(maker) = maker {
+                        touch(maker);
+                    }
+                }
+                MatchEvent::AggressorResting { order_uuid, .. }
+                | MatchEvent::Cancel { order_uuid, .. }
+                | MatchEvent::Update { order_uuid, .. } => touch(order_uuid),
+            }
+        }
+
+        frames.extend(levels.into_iter().map(|(asset, side, price)| {
+            let orderbook = &self.match_asset(asset).orderbook;
+            level(asset, side, price, orderbook)
+        }));
+        frames
+    }
+}
+
+/// the [`MarketData::Level`] of `price` on `side` of `orderbook`.
+fn level(asset: Asset, side: OrderSide, price: NonZeroU32, orderbook: &Orderbook) -> MarketData {
+    MarketData::Level {
+        asset,
+        side,
+        price: price.get(),
+        quantity: orderbook.level_quantity(side, price),
+        orders: orderbook.level_len(side, price),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(side: OrderSide, price: u32, quantity: u32) -> PlaceOrder {
+        PlaceOrder::new(
+            Asset::Bitcoin,
+            uuid::Uuid::new_v4(),
+            NonZeroU32::new(price).unwrap(),
+            NonZeroU32::new(quantity).unwrap(),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilCanceled,
+            side,
+            false,
+            false,
+            None,
+        )
+    }
+
+    fn level(side: OrderSide, price: u32, quantity: u64, orders: usize) -> MarketData {
+        MarketData::Level {
+            asset: Asset::Bitcoin,
+            side,
+            price,
+            quantity,
+            orders,
+        }
+    }
+
+    #[test]
+    fn test_trades_then_the_levels_they_changed() {
+        let mut assets = Assets::new();
+        do_place_order(&mut assets, order(OrderSide::Sell, 100, 2)).unwrap();
+        do_place_order(&mut assets, order(OrderSide::Sell, 100, 2)).unwrap();
+        do_place_order(&mut assets, order(OrderSide::Sell, 101, 3)).unwrap();
+        let events = assets.drain_match_events().collect::<Vec<_>>();
+        assert_eq!(
+            assets.market_data(&events),
+            vec![
+                level(OrderSide::Sell, 100, 4, 2),
+                level(OrderSide::Sell, 101, 3, 1),
+            ]
+        );
+
+        // takes the level at 100 and one from 101, the rest rests as a bid.
+        do_place_order(&mut assets, order(OrderSide::Buy, 101, 8)).unwrap();
+        let events = assets.drain_match_events().collect::<Vec<_>>();
+        let trade = |price, quantity| MarketData::Trade {
+            asset: Asset::Bitcoin,
+            price,
+            quantity,
+            taker_side: OrderSide::Buy,
+        };
+        assert_eq!(
+            assets.market_data(&events),
+            vec![
+                trade(100, 2),
+                trade(100, 2),
+                trade(101, 3),
+                level(OrderSide::Sell, 100, 0, 0),
+                level(OrderSide::Sell, 101, 0, 0),
+                level(OrderSide::Buy, 101, 1, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! The events the matcher emits as it changes the books.
+//!
+//! Every change to a book, a fill, an incoming order coming to rest, a resting order shrinking
+//! or leaving, is emitted as a [`MatchEvent`] in the order it happened. Consumers such as market
+//! data broadcasts, webhooks, ledger settlement and order persistence subscribe to this one
+//! stream rather than each picking the changes out of command results.
+//!
+//! A sweep of a buy for 7 through asks of 2 @ 100, 3 @ 101 and 5 @ 102 emits:
+//!
+//! 1. `Fill` of 2 @ 100 against the first ask
+//! 2. `Fill` of 3 @ 101 against the second ask
+//! 3. `Fill` of 2 @ 102 against the third ask
+//! 4. `Update` of the third ask, 3 remaining
+//!
+//! and had the buy been limited to 101 instead, the first two fills and an `AggressorResting`
+//! of the remaining 2 @ 101.
+
+use std::num::NonZeroU32;
+
+use serde::Serialize;
+
+use super::{OrderSide, OrderUuid};
+use crate::Asset;
+
+/// A single change the matcher made to a book.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MatchEvent {
+    /// the remainder of an incoming order was put on the book.
+    AggressorResting {
+        /// the book the order rests in.
+        asset: Asset,
+        /// the order that came to rest.
+        order_uuid: OrderUuid,
+        /// the side of the book it rests on.
+        side: OrderSide,
+        /// the price it rests at.
+        price: NonZeroU32,
+        /// the quantity left resting.
+        quantity: u32,
+    },
+    /// an incoming order traded with a resting one.
+    Fill {
+        /// the book the trade happened in.
+        asset: Asset,
+        /// the resting order, `None` if the engine has no uuid for it.
+        maker: Option<OrderUuid>,
+        /// the incoming order.
+        taker: OrderUuid,
+        /// the quantity exchanged.
+        quantity: u32,
+        /// the price of the trade, the resting order's price.
+        price: NonZeroU32,
+    },
+    /// an order left the book, or an incoming order was not put on it, with quantity unfilled.
+    Cancel {
+        /// the book the order was placed in.
+        asset: Asset,
+        /// the cancelled order.
+        order_uuid: OrderUuid,
+        /// the unfilled quantity that was cancelled.
+        quantity: u32,
+    },
+    /// a resting order was partially filled, or reduced, and stays on the book.
+    Update {
+        /// the book the order rests in.
+        asset: Asset,
+        /// the resting order.
+        order_uuid: OrderUuid,
+        /// the quantity still resting.
+        quantity_remaining: u32,
+        /// the quantity taken off it without trading, by a reduce or self-trade protection, zero
+        /// for a partial fill.
+        quantity_reduced: u32,
+    },
+}
@@ -4,12 +4,15 @@ use std::num::NonZeroU32;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::mpsc;
 
+use crate::config::{MarketOrderLiquidity, OrderTtl, OrderbookCapacity};
 use crate::Asset;
 
 pub mod orderbook;
-pub use orderbook::{Order, OrderIndex, OrderSide, OrderType, Orderbook};
+pub use orderbook::{
+    CompactionReport, Order, OrderIndex, OrderSide, OrderType, Orderbook, OrderbookSnapshot,
+};
 
 pub mod self_trade_protection;
 pub use self_trade_protection::SelfTradeProtection;
@@ -21,11 +24,44 @@ pub mod pending_fill;
 pub use pending_fill::{ExecutePendingFillError, FillType, PendingFill};
 
 pub mod try_fill_order;
-pub use try_fill_order::{try_fill_orders, TryFillOrdersError};
+pub use try_fill_order::{
+    try_fill_orders, try_fill_orders_skipping, MakerScreen, MatchingPolicy, TryFillOrdersError,
+};
 
 mod te_response;
 pub use te_response::TeResponse;
 
+pub mod depth;
+pub use depth::{DepthLevel, DepthSnapshot, Quote, Ticker};
+
+pub mod response_ring;
+pub use response_ring::{response_channel, ResponseRing, ResponseRx, ResponseTx};
+
+pub mod order_record;
+pub use order_record::{OrderRecord, OrderStatus};
+
+pub mod triggers;
+pub use triggers::{Trigger, TriggerKind, Triggers};
+
+pub mod match_event;
+pub use match_event::MatchEvent;
+
+pub mod market_data;
+pub use market_data::MarketData;
+
+pub mod execution_report;
+pub use execution_report::{
+    ExecType, ExecutionReport, ExecutionReportFormat, FixExecutionReport, OrdStatus,
+};
+
+pub mod last_look;
+pub use last_look::{LastLookDesks, LastLookQuote, LastLookRequest, SubscribeLastLookTx};
+
+pub mod notional;
+pub use notional::{checked_notional, notional, wide_notional, NotionalOverflow};
+
+pub mod settlement;
+pub use settlement::{Settlement, SettlementOrder};
 
 /// The unique identifier for an order.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
@@ -34,6 +70,11 @@ impl OrderUuid {
     fn new_v4() -> OrderUuid {
         OrderUuid(uuid::Uuid::new_v4())
     }
+
+    /// a different uuid derived from this one, the same every time so replays agree on it.
+    fn rederive(self) -> OrderUuid {
+        OrderUuid(uuid::Uuid::new_v5(&self.0, b"order uuid collision"))
+    }
 }
 
 /// type-alias for a [`tokio::sync::mpsc::Sender``] that sends [TradingEngineCmd]s.
@@ -61,10 +102,45 @@ pub struct PlaceOrder {
     time_in_force: TimeInForce,
     /// the side of the order, buy or sell
     side: OrderSide,
+    /// cancel any unfilled remainder instead of resting it on the book
+    #[serde(default)]
+    reduce_only: bool,
+    /// only ever fill the order in its entirety, never partially
+    #[serde(default)]
+    all_or_none: bool,
+    /// the unique identifier assigned to the order, logged so replays produce the same uuid
+    #[serde(default = "OrderUuid::new_v4")]
+    order_uuid: OrderUuid,
+    /// when the order was placed, in milliseconds since the unix epoch
+    #[serde(default)]
+    created_at: i64,
+    /// when a good-til-date order expires, in milliseconds since the unix epoch
+    #[serde(default)]
+    expires_at: Option<i64>,
+    /// must be greater than the nonce of the previous order from the same user, if given
+    #[serde(default)]
+    nonce: Option<u64>,
+    /// hold the order off the book until the last trade touches this, see [`Triggers`]
+    #[serde(default)]
+    trigger: Option<Trigger>,
+    /// the client's own identifier for the order, unique among the user's open orders
+    #[serde(default)]
+    client_order_id: Option<String>,
+    /// let the maker reject matches against the order while it rests, see [`last_look`]
+    #[serde(default)]
+    last_look: bool,
+    /// the resting orders whose makers rejected matching this order on a last look, logged so
+    /// replays skip the same orders
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    last_look_rejections: Vec<OrderUuid>,
+    /// how the order is matched within a price level, logged so replays fill the same makers
+    /// after the book's policy is changed. orders logged without one use the book's policy.
+    #[serde(default)]
+    matching_policy: Option<MatchingPolicy>,
 }
 
-/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends [PlaceOrderResult]s.
-pub type PlaceOrderTx = oneshot::Sender<Result<PlaceOrderResult, TradingEngineError>>;
+/// type-alias for a [`ResponseTx`] that sends [PlaceOrderResult]s.
+pub type PlaceOrderTx = ResponseTx<Result<PlaceOrderResult, TradingEngineError>>;
 
 impl PlaceOrder {
     /// create a new [`PlaceOrder``]
@@ -77,6 +153,9 @@ impl PlaceOrder {
         stp: SelfTradeProtection,
         time_in_force: TimeInForce,
         side: OrderSide,
+        reduce_only: bool,
+        all_or_none: bool,
+        expires_at: Option<i64>,
     ) -> Self {
         Self {
             asset,
@@ -87,10 +166,113 @@ impl PlaceOrder {
             stp,
             time_in_force,
             side,
+            reduce_only,
+            all_or_none,
+            order_uuid: OrderUuid::new_v4(),
+            created_at: chrono::Utc::now().timestamp_millis(),
+            expires_at,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+            last_look_rejections: Vec::new(),
+            matching_policy: None,
+        }
+    }
+
+    /// sequence the order by `nonce`, see [`PlaceOrderError::StaleNonce`].
+    pub fn with_nonce(mut self, nonce: Option<u64>) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    /// hold the order until `trigger` is touched, see [`Triggers`].
+    pub fn with_trigger(mut self, trigger: Option<Trigger>) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// name the order by the client's own id, see [`CancelOrderByClientId`].
+    pub fn with_client_order_id(mut self, client_order_id: Option<String>) -> Self {
+        self.client_order_id = client_order_id;
+        self
+    }
+
+    /// let the maker take a last look before the order is matched while it rests, see [`last_look`].
+    pub fn with_last_look(mut self, last_look: bool) -> Self {
+        self.last_look = last_look;
+        self
+    }
+
+    /// when the order expires, in milliseconds since the unix epoch.
+    pub fn expires_at(&self) -> Option<i64> {
+        self.expires_at
+    }
+
+    /// expire the order `after` it was placed, measured from the server's clock at placement.
+    ///
+    /// clients with a skewed clock can't express "good for 30 seconds" as an absolute expiry, this
+    /// resolves it against `created_at` instead. the expiry is still bounded by [`Self::apply_ttl`],
+    /// so `after` beyond the maximum TTL is clamped or rejected just like an absolute expiry.
+    pub fn expire_after(&mut self, after: std::time::Duration) -> Result<(), ExpiryError> {
+        if self.expires_at.is_some() {
+            return Err(ExpiryError::Conflicting);
+        }
+
+        let after = i64::try_from(after.as_millis()).unwrap_or(i64::MAX);
+        self.expires_at = Some(self.created_at.saturating_add(after));
+        Ok(())
+    }
+
+    /// apply `ttl` to the expiry of the order, relative to when the order was created.
+    ///
+    /// good-til-date orders without an expiry get the default, expiries beyond the maximum
+    /// are either clamped or rejected. other orders may not have an expiry at all.
+    pub fn apply_ttl(&mut self, ttl: &OrderTtl) -> Result<(), ExpiryError> {
+        if self.time_in_force != TimeInForce::GoodTilDate {
+            return match self.expires_at {
+                Some(_) => Err(ExpiryError::NotGoodTilDate),
+                None => Ok(()),
+            };
         }
+
+        let secs_to_ms = |secs: u64| i64::try_from(secs.saturating_mul(1000)).unwrap_or(i64::MAX);
+        let max_expires_at = self.created_at.saturating_add(secs_to_ms(ttl.max_secs));
+
+        let expires_at = match self.expires_at {
+            None => self.created_at.saturating_add(secs_to_ms(ttl.default_secs)),
+            Some(expires_at) if expires_at <= self.created_at => {
+                return Err(ExpiryError::InThePast)
+            }
+            Some(expires_at) if expires_at > max_expires_at && ttl.clamp_to_max => max_expires_at,
+            Some(expires_at) if expires_at > max_expires_at => {
+                return Err(ExpiryError::BeyondMaximum)
+            }
+            Some(expires_at) => expires_at,
+        };
+
+        self.expires_at = Some(expires_at.min(max_expires_at));
+        Ok(())
     }
 }
 
+/// Error that can occur when validating the expiry of an order.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ExpiryError {
+    /// only good-til-date orders can expire.
+    #[error("only good-til-date orders can have an expiry")]
+    NotGoodTilDate,
+    /// the expiry is not after the order was placed.
+    #[error("the expiry is in the past")]
+    InThePast,
+    /// the expiry is further in the future than the configured maximum.
+    #[error("the expiry is beyond the maximum allowed")]
+    BeyondMaximum,
+    /// both an absolute and a relative expiry were given.
+    #[error("only one of an absolute or a relative expiry can be given")]
+    Conflicting,
+}
+
 /// Data for canceling an order.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CancelOrder {
@@ -100,8 +282,8 @@ pub struct CancelOrder {
     order_uuid: OrderUuid,
 }
 
-/// type-alias for a [`tokio::sync::oneshot::Sender``] that sends [Result]s.
-pub type CancelOrderTx = oneshot::Sender<Result<(), TradingEngineError>>;
+/// type-alias for a [`ResponseTx`] that sends [Result]s.
+pub type CancelOrderTx = ResponseTx<Result<(), TradingEngineError>>;
 
 impl CancelOrder {
     /// create a new [`CancelOrder``]
@@ -113,6 +295,50 @@ impl CancelOrder {
     }
 }
 
+/// Data for taking quantity off a resting order, which keeps its place in the queue.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReduceOrder {
+    /// the user that placed the order
+    user_uuid: uuid::Uuid,
+    /// the order to reduce
+    order_uuid: OrderUuid,
+    /// the quantity to take off, all of what is left cancels the order
+    by: NonZeroU32,
+}
+
+/// type-alias for a [`ResponseTx`] that sends the quantity left resting.
+pub type ReduceOrderTx = ResponseTx<Result<u32, TradingEngineError>>;
+
+impl ReduceOrder {
+    /// create a new [`ReduceOrder`]
+    pub fn new(user_uuid: uuid::Uuid, order_uuid: OrderUuid, by: NonZeroU32) -> Self {
+        Self {
+            user_uuid,
+            order_uuid,
+            by,
+        }
+    }
+}
+
+/// Data for canceling an order by the id the client placed it with.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CancelOrderByClientId {
+    /// the user that placed the order
+    user_uuid: uuid::Uuid,
+    /// the client order id the order was placed with
+    client_order_id: String,
+}
+
+impl CancelOrderByClientId {
+    /// create a new [`CancelOrderByClientId`]
+    pub fn new(user_uuid: uuid::Uuid, client_order_id: String) -> Self {
+        Self {
+            user_uuid,
+            client_order_id,
+        }
+    }
+}
+
 /// Error that can occur when placing an order.
 #[derive(Debug, Error)]
 pub enum PlaceOrderError {
@@ -122,11 +348,81 @@ pub enum PlaceOrderError {
     /// error that can occur when executing a pending fill operation.
     #[error("order was not completely filled due to insufficient liquidity")]
     InsufficientLiquidity,
+    /// the price level the order would rest at already holds the maximum number of orders.
+    #[error("too many orders resting at this price")]
+    PriceLevelFull,
+    /// the nonce of the order is not greater than the last nonce processed for the user.
+    #[error("nonce must be greater than {last}")]
+    StaleNonce {
+        /// the last nonce processed for the user.
+        last: u64,
+    },
+    /// the asset does not accept orders of this type.
+    #[error("{order_type:?} orders are not allowed for this asset")]
+    OrderTypeNotAllowed {
+        /// the type of the rejected order.
+        order_type: OrderType,
+    },
+    /// the user has placed orders with a nonce before, so every order must carry one.
+    #[error("orders from this user must carry a nonce")]
+    NonceRequired,
+    /// the client order id already names one of the user's open orders.
+    #[error("an open order already uses this client order id")]
+    DuplicateClientOrderId,
+    /// the order could not be matched against the book.
+    #[error("order could not be matched: {0}")]
+    Match(#[from] TryFillOrdersError),
     /// error that can occur when executing a pending fill operation.
     #[error("error while executing pending fill")]
     ExecutePendingFillError(#[from] ExecutePendingFillError),
 }
 
+/// A single match of a taker order against a resting order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Execution {
+    /// the resting order that was matched, `None` if the engine has no uuid for it.
+    pub maker_order_uuid: Option<OrderUuid>,
+    /// the owner of the resting order, kept out of responses sent to the taker.
+    #[serde(skip)]
+    pub maker_user_uuid: Option<uuid::Uuid>,
+    /// the price the match executed at, the resting order's price.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub price: NonZeroU32,
+    /// the quantity exchanged.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub quantity: u32,
+    /// what the taker saved by matching at this price instead of its own, in the quote currency.
+    ///
+    /// `(taker price - price) * quantity` for a buy and `(price - taker price) * quantity` for a
+    /// sell, never negative since a taker only matches at its price or better. Always zero for a
+    /// market taker, its price is only the worst it accepts and nothing it expected to pay.
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    pub price_improvement: i64,
+}
+
+impl Execution {
+    /// the price improvement of a `side` taker with `taker_price` matching `quantity` at `price`.
+    pub fn price_improvement_of(
+        side: OrderSide,
+        order_type: OrderType,
+        taker_price: NonZeroU32,
+        price: NonZeroU32,
+        quantity: u32,
+    ) -> i64 {
+        if order_type == OrderType::Market {
+            return 0;
+        }
+
+        let per_unit = match side {
+            OrderSide::Buy => i128::from(taker_price.get()) - i128::from(price.get()),
+            OrderSide::Sell => i128::from(price.get()) - i128::from(taker_price.get()),
+        };
+
+        let improvement = per_unit * i128::from(quantity);
+        i64::try_from(improvement).unwrap_or(if improvement < 0 { i64::MIN } else { i64::MAX })
+    }
+}
+
 /// Result of placing an order.
 pub struct PlaceOrderResult {
     // original order information
@@ -146,6 +442,10 @@ pub struct PlaceOrderResult {
     pub time_in_force: TimeInForce,
     /// the side of the order, buy or sell
     pub side: OrderSide,
+    /// whether the order was reduce-only
+    pub reduce_only: bool,
+    /// whether the order was all-or-none
+    pub all_or_none: bool,
     // result of the order
     /// the unique identifier for the order
     pub order_uuid: OrderUuid,
@@ -157,12 +457,76 @@ pub struct PlaceOrderResult {
     pub quantity_filled: u32,
     /// the quantity remaining
     pub quantity_remaining: u32,
+    /// the part of the remaining quantity that was cancelled instead of resting on the book,
+    /// e.g. the unfilled remainder of an IOC or market order.
+    pub quantity_cancelled: u32,
+    /// the individual matches against resting orders, in the order they were made.
+    pub executions: Vec<Execution>,
+    /// the sum of [`Execution::price_improvement`] over `executions`.
+    pub price_improvement: i64,
 }
 
 /// place an order
 pub fn do_place_order(
     assets: &mut Assets,
     place_order: PlaceOrder,
+) -> Result<PlaceOrderResult, TradingEngineError> {
+    // checked ahead of the nonce so a rejected order does not use it up.
+    let asset_book = assets.match_asset(place_order.asset);
+    if !asset_book
+        .allowed_order_types
+        .contains(&place_order.order_type)
+    {
+        return Err(PlaceOrderError::OrderTypeNotAllowed {
+            order_type: place_order.order_type,
+        }
+        .into());
+    }
+
+    place_journalled_order(assets, place_order)
+}
+
+/// place an order whatever its type, the allowed types may have changed since it was journalled.
+fn place_journalled_order(
+    assets: &mut Assets,
+    mut place_order: PlaceOrder,
+) -> Result<PlaceOrderResult, TradingEngineError> {
+    let client_order_id = place_order.client_order_id.clone();
+    if let Some(client_order_id) = &client_order_id {
+        assets.check_client_order_id(place_order.user_uuid, client_order_id)?;
+    }
+
+    assets.check_nonce(place_order.user_uuid, place_order.nonce)?;
+    place_order.order_uuid = assets.unique_order_uuid(place_order.order_uuid);
+
+    if let Some(trigger) = place_order.trigger {
+        let last_price = assets.match_asset(place_order.asset).last_price;
+
+        // an order whose trigger the market has already passed is matched straight away.
+        if last_price.is_some_and(|last_price| trigger.is_touched(place_order.side, last_price)) {
+            place_order.trigger = None;
+        }
+    }
+
+    let result = match place_order.trigger {
+        Some(_) => assets.hold_order(place_order),
+        None => match_order(assets, place_order)?,
+    };
+
+    if let Some(client_order_id) = client_order_id {
+        assets
+            .client_order_ids
+            .insert((result.user_uuid, client_order_id), result.order_uuid);
+    }
+
+    Ok(result)
+}
+
+/// match an order that passed the checks of [`do_place_order`] against the book, then release
+/// any held orders the resulting trades touched.
+fn match_order(
+    assets: &mut Assets,
+    place_order: PlaceOrder,
 ) -> Result<PlaceOrderResult, TradingEngineError> {
     let PlaceOrder {
         asset,
@@ -173,21 +537,80 @@ pub fn do_place_order(
         stp,
         time_in_force,
         side,
+        reduce_only,
+        all_or_none,
+        order_uuid,
+        created_at,
+        expires_at,
+        last_look,
+        last_look_rejections,
+        client_order_id,
+        matching_policy,
+        ..
     } = place_order;
 
-    let asset_book = assets.match_asset_mut(asset);
+    // coarse pre-check so a market order can not sweep an empty or near-empty book.
+    if order_type == OrderType::Market && !assets.has_market_liquidity(asset, side) {
+        return Err(PlaceOrderError::InsufficientLiquidity.into());
+    }
+
+    let max_orders_per_price_level = assets.max_orders_per_price_level;
+    let rejected = assets.last_look_rejected(&last_look_rejections);
+    let (asset_book, orders) = assets.match_asset_and_orders_mut(asset);
+
+    // matching only takes from the opposite side so the level the order would rest at can be measured up front.
+    let level_len = asset_book.orderbook.level_len(side, price);
 
     let taker: Order = Order {
         memo: u32::MAX,
         quantity,
         price,
+        all_or_none,
+    };
+
+    // makers of the taker's own owner are handled by its self-trade protection instead of traded with.
+    let resting = &asset_book.resting;
+    let is_own = |oix| {
+        resting
+            .get(&oix)
+            .and_then(|order_uuid| orders.get(order_uuid))
+            .is_some_and(|record| record.user_uuid == user_uuid)
     };
+    let stp_cancels = std::cell::RefCell::new(Vec::new());
+    let stp_stopped = std::cell::Cell::new(false);
+
+    // create a pending fill and maybe execute it, passing over the makers that rejected it.
+    let matching_policy = matching_policy.unwrap_or(asset_book.matching_policy);
+    let pending_fill = try_fill_orders_skipping(
+        &mut asset_book.orderbook,
+        taker,
+        side,
+        order_type,
+        matching_policy,
+        |oix| {
+            if rejected.contains(&oix) {
+                return MakerScreen::Skip;
+            } else if !is_own(oix) {
+                return MakerScreen::Match;
+            }
 
-    // create a pending fill and maybe execute it.
-    let pending_fill = try_fill_orders(asset_book.orderbook_mut(), taker, side, order_type)
-        .expect("todo: handle error");
+            if stp.cancels_resting() {
+                stp_cancels.borrow_mut().push(oix);
+            }
+            let screen = stp.screen();
+            if screen == MakerScreen::Stop {
+                stp_stopped.set(true);
+            }
+            screen
+        },
+    )
+    .map_err(PlaceOrderError::from)?;
 
-    // TODO: self trade protection
+    let stp_cancels = stp_cancels.into_inner();
+    let stp_stopped = stp_stopped.get();
+    let stp_decrements = pending_fill.decrements().to_vec();
+    // an order that ran into its owner's orders is accepted for what that did, filled or not.
+    let self_traded = stp_stopped || !stp_cancels.is_empty() || !stp_decrements.is_empty();
 
     // enforce time-in-force depending on fill type.
     match (pending_fill.taker_fill_outcome(), time_in_force) {
@@ -201,73 +624,175 @@ pub fn do_place_order(
         }
         (FillType::None, TimeInForce::GoodTilCanceled) => (), // add to orderbook as resting order.
         (FillType::None, TimeInForce::GoodTilDate) => (), // add to orderbook as resting order, it will be tracked and cancelled separately
-        (FillType::None, TimeInForce::ImmediateOrCancel) => {
+        (FillType::None, TimeInForce::ImmediateOrCancel) if !self_traded => {
             // no fill, no orderbook entry, NO SOUP FOR YOU!
             return Err(PlaceOrderError::InsufficientLiquidity.into());
         }
+        (FillType::None, TimeInForce::ImmediateOrCancel) => (), // cancelled by self-trade protection.
         (FillType::None, TimeInForce::FillOrKill) => {
             return Err(PlaceOrderError::FillOrKillFailed.into())
         }
     }
 
-    // commit the fill.
-    match pending_fill.commit() {
-        Ok((fill_type, order)) => {
-            if let Some(order) = order {
-                let order_index = if matches!(time_in_force, TimeInForce::ImmediateOrCancel) {
-                    // partial fill, but we do not add it to the orderbook because it is an IOC order.
-                    None
-                } else {
-                    // order was not completely filled, add it to the orderbook.
-                    Some(match side {
-                        OrderSide::Buy => asset_book.orderbook_mut().push_bid(order),
-                        OrderSide::Sell => asset_book.orderbook_mut().push_ask(order),
-                    })
-                };
+    // market and reduce-only orders never rest on the book whatever their time in force, if
+    // nothing filled then there is nothing to do.
+    let never_rests = reduce_only || order_type == OrderType::Market;
+    if never_rests && !self_traded && pending_fill.taker_fill_outcome() == FillType::None {
+        return Err(PlaceOrderError::InsufficientLiquidity.into());
+    }
 
-                assert!(quantity.get() >= order.quantity.get());
-
-                Ok(PlaceOrderResult {
-                    asset,
-                    user_uuid,
-                    order_index,
-                    price,
-                    quantity,
-                    order_type,
-                    stp,
-                    time_in_force,
-                    side,
-                    order_uuid: OrderUuid::new_v4(),
-                    fill_type,
-                    quantity_filled: quantity.get() - order.quantity.get(),
-                    quantity_remaining: order.quantity.get(),
-                })
-            } else {
-                // order is None means that the order was completely filled.
-                Ok(PlaceOrderResult {
-                    asset,
-                    user_uuid,
-                    order_index: None,
-                    price,
-                    quantity,
-                    order_type,
-                    stp,
-                    time_in_force,
-                    side,
-                    order_uuid: OrderUuid::new_v4(),
-                    fill_type,
-                    quantity_filled: quantity.get(),
-                    quantity_remaining: 0,
-                })
-            }
-        }
+    // cap the number of orders at a single price to bound the per-level scan when matching.
+    let would_rest = pending_fill.taker_fill_outcome() != FillType::Complete
+        && !matches!(time_in_force, TimeInForce::ImmediateOrCancel)
+        && !never_rests
+        && !stp_stopped;
+
+    if would_rest && max_orders_per_price_level.is_some_and(|max| level_len >= max) {
+        return Err(PlaceOrderError::PriceLevelFull.into());
+    }
+
+    let maker_fills = pending_fill.maker_fills.clone();
+
+    // commit the fill.
+    let (fill_type, order) = match pending_fill.commit() {
+        Ok(t) => t,
         Err(err) => {
             tracing::error!(?err, "failed to commit fill");
-            Err(TradingEngineError::from(
+            return Err(TradingEngineError::from(
                 PlaceOrderError::ExecutePendingFillError(err),
-            ))
+            ));
+        }
+    };
+
+    let (order_index, quantity_remaining) = match order {
+        Some(order) => {
+            let order_index = if matches!(time_in_force, TimeInForce::ImmediateOrCancel) {
+                // partial fill, but we do not add it to the orderbook because it is an IOC order.
+                None
+            } else if never_rests {
+                // partial fill, the remainder is cancelled because the order is market or reduce-only.
+                None
+            } else if stp_stopped {
+                // the remainder is cancelled by self-trade protection.
+                None
+            } else {
+                // order was not completely filled, add it to the orderbook.
+                Some(asset_book.orderbook_mut().push(side, order))
+            };
+
+            assert!(quantity.get() >= order.quantity.get());
+            (order_index, order.quantity.get())
+        }
+        // order is None means that the order was completely filled, or decremented.
+        None => (None, 0),
+    };
+
+    let quantity_decremented = stp_decrements
+        .iter()
+        .map(|decrement| decrement.fill_amount)
+        .sum::<u32>();
+    let quantity_filled = quantity.get() - quantity_remaining - quantity_decremented;
+    let quantity_cancelled = if order_index.is_none() {
+        quantity_remaining + quantity_decremented
+    } else {
+        quantity_decremented
+    };
+    let status = if quantity_remaining == 0 && quantity_decremented == 0 {
+        OrderStatus::Filled
+    } else if order_index.is_none() {
+        OrderStatus::Cancelled
+    } else if quantity_filled > 0 {
+        OrderStatus::PartiallyFilled
+    } else {
+        OrderStatus::Open
+    };
+
+    let executions =
+        assets.record_maker_fills(asset, order_uuid, side, order_type, price, &maker_fills);
+    assets.apply_self_trade_protection(asset, user_uuid, &stp_decrements, &stp_cancels);
+
+    // a resting order is recorded with what self-trade protection left of it.
+    let recorded_quantity = match order_index {
+        Some(_) => NonZeroU32::new(quantity.get() - quantity_decremented)
+            .expect("a resting order has quantity left"),
+        None => quantity,
+    };
+    let price_improvement = executions.iter().fold(0i64, |total, execution| {
+        total.saturating_add(execution.price_improvement)
+    });
+    assets.record_order(
+        OrderRecord {
+            order_uuid,
+            user_uuid,
+            asset,
+            side,
+            order_type,
+            price,
+            quantity: recorded_quantity,
+            quantity_filled,
+            status,
+            created_at,
+            expires_at,
+            closed_at: None,
+            client_order_id,
+        },
+        order_index,
+    );
+
+    if last_look && order_index.is_some() {
+        assets.last_look_orders.insert(order_uuid);
+    }
+
+    if order_index.is_some() {
+        assets.match_events.push(MatchEvent::AggressorResting {
+            asset,
+            order_uuid,
+            side,
+            price,
+            quantity: quantity_remaining,
+        });
+
+        // what self-trade protection took off the order rests no more than it filled.
+        if quantity_decremented > 0 {
+            assets.match_events.push(MatchEvent::Update {
+                asset,
+                order_uuid,
+                quantity_remaining,
+                quantity_reduced: quantity_decremented,
+            });
         }
+    } else if quantity_cancelled > 0 {
+        assets.match_events.push(MatchEvent::Cancel {
+            asset,
+            order_uuid,
+            quantity: quantity_cancelled,
+        });
+    }
+
+    if let Some(execution) = executions.last() {
+        assets.release_triggers(asset, execution.price);
     }
+
+    Ok(PlaceOrderResult {
+        asset,
+        user_uuid,
+        order_index,
+        price,
+        quantity,
+        order_type,
+        stp,
+        time_in_force,
+        side,
+        reduce_only,
+        all_or_none,
+        order_uuid,
+        fill_type,
+        quantity_filled,
+        quantity_remaining,
+        quantity_cancelled,
+        executions,
+        price_improvement,
+    })
 }
 
 /// cancel an order
@@ -280,105 +805,678 @@ pub fn do_cancel_order(
 ) -> Result<(), TradingEngineError> {
     let (order_index, asset) = match assets.order_uuids.get(&order_uuid).cloned() {
         Some((a, b)) => (a, b),
-        None => {
-            return Err(TradingEngineError::OrderNotFound(user_uuid, order_uuid));
-        }
+        None => return assets.cancel_held_order(user_uuid, order_uuid),
     };
 
+    // only the owner of an order may cancel it.
+    match assets.orders.get(&order_uuid) {
+        Some(record) if record.user_uuid == user_uuid => (),
+        _ => return Err(TradingEngineError::OrderNotFound(user_uuid, order_uuid)),
+    }
+
     let asset_book = assets.match_asset_mut(asset);
 
-    asset_book
+    let order = asset_book
         .orderbook_mut()
         .remove(order_index)
         .expect("checked order");
 
-    Ok(())
-}
-
-/// Error that can occur when interacting with the trading engine.
-#[derive(Debug, Error)]
-pub enum TradingEngineError {
-    /// the trading engine is suspended
-    #[error("the trading engine is suspended")]
-    Suspended,
-    /// unserializable input to trading engine
-    #[error("unserializable input to trading engine")]
-    UnserializableInput,
-    /// order not found
-    #[error("order not found for user {0:?} and order uuid {1:?}")]
-    OrderNotFound(uuid::Uuid, OrderUuid),
-    /// database error
-    #[error("database error")]
-    Database(#[from] sqlx::Error),
-    /// error that can occur when executing a pending fill operation.
-    #[error("place order error")]
-    PlaceOrder(#[from] PlaceOrderError),
-}
+    asset_book.resting.remove(&order_index);
+    assets.order_uuids.remove(&order_uuid);
+    assets.match_events.push(MatchEvent::Cancel {
+        asset,
+        order_uuid,
+        quantity: order.quantity.get(),
+    });
+    if let Some(record) = assets.orders.get_mut(&order_uuid) {
+        record.close(OrderStatus::Cancelled);
+    }
 
-/// payload for a trade command
-#[derive(Deserialize, Serialize)]
-#[serde(untagged)]
-pub enum TradeCmdPayload {
-    /// place order data
-    PlaceOrder(PlaceOrder),
-    /// cancel order data
-    CancelOrder(CancelOrder),
+    Ok(())
 }
 
-/// enumeration of all the commands the trading engine can process.
-pub enum TradeCmd {
-    /// place an order
-    PlaceOrder((PlaceOrder, PlaceOrderTx)),
-    /// cancel an order
-    CancelOrder((CancelOrder, CancelOrderTx)),
-}
+/// take quantity off a resting order, returns the quantity left resting.
+///
+/// Only ever shrinking the order lets it keep its place in the queue, an order reduced by all
+/// of what is left is cancelled.
+pub fn do_reduce_order(
+    assets: &mut Assets,
+    ReduceOrder {
+        user_uuid,
+        order_uuid,
+        by,
+    }: ReduceOrder,
+) -> Result<u32, TradingEngineError> {
+    let not_found = || TradingEngineError::OrderNotFound(user_uuid, order_uuid);
+    let (order_index, asset) = assets
+        .order_uuids
+        .get(&order_uuid)
+        .cloned()
+        .ok_or_else(not_found)?;
 
-/// enumeration of all the commands the trading engine can process.
-pub enum TradingEngineCmd {
-    /// a signal to shutdown the trading engine
-    Shutdown,
-    /// suspend the engine
-    Suspend,
-    /// resume the engine if suspended
-    Resume,
-    /// a trade command like placing an order or canceling an order.
-    Trade(TradeCmd),
-    /// a trade command deserialized from json used to initialize the trading engine.
-    Bootstrap(TradeCmdPayload),
-}
-impl TradingEngineCmd {
-    pub(crate) fn consume_respond_with_error(self, err: TradingEngineError) {
-        if let Self::Trade(cmd) = self {
-            match cmd {
-                TradeCmd::PlaceOrder((_, tx)) => {
-                    let _ = tx.send(Err(err));
-                }
-                TradeCmd::CancelOrder((_, tx)) => {
-                    let _ = tx.send(Err(err));
-                }
-            };
-        }
+    // only the owner of an order may reduce it.
+    match assets.orders.get(&order_uuid) {
+        Some(record) if record.user_uuid == user_uuid => (),
+        _ => return Err(not_found()),
     }
-}
 
-/// the "state" of an asset book for a trading engine.
-pub struct AssetBook {
-    asset: Asset,
-    orderbook: Orderbook,
-}
+    let quantity = assets
+        .match_asset(asset)
+        .orderbook
+        .get(order_index)
+        .expect("checked order")
+        .quantity
+        .get();
 
-impl AssetBook {
-    /// create a new asset book
-    pub fn new(asset: Asset) -> Self {
-        Self {
-            asset,
-            orderbook: Orderbook::new(),
-        }
+    if by.get() > quantity {
+        return Err(TradingEngineError::ReduceExceedsQuantity {
+            order_uuid,
+            quantity,
+        });
     }
 
-    /// get the asset
-    pub fn orderbook_mut(&mut self) -> &mut Orderbook {
-        &mut self.orderbook
+    if by.get() == quantity {
+        return do_cancel_order(assets, CancelOrder::new(user_uuid, order_uuid)).map(|()| 0);
+    }
+
+    let order = assets
+        .match_asset_mut(asset)
+        .orderbook_mut()
+        .reduce(order_index, by.get())
+        .expect("checked quantity");
+
+    assets.match_events.push(MatchEvent::Update {
+        asset,
+        order_uuid,
+        quantity_remaining: order.quantity.get(),
+        quantity_reduced: by.get(),
+    });
+    if let Some(record) = assets.orders.get_mut(&order_uuid) {
+        record.record_reduce(by.get());
+    }
+
+    Ok(order.quantity.get())
+}
+
+/// cancel the open order the user placed with a client order id
+pub fn do_cancel_order_by_client_id(
+    assets: &mut Assets,
+    CancelOrderByClientId {
+        user_uuid,
+        client_order_id,
+    }: CancelOrderByClientId,
+) -> Result<(), TradingEngineError> {
+    let key = (user_uuid, client_order_id);
+
+    match assets.open_order_by_client_id(&key) {
+        Some(order_uuid) => do_cancel_order(assets, CancelOrder::new(user_uuid, order_uuid)),
+        None => Err(TradingEngineError::ClientOrderIdNotFound(user_uuid, key.1)),
+    }
+}
+
+/// type-alias for a [`ResponseTx`] that sends [OrderRecord]s.
+pub type FetchOrderTx = ResponseTx<Result<Option<OrderRecord>, TradingEngineError>>;
+
+/// look up the record of an order, `None` if the engine has never seen it.
+pub fn do_fetch_order(assets: &Assets, order_uuid: OrderUuid) -> Option<OrderRecord> {
+    assets.orders.get(&order_uuid).cloned()
+}
+
+/// type-alias for a [`ResponseTx`] that sends the [OrderRecord]s of closed orders.
+pub type ClosedOrdersTx = ResponseTx<Result<Vec<OrderRecord>, TradingEngineError>>;
+
+/// the records of the orders closed before `closed_before`, in milliseconds since the unix epoch,
+/// to be archived.
+///
+/// closed orders are no longer in the book, the engine only keeps their records to answer
+/// [`do_fetch_order`]. they stay until [`do_drop_archived_orders`] is told they are archived.
+pub fn do_closed_orders(assets: &Assets, closed_before: i64) -> Vec<OrderRecord> {
+    assets
+        .orders
+        .values()
+        .filter(|record| record.is_closed())
+        .filter(|record| {
+            record
+                .closed_at
+                .map_or(true, |closed_at| closed_at < closed_before)
+        })
+        .cloned()
+        .collect()
+}
+
+/// type-alias for a [`ResponseTx`] that sends how many archived orders were dropped.
+pub type DropArchivedOrdersTx = ResponseTx<Result<usize, TradingEngineError>>;
+
+/// drop the records of `order_uuids`, once archived, out of the engine. returns how many were
+/// dropped, orders that are unknown or not closed are left alone.
+///
+/// replaying the journal brings them back, dropping them again is harmless.
+pub fn do_drop_archived_orders(assets: &mut Assets, order_uuids: &[OrderUuid]) -> usize {
+    let mut dropped = 0;
+
+    for order_uuid in order_uuids {
+        if assets
+            .orders
+            .get(order_uuid)
+            .is_some_and(|record| record.is_closed())
+        {
+            assets.orders.remove(order_uuid);
+            dropped += 1;
+        }
+    }
+
+    let orders = &assets.orders;
+    assets
+        .client_order_ids
+        .retain(|_, order_uuid| orders.contains_key(order_uuid));
+    assets
+        .last_look_orders
+        .retain(|order_uuid| orders.contains_key(order_uuid));
+
+    dropped
+}
+
+/// the cancels of the good-til-date orders that have expired by `now`, in milliseconds since the
+/// unix epoch, the ones expiring first, first.
+///
+/// the cancels are left to the caller so they are journaled like any other, replaying the
+/// journal then closes the orders at the same point.
+pub fn do_poll_expired(assets: &mut Assets, now: i64) -> Vec<CancelOrder> {
+    let expired = [&mut assets.btc, &mut assets.eth]
+        .into_iter()
+        .flat_map(|asset_book| asset_book.triggers.poll_expired(now))
+        .collect::<Vec<_>>();
+
+    // orders filled or cancelled before they expired were never unscheduled.
+    expired
+        .into_iter()
+        .filter_map(|order_uuid| {
+            let record = assets.orders.get(&order_uuid)?;
+            (!record.is_closed()).then(|| CancelOrder::new(record.user_uuid, order_uuid))
+        })
+        .collect()
+}
+
+/// type-alias for a [`ResponseTx`] that sends subscriptions to [MatchEvent]s.
+pub type SubscribeMatchEventsTx =
+    ResponseTx<Result<tokio::sync::broadcast::Receiver<MatchEvent>, TradingEngineError>>;
+
+/// type-alias for a [`ResponseTx`] that sends a [DepthSnapshot] of a book along with a
+/// subscription to the [MarketData] that follows it.
+pub type SubscribeMarketDataTx = ResponseTx<
+    Result<(DepthSnapshot, tokio::sync::broadcast::Receiver<MarketData>), TradingEngineError>,
+>;
+
+/// type-alias for a [`ResponseTx`] that sends subscriptions to [ExecutionReport]s.
+pub type SubscribeExecutionReportsTx =
+    ResponseTx<Result<tokio::sync::broadcast::Receiver<ExecutionReport>, TradingEngineError>>;
+
+/// type-alias for a [`ResponseTx`] that sends [DepthSnapshot]s.
+pub type DepthSnapshotTx = ResponseTx<Result<DepthSnapshot, TradingEngineError>>;
+
+/// take a snapshot of the price levels of the book for `asset`
+pub fn do_depth_snapshot(assets: &Assets, asset: Asset) -> DepthSnapshot {
+    DepthSnapshot::from_orderbook(asset, &assets.match_asset(asset).orderbook)
+}
+
+/// A single order resting in a book, as seen by the matching engine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RestingOrder {
+    /// the unique identifier for the order, `None` if the engine lost track of it.
+    pub order_uuid: Option<OrderUuid>,
+    /// the user that placed the order, `None` if the engine lost track of it.
+    pub user_uuid: Option<uuid::Uuid>,
+    /// the side of the book the order rests on.
+    pub side: OrderSide,
+    /// the price of the order.
+    pub price: u32,
+    /// the quantity left to fill.
+    pub quantity_remaining: u32,
+    /// the sequence number of the order on its side of the book, earlier orders have lower numbers.
+    pub sequence: u32,
+}
+
+/// A page of the resting orders of a book.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestingOrdersPage {
+    /// the total number of resting orders in the book.
+    pub total: usize,
+    /// the resting orders of this page, bids then asks, each in priority order.
+    pub orders: Vec<RestingOrder>,
+}
+
+/// type-alias for a [`ResponseTx`] that sends [RestingOrdersPage]s.
+pub type RestingOrdersTx = ResponseTx<Result<RestingOrdersPage, TradingEngineError>>;
+
+/// list `limit` resting orders of the book for `asset` starting at `offset`, bids then asks, each in priority order.
+pub fn do_resting_orders(
+    assets: &Assets,
+    asset: Asset,
+    offset: usize,
+    limit: usize,
+) -> RestingOrdersPage {
+    let asset_book = assets.match_asset(asset);
+    let orderbook = &asset_book.orderbook;
+
+    let queue = || orderbook.iter();
+
+    let orders = queue()
+        .skip(offset)
+        .take(limit)
+        .map(|(oix, order)| {
+            let order_uuid = asset_book.resting.get(&oix).copied();
+            let user_uuid = order_uuid
+                .and_then(|order_uuid| assets.orders.get(&order_uuid))
+                .map(|record| record.user_uuid);
+
+            RestingOrder {
+                order_uuid,
+                user_uuid,
+                side: oix.side(),
+                price: order.price.get(),
+                quantity_remaining: order.quantity.get(),
+                sequence: order.memo,
+            }
+        })
+        .collect();
+
+    RestingOrdersPage {
+        total: queue().count(),
+        orders,
+    }
+}
+
+/// type-alias for a [`ResponseTx`] that sends the order next in line to match.
+pub type PeekTopTx =
+    ResponseTx<Result<Option<(NonZeroU32, NonZeroU32, OrderUuid)>, TradingEngineError>>;
+
+/// the price, remaining quantity and uuid of the order on `side` of the book for `asset` that
+/// an incoming order matches first, the front of the best price level.
+pub fn do_peek_top(
+    assets: &Assets,
+    asset: Asset,
+    side: OrderSide,
+) -> Option<(NonZeroU32, NonZeroU32, OrderUuid)> {
+    let asset_book = assets.match_asset(asset);
+    let (oix, order) = asset_book.orderbook.iter_rel(side).next()?;
+    let order_uuid = asset_book.resting.get(&oix).copied()?;
+
+    Some((order.price, order.quantity, order_uuid))
+}
+
+/// The orders of one asset book, part of [`EngineStats`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BookStats {
+    /// the asset of the book.
+    pub asset: Asset,
+    /// orders resting on the bid side.
+    pub resting_bids: usize,
+    /// orders resting on the ask side.
+    pub resting_asks: usize,
+    /// orders held off the book until their trigger is touched.
+    pub held: usize,
+}
+
+/// A snapshot of the trading engine's internals, taken inside the engine task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EngineStats {
+    /// the orders of each asset book.
+    pub books: Vec<BookStats>,
+    /// order records kept by the engine, open and closed.
+    pub orders_tracked: usize,
+    /// trade commands processed since the engine started, replayed ones included.
+    pub commands_processed: u64,
+    /// how long the last trade command took, journal write included, in microseconds.
+    pub last_command_latency_us: Option<u64>,
+}
+
+/// type-alias for a [`ResponseTx`] that sends [EngineStats].
+pub type EngineStatsTx = ResponseTx<Result<EngineStats, TradingEngineError>>;
+
+/// count the orders of every book, the command counters are left for the engine task to fill in.
+pub fn do_engine_stats(assets: &Assets) -> EngineStats {
+    let books = [&assets.btc, &assets.eth]
+        .into_iter()
+        .map(|asset_book| BookStats {
+            asset: asset_book.asset,
+            resting_bids: asset_book.orderbook.iter_rel(OrderSide::Buy).count(),
+            resting_asks: asset_book.orderbook.iter_rel(OrderSide::Sell).count(),
+            held: asset_book.triggers.len(),
+        })
+        .collect();
+
+    EngineStats {
+        books,
+        orders_tracked: assets.orders.len(),
+        commands_processed: 0,
+        last_command_latency_us: None,
+    }
+}
+
+/// The resting orders of a book before and after it was rebuilt from the journal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RebuildReport {
+    /// the asset of the book.
+    pub asset: Asset,
+    /// the orders resting in the book it replaced.
+    pub orders_before: usize,
+    /// the orders resting in the rebuilt book.
+    pub orders_after: usize,
+}
+
+/// type-alias for a [`ResponseTx`] that sends [RebuildReport]s.
+pub type RebuildBookTx = ResponseTx<Result<RebuildReport, TradingEngineError>>;
+
+/// apply a command read back from the journal, its events are dropped since they were published
+/// when the command first ran.
+///
+/// the per-level cap is not applied, it may have been lowered since an order it let rest was
+/// journalled and turning the order away now would undo fills that were already settled.
+pub fn do_replay(assets: &mut Assets, payload: TradeCmdPayload) {
+    let max_orders_per_price_level = assets.max_orders_per_price_level.take();
+
+    let _ = match payload {
+        TradeCmdPayload::PlaceOrder(place_order) => {
+            place_journalled_order(assets, place_order).map(drop)
+        }
+        TradeCmdPayload::CancelOrder(cancel_order) => do_cancel_order(assets, cancel_order),
+        TradeCmdPayload::CancelOrderByClientId(cancel_order) => {
+            do_cancel_order_by_client_id(assets, cancel_order)
+        }
+        TradeCmdPayload::ReduceOrder(reduce_order) => {
+            do_reduce_order(assets, reduce_order).map(drop)
+        }
+    };
+
+    assets.max_orders_per_price_level = max_orders_per_price_level;
+    assets.drain_match_events().for_each(drop);
+}
+
+/// swap the book for `asset`, and what the engine tracks about its orders, for the ones in
+/// `rebuilt`. the other books are left as they are.
+pub fn do_rebuild_book(assets: &mut Assets, mut rebuilt: Assets, asset: Asset) -> RebuildReport {
+    let resting = |assets: &Assets| assets.match_asset(asset).orderbook.iter().count();
+    let orders_before = resting(assets);
+
+    std::mem::swap(
+        assets.match_asset_mut(asset),
+        rebuilt.match_asset_mut(asset),
+    );
+
+    // forget the orders of the replaced book, then take the rebuilt ones.
+    let orders = &assets.orders;
+    let other_asset = |order_uuid: &OrderUuid| {
+        orders
+            .get(order_uuid)
+            .is_some_and(|record| record.asset != asset)
+    };
+    assets
+        .client_order_ids
+        .retain(|_, order_uuid| other_asset(order_uuid));
+    assets
+        .last_look_orders
+        .retain(|order_uuid| other_asset(order_uuid));
+    assets.order_uuids.retain(|_, (_, a)| *a != asset);
+    assets.orders.retain(|_, record| record.asset != asset);
+
+    assets.order_uuids.extend(
+        rebuilt
+            .order_uuids
+            .into_iter()
+            .filter(|(_, (_, a))| *a == asset),
+    );
+    assets.orders.extend(
+        rebuilt
+            .orders
+            .into_iter()
+            .filter(|(_, record)| record.asset == asset),
+    );
+
+    let orders = &assets.orders;
+    let this_asset = |order_uuid: &OrderUuid| {
+        orders
+            .get(order_uuid)
+            .is_some_and(|record| record.asset == asset)
+    };
+    assets.client_order_ids.extend(
+        rebuilt
+            .client_order_ids
+            .into_iter()
+            .filter(|(_, order_uuid)| this_asset(order_uuid)),
+    );
+    assets.last_look_orders.extend(
+        rebuilt
+            .last_look_orders
+            .into_iter()
+            .filter(|order_uuid| this_asset(order_uuid)),
+    );
+
+    RebuildReport {
+        asset,
+        orders_before,
+        orders_after: resting(assets),
+    }
+}
+
+/// Error that can occur when interacting with the trading engine.
+#[derive(Debug, Error)]
+pub enum TradingEngineError {
+    /// the trading engine is suspended
+    #[error("the trading engine is suspended")]
+    Suspended,
+    /// unserializable input to trading engine
+    #[error("unserializable input to trading engine")]
+    UnserializableInput,
+    /// order not found
+    #[error("order not found for user {0:?} and order uuid {1:?}")]
+    OrderNotFound(uuid::Uuid, OrderUuid),
+    /// the command waited in the queue past its deadline and was not processed
+    #[error("deadline exceeded before the command was processed")]
+    DeadlineExceeded,
+    /// a row of the journal could not be read back as a command
+    #[error("journal entry {0} is not a trade command")]
+    JournalUnreadable(i64),
+    /// no open order with the client order id
+    #[error("no open order for user {0:?} with client order id {1:?}")]
+    ClientOrderIdNotFound(uuid::Uuid, String),
+    /// a reduce asked for more than the order has resting
+    #[error("order {order_uuid:?} has only {quantity} resting")]
+    ReduceExceedsQuantity {
+        /// the order that was to be reduced.
+        order_uuid: OrderUuid,
+        /// the quantity it has resting.
+        quantity: u32,
+    },
+    /// database error
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+    /// error that can occur when executing a pending fill operation.
+    #[error("place order error")]
+    PlaceOrder(#[from] PlaceOrderError),
+}
+
+/// payload for a trade command
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum TradeCmdPayload {
+    /// place order data
+    PlaceOrder(PlaceOrder),
+    /// reduce order data, ahead of [`CancelOrder`] which would otherwise read it too.
+    ReduceOrder(ReduceOrder),
+    /// cancel order data
+    CancelOrder(CancelOrder),
+    /// cancel order by client order id data
+    CancelOrderByClientId(CancelOrderByClientId),
+}
+
+/// enumeration of all the commands the trading engine can process.
+pub enum TradeCmd {
+    /// place an order
+    PlaceOrder((PlaceOrder, PlaceOrderTx)),
+    /// cancel an order
+    CancelOrder((CancelOrder, CancelOrderTx)),
+    /// cancel an order by the client order id it was placed with
+    CancelOrderByClientId((CancelOrderByClientId, CancelOrderTx)),
+    /// take quantity off a resting order
+    ReduceOrder((ReduceOrder, ReduceOrderTx)),
+}
+
+/// enumeration of all the commands the trading engine can process.
+pub enum TradingEngineCmd {
+    /// a signal to shutdown the trading engine
+    Shutdown,
+    /// suspend the engine
+    Suspend,
+    /// resume the engine if suspended
+    Resume,
+    /// a trade command like placing an order or canceling an order, processed inside the span of the caller.
+    ///
+    /// a command still queued at its deadline is answered with
+    /// [`TradingEngineError::DeadlineExceeded`] instead, the caller has stopped waiting for it.
+    Trade(TradeCmd, tracing::Span, Option<tokio::time::Instant>),
+    /// a trade command deserialized from json used to initialize the trading engine.
+    Bootstrap(TradeCmdPayload),
+    /// take a snapshot of the price levels of an asset book.
+    DepthSnapshot((Asset, DepthSnapshotTx)),
+    /// fetch the record of a single order.
+    FetchOrder((OrderUuid, FetchOrderTx)),
+    /// list a page of `(offset, limit)` resting orders of an asset book.
+    RestingOrders((Asset, usize, usize, RestingOrdersTx)),
+    /// look at the order next in line to match on one side of an asset book.
+    PeekTop((Asset, OrderSide, PeekTopTx)),
+    /// subscribe to the [`MatchEvent`]s of every book from now on.
+    SubscribeMatchEvents(SubscribeMatchEventsTx),
+    /// subscribe to the [`ExecutionReport`]s of every order from now on.
+    SubscribeExecutionReports(SubscribeExecutionReportsTx),
+    /// take a snapshot of an asset book and subscribe to the [`MarketData`] of every book from
+    /// then on.
+    SubscribeMarketData((Asset, SubscribeMarketDataTx)),
+    /// list the records of the orders closed before a time, to be archived.
+    ClosedOrders((i64, ClosedOrdersTx)),
+    /// drop the records of closed orders out of the engine, once they are archived.
+    DropArchivedOrders((Vec<OrderUuid>, DropArchivedOrdersTx)),
+    /// report the engine's internal counters.
+    Stats(EngineStatsTx),
+    /// ask a maker about the matches against their last-look orders from now on.
+    SubscribeLastLook((uuid::Uuid, SubscribeLastLookTx)),
+    /// replace the book of an asset with the one in assets rebuilt from the journal up to an id,
+    /// after the entries journalled since are replayed into them too.
+    RebuildBook((Asset, Box<Assets>, i64, RebuildBookTx)),
+    /// heartbeat a registered task as the engine runs, dropping it marks the engine stopped.
+    TrackLiveness(crate::app_cx::TaskHandle),
+}
+impl TradingEngineCmd {
+    /// wrap a [`TradeCmd`] so the engine processes it inside the current span, e.g. the span of a web request.
+    pub fn trade(cmd: TradeCmd) -> Self {
+        Self::Trade(cmd, tracing::Span::current(), None)
+    }
+
+    /// like [`TradingEngineCmd::trade`] but skipped if the engine only gets to it after `deadline`.
+    pub fn trade_by(cmd: TradeCmd, deadline: tokio::time::Instant) -> Self {
+        Self::Trade(cmd, tracing::Span::current(), Some(deadline))
+    }
+
+    /// `true` for a trade command the engine got to after its deadline.
+    pub(crate) fn is_past_deadline(&self, now: tokio::time::Instant) -> bool {
+        matches!(self, Self::Trade(_, _, Some(deadline)) if *deadline <= now)
+    }
+
+    pub(crate) fn consume_respond_with_error(self, err: TradingEngineError) {
+        match self {
+            Self::Trade(TradeCmd::PlaceOrder((_, tx)), ..) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::Trade(TradeCmd::CancelOrder((_, tx)), ..) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::Trade(TradeCmd::CancelOrderByClientId((_, tx)), ..) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::Trade(TradeCmd::ReduceOrder((_, tx)), ..) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::DepthSnapshot((_, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::FetchOrder((_, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::RestingOrders((_, _, _, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::PeekTop((_, _, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::SubscribeMatchEvents(tx) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::SubscribeExecutionReports(tx) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::SubscribeMarketData((_, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::ClosedOrders((_, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::DropArchivedOrders((_, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::Stats(tx) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::SubscribeLastLook((_, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            Self::RebuildBook((_, _, _, tx)) => {
+                let _ = tx.send(Err(err));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// the "state" of an asset book for a trading engine.
+pub struct AssetBook {
+    asset: Asset,
+    orderbook: Orderbook,
+    /// map of resting order indexes back to their order uuids.
+    resting: ahash::AHashMap<OrderIndex, OrderUuid>,
+    /// orders held off the book until the last trade touches their trigger.
+    triggers: Triggers,
+    /// the price of the most recent trade, `None` until the book trades.
+    last_price: Option<NonZeroU32>,
+    /// the order types the book accepts.
+    allowed_order_types: Vec<OrderType>,
+    /// how a taker is shared out among the orders at a price level.
+    matching_policy: MatchingPolicy,
+}
+
+impl AssetBook {
+    /// create a new asset book
+    pub fn new(asset: Asset) -> Self {
+        Self::with_capacity(asset, OrderbookCapacity::default())
+    }
+
+    /// create a new asset book with room for `capacity` price levels and resting orders.
+    ///
+    /// the space is allocated up front and held for the life of the book, whether or not it
+    /// is used, trading memory for never reallocating as a busy book fills up.
+    pub fn with_capacity(asset: Asset, capacity: OrderbookCapacity) -> Self {
+        Self {
+            asset,
+            orderbook: Orderbook::with_capacity(capacity.price_levels),
+            resting: ahash::AHashMap::with_capacity(capacity.orders),
+            triggers: Triggers::default(),
+            last_price: None,
+            allowed_order_types: OrderType::ALL.to_vec(),
+            matching_policy: MatchingPolicy::default(),
+        }
+    }
+
+    /// get the asset
+    pub fn orderbook_mut(&mut self) -> &mut Orderbook {
+        &mut self.orderbook
     }
 }
 
@@ -386,6 +1484,20 @@ impl AssetBook {
 pub struct Assets {
     /// map of order uuids to order indexes and assets.
     pub order_uuids: ahash::AHashMap<OrderUuid, (OrderIndex, Asset)>,
+    /// records of every order placed, resting or not.
+    pub orders: ahash::AHashMap<OrderUuid, OrderRecord>,
+    /// liquidity the opposite side of a book must have before a market order is matched against it.
+    pub market_order_liquidity: MarketOrderLiquidity,
+    /// the maximum number of orders that may rest at a single price, unlimited if `None`.
+    pub max_orders_per_price_level: Option<usize>,
+    /// the last nonce processed for each user that has opted into nonce sequencing.
+    pub nonces: ahash::AHashMap<uuid::Uuid, u64>,
+    /// map of `(user uuid, client order id)` to the order last placed with it.
+    pub client_order_ids: ahash::AHashMap<(uuid::Uuid, String), OrderUuid>,
+    /// orders whose makers take a last look before they are matched, see [`last_look`].
+    pub last_look_orders: ahash::AHashSet<OrderUuid>,
+    /// the changes made to the books since they were last drained, in the order they were made.
+    match_events: Vec<MatchEvent>,
     /// the asset book for ether
     pub eth: AssetBook,
     /// the asset book for bitcoin
@@ -393,76 +1505,473 @@ pub struct Assets {
 }
 
 impl Assets {
-    fn match_asset_mut(&mut self, asset: Asset) -> &mut AssetBook {
-        match asset {
-            Asset::Ether => &mut self.eth,
-            Asset::Bitcoin => &mut self.btc,
+    /// create empty asset books for every asset.
+    pub fn new() -> Self {
+        Self {
+            order_uuids: Default::default(),
+            orders: Default::default(),
+            market_order_liquidity: Default::default(),
+            max_orders_per_price_level: None,
+            nonces: Default::default(),
+            client_order_ids: Default::default(),
+            last_look_orders: Default::default(),
+            match_events: Vec::new(),
+            eth: AssetBook::new(Asset::Ether),
+            btc: AssetBook::new(Asset::Bitcoin),
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use tokio::task_local;
-    use uuid::Uuid;
 
-    use crate::spawn_trading_engine::{spawn_trading_engine, SpawnTradingEngine};
-    use crate::Configuration;
+    /// create empty asset books that enforce the matching limits set in `config`.
+    pub fn from_config(config: &crate::Configuration) -> Self {
+        let capacity = |asset| {
+            let capacity = config.orderbook_capacity.get(&asset).copied();
+            let mut asset_book = AssetBook::with_capacity(asset, capacity.unwrap_or_default());
+            asset_book.allowed_order_types = config.allowed_order_types(asset).to_vec();
+            asset_book.matching_policy = config.matching_policy(asset);
+            asset_book
+        };
 
-    use super::*;
+        let mut assets = Self::new();
+        assets.eth = capacity(Asset::Ether);
+        assets.btc = capacity(Asset::Bitcoin);
+        assets.market_order_liquidity = config.market_order_liquidity;
+        assets.max_orders_per_price_level = config.max_orders_per_price_level;
+        assets
+    }
 
-    task_local! {
-        static CX: (TradingEngineTx, sqlx::PgPool);
+    /// the [`MatchEvent`]s emitted since they were last drained, oldest first.
+    pub fn match_events(&self) -> &[MatchEvent] {
+        &self.match_events
     }
 
-    async fn trading_engine_fixture(db: sqlx::PgPool) -> (Configuration, SpawnTradingEngine) {
-        let config = crate::config::Configuration::load_from_toml("");
-        let spawn_trading_engine = spawn_trading_engine(&config, db);
-        (config, spawn_trading_engine)
+    /// take the [`MatchEvent`]s emitted since the last call, oldest first.
+    pub fn drain_match_events(&mut self) -> std::vec::Drain<'_, MatchEvent> {
+        self.match_events.drain(..)
     }
 
-    async fn place_order(user_uuid: Uuid, price: u32, quantity: u32) -> PlaceOrderResult {
-        let (te, db) = CX.with(|cx| cx.clone());
+    /// the places in the book of the resting orders in `rejections`.
+    fn last_look_rejected(&self, rejections: &[OrderUuid]) -> Vec<OrderIndex> {
+        rejections
+            .iter()
+            .filter_map(|order_uuid| self.order_uuids.get(order_uuid))
+            .map(|(order_index, _)| *order_index)
+            .collect()
+    }
 
-        let (tx, rx) = oneshot::channel();
-        let order = PlaceOrder {
-            asset: Asset::Bitcoin,
-            user_uuid: Uuid::new_v4(),
-            price: NonZeroU32::new(price).expect("price was zero"),
-            quantity: NonZeroU32::new(quantity).expect("quantity was zero"),
-            order_type: OrderType::Market,
-            stp: SelfTradeProtection::CancelOldest,
-            time_in_force: TimeInForce::GoodTilCanceled,
-            side: OrderSide::Buy,
-        };
+    /// record on `place_order` the matching policy of its book, so the order is matched the
+    /// same way when the log is replayed under a different policy.
+    pub fn record_matching_policy(&self, place_order: &mut PlaceOrder) {
+        place_order.matching_policy = Some(self.match_asset(place_order.asset).matching_policy);
+    }
 
-        te.send(TradingEngineCmd::Trade(TradeCmd::PlaceOrder((order, tx))))
-            .await
-            .expect("place-order send error");
+    /// the open order placed with the client order id of `key`, if any.
+    fn open_order_by_client_id(&self, key: &(uuid::Uuid, String)) -> Option<OrderUuid> {
+        let order_uuid = self.client_order_ids.get(key).copied()?;
+        let record = self.orders.get(&order_uuid)?;
+        (!record.is_closed()).then_some(order_uuid)
+    }
 
-        rx.await
-            .expect("oneshot rx failure")
-            .expect("place-order Err")
+    /// reject `client_order_id` if it already names one of the open orders of `user_uuid`.
+    ///
+    /// the id of a filled or cancelled order can be used again.
+    fn check_client_order_id(
+        &self,
+        user_uuid: uuid::Uuid,
+        client_order_id: &str,
+    ) -> Result<(), PlaceOrderError> {
+        match self.open_order_by_client_id(&(user_uuid, client_order_id.to_owned())) {
+            Some(_) => Err(PlaceOrderError::DuplicateClientOrderId),
+            None => Ok(()),
+        }
     }
 
-    #[sqlx::test]
-    async fn test_startup_then_shutdown(db: sqlx::PgPool) {
-        let (_config, te) = trading_engine_fixture(db).await;
-        te.input.send(TradingEngineCmd::Shutdown).await.unwrap();
-        te.handle.await.unwrap();
+    /// consume `nonce` for `user_uuid`, rejecting it unless it is greater than the last one processed.
+    ///
+    /// users opt in by sending their first nonce, from then on orders without one are rejected
+    /// so a replayed request can not slip past the check by dropping the field.
+    fn check_nonce(
+        &mut self,
+        user_uuid: uuid::Uuid,
+        nonce: Option<u64>,
+    ) -> Result<(), PlaceOrderError> {
+        match (self.nonces.get(&user_uuid).copied(), nonce) {
+            (None, None) => Ok(()),
+            (Some(_), None) => Err(PlaceOrderError::NonceRequired),
+            (Some(last), Some(nonce)) if nonce <= last => Err(PlaceOrderError::StaleNonce { last }),
+            (_, Some(nonce)) => {
+                self.nonces.insert(user_uuid, nonce);
+                Ok(())
+            }
+        }
     }
 
-    pub fn new_user_uuid() -> Uuid {
-        Uuid::new_v4()
+    fn match_asset(&self, asset: Asset) -> &AssetBook {
+        match asset {
+            Asset::Ether => &self.eth,
+            Asset::Bitcoin => &self.btc,
+        }
     }
 
-    #[sqlx::test(migrations = "../migrations")]
-    async fn test_place_order(db: sqlx::PgPool) {
-        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+    fn match_asset_mut(&mut self, asset: Asset) -> &mut AssetBook {
+        match asset {
+            Asset::Ether => &mut self.eth,
+            Asset::Bitcoin => &mut self.btc,
+        }
+    }
 
-        let (_config, te) = trading_engine_fixture(db.clone()).await;
-        let (te, _task) = te.init_from_db(db.clone()).await.unwrap();
-        CX.scope((te, db), async {
+    /// the book of `asset` along with the records of every order, borrowed apart.
+    fn match_asset_and_orders_mut(
+        &mut self,
+        asset: Asset,
+    ) -> (&mut AssetBook, &ahash::AHashMap<OrderUuid, OrderRecord>) {
+        let book = match asset {
+            Asset::Ether => &mut self.eth,
+            Asset::Bitcoin => &mut self.btc,
+        };
+        (book, &self.orders)
+    }
+
+    /// `true` if the side opposite `side` meets [`Assets::market_order_liquidity`].
+    fn has_market_liquidity(&self, asset: Asset, side: OrderSide) -> bool {
+        let MarketOrderLiquidity {
+            min_quantity,
+            min_levels,
+        } = self.market_order_liquidity;
+
+        if min_quantity.is_none() && min_levels.is_none() {
+            return true;
+        }
+
+        let levels = self.match_asset(asset).orderbook.depth(side.opposite());
+        let quantity: u64 = levels.iter().map(|level| level.quantity).sum();
+
+        min_quantity.map_or(true, |min| quantity >= min)
+            && min_levels.map_or(true, |min| levels.len() >= min)
+    }
+
+    /// apply the fills of resting maker orders by `taker` to their records.
+    /// take what self-trade protection decided off the resting orders of `user_uuid` the taker ran
+    /// into, reducing the `decrements` and cancelling the `cancels`.
+    fn apply_self_trade_protection(
+        &mut self,
+        asset: Asset,
+        user_uuid: uuid::Uuid,
+        decrements: &[pending_fill::MakerFill],
+        cancels: &[OrderIndex],
+    ) {
+        for decrement in decrements {
+            let Some(&order_uuid) = self.match_asset(asset).resting.get(&decrement.oix) else {
+                tracing::warn!(oix = ?decrement.oix, "decrement of an order without a uuid");
+                continue;
+            };
+            let by = NonZeroU32::new(decrement.fill_amount).expect("decrements are never empty");
+            if let Err(err) = do_reduce_order(self, ReduceOrder::new(user_uuid, order_uuid, by)) {
+                tracing::error!(?err, ?order_uuid, "failed to decrement self-trade");
+            }
+        }
+
+        for oix in cancels {
+            let Some(&order_uuid) = self.match_asset(asset).resting.get(oix) else {
+                tracing::warn!(?oix, "self-trade cancel of an order without a uuid");
+                continue;
+            };
+            if let Err(err) = do_cancel_order(self, CancelOrder::new(user_uuid, order_uuid)) {
+                tracing::error!(?err, ?order_uuid, "failed to cancel self-trade");
+            }
+        }
+    }
+
+    fn record_maker_fills(
+        &mut self,
+        asset: Asset,
+        taker: OrderUuid,
+        taker_side: OrderSide,
+        taker_type: OrderType,
+        taker_price: NonZeroU32,
+        maker_fills: &[pending_fill::MakerFill],
+    ) -> Vec<Execution> {
+        let mut executions = Vec::with_capacity(maker_fills.len());
+
+        for fill in maker_fills {
+            let resting = &mut self.match_asset_mut(asset).resting;
+            let order_uuid = match fill.fill_type {
+                FillType::Complete => resting.remove(&fill.oix),
+                _ => resting.get(&fill.oix).copied(),
+            };
+
+            executions.push(Execution {
+                maker_order_uuid: order_uuid,
+                maker_user_uuid: order_uuid
+                    .and_then(|order_uuid| self.orders.get(&order_uuid))
+                    .map(|record| record.user_uuid),
+                price: fill.maker.price,
+                quantity: fill.fill_amount,
+                price_improvement: Execution::price_improvement_of(
+                    taker_side,
+                    taker_type,
+                    taker_price,
+                    fill.maker.price,
+                    fill.fill_amount,
+                ),
+            });
+
+            self.match_events.push(MatchEvent::Fill {
+                asset,
+                maker: order_uuid,
+                taker,
+                quantity: fill.fill_amount,
+                price: fill.maker.price,
+            });
+
+            let Some(order_uuid) = order_uuid else {
+                tracing::warn!(oix = ?fill.oix, "maker fill for an order without a uuid");
+                continue;
+            };
+
+            if fill.fill_type == FillType::Complete {
+                self.order_uuids.remove(&order_uuid);
+            }
+
+            if let Some(record) = self.orders.get_mut(&order_uuid) {
+                record.record_fill(fill.fill_amount);
+
+                if fill.fill_type != FillType::Complete {
+                    self.match_events.push(MatchEvent::Update {
+                        asset,
+                        order_uuid,
+                        quantity_remaining: record.quantity_remaining(),
+                        quantity_reduced: 0,
+                    });
+                }
+            }
+        }
+
+        executions
+    }
+
+    /// `order_uuid`, or one derived from it if another order already has it.
+    ///
+    /// a v4 collision is practically impossible, but if one happened inserting the order would
+    /// overwrite the other order's entries and orphan it in the book.
+    fn unique_order_uuid(&self, mut order_uuid: OrderUuid) -> OrderUuid {
+        while self.orders.contains_key(&order_uuid) {
+            let fresh = order_uuid.rederive();
+            tracing::error!(
+                ?order_uuid,
+                ?fresh,
+                "order uuid collision, using a fresh uuid"
+            );
+            order_uuid = fresh;
+        }
+
+        order_uuid
+    }
+
+    /// hold an order with a trigger off the book, it is matched once [`Self::release_triggers`] finds it touched.
+    fn hold_order(&mut self, place_order: PlaceOrder) -> PlaceOrderResult {
+        let result = PlaceOrderResult {
+            asset: place_order.asset,
+            user_uuid: place_order.user_uuid,
+            price: place_order.price,
+            quantity: place_order.quantity,
+            order_type: place_order.order_type,
+            stp: place_order.stp,
+            time_in_force: place_order.time_in_force,
+            side: place_order.side,
+            reduce_only: place_order.reduce_only,
+            all_or_none: place_order.all_or_none,
+            order_uuid: place_order.order_uuid,
+            order_index: None,
+            fill_type: FillType::None,
+            quantity_filled: 0,
+            quantity_remaining: place_order.quantity.get(),
+            quantity_cancelled: 0,
+            executions: vec![],
+            price_improvement: 0,
+        };
+
+        self.record_order(
+            OrderRecord {
+                order_uuid: place_order.order_uuid,
+                user_uuid: place_order.user_uuid,
+                asset: place_order.asset,
+                side: place_order.side,
+                order_type: place_order.order_type,
+                price: place_order.price,
+                quantity: place_order.quantity,
+                quantity_filled: 0,
+                status: OrderStatus::Held,
+                created_at: place_order.created_at,
+                expires_at: place_order.expires_at,
+                closed_at: None,
+                client_order_id: place_order.client_order_id.clone(),
+            },
+            None,
+        );
+
+        self.match_asset_mut(place_order.asset)
+            .triggers
+            .hold(place_order);
+
+        result
+    }
+
+    /// record a trade at `last_price` and match the held orders it touches, oldest first.
+    ///
+    /// released orders can trade and release more orders in turn. nobody is waiting on their
+    /// results, an order that fails to match is logged and marked cancelled.
+    fn release_triggers(&mut self, asset: Asset, last_price: NonZeroU32) {
+        let asset_book = self.match_asset_mut(asset);
+        asset_book.last_price = Some(last_price);
+
+        for place_order in asset_book.triggers.release(last_price) {
+            let order_uuid = place_order.order_uuid;
+            tracing::debug!(?order_uuid, %last_price, "trigger touched, matching held order");
+
+            if let Err(err) = match_order(self, place_order) {
+                tracing::warn!(
+                    ?err,
+                    ?order_uuid,
+                    "held order failed to match once released"
+                );
+
+                if let Some(record) = self.orders.get_mut(&order_uuid) {
+                    record.close(OrderStatus::Cancelled);
+                    self.match_events.push(MatchEvent::Cancel {
+                        asset,
+                        order_uuid,
+                        quantity: record.quantity_remaining(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// cancel an order held by its trigger, the owner of an order is the only one who may cancel it.
+    fn cancel_held_order(
+        &mut self,
+        user_uuid: uuid::Uuid,
+        order_uuid: OrderUuid,
+    ) -> Result<(), TradingEngineError> {
+        let asset = match self.orders.get(&order_uuid) {
+            Some(record) if record.user_uuid == user_uuid && record.status == OrderStatus::Held => {
+                record.asset
+            }
+            _ => return Err(TradingEngineError::OrderNotFound(user_uuid, order_uuid)),
+        };
+
+        self.match_asset_mut(asset).triggers.cancel(order_uuid);
+        if let Some(record) = self.orders.get_mut(&order_uuid) {
+            record.close(OrderStatus::Cancelled);
+            self.match_events.push(MatchEvent::Cancel {
+                asset,
+                order_uuid,
+                quantity: record.quantity_remaining(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// track a newly placed order, and its place in the book if it is resting.
+    fn record_order(&mut self, mut record: OrderRecord, order_index: Option<OrderIndex>) {
+        // filled or cancelled on placement.
+        if record.is_closed() && record.closed_at.is_none() {
+            record.close(record.status);
+        }
+
+        if let (Some(expires_at), false) = (record.expires_at, record.is_closed()) {
+            self.match_asset_mut(record.asset)
+                .triggers
+                .schedule_expiry(record.order_uuid, expires_at);
+        }
+
+        if let Some(order_index) = order_index {
+            self.match_asset_mut(record.asset)
+                .resting
+                .insert(order_index, record.order_uuid);
+            self.order_uuids
+                .insert(record.order_uuid, (order_index, record.asset));
+        }
+
+        self.orders.insert(record.order_uuid, record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::task_local;
+    use uuid::Uuid;
+
+    use crate::spawn_trading_engine::{spawn_trading_engine, SpawnTradingEngine};
+    use crate::Configuration;
+
+    use super::*;
+
+    task_local! {
+        static CX: (TradingEngineTx, sqlx::PgPool);
+    }
+
+    async fn trading_engine_fixture(db: sqlx::PgPool) -> (Configuration, SpawnTradingEngine) {
+        let config = crate::config::Configuration::defaults_for_test();
+        let spawn_trading_engine = spawn_trading_engine(&config, db);
+        (config, spawn_trading_engine)
+    }
+
+    async fn place_order(user_uuid: Uuid, price: u32, quantity: u32) -> PlaceOrderResult {
+        let (te, db) = CX.with(|cx| cx.clone());
+
+        let (tx, rx) = response_channel(None);
+        let order = PlaceOrder {
+            asset: Asset::Bitcoin,
+            user_uuid: Uuid::new_v4(),
+            price: NonZeroU32::new(price).expect("price was zero"),
+            quantity: NonZeroU32::new(quantity).expect("quantity was zero"),
+            order_type: OrderType::Market,
+            stp: SelfTradeProtection::CancelOldest,
+            time_in_force: TimeInForce::GoodTilCanceled,
+            side: OrderSide::Buy,
+            reduce_only: false,
+            all_or_none: false,
+            order_uuid: OrderUuid::new_v4(),
+            created_at: 0,
+            expires_at: None,
+            nonce: None,
+            trigger: None,
+            client_order_id: None,
+            last_look: false,
+            last_look_rejections: Vec::new(),
+            matching_policy: None,
+        };
+
+        te.send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((order, tx))))
+            .await
+            .expect("place-order send error");
+
+        rx.recv()
+            .await
+            .expect("response rx failure")
+            .expect("place-order Err")
+    }
+
+    #[sqlx::test]
+    async fn test_startup_then_shutdown(db: sqlx::PgPool) {
+        let (_config, te) = trading_engine_fixture(db).await;
+        te.input.send(TradingEngineCmd::Shutdown).await.unwrap();
+        te.handle.await.unwrap();
+    }
+
+    pub fn new_user_uuid() -> Uuid {
+        Uuid::new_v4()
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_place_order(db: sqlx::PgPool) {
+        let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+
+        let (_config, te) = trading_engine_fixture(db.clone()).await;
+        let (te, _task) = te.init_from_db(db.clone()).await.unwrap();
+        CX.scope((te, db), async {
             let users = (0..100).map(|_| new_user_uuid()).collect::<Vec<_>>();
             let bob = users[0];
 
@@ -470,4 +1979,1558 @@ mod tests {
             assert_eq!(asset, Asset::Bitcoin);
         });
     }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_last_look_rejection_moves_the_taker_to_the_next_level(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.last_look.enabled = true;
+        config.last_look.window_ms = 250;
+        config.last_look.total_ms = 250;
+        let (te, _task) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db)
+            .await
+            .unwrap();
+
+        let place = |order: PlaceOrder| {
+            let te = te.clone();
+            async move {
+                let (tx, rx) = response_channel(None);
+                te.send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((order, tx))))
+                    .await
+                    .unwrap();
+                rx.recv().await.unwrap().unwrap()
+            }
+        };
+
+        // the better priced ask takes a last look, the next level does not.
+        let mut last_look_ask = limit_order(OrderSide::Sell, 100, 5, false).with_last_look(true);
+        let maker_uuid = new_user_uuid();
+        last_look_ask.user_uuid = maker_uuid;
+        let last_look_ask = place(last_look_ask).await.order_uuid;
+        let next_ask = place(limit_order(OrderSide::Sell, 101, 5, false))
+            .await
+            .order_uuid;
+
+        let (tx, rx) = response_channel(None);
+        te.send(TradingEngineCmd::SubscribeLastLook((maker_uuid, tx)))
+            .await
+            .unwrap();
+        let mut last_looks = rx.recv().await.unwrap().unwrap();
+
+        let maker = tokio::spawn(async move {
+            let request = last_looks.recv().await.unwrap();
+            request.decision.send(false).unwrap();
+            request.quote
+        });
+
+        let taker = place(limit_order(OrderSide::Buy, 101, 5, false)).await;
+        let quote = maker.await.unwrap();
+
+        assert_eq!(quote.order_uuid, last_look_ask);
+        assert_eq!((quote.price.get(), quote.quantity), (100, 5));
+        assert_eq!(taker.executions.len(), 1);
+        assert_eq!(taker.executions[0].maker_order_uuid, Some(next_ask));
+        assert_eq!(taker.executions[0].price.get(), 101);
+        assert_eq!(taker.fill_type, FillType::Complete);
+
+        // the rejected order keeps its place in the book.
+        let (tx, rx) = response_channel(None);
+        te.send(TradingEngineCmd::FetchOrder((last_look_ask, tx)))
+            .await
+            .unwrap();
+        let record = rx.recv().await.unwrap().unwrap().unwrap();
+        assert_eq!(record.status, OrderStatus::Open);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_last_looks_of_one_taker_share_a_total_wait(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.last_look.enabled = true;
+        config.last_look.window_ms = 100;
+        config.last_look.total_ms = 150;
+        let (te, _task) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db)
+            .await
+            .unwrap();
+
+        let place = |order: PlaceOrder| {
+            let te = te.clone();
+            async move {
+                let (tx, rx) = response_channel(None);
+                te.send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((order, tx))))
+                    .await
+                    .unwrap();
+                rx.recv().await.unwrap().unwrap()
+            }
+        };
+
+        let maker_uuid = new_user_uuid();
+        for price in [100, 101, 102] {
+            let mut ask = limit_order(OrderSide::Sell, price, 1, false).with_last_look(true);
+            ask.user_uuid = maker_uuid;
+            place(ask).await;
+        }
+
+        let (tx, rx) = response_channel(None);
+        te.send(TradingEngineCmd::SubscribeLastLook((maker_uuid, tx)))
+            .await
+            .unwrap();
+        let mut last_looks = rx.recv().await.unwrap().unwrap();
+
+        // the maker never answers, the first look takes a whole window and the second the rest.
+        let taker = place(limit_order(OrderSide::Buy, 102, 3, false)).await;
+        assert_eq!(taker.fill_type, FillType::Complete);
+
+        let mut asked = 0;
+        while last_looks.try_recv().is_ok() {
+            asked += 1;
+        }
+        assert_eq!(asked, 2);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_rebuild_book_restores_the_journalled_state(db: sqlx::PgPool) {
+        let config = Configuration::defaults_for_test();
+        let (te, _task) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+
+        for order in [
+            limit_order(OrderSide::Buy, 100, 5, false),
+            limit_order(OrderSide::Sell, 110, 3, false),
+        ] {
+            let (tx, rx) = response_channel(None);
+            te.send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((order, tx))))
+                .await
+                .unwrap();
+            rx.recv().await.unwrap().unwrap();
+        }
+
+        // an order the journal never saw stands in for a corrupt book.
+        let stray = limit_order(OrderSide::Buy, 105, 1, false);
+        te.send(TradingEngineCmd::Bootstrap(TradeCmdPayload::PlaceOrder(
+            stray,
+        )))
+        .await
+        .unwrap();
+
+        let mut rebuilt = Box::new(Assets::from_config(&config));
+        let through = crate::spawn_trading_engine::replay_journal(&mut rebuilt, &db, 0)
+            .await
+            .unwrap();
+
+        // placed after the replay above, the engine catches the rebuilt book up with it.
+        let (tx, rx) = response_channel(None);
+        te.send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((
+            limit_order(OrderSide::Buy, 99, 2, false),
+            tx,
+        ))))
+        .await
+        .unwrap();
+        rx.recv().await.unwrap().unwrap();
+
+        let (tx, rx) = response_channel(None);
+        te.send(TradingEngineCmd::RebuildBook((
+            Asset::Bitcoin,
+            rebuilt,
+            through,
+            tx,
+        )))
+        .await
+        .unwrap();
+        let report = rx.recv().await.unwrap().unwrap();
+        assert_eq!((report.orders_before, report.orders_after), (4, 3));
+
+        let mut journalled = Assets::new();
+        let rows = sqlx::query!("SELECT jstr FROM trading_event_source ORDER BY id")
+            .fetch_all(&db)
+            .await
+            .unwrap();
+        for row in rows {
+            do_replay(&mut journalled, serde_json::from_value(row.jstr).unwrap());
+        }
+
+        let (tx, rx) = response_channel(None);
+        te.send(TradingEngineCmd::DepthSnapshot((Asset::Bitcoin, tx)))
+            .await
+            .unwrap();
+        let depth = rx.recv().await.unwrap().unwrap();
+        assert_eq!(depth, do_depth_snapshot(&journalled, Asset::Bitcoin));
+        assert_eq!(depth.bids.len(), 2);
+    }
+
+    fn limit_order(side: OrderSide, price: u32, quantity: u32, reduce_only: bool) -> PlaceOrder {
+        PlaceOrder::new(
+            Asset::Bitcoin,
+            new_user_uuid(),
+            NonZeroU32::new(price).expect("price was zero"),
+            NonZeroU32::new(quantity).expect("quantity was zero"),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilCanceled,
+            side,
+            reduce_only,
+            false,
+            None,
+        )
+    }
+
+    fn gtd_order(expires_at: Option<i64>) -> PlaceOrder {
+        let mut order = limit_order(OrderSide::Buy, 100, 1, false);
+        order.time_in_force = TimeInForce::GoodTilDate;
+        order.created_at = 1_000_000;
+        order.expires_at = expires_at;
+        order
+    }
+
+    const TTL: OrderTtl = OrderTtl {
+        default_secs: 60,
+        max_secs: 3600,
+        clamp_to_max: false,
+    };
+
+    #[test]
+    fn test_in_order_nonces_are_accepted() {
+        let mut assets = Assets::new();
+        let user_uuid = new_user_uuid();
+
+        for nonce in [1, 2, 10] {
+            let mut order = limit_order(OrderSide::Buy, 100, 1, false).with_nonce(Some(nonce));
+            order.user_uuid = user_uuid;
+            do_place_order(&mut assets, order).expect("in-order nonce was rejected");
+        }
+
+        assert_eq!(assets.nonces.get(&user_uuid), Some(&10));
+    }
+
+    #[test]
+    fn test_stale_nonces_are_rejected() {
+        let mut assets = Assets::new();
+        let user_uuid = new_user_uuid();
+
+        let order = |nonce| {
+            let mut order = limit_order(OrderSide::Buy, 100, 1, false).with_nonce(nonce);
+            order.user_uuid = user_uuid;
+            order
+        };
+
+        do_place_order(&mut assets, order(Some(5))).unwrap();
+
+        for (nonce, expected) in [
+            (Some(5), "duplicate"),
+            (Some(4), "out-of-order"),
+            (None, "missing"),
+        ] {
+            let err = do_place_order(&mut assets, order(nonce)).err();
+            assert!(
+                matches!(
+                    err,
+                    Some(TradingEngineError::PlaceOrder(
+                        PlaceOrderError::StaleNonce { last: 5 } | PlaceOrderError::NonceRequired
+                    ))
+                ),
+                "{expected} nonce was accepted"
+            );
+        }
+
+        // rejected orders never reach the book.
+        assert_eq!(assets.orders.len(), 1);
+
+        // other users are unaffected until they opt in themselves.
+        do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 1, false)).unwrap();
+    }
+
+    #[test]
+    fn test_client_order_ids_are_unique_among_open_orders() {
+        let mut assets = Assets::new();
+        let user_uuid = new_user_uuid();
+
+        let order = || {
+            let mut order = limit_order(OrderSide::Buy, 100, 1, false)
+                .with_client_order_id(Some("abc".to_owned()));
+            order.user_uuid = user_uuid;
+            order
+        };
+
+        let placed = do_place_order(&mut assets, order()).unwrap();
+
+        assert!(matches!(
+            do_place_order(&mut assets, order()),
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::DuplicateClientOrderId
+            ))
+        ));
+
+        let cancel = || CancelOrderByClientId::new(user_uuid, "abc".to_owned());
+        do_cancel_order_by_client_id(&mut assets, cancel()).unwrap();
+        assert_eq!(
+            assets.orders[&placed.order_uuid].status,
+            OrderStatus::Cancelled
+        );
+
+        // the order is closed, so the id no longer resolves and is free to be used again.
+        assert!(matches!(
+            do_cancel_order_by_client_id(&mut assets, cancel()),
+            Err(TradingEngineError::ClientOrderIdNotFound(..))
+        ));
+        let replaced = do_place_order(&mut assets, order()).unwrap();
+        assert_ne!(replaced.order_uuid, placed.order_uuid);
+    }
+
+    #[test]
+    fn test_closed_orders_stay_until_dropped_as_archived() {
+        let mut assets = Assets::new();
+        let closed = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 1, false))
+            .unwrap()
+            .order_uuid;
+        let open = do_place_order(&mut assets, limit_order(OrderSide::Buy, 101, 1, false))
+            .unwrap()
+            .order_uuid;
+        let user_uuid = assets.orders[&closed].user_uuid;
+        do_cancel_order(&mut assets, CancelOrder::new(user_uuid, closed)).unwrap();
+
+        // listing them for the archive leaves them in the engine.
+        let records = do_closed_orders(&assets, i64::MAX);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].order_uuid, closed);
+        assert!(assets.orders.contains_key(&closed));
+
+        // only closed orders are dropped, whatever the caller asks for.
+        assert_eq!(do_drop_archived_orders(&mut assets, &[closed, open]), 1);
+        assert!(!assets.orders.contains_key(&closed));
+        assert!(assets.orders.contains_key(&open));
+        assert_eq!(do_drop_archived_orders(&mut assets, &[closed]), 0);
+    }
+
+    #[test]
+    fn test_gtd_without_expiry_gets_default_ttl() {
+        let mut order = gtd_order(None);
+        order.apply_ttl(&TTL).unwrap();
+        assert_eq!(order.expires_at(), Some(1_000_000 + 60_000));
+    }
+
+    #[test]
+    fn test_gtd_beyond_max_ttl() {
+        let beyond = Some(1_000_000 + 3_600_001);
+
+        let mut order = gtd_order(beyond);
+        assert_eq!(order.apply_ttl(&TTL), Err(ExpiryError::BeyondMaximum));
+
+        let clamp = OrderTtl {
+            clamp_to_max: true,
+            ..TTL
+        };
+        let mut order = gtd_order(beyond);
+        order.apply_ttl(&clamp).unwrap();
+        assert_eq!(order.expires_at(), Some(1_000_000 + 3_600_000));
+    }
+
+    #[test]
+    fn test_gtd_within_bounds_is_honored() {
+        let mut order = gtd_order(Some(1_000_000 + 120_000));
+        order.apply_ttl(&TTL).unwrap();
+        assert_eq!(order.expires_at(), Some(1_000_000 + 120_000));
+
+        let mut order = gtd_order(Some(1_000_000));
+        assert_eq!(order.apply_ttl(&TTL), Err(ExpiryError::InThePast));
+
+        let mut order = limit_order(OrderSide::Buy, 100, 1, false);
+        order.expires_at = Some(2_000_000);
+        assert_eq!(order.apply_ttl(&TTL), Err(ExpiryError::NotGoodTilDate));
+    }
+
+    #[test]
+    fn test_gtd_relative_expiry_uses_server_time() {
+        use std::time::Duration;
+
+        // `created_at` is stamped by the server, the client only says "good for 30 seconds".
+        let mut order = gtd_order(None);
+        order.expire_after(Duration::from_secs(30)).unwrap();
+        order.apply_ttl(&TTL).unwrap();
+        assert_eq!(order.expires_at(), Some(1_000_000 + 30_000));
+
+        // relative expiries are capped like absolute ones.
+        let mut order = gtd_order(None);
+        order.expire_after(Duration::from_secs(3601)).unwrap();
+        assert_eq!(order.apply_ttl(&TTL), Err(ExpiryError::BeyondMaximum));
+
+        let mut order = gtd_order(Some(1_000_000 + 120_000));
+        assert_eq!(
+            order.expire_after(Duration::from_secs(30)),
+            Err(ExpiryError::Conflicting)
+        );
+    }
+
+    #[test]
+    fn test_expired_gtd_orders_are_cancelled() {
+        let mut assets = Assets::new();
+
+        let mut place = |expires_at| {
+            do_place_order(&mut assets, gtd_order(Some(expires_at)))
+                .unwrap()
+                .order_uuid
+        };
+        let first = place(1_060_000);
+        let soon = place(1_060_000);
+        let later = place(1_120_000);
+
+        // the oldest is filled before it expires, it is not cancelled again.
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 1, false)).unwrap();
+        assert_eq!(assets.orders[&first].status, OrderStatus::Filled);
+
+        assert!(do_poll_expired(&mut assets, 1_059_999).is_empty());
+
+        let cancels = do_poll_expired(&mut assets, 1_060_000);
+        assert_eq!(
+            cancels.iter().map(|c| c.order_uuid).collect::<Vec<_>>(),
+            vec![soon]
+        );
+        for cancel_order in cancels {
+            do_cancel_order(&mut assets, cancel_order).unwrap();
+        }
+
+        assert_eq!(assets.orders[&soon].status, OrderStatus::Cancelled);
+        assert_eq!(assets.orders[&later].status, OrderStatus::Open);
+        assert_engine_invariants(&assets);
+
+        let cancels = do_poll_expired(&mut assets, 1_120_000);
+        assert_eq!(cancels.len(), 1);
+        assert_eq!(cancels[0].order_uuid, later);
+    }
+
+    #[test]
+    fn test_reserved_capacity_is_not_outgrown() {
+        const ORDERS: usize = 1000;
+
+        let mut assets = Assets::new();
+        assets.btc = AssetBook::with_capacity(
+            Asset::Bitcoin,
+            OrderbookCapacity {
+                price_levels: ORDERS,
+                orders: ORDERS,
+            },
+        );
+
+        let levels = assets.btc.orderbook.bids.inner.capacity();
+        let resting = assets.btc.resting.capacity();
+        assert!(levels >= ORDERS && resting >= ORDERS);
+
+        // every order rests at its own price, so each adds both a level and an index entry.
+        for price in 1..=ORDERS as u32 {
+            do_place_order(&mut assets, limit_order(OrderSide::Buy, price, 1, false)).unwrap();
+        }
+
+        assert_eq!(assets.btc.orderbook.bids.inner.len(), ORDERS);
+        assert_eq!(assets.btc.resting.len(), ORDERS);
+        assert_eq!(assets.btc.orderbook.bids.inner.capacity(), levels);
+        assert_eq!(assets.btc.resting.capacity(), resting);
+    }
+
+    #[test]
+    fn test_colliding_order_uuid_is_replaced() {
+        let mut assets = Assets::new();
+
+        let first = limit_order(OrderSide::Buy, 100, 1, false);
+        let taken = first.order_uuid;
+        do_place_order(&mut assets, first).unwrap();
+
+        // force the collision by reusing the uuid of the resting order.
+        let mut second = limit_order(OrderSide::Buy, 90, 1, false);
+        second.order_uuid = taken;
+        let res = do_place_order(&mut assets, second).unwrap();
+
+        assert_ne!(res.order_uuid, taken);
+        assert_eq!(
+            res.order_uuid,
+            taken.rederive(),
+            "replays must agree on the uuid"
+        );
+        assert_eq!(assets.orders[&taken].price.get(), 100);
+        assert_eq!(assets.orders[&res.order_uuid].price.get(), 90);
+
+        // both orders are still reachable through their own uuid.
+        for (order_index, order_uuid) in &assets.btc.resting {
+            assert_eq!(assets.order_uuids[order_uuid].0, *order_index);
+        }
+        assert_eq!(assets.btc.resting.len(), 2);
+
+        let user_uuid = assets.orders[&taken].user_uuid;
+        do_cancel_order(&mut assets, CancelOrder::new(user_uuid, taken)).unwrap();
+        assert_eq!(assets.btc.resting.len(), 1);
+    }
+
+    /// an ask of 3 at 100 by the taker's owner ahead of an ask of 5 at 101 by someone else, then
+    /// a buy of `quantity` at 101 under `stp`.
+    fn place_self_trade(
+        stp: SelfTradeProtection,
+        quantity: u32,
+    ) -> (Assets, OrderUuid, OrderUuid, PlaceOrderResult) {
+        let mut assets = Assets::new();
+        let owner = new_user_uuid();
+
+        let mut own_ask = limit_order(OrderSide::Sell, 100, 3, false);
+        own_ask.user_uuid = owner;
+        let own_ask = do_place_order(&mut assets, own_ask).unwrap().order_uuid;
+        let other_ask = do_place_order(&mut assets, limit_order(OrderSide::Sell, 101, 5, false))
+            .unwrap()
+            .order_uuid;
+
+        let mut taker = limit_order(OrderSide::Buy, 101, quantity, false);
+        taker.user_uuid = owner;
+        taker.stp = stp;
+        let res = do_place_order(&mut assets, taker).unwrap();
+        assert_engine_invariants(&assets);
+
+        (assets, own_ask, other_ask, res)
+    }
+
+    #[test]
+    fn test_self_trade_decrease_cancel() {
+        // the smaller own ask is cancelled and the taker carries on with what is left of it.
+        let (assets, own_ask, other_ask, res) =
+            place_self_trade(SelfTradeProtection::DecreaseCancel, 6);
+        assert_eq!(res.quantity_filled, 3);
+        assert_eq!(res.quantity_cancelled, 3);
+        assert_eq!(res.executions.len(), 1);
+        assert_eq!(res.executions[0].maker_order_uuid, Some(other_ask));
+        assert_eq!(assets.orders[&own_ask].status, OrderStatus::Cancelled);
+        assert_eq!(assets.orders[&other_ask].quantity_remaining(), 2);
+
+        // the larger own ask is reduced by the taker, which is cancelled without trading.
+        let (assets, own_ask, _, res) = place_self_trade(SelfTradeProtection::DecreaseCancel, 2);
+        assert_eq!(res.fill_type, FillType::None);
+        assert_eq!(res.quantity_filled, 0);
+        assert_eq!(res.quantity_cancelled, 2);
+        assert_eq!(
+            assets.orders[&res.order_uuid].status,
+            OrderStatus::Cancelled
+        );
+        assert_eq!(assets.orders[&own_ask].quantity_remaining(), 1);
+        assert_eq!(assets.orders[&own_ask].status, OrderStatus::Open);
+
+        // a taker that rests after its decrement rests with what is left of it.
+        let (assets, _, _, res) = place_self_trade(SelfTradeProtection::DecreaseCancel, 10);
+        assert_eq!(res.quantity_filled, 5);
+        assert_eq!(res.quantity_remaining, 2);
+        assert!(res.order_index.is_some());
+        assert_eq!(assets.orders[&res.order_uuid].quantity_remaining(), 2);
+    }
+
+    #[test]
+    fn test_self_trade_decrements_release_their_reserve() {
+        let releases = |assets: &Assets| {
+            assets
+                .settlements(assets.match_events())
+                .into_iter()
+                .filter_map(|settlement| match settlement {
+                    Settlement::Release {
+                        order, quantity, ..
+                    } => Some((order.order_uuid, quantity)),
+                    Settlement::Fill { .. } => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the own ask is decremented away, the taker rests what is left after its fill.
+        let (assets, own_ask, _, res) = place_self_trade(SelfTradeProtection::DecreaseCancel, 10);
+        assert_eq!(releases(&assets), [(own_ask, 3), (res.order_uuid, 3)]);
+
+        // the own ask is reduced and stays, the taker is decremented away.
+        let (assets, own_ask, _, res) = place_self_trade(SelfTradeProtection::DecreaseCancel, 2);
+        assert_eq!(releases(&assets), [(own_ask, 2), (res.order_uuid, 2)]);
+    }
+
+    #[test]
+    fn test_self_trade_cancel_oldest() {
+        let (assets, own_ask, other_ask, res) =
+            place_self_trade(SelfTradeProtection::CancelOldest, 6);
+        assert_eq!(res.quantity_filled, 5);
+        assert_eq!(res.executions[0].maker_order_uuid, Some(other_ask));
+        assert_eq!(assets.orders[&own_ask].status, OrderStatus::Cancelled);
+
+        // the rest of the taker rests, the book is not left crossed.
+        assert!(res.order_index.is_some());
+        assert_eq!(res.quantity_remaining, 1);
+        assert_eq!(assets.btc.orderbook.iter_rel(OrderSide::Sell).count(), 0);
+    }
+
+    #[test]
+    fn test_self_trade_cancel_newest() {
+        let (assets, own_ask, other_ask, res) =
+            place_self_trade(SelfTradeProtection::CancelNewest, 6);
+        assert_eq!(res.fill_type, FillType::None);
+        assert_eq!(res.quantity_cancelled, 6);
+        assert_eq!(res.order_index, None);
+        assert_eq!(
+            assets.orders[&res.order_uuid].status,
+            OrderStatus::Cancelled
+        );
+        assert_eq!(assets.orders[&own_ask].quantity_remaining(), 3);
+        assert_eq!(assets.orders[&other_ask].quantity_remaining(), 5);
+    }
+
+    #[test]
+    fn test_self_trade_cancel_both() {
+        let (assets, own_ask, other_ask, res) =
+            place_self_trade(SelfTradeProtection::CancelBoth, 6);
+        assert_eq!(res.quantity_filled, 0);
+        assert_eq!(res.quantity_cancelled, 6);
+        assert_eq!(
+            assets.orders[&res.order_uuid].status,
+            OrderStatus::Cancelled
+        );
+        assert_eq!(assets.orders[&own_ask].status, OrderStatus::Cancelled);
+        assert_eq!(assets.orders[&other_ask].quantity_remaining(), 5);
+    }
+
+    #[test]
+    fn test_reduce_order_keeps_its_place_in_the_queue() {
+        let mut assets = Assets::new();
+        let first = limit_order(OrderSide::Sell, 100, 10, false);
+        let second = limit_order(OrderSide::Sell, 100, 5, false);
+        let (first_uuid, second_uuid) = (first.order_uuid, second.order_uuid);
+        let owner = first.user_uuid;
+        do_place_order(&mut assets, first).unwrap();
+        do_place_order(&mut assets, second).unwrap();
+        assets.drain_match_events().for_each(drop);
+
+        let reduce =
+            |order_uuid, by| ReduceOrder::new(owner, order_uuid, NonZeroU32::new(by).unwrap());
+
+        // only the owner may reduce an order.
+        let res = do_reduce_order(
+            &mut assets,
+            ReduceOrder::new(new_user_uuid(), first_uuid, NonZeroU32::new(1).unwrap()),
+        );
+        assert!(matches!(res, Err(TradingEngineError::OrderNotFound(..))));
+
+        assert_eq!(
+            do_reduce_order(&mut assets, reduce(first_uuid, 4)).unwrap(),
+            6
+        );
+        assert_eq!(
+            assets.drain_match_events().collect::<Vec<_>>(),
+            vec![MatchEvent::Update {
+                asset: Asset::Bitcoin,
+                order_uuid: first_uuid,
+                quantity_remaining: 6,
+                quantity_reduced: 4,
+            }]
+        );
+
+        // a buy for 6 is still filled by the reduced order first.
+        let res = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 6, false)).unwrap();
+        assert_eq!(res.executions.len(), 1);
+        assert_eq!(res.executions[0].maker_order_uuid, Some(first_uuid));
+        assert_engine_invariants(&assets);
+    }
+
+    #[test]
+    fn test_reduce_order_by_too_much_or_everything() {
+        let mut assets = Assets::new();
+        let order = limit_order(OrderSide::Sell, 100, 5, false);
+        let (order_uuid, owner) = (order.order_uuid, order.user_uuid);
+        do_place_order(&mut assets, order).unwrap();
+        assets.drain_match_events().for_each(drop);
+
+        let reduce = |by| ReduceOrder::new(owner, order_uuid, NonZeroU32::new(by).unwrap());
+
+        let res = do_reduce_order(&mut assets, reduce(6));
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::ReduceExceedsQuantity { quantity: 5, .. })
+        ));
+        assert_eq!(assets.orders[&order_uuid].quantity_remaining(), 5);
+        assert_eq!(assets.drain_match_events().count(), 0);
+
+        // taking off all of it cancels the order.
+        assert_eq!(do_reduce_order(&mut assets, reduce(5)).unwrap(), 0);
+        assert_eq!(
+            assets.drain_match_events().collect::<Vec<_>>(),
+            vec![MatchEvent::Cancel {
+                asset: Asset::Bitcoin,
+                order_uuid,
+                quantity: 5,
+            }]
+        );
+        assert_eq!(assets.orders[&order_uuid].status, OrderStatus::Cancelled);
+        assert!(!assets.order_uuids.contains_key(&order_uuid));
+        assert_engine_invariants(&assets);
+
+        // a reduce is read back from the journal as itself, not as the cancel it resembles.
+        let jstr = serde_json::to_value(reduce(1)).unwrap();
+        let payload = serde_json::from_value::<TradeCmdPayload>(jstr).unwrap();
+        assert!(matches!(payload, TradeCmdPayload::ReduceOrder(_)));
+
+        let jstr = serde_json::to_value(CancelOrder::new(owner, order_uuid)).unwrap();
+        let payload = serde_json::from_value::<TradeCmdPayload>(jstr).unwrap();
+        assert!(matches!(payload, TradeCmdPayload::CancelOrder(_)));
+    }
+
+    #[test]
+    fn test_reduce_only_complete_fill() {
+        let mut assets = Assets::new();
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 10, false)).unwrap();
+
+        let res = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 10, true)).unwrap();
+        assert_eq!(res.fill_type, FillType::Complete);
+        assert_eq!(res.quantity_filled, 10);
+        assert_eq!(res.quantity_remaining, 0);
+        assert_eq!(res.order_index, None);
+    }
+
+    #[test]
+    fn test_reduce_only_remainder_is_not_booked() {
+        let mut assets = Assets::new();
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 4, false)).unwrap();
+
+        let res = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 10, true)).unwrap();
+        assert_eq!(res.fill_type, FillType::Partial);
+        assert_eq!(res.quantity_filled, 4);
+        assert_eq!(res.order_index, None);
+        assert_eq!(
+            assets.btc.orderbook_mut().iter_rel(OrderSide::Buy).count(),
+            0
+        );
+
+        // the same order without reduce-only rests its remainder.
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 4, false)).unwrap();
+        let res = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 10, false)).unwrap();
+        assert!(res.order_index.is_some());
+        assert_eq!(
+            assets.btc.orderbook_mut().iter_rel(OrderSide::Buy).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_reduce_only_without_liquidity_is_rejected() {
+        let mut assets = Assets::new();
+
+        let res = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 10, true));
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::InsufficientLiquidity
+            ))
+        ));
+        assert_eq!(
+            assets.btc.orderbook_mut().iter_rel(OrderSide::Buy).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_market_order_below_liquidity_threshold_is_rejected() {
+        let mut assets = Assets::new();
+        assets.market_order_liquidity = MarketOrderLiquidity {
+            min_quantity: Some(10),
+            min_levels: Some(2),
+        };
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 4, false)).unwrap();
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 101, 4, false)).unwrap();
+
+        let mut order = limit_order(OrderSide::Buy, 101, 1, false);
+        order.order_type = OrderType::Market;
+
+        let res = do_place_order(&mut assets, order);
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::InsufficientLiquidity
+            ))
+        ));
+
+        let asks = assets.btc.orderbook.depth(OrderSide::Sell);
+        assert_eq!(asks.iter().map(|level| level.quantity).sum::<u64>(), 8);
+
+        // enough resting quantity lets the same order through.
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 101, 2, false)).unwrap();
+        let mut order = limit_order(OrderSide::Buy, 101, 1, false);
+        order.order_type = OrderType::Market;
+        assert!(do_place_order(&mut assets, order).is_ok());
+    }
+
+    #[test]
+    fn test_market_order_sweeps_the_book_and_never_rests() {
+        let mut assets = Assets::new();
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 2, false)).unwrap();
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 105, 3, false)).unwrap();
+
+        // only the levels within the worst price are taken, the level beyond it is left.
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 106, 4, false)).unwrap();
+
+        // a good-til-canceled market order still does not rest.
+        let mut order = limit_order(OrderSide::Buy, 105, 10, false);
+        order.order_type = OrderType::Market;
+
+        let res = do_place_order(&mut assets, order).unwrap();
+        assert_eq!(res.fill_type, FillType::Partial);
+        assert_eq!(res.quantity_filled, 5);
+        assert_eq!(res.quantity_cancelled, 5);
+        assert_eq!(res.order_index, None);
+        assert_eq!(
+            res.executions
+                .iter()
+                .map(|execution| execution.price.get())
+                .collect::<Vec<_>>(),
+            vec![100, 105]
+        );
+        assert_eq!(
+            assets.orders[&res.order_uuid].status,
+            OrderStatus::Cancelled
+        );
+        assert_eq!(
+            assets.btc.orderbook_mut().iter_rel(OrderSide::Buy).count(),
+            0
+        );
+        assert_eq!(
+            assets.btc.orderbook_mut().iter_rel(OrderSide::Sell).count(),
+            1
+        );
+        assert_engine_invariants(&assets);
+
+        // nothing within the worst price is no liquidity at all.
+        let mut order = limit_order(OrderSide::Buy, 105, 1, false);
+        order.order_type = OrderType::Market;
+        let res = do_place_order(&mut assets, order);
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::InsufficientLiquidity
+            ))
+        ));
+
+        // a book it can not take anything from turns the order away rather than resting it.
+        let mut maker = limit_order(OrderSide::Sell, 100, 5, false);
+        maker.all_or_none = true;
+        do_place_order(&mut assets, maker).unwrap();
+
+        let mut order = limit_order(OrderSide::Buy, 100, 1, false);
+        order.order_type = OrderType::Market;
+        let res = do_place_order(&mut assets, order);
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::InsufficientLiquidity
+            ))
+        ));
+        assert_eq!(
+            assets.btc.orderbook_mut().iter_rel(OrderSide::Buy).count(),
+            0
+        );
+        assert_engine_invariants(&assets);
+    }
+
+    #[test]
+    fn test_market_order_into_an_empty_book_is_rejected() {
+        let mut assets = Assets::new();
+
+        let mut order = limit_order(OrderSide::Buy, 100, 1, false);
+        order.order_type = OrderType::Market;
+
+        let res = do_place_order(&mut assets, order);
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(PlaceOrderError::Match(
+                TryFillOrdersError::NoLiquidity {
+                    side: OrderSide::Sell
+                }
+            )))
+        ));
+        assert_eq!(
+            assets.btc.orderbook_mut().iter_rel(OrderSide::Buy).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_disallowed_order_type_is_rejected() {
+        let mut config = crate::config::Configuration::defaults_for_test();
+        config
+            .allowed_order_types
+            .insert(Asset::Bitcoin, vec![OrderType::Limit]);
+        let mut assets = Assets::from_config(&config);
+
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 4, false)).unwrap();
+
+        let mut order = limit_order(OrderSide::Buy, 100, 1, false);
+        order.order_type = OrderType::Market;
+        let res = do_place_order(&mut assets, order);
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::OrderTypeNotAllowed {
+                    order_type: OrderType::Market
+                }
+            ))
+        ));
+
+        // a limit order on the same asset still trades.
+        let res = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 1, false)).unwrap();
+        assert_eq!(res.quantity_filled, 1);
+        assert_engine_invariants(&assets);
+    }
+
+    #[test]
+    fn test_replay_places_orders_of_types_no_longer_allowed() {
+        let mut config = crate::config::Configuration::defaults_for_test();
+        config
+            .allowed_order_types
+            .insert(Asset::Bitcoin, vec![OrderType::Limit]);
+        let mut assets = Assets::from_config(&config);
+
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 4, false)).unwrap();
+
+        // journalled while market orders were still allowed, replaying it must trade again.
+        let mut order = limit_order(OrderSide::Buy, 100, 1, false);
+        order.order_type = OrderType::Market;
+        let order_uuid = order.order_uuid;
+        do_replay(&mut assets, TradeCmdPayload::PlaceOrder(order));
+
+        assert_eq!(assets.orders[&order_uuid].status, OrderStatus::Filled);
+        assert_engine_invariants(&assets);
+    }
+
+    #[test]
+    fn test_replay_ignores_a_lowered_price_level_cap() {
+        let mut assets = Assets::new();
+        assets.max_orders_per_price_level = Some(1);
+
+        // journalled while the cap allowed two orders at 100, then the sell that filled both.
+        let first = limit_order(OrderSide::Buy, 100, 1, false);
+        let second = limit_order(OrderSide::Buy, 100, 1, false);
+        let second_uuid = second.order_uuid;
+        let sell = limit_order(OrderSide::Sell, 100, 2, false);
+        let sell_uuid = sell.order_uuid;
+        for order in [first, second, sell] {
+            do_replay(&mut assets, TradeCmdPayload::PlaceOrder(order));
+        }
+
+        assert_eq!(assets.orders[&second_uuid].status, OrderStatus::Filled);
+        assert_eq!(assets.orders[&sell_uuid].status, OrderStatus::Filled);
+        assert_eq!(assets.btc.orderbook.iter().count(), 0);
+        assert_engine_invariants(&assets);
+
+        // orders placed after the replay are held to the cap.
+        do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 1, false)).unwrap();
+        let res = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 1, false));
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::PriceLevelFull
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_replay_uses_the_logged_matching_policy() {
+        let mut live = Assets::new();
+        let mut journal = Vec::new();
+
+        // two makers at 100, then a taker for 3 logged while the book matched by price-time.
+        let first = limit_order(OrderSide::Sell, 100, 3, false);
+        let first_uuid = first.order_uuid;
+        let second = limit_order(OrderSide::Sell, 100, 3, false);
+        let second_uuid = second.order_uuid;
+        let buy = limit_order(OrderSide::Buy, 100, 3, false);
+        for mut order in [first, second, buy] {
+            live.record_matching_policy(&mut order);
+            journal.push(serde_json::to_string(&order).unwrap());
+            do_place_order(&mut live, order).unwrap();
+        }
+        assert_eq!(live.orders[&first_uuid].status, OrderStatus::Filled);
+        assert_eq!(live.orders[&second_uuid].status, OrderStatus::Open);
+
+        // the policy is changed to pro-rata before the log is replayed.
+        let mut assets = Assets::new();
+        assets.btc.matching_policy = MatchingPolicy::ProRata;
+        for order in &journal {
+            let order: PlaceOrder = serde_json::from_str(order).unwrap();
+            do_replay(&mut assets, TradeCmdPayload::PlaceOrder(order));
+        }
+
+        assert_eq!(assets.orders[&first_uuid].status, OrderStatus::Filled);
+        assert_eq!(assets.orders[&second_uuid].status, OrderStatus::Open);
+        assert_eq!(assets.orders[&second_uuid].quantity_filled, 0);
+        assert_engine_invariants(&assets);
+
+        // orders placed after the replay are matched by the new policy.
+        let mut order = limit_order(OrderSide::Sell, 100, 3, false);
+        assets.record_matching_policy(&mut order);
+        assert_eq!(order.matching_policy, Some(MatchingPolicy::ProRata));
+    }
+
+    #[test]
+    fn test_full_price_level_rejects_resting_orders() {
+        let mut assets = Assets::new();
+        assets.max_orders_per_price_level = Some(3);
+
+        for _ in 0..3 {
+            do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 1, false)).unwrap();
+        }
+
+        let res = do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 1, false));
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::PriceLevelFull
+            ))
+        ));
+        assert_eq!(
+            assets
+                .btc
+                .orderbook
+                .level_len(OrderSide::Buy, NonZeroU32::new(100).unwrap()),
+            3
+        );
+
+        // other prices, and orders that do not rest, are unaffected.
+        do_place_order(&mut assets, limit_order(OrderSide::Buy, 99, 1, false)).unwrap();
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 1, false)).unwrap();
+    }
+
+    #[test]
+    fn test_resting_orders_in_priority_order() {
+        let mut assets = Assets::new();
+
+        let mut place = |side, price, quantity| {
+            do_place_order(&mut assets, limit_order(side, price, quantity, false))
+                .unwrap()
+                .order_uuid
+        };
+
+        // a filled order is no longer resting.
+        let filled = place(OrderSide::Sell, 103, 6);
+        place(OrderSide::Buy, 103, 6);
+
+        let bid_99 = place(OrderSide::Buy, 99, 1);
+        let bid_100_a = place(OrderSide::Buy, 100, 2);
+        let bid_100_b = place(OrderSide::Buy, 100, 3);
+        let ask_102 = place(OrderSide::Sell, 102, 4);
+        let ask_101 = place(OrderSide::Sell, 101, 5);
+
+        let page = do_resting_orders(&assets, Asset::Bitcoin, 0, 100);
+        let uuids = page
+            .orders
+            .iter()
+            .map(|order| order.order_uuid.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(page.total, 5);
+        assert_eq!(uuids, vec![bid_100_a, bid_100_b, bid_99, ask_101, ask_102]);
+        assert!(!uuids.contains(&filled));
+        assert_eq!(page.orders[0].quantity_remaining, 2);
+        assert!(page.orders[0].sequence < page.orders[1].sequence);
+        assert_eq!(
+            page.orders[0].user_uuid,
+            Some(assets.orders[&bid_100_a].user_uuid)
+        );
+
+        let page = do_resting_orders(&assets, Asset::Bitcoin, 2, 2);
+        assert_eq!(page.total, 5);
+        assert_eq!(
+            page.orders
+                .iter()
+                .map(|order| order.order_uuid.unwrap())
+                .collect::<Vec<_>>(),
+            vec![bid_99, ask_101]
+        );
+    }
+
+    #[test]
+    fn test_executions_for_each_level_swept() {
+        let mut assets = Assets::new();
+
+        let mut place = |side, price, quantity| {
+            do_place_order(&mut assets, limit_order(side, price, quantity, false)).unwrap()
+        };
+
+        let ask_100 = place(OrderSide::Sell, 100, 2);
+        let ask_101 = place(OrderSide::Sell, 101, 3);
+        let ask_102 = place(OrderSide::Sell, 102, 4);
+
+        let result = place(OrderSide::Buy, 102, 7);
+
+        assert_eq!(result.quantity_filled, 7);
+        assert_eq!(
+            result.executions,
+            vec![
+                Execution {
+                    maker_order_uuid: Some(ask_100.order_uuid),
+                    maker_user_uuid: Some(ask_100.user_uuid),
+                    price: NonZeroU32::new(100).unwrap(),
+                    quantity: 2,
+                    price_improvement: 4,
+                },
+                Execution {
+                    maker_order_uuid: Some(ask_101.order_uuid),
+                    maker_user_uuid: Some(ask_101.user_uuid),
+                    price: NonZeroU32::new(101).unwrap(),
+                    quantity: 3,
+                    price_improvement: 3,
+                },
+                Execution {
+                    maker_order_uuid: Some(ask_102.order_uuid),
+                    maker_user_uuid: Some(ask_102.user_uuid),
+                    price: NonZeroU32::new(102).unwrap(),
+                    quantity: 2,
+                    price_improvement: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_price_improvement_is_the_sum_of_per_level_savings() {
+        let mut assets = Assets::new();
+
+        let mut place = |side, price, quantity| {
+            do_place_order(&mut assets, limit_order(side, price, quantity, false)).unwrap()
+        };
+
+        place(OrderSide::Sell, 100, 2);
+        place(OrderSide::Sell, 101, 3);
+
+        let result = place(OrderSide::Buy, 105, 5);
+
+        let per_level = result
+            .executions
+            .iter()
+            .map(|execution| execution.price_improvement)
+            .collect::<Vec<_>>();
+
+        // 5 saved on each of 2 at 100, 4 on each of 3 at 101.
+        assert_eq!(per_level, vec![10, 12]);
+        assert_eq!(result.price_improvement, 22);
+
+        // a sell taker improves when it matches above its price.
+        place(OrderSide::Buy, 99, 4);
+        let result = place(OrderSide::Sell, 97, 4);
+        assert_eq!(result.price_improvement, 8);
+    }
+
+    #[test]
+    fn test_market_orders_have_no_price_improvement() {
+        let mut assets = Assets::new();
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 2, false)).unwrap();
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 101, 3, false)).unwrap();
+
+        // the price of a market order is a bound, not what the taker meant to pay.
+        let mut order = limit_order(OrderSide::Buy, 105, 5, false);
+        order.order_type = OrderType::Market;
+        let result = do_place_order(&mut assets, order).unwrap();
+
+        assert_eq!(result.quantity_filled, 5);
+        assert!(result
+            .executions
+            .iter()
+            .all(|execution| execution.price_improvement == 0));
+        assert_eq!(result.price_improvement, 0);
+    }
+
+    /// check that the order records, the uuid maps and the book all agree with each other.
+    fn assert_engine_invariants(assets: &Assets) {
+        let book = &assets.btc;
+        let resting = [OrderSide::Buy, OrderSide::Sell]
+            .into_iter()
+            .flat_map(|side| book.orderbook.iter_rel(side))
+            .collect::<ahash::AHashMap<_, _>>();
+
+        for record in assets.orders.values() {
+            assert!(
+                record.quantity_filled <= record.quantity.get(),
+                "{record:?}"
+            );
+
+            let is_open = matches!(
+                record.status,
+                OrderStatus::Open | OrderStatus::PartiallyFilled
+            );
+            assert_eq!(
+                is_open,
+                assets.order_uuids.contains_key(&record.order_uuid),
+                "{record:?}"
+            );
+        }
+
+        assert_eq!(assets.order_uuids.len(), resting.len());
+        assert_eq!(book.resting.len(), resting.len());
+
+        for (order_uuid, (order_index, asset)) in &assets.order_uuids {
+            assert_eq!(*asset, Asset::Bitcoin);
+            assert_eq!(book.resting.get(order_index), Some(order_uuid));
+
+            let order = resting
+                .get(order_index)
+                .expect("uuid map points off the book");
+            let record = &assets.orders[order_uuid];
+            assert_eq!(
+                order.quantity.get(),
+                record.quantity_remaining(),
+                "{record:?}"
+            );
+            assert_eq!(order.price, record.price);
+            assert_eq!(order_index.side(), record.side);
+        }
+
+        assert!(book.orderbook.depth_is_consistent());
+    }
+
+    fn triggered_order(side: OrderSide, price: u32, kind: TriggerKind, at: u32) -> PlaceOrder {
+        limit_order(side, price, 2, false).with_trigger(Some(Trigger {
+            kind,
+            price: NonZeroU32::new(at).unwrap(),
+        }))
+    }
+
+    #[test]
+    fn test_market_if_touched_buy_triggers_on_fall_and_stop_buy_on_rise() {
+        let mut assets = Assets::new();
+
+        // trade at 100.
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 1, false)).unwrap();
+        do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 1, false)).unwrap();
+
+        let mit = do_place_order(
+            &mut assets,
+            triggered_order(OrderSide::Buy, 96, TriggerKind::MarketIfTouched, 95),
+        )
+        .unwrap();
+        let stop = do_place_order(
+            &mut assets,
+            triggered_order(OrderSide::Buy, 106, TriggerKind::Stop, 105),
+        )
+        .unwrap();
+
+        let status = |assets: &Assets, order_uuid| assets.orders[&order_uuid].status;
+        assert_eq!(status(&assets, mit.order_uuid), OrderStatus::Held);
+        assert_eq!(status(&assets, stop.order_uuid), OrderStatus::Held);
+        assert_eq!(assets.btc.triggers.len(), 2);
+
+        // the price falls to 95, releasing the MIT buy which takes the rest of the ask.
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 95, 3, false)).unwrap();
+        do_place_order(&mut assets, limit_order(OrderSide::Buy, 95, 1, false)).unwrap();
+
+        assert_eq!(status(&assets, mit.order_uuid), OrderStatus::Filled);
+        assert_eq!(status(&assets, stop.order_uuid), OrderStatus::Held);
+
+        // the price rises to 105, releasing the stop buy.
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 105, 3, false)).unwrap();
+        do_place_order(&mut assets, limit_order(OrderSide::Buy, 105, 1, false)).unwrap();
+
+        assert_eq!(status(&assets, stop.order_uuid), OrderStatus::Filled);
+        assert!(assets.btc.triggers.is_empty());
+        assert_engine_invariants(&assets);
+    }
+
+    #[test]
+    fn test_held_order_can_be_cancelled() {
+        let mut assets = Assets::new();
+
+        let held = do_place_order(
+            &mut assets,
+            triggered_order(OrderSide::Sell, 90, TriggerKind::Stop, 90),
+        )
+        .unwrap();
+        assert_eq!(held.quantity_remaining, 2);
+        assert!(held.order_index.is_none());
+
+        do_cancel_order(
+            &mut assets,
+            CancelOrder::new(held.user_uuid, held.order_uuid),
+        )
+        .unwrap();
+
+        assert_eq!(
+            assets.orders[&held.order_uuid].status,
+            OrderStatus::Cancelled
+        );
+        assert!(assets.btc.triggers.is_empty());
+    }
+
+    #[test]
+    fn test_ioc_remainder_is_cancelled() {
+        let mut assets = Assets::new();
+
+        do_place_order(&mut assets, limit_order(OrderSide::Sell, 100, 5, false)).unwrap();
+
+        let mut ioc = limit_order(OrderSide::Buy, 100, 10, false);
+        ioc.time_in_force = TimeInForce::ImmediateOrCancel;
+        let result = do_place_order(&mut assets, ioc).unwrap();
+        assert_eq!(
+            (
+                result.quantity_filled,
+                result.quantity_remaining,
+                result.quantity_cancelled
+            ),
+            (5, 5, 5)
+        );
+
+        // a resting remainder is not cancelled.
+        let result =
+            do_place_order(&mut assets, limit_order(OrderSide::Buy, 100, 10, false)).unwrap();
+        assert_eq!(
+            (result.quantity_remaining, result.quantity_cancelled),
+            (10, 0)
+        );
+    }
+
+    #[test]
+    fn test_depth_matches_raw_book_after_fills() {
+        let mut assets = Assets::new();
+
+        let mut place = |side, price, quantity| {
+            do_place_order(&mut assets, limit_order(side, price, quantity, false)).unwrap()
+        };
+
+        place(OrderSide::Sell, 100, 2);
+        place(OrderSide::Sell, 100, 5);
+        let cancelled = place(OrderSide::Sell, 101, 3);
+        place(OrderSide::Sell, 102, 4);
+        place(OrderSide::Buy, 98, 6);
+        place(OrderSide::Buy, 99, 1);
+
+        // a complete fill of the first ask and a partial fill of the second.
+        place(OrderSide::Buy, 100, 4);
+        // a partial fill of the best bid level.
+        place(OrderSide::Sell, 98, 3);
+
+        do_cancel_order(
+            &mut assets,
+            CancelOrder::new(cancelled.user_uuid, cancelled.order_uuid),
+        )
+        .unwrap();
+
+        let orderbook = &assets.btc.orderbook;
+        for side in [OrderSide::Buy, OrderSide::Sell] {
+            let mut recomputed: Vec<DepthLevel> = vec![];
+            for (_, order) in orderbook.iter_rel(side) {
+                match recomputed.last_mut() {
+                    Some(level) if level.price == order.price.get() => {
+                        level.quantity += u64::from(order.quantity.get());
+                        level.orders += 1;
+                    }
+                    _ => recomputed.push(DepthLevel {
+                        price: order.price.get(),
+                        quantity: u64::from(order.quantity.get()),
+                        orders: 1,
+                    }),
+                }
+            }
+
+            assert_eq!(orderbook.depth(side), recomputed, "{side:?}");
+        }
+
+        assert_eq!(
+            orderbook.depth(OrderSide::Sell)[0],
+            DepthLevel {
+                price: 100,
+                quantity: 3,
+                orders: 1
+            }
+        );
+        assert!(orderbook.depth_is_consistent());
+    }
+
+    #[test]
+    fn test_resting_orders_placed_out_of_price_order_match_best_price_first() {
+        let mut assets = Assets::new();
+
+        let mut place = |side, price, quantity| {
+            do_place_order(&mut assets, limit_order(side, price, quantity, false)).unwrap()
+        };
+
+        let ask_103 = place(OrderSide::Sell, 103, 1);
+        let ask_101 = place(OrderSide::Sell, 101, 1);
+        let ask_102 = place(OrderSide::Sell, 102, 1);
+        let later_ask_101 = place(OrderSide::Sell, 101, 1);
+
+        let taker = place(OrderSide::Buy, 103, 4);
+        let matched = taker
+            .executions
+            .iter()
+            .map(|execution| (execution.maker_order_uuid, execution.price.get()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            matched,
+            [
+                (Some(ask_101.order_uuid), 101),
+                (Some(later_ask_101.order_uuid), 101),
+                (Some(ask_102.order_uuid), 102),
+                (Some(ask_103.order_uuid), 103),
+            ]
+        );
+
+        assert_engine_invariants(&assets);
+    }
+
+    #[test]
+    fn test_multi_level_sweep_emits_match_events_in_order() {
+        let mut assets = Assets::new();
+
+        let mut place = |side, price, quantity| {
+            do_place_order(&mut assets, limit_order(side, price, quantity, false)).unwrap()
+        };
+
+        let first = place(OrderSide::Sell, 100, 2);
+        let second = place(OrderSide::Sell, 101, 3);
+        let third = place(OrderSide::Sell, 102, 5);
+
+        let resting = assets.drain_match_events().collect::<Vec<_>>();
+        assert_eq!(resting.len(), 3);
+        assert!(resting
+            .iter()
+            .all(|event| matches!(event, MatchEvent::AggressorResting { .. })));
+
+        let taker =
+            do_place_order(&mut assets, limit_order(OrderSide::Buy, 102, 7, false)).unwrap();
+
+        let fill = |maker: &PlaceOrderResult, quantity, price| MatchEvent::Fill {
+            asset: Asset::Bitcoin,
+            maker: Some(maker.order_uuid),
+            taker: taker.order_uuid,
+            quantity,
+            price: NonZeroU32::new(price).unwrap(),
+        };
+
+        assert_eq!(
+            assets.drain_match_events().collect::<Vec<_>>(),
+            [
+                fill(&first, 2, 100),
+                fill(&second, 3, 101),
+                fill(&third, 2, 102),
+                MatchEvent::Update {
+                    asset: Asset::Bitcoin,
+                    order_uuid: third.order_uuid,
+                    quantity_remaining: 3,
+                    quantity_reduced: 0,
+                },
+            ]
+        );
+
+        // a buy outlasting the book rests what it could not fill, then is cancelled.
+        let taker =
+            do_place_order(&mut assets, limit_order(OrderSide::Buy, 102, 5, false)).unwrap();
+        do_cancel_order(
+            &mut assets,
+            CancelOrder::new(taker.user_uuid, taker.order_uuid),
+        )
+        .unwrap();
+
+        assert_eq!(
+            assets.drain_match_events().collect::<Vec<_>>(),
+            [
+                MatchEvent::Fill {
+                    asset: Asset::Bitcoin,
+                    maker: Some(third.order_uuid),
+                    taker: taker.order_uuid,
+                    quantity: 3,
+                    price: NonZeroU32::new(102).unwrap(),
+                },
+                MatchEvent::AggressorResting {
+                    asset: Asset::Bitcoin,
+                    order_uuid: taker.order_uuid,
+                    side: OrderSide::Buy,
+                    price: NonZeroU32::new(102).unwrap(),
+                    quantity: 2,
+                },
+                MatchEvent::Cancel {
+                    asset: Asset::Bitcoin,
+                    order_uuid: taker.order_uuid,
+                    quantity: 2,
+                },
+            ]
+        );
+        assert_engine_invariants(&assets);
+    }
+
+    #[test]
+    fn test_randomized_place_and_cancel_keep_invariants() {
+        use rand::rngs::StdRng;
+        use rand::{Rng as _, SeedableRng as _};
+
+        const STEPS: usize = 5_000;
+
+        // a fixed seed keeps failures reproducible.
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mut assets = Assets::new();
+        let mut placed: Vec<OrderUuid> = vec![];
+        let mut traded = 0u64;
+
+        for _ in 0..STEPS {
+            if !placed.is_empty() && rng.gen_bool(0.25) {
+                let order_uuid = placed.swap_remove(rng.gen_range(0..placed.len()));
+                let user_uuid = assets.orders[&order_uuid].user_uuid;
+                let was_resting = assets.order_uuids.contains_key(&order_uuid);
+
+                let res = do_cancel_order(&mut assets, CancelOrder::new(user_uuid, order_uuid));
+                assert_eq!(res.is_ok(), was_resting, "{res:?}");
+            } else {
+                let side = if rng.gen_bool(0.5) {
+                    OrderSide::Buy
+                } else {
+                    OrderSide::Sell
+                };
+
+                let mut order = limit_order(
+                    side,
+                    rng.gen_range(95..=105),
+                    rng.gen_range(1..=10),
+                    rng.gen_bool(0.05),
+                );
+                order.all_or_none = rng.gen_bool(0.1);
+                order.time_in_force = match rng.gen_range(0..10) {
+                    0 => TimeInForce::ImmediateOrCancel,
+                    1 => TimeInForce::FillOrKill,
+                    _ => TimeInForce::GoodTilCanceled,
+                };
+
+                let quantity = order.quantity.get();
+                if let Ok(result) = do_place_order(&mut assets, order) {
+                    let executed = result
+                        .executions
+                        .iter()
+                        .map(|execution| execution.quantity)
+                        .sum::<u32>();
+
+                    assert_eq!(executed, result.quantity_filled);
+                    assert_eq!(result.quantity_filled + result.quantity_remaining, quantity);
+
+                    traded += u64::from(executed);
+                    placed.push(result.order_uuid);
+                }
+            }
+
+            assert_engine_invariants(&assets);
+        }
+
+        // every unit traded was filled once on the taker's order and once on a maker's.
+        let filled = assets
+            .orders
+            .values()
+            .map(|record| u64::from(record.quantity_filled))
+            .sum::<u64>();
+        assert_eq!(filled, traded * 2);
+        assert!(traded > 0, "the random orders never crossed");
+    }
+
+    #[test]
+    fn test_ioc_without_liquidity_does_not_touch_book() {
+        let mut assets = Assets::new();
+        let mut order = limit_order(OrderSide::Buy, 100, 10, false);
+        order.time_in_force = TimeInForce::ImmediateOrCancel;
+
+        let res = do_place_order(&mut assets, order);
+        assert!(matches!(
+            res,
+            Err(TradingEngineError::PlaceOrder(
+                PlaceOrderError::InsufficientLiquidity
+            ))
+        ));
+        assert!(assets.btc.orderbook_mut().bids.inner.is_empty());
+        assert!(assets.btc.orderbook_mut().asks.inner.is_empty());
+    }
 }
@@ -0,0 +1,79 @@
+//! Checked `price * quantity`, the notional of an order or fill in the quote currency.
+//!
+//! The product of a `u32` price and quantity always fits in a `u64`, but amounts are journaled
+//! as a postgres `BIGINT`, so a notional above [`MAX_NOTIONAL`] would wrap negative on its way
+//! into the ledger and credit instead of debit. Every notional is computed here and refused
+//! rather than wrapped. Sums of notionals, such as a walk of the book, use [`wide_notional`].
+
+use thiserror::Error;
+
+/// the largest notional the ledger can hold.
+pub const MAX_NOTIONAL: u64 = i64::MAX as u64;
+
+/// A notional exceeded the bound it was checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("the notional of {quantity} @ {price} exceeds {max}")]
+pub struct NotionalOverflow {
+    /// the price of the order or fill.
+    pub price: u32,
+    /// the quantity of the order or fill.
+    pub quantity: u64,
+    /// the bound it was checked against.
+    pub max: u64,
+}
+
+/// `price * quantity`, or an error if it exceeds `max`.
+pub fn checked_notional(price: u32, quantity: u64, max: u64) -> Result<u64, NotionalOverflow> {
+    u64::from(price)
+        .checked_mul(quantity)
+        .filter(|notional| *notional <= max)
+        .ok_or(NotionalOverflow {
+            price,
+            quantity,
+            max,
+        })
+}
+
+/// `price * quantity`, or an error if the ledger can not hold it.
+pub fn notional(price: u32, quantity: u64) -> Result<u64, NotionalOverflow> {
+    checked_notional(price, quantity, MAX_NOTIONAL)
+}
+
+/// `price * quantity` widened so it can never overflow, for fees and sums over many fills.
+pub fn wide_notional(price: u32, quantity: u64) -> u128 {
+    u128::from(price) * u128::from(quantity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notional_near_u32_max() {
+        let max = u64::from(u32::MAX);
+
+        // fits a u64 but not the ledger, it must not wrap into a negative amount.
+        assert_eq!(
+            notional(u32::MAX, max),
+            Err(NotionalOverflow {
+                price: u32::MAX,
+                quantity: max,
+                max: MAX_NOTIONAL,
+            })
+        );
+        assert_eq!(
+            wide_notional(u32::MAX, max),
+            u128::from(max) * u128::from(max)
+        );
+
+        // the largest quantity that still fits at the highest price.
+        let quantity = MAX_NOTIONAL / max;
+        assert_eq!(notional(u32::MAX, quantity), Ok(quantity * max));
+        assert!(notional(u32::MAX, quantity + 1).is_err());
+
+        assert!(notional(u32::MAX, u64::MAX).is_err());
+        assert_eq!(notional(u32::MAX, 0), Ok(0));
+        assert_eq!(checked_notional(100, 10, 1000), Ok(1000));
+        assert!(checked_notional(100, 11, 1000).is_err());
+    }
+}
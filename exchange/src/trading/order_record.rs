@@ -0,0 +1,95 @@
+//! Records of the orders the trading engine has accepted.
+
+use std::num::NonZeroU32;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// The lifecycle status of an order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum OrderStatus {
+    /// resting on the book, nothing filled yet.
+    #[serde(rename = "open")]
+    Open,
+    /// resting on the book with some quantity filled.
+    #[serde(rename = "partially_filled")]
+    PartiallyFilled,
+    /// held off the book until its trigger is touched, see [`Triggers`].
+    #[serde(rename = "held")]
+    Held,
+    /// completely filled.
+    #[serde(rename = "filled")]
+    Filled,
+    /// removed from the book, or never booked, before being completely filled.
+    #[serde(rename = "cancelled")]
+    Cancelled,
+}
+
+/// The engine's view of an order it has accepted.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OrderRecord {
+    /// the unique identifier for the order
+    pub order_uuid: OrderUuid,
+    /// the user that placed the order
+    pub user_uuid: uuid::Uuid,
+    /// the asset traded
+    pub asset: Asset,
+    /// the side of the order, buy or sell
+    pub side: OrderSide,
+    /// the type of order
+    pub order_type: OrderType,
+    /// the price of the order
+    pub price: NonZeroU32,
+    /// the quantity of the order, less what was taken off it with a [`ReduceOrder`]
+    pub quantity: NonZeroU32,
+    /// the quantity filled so far
+    pub quantity_filled: u32,
+    /// the status of the order
+    pub status: OrderStatus,
+    /// when the order was placed, in milliseconds since the unix epoch
+    pub created_at: i64,
+    /// when a good-til-date order expires, in milliseconds since the unix epoch
+    pub expires_at: Option<i64>,
+    /// when the order was filled or cancelled, in milliseconds since the unix epoch
+    #[serde(default)]
+    pub closed_at: Option<i64>,
+    /// the client's own identifier for the order, if it was placed with one
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+}
+
+impl OrderRecord {
+    /// the quantity that has not been filled.
+    pub fn quantity_remaining(&self) -> u32 {
+        self.quantity.get() - self.quantity_filled
+    }
+
+    /// `true` if the order can no longer be filled.
+    pub fn is_closed(&self) -> bool {
+        matches!(self.status, OrderStatus::Filled | OrderStatus::Cancelled)
+    }
+
+    /// record a fill of `amount` against a resting order.
+    pub(super) fn record_fill(&mut self, amount: u32) {
+        self.quantity_filled += amount;
+
+        if self.quantity_filled >= self.quantity.get() {
+            self.close(OrderStatus::Filled);
+        } else {
+            self.status = OrderStatus::PartiallyFilled;
+        }
+    }
+
+    /// record `by` taken off a resting order, less than what it has left.
+    pub(super) fn record_reduce(&mut self, by: u32) {
+        self.quantity =
+            NonZeroU32::new(self.quantity.get() - by).expect("a reduce leaves some quantity");
+    }
+
+    /// move the order to the closed `status`, stamping when it closed.
+    pub(super) fn close(&mut self, status: OrderStatus) {
+        self.status = status;
+        self.closed_at = Some(chrono::Utc::now().timestamp_millis());
+    }
+}
@@ -6,8 +6,10 @@ use tinyvec::{tiny_vec, TinyVec};
 
 use serde::{Deserialize, Serialize};
 
+use super::depth::DepthLevel;
+
 /// The side of an order.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum OrderSide {
     /// Buy side.
@@ -18,19 +20,45 @@ pub enum OrderSide {
     Sell,
 }
 
+impl OrderSide {
+    /// The other side of the book, the side a taker on this side matches against.
+    #[inline]
+    pub const fn opposite(self) -> Self {
+        match self {
+            Self::Buy => Self::Sell,
+            Self::Sell => Self::Buy,
+        }
+    }
+
+    /// `true` if a taker on this side at `taker_price` may trade with a maker resting at `maker_price`,
+    /// the price of a market order being the worst it accepts.
+    #[inline]
+    pub fn crosses(self, taker_price: NonZeroU32, maker_price: NonZeroU32) -> bool {
+        match self {
+            Self::Buy => maker_price <= taker_price,
+            Self::Sell => maker_price >= taker_price,
+        }
+    }
+}
+
 /// The type of an order.
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum OrderType {
     /// Limit order.
     #[serde(rename = "limit")]
     Limit,
-    /// Market order.
+    /// Market order, matched at any price up to its own, the worst price it accepts.
     #[serde(rename = "market")]
     Market,
 }
 
+impl OrderType {
+    /// Every order type.
+    pub const ALL: [Self; 2] = [Self::Limit, Self::Market];
+}
+
 /// The time in force of an order.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Order {
     /// Some distinct, monotonic sequence number for the order.
     pub(super) memo: u32,
@@ -38,6 +66,8 @@ pub struct Order {
     pub(super) quantity: NonZeroU32,
     /// The price of the order.
     pub(super) price: NonZeroU32,
+    /// Only ever fill this order in its entirety, never partially.
+    pub(super) all_or_none: bool,
 }
 
 impl Order {
@@ -52,6 +82,12 @@ impl Order {
     pub fn price(&self) -> NonZeroU32 {
         self.price
     }
+
+    /// Returns `true` if the order may only be filled in its entirety.
+    #[inline]
+    pub fn all_or_none(&self) -> bool {
+        self.all_or_none
+    }
 }
 
 /// The threshold at which the [`PriceLevel`] will switch from using array storage to heap storage.
@@ -62,8 +98,9 @@ const PRICE_LEVEL_INNER_CAPACITY: usize = 64;
 pub struct PriceLevel {
     /// The price of the orders in this price level.
     price: u32,
-    /// The sequence number generator for the next order to be added to this price level.
-    memo_seq: u32,
+    /// The total quantity of the orders in this price level, kept as orders come and go so that
+    /// depth is read per level instead of per order.
+    quantity: u64,
     /// The inner data structure storing the orders in this price level.
     inner: TinyVec<[Option<Order>; PRICE_LEVEL_INNER_CAPACITY]>,
 }
@@ -77,21 +114,36 @@ impl PriceLevel {
             .map(|o| o.as_ref().expect("all valid orders are always Some"))
     }
 
+    /// Recompute the total quantity by visiting every order, for checking [`PriceLevel::quantity`].
+    fn sum_quantity(&self) -> u64 {
+        self.iter().map(|o| u64::from(o.quantity.get())).sum()
+    }
+
     #[inline]
     #[track_caller]
-    fn push_order(&mut self, mut t: Order) -> (NonZeroU32, u32) {
+    fn push_order(&mut self, mut t: Order, memo: u32) -> (NonZeroU32, u32) {
         let price = NonZeroU32::new(self.price).expect("price for price-level should not be zero");
-        let memo = self.memo_seq;
-        self.memo_seq += 1;
         t.memo = memo;
         let rval = (price, memo);
+        self.quantity += u64::from(t.quantity.get());
         self.inner.push(Some(t));
         rval
     }
 
+    fn reduce_order(&mut self, memo: u32, by: u32) -> Option<Order> {
+        let order = self.inner.iter_mut().flatten().find(|o| o.memo == memo)?;
+
+        order.quantity = NonZeroU32::new(order.quantity.get().checked_sub(by)?)?;
+        self.quantity -= u64::from(by);
+        Some(*order)
+    }
+
     fn remove_order(&mut self, memo: u32) -> Option<Order> {
         let index = self.iter().position(|o| o.memo == memo)?;
         let rval = self.inner.remove(index);
+        if let Some(order) = rval {
+            self.quantity -= u64::from(order.quantity.get());
+        }
 
         if self.inner.len() <= PRICE_LEVEL_INNER_CAPACITY {
             self.inner.shrink_to_fit();
@@ -99,6 +151,17 @@ impl PriceLevel {
 
         rval
     }
+
+    /// Give back the spare capacity of the level, returns the number of order slots freed.
+    fn compact(&mut self) -> usize {
+        let capacity = self.inner.capacity();
+        if capacity == self.inner.len() || !self.inner.is_heap() {
+            return 0;
+        }
+
+        self.inner.shrink_to_fit();
+        capacity - self.inner.capacity()
+    }
 }
 
 /// The threshold at which the [`MultiplePriceLevels`] will switch from using array storage to heap storage.
@@ -107,6 +170,16 @@ pub const MULTIPLE_PRICE_LEVEL_INNER_CAPACITY: usize = 64;
 /// Stores multiple price levels in a contiguous vector.
 pub struct MultiplePriceLevels {
     pub(super) inner: TinyVec<[PriceLevel; MULTIPLE_PRICE_LEVEL_INNER_CAPACITY]>,
+    /// The memo for the next order pushed to any price level.
+    ///
+    /// This is shared by every level rather than kept per level, a level is dropped when it
+    /// empties and if it were recreated with its own counter an old [`OrderIndex`] would
+    /// resolve to whichever new order was given the same memo.
+    ///
+    /// The counter wraps after `u32::MAX` pushes, so a memo is only unique among the last 2^32
+    /// orders pushed to this side: an order still resting after that many others can share its
+    /// memo with a newer order at the same price.
+    memo_seq: u32,
 }
 
 impl MultiplePriceLevels {
@@ -120,6 +193,20 @@ impl MultiplePriceLevels {
         self.inner.iter()
     }
 
+    /// Returns the number of orders resting at the given price, zero if there is no such level.
+    pub fn level_len(&self, price: NonZeroU32) -> usize {
+        self.inner
+            .binary_search_by_key(&price.get(), |level| level.price)
+            .map_or(0, |index| self.inner[index].inner.len())
+    }
+
+    /// Returns the total quantity resting at the given price, zero if there is no such level.
+    pub fn level_quantity(&self, price: NonZeroU32) -> u64 {
+        self.inner
+            .binary_search_by_key(&price.get(), |level| level.price)
+            .map_or(0, |index| self.inner[index].quantity)
+    }
+
     /// Returns the [`PriceLevel`] for the given price.
     pub fn get_or_insert_price_level(&mut self, price: NonZeroU32) -> &mut PriceLevel {
         let index = self
@@ -133,7 +220,7 @@ impl MultiplePriceLevels {
                     index,
                     PriceLevel {
                         price: price.get(),
-                        memo_seq: 0,
+                        quantity: 0,
                         inner: tiny_vec!(),
                     },
                 );
@@ -143,7 +230,15 @@ impl MultiplePriceLevels {
     }
 
     /// Pushes an order to the [`MultiplePriceLevels`] returns a tuple of the price and memo of the order.
+    ///
+    /// The levels are kept sorted by price and the orders of a level in arrival order, so the
+    /// order lands behind every order already resting at its price and ahead of every order at
+    /// a worse one. Finding the level is `O(log L)` for `L` levels, appending to an existing
+    /// level is amortized `O(1)` and opening a new level shifts the levels above it, `O(L)`.
     pub fn push_order_to_level(&mut self, t: Order) -> (NonZeroU32, u32) {
+        let memo = self.memo_seq;
+        self.memo_seq = memo.wrapping_add(1);
+
         let index = self
             .inner
             .binary_search_by_key(&t.price.get(), |level| level.price);
@@ -151,15 +246,15 @@ impl MultiplePriceLevels {
         match index {
             Ok(index) => {
                 let price_level = self.inner.get_mut(index);
-                price_level.expect("checked index").push_order(t)
+                price_level.expect("checked index").push_order(t, memo)
             }
             Err(index) => {
                 let mut price_level_inner = PriceLevel {
                     price: t.price.get(),
-                    memo_seq: 0,
+                    quantity: 0,
                     inner: tiny_vec!(),
                 };
-                let ret = price_level_inner.push_order(t);
+                let ret = price_level_inner.push_order(t, memo);
                 self.inner.insert(index, price_level_inner);
                 ret
             }
@@ -186,30 +281,133 @@ impl MultiplePriceLevels {
         order
     }
 
-    /// Returns a mutable reference to an [`Order`] in the [`MultiplePriceLevels`] if it exists.
-    pub fn get_mut(&mut self, (price, memo): (NonZeroU32, u32)) -> Option<&mut Order> {
+    /// Returns a reference to an [`Order`] in the [`MultiplePriceLevels`] if it exists.
+    pub fn get(&self, (price, memo): (NonZeroU32, u32)) -> Option<&Order> {
         let index = self
             .inner
             .binary_search_by_key(&price.get(), |level| level.price)
             .ok()?;
 
-        self.inner
-            .get_mut(index)?
+        self.inner.get(index)?.iter().find(|o| o.memo == memo)
+    }
+
+    /// Every order on this side, lowest price first and each level in time priority.
+    fn snapshot(&self) -> SideSnapshot {
+        SideSnapshot {
+            memo_seq: self.memo_seq,
+            orders: self
+                .inner
+                .iter()
+                .flat_map(PriceLevel::iter)
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Rebuild a side from [`MultiplePriceLevels::snapshot`], every order keeps its memo.
+    fn restore(snapshot: SideSnapshot) -> Self {
+        let mut levels = Self {
+            inner: TinyVec::new(),
+            memo_seq: snapshot.memo_seq,
+        };
+
+        for order in snapshot.orders {
+            levels
+                .get_or_insert_price_level(order.price)
+                .push_order(order, order.memo);
+        }
+
+        levels
+    }
+
+    /// Give back the spare capacity of every level and of the levels themselves.
+    fn compact(&mut self) -> CompactionReport {
+        let order_slots = self.inner.iter_mut().map(PriceLevel::compact).sum();
+
+        let capacity = self.inner.capacity();
+        let level_slots = if capacity != self.inner.len() && self.inner.is_heap() {
+            self.inner.shrink_to_fit();
+            capacity - self.inner.capacity()
+        } else {
+            0
+        };
+
+        CompactionReport {
+            order_slots,
+            level_slots,
+        }
+    }
+
+    /// Take `by` off the quantity of an order, returns the order as it is left.
+    ///
+    /// Returns `None` and leaves the order untouched if it does not exist or would be left
+    /// with nothing, remove the order instead.
+    pub fn reduce_order(&mut self, (price, memo): (NonZeroU32, u32), by: u32) -> Option<Order> {
+        let index = self
             .inner
-            .iter_mut()
-            .find(|o| matches!(o, Some(o) if o.memo == memo))
-            .map(|o| o.as_mut().expect("checked order"))
+            .binary_search_by_key(&price.get(), |level| level.price)
+            .ok()?;
+
+        self.inner.get_mut(index)?.reduce_order(memo, by)
     }
 }
 
 /// An index into the [`Orderbook`] which can be used to identify an order.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct OrderIndex {
     side: OrderSide,
     price: NonZeroU32,
     memo: u32,
 }
 
+impl OrderIndex {
+    /// the side of the book the order rests on.
+    pub fn side(&self) -> OrderSide {
+        self.side
+    }
+
+    /// the price level the order rests at.
+    pub fn price(&self) -> NonZeroU32 {
+        self.price
+    }
+}
+
+/// One side of an [`OrderbookSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SideSnapshot {
+    /// the memo the next order pushed to the side is given.
+    memo_seq: u32,
+    /// the resting orders, lowest price first and each level in time priority.
+    orders: Vec<Order>,
+}
+
+/// A copy of every resting order of an [`Orderbook`], see [`Orderbook::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderbookSnapshot {
+    bids: SideSnapshot,
+    asks: SideSnapshot,
+}
+
+/// The storage given back by [`Orderbook::compact`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// the order slots freed across every price level.
+    pub order_slots: usize,
+    /// the price level slots freed.
+    pub level_slots: usize,
+}
+
+impl std::ops::Add for CompactionReport {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            order_slots: self.order_slots + rhs.order_slots,
+            level_slots: self.level_slots + rhs.level_slots,
+        }
+    }
+}
+
 /// The orderbook.
 pub struct Orderbook {
     /// The bids in the orderbook.
@@ -223,16 +421,50 @@ impl Orderbook {
     #[inline]
     #[track_caller]
     pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates a new [`Orderbook`] with room for `price_levels` price levels on each side.
+    #[inline]
+    #[track_caller]
+    pub fn with_capacity(price_levels: usize) -> Self {
         let bids = MultiplePriceLevels {
-            inner: tinyvec::tiny_vec!(),
+            inner: TinyVec::with_capacity(price_levels),
+            memo_seq: 0,
         };
         let asks = MultiplePriceLevels {
-            inner: tinyvec::tiny_vec!(),
+            inner: TinyVec::with_capacity(price_levels),
+            memo_seq: 0,
         };
         Self { bids, asks }
     }
 
+    /// copy every resting order, with the memos they were given, for [`Orderbook::restore`].
+    pub fn snapshot(&self) -> OrderbookSnapshot {
+        OrderbookSnapshot {
+            bids: self.bids.snapshot(),
+            asks: self.asks.snapshot(),
+        }
+    }
+
+    /// rebuild the orderbook a [`OrderbookSnapshot`] was taken of.
+    ///
+    /// Every order keeps its memo and its place in time priority and the memo sequences carry
+    /// on where they were, so an [`OrderIndex`] of the original book resolves to the same order
+    /// in the restored one and orders pushed afterwards are given the same indices they would
+    /// have been in the original.
+    pub fn restore(snapshot: OrderbookSnapshot) -> Self {
+        Self {
+            bids: MultiplePriceLevels::restore(snapshot.bids),
+            asks: MultiplePriceLevels::restore(snapshot.asks),
+        }
+    }
+
     /// add a new bid to the orderbook, returns the [`OrderIndex`] for the order.
+    ///
+    /// The bid is queued in price-time priority: behind every bid at its price or higher, ahead
+    /// of every bid at a lower price, see [`MultiplePriceLevels::push_order_to_level`] for the
+    /// cost.
     #[inline]
     #[track_caller]
     pub fn push_bid(&mut self, t: Order) -> OrderIndex {
@@ -242,6 +474,10 @@ impl Orderbook {
     }
 
     /// add a new ask to the orderbook, returns the [`OrderIndex`] for the order.
+    ///
+    /// The ask is queued in price-time priority: behind every ask at its price or lower, ahead
+    /// of every ask at a higher price, see [`MultiplePriceLevels::push_order_to_level`] for the
+    /// cost.
     #[inline]
     #[track_caller]
     pub fn push_ask(&mut self, t: Order) -> OrderIndex {
@@ -250,6 +486,32 @@ impl Orderbook {
         OrderIndex { side, price, memo }
     }
 
+    /// add a new order to `side` of the orderbook, returns the [`OrderIndex`] for the order.
+    #[inline]
+    #[track_caller]
+    pub fn push(&mut self, side: OrderSide, t: Order) -> OrderIndex {
+        match side {
+            OrderSide::Buy => self.push_bid(t),
+            OrderSide::Sell => self.push_ask(t),
+        }
+    }
+
+    /// the number of orders resting at `price` on `side`.
+    pub fn level_len(&self, side: OrderSide, price: NonZeroU32) -> usize {
+        match side {
+            OrderSide::Buy => self.bids.level_len(price),
+            OrderSide::Sell => self.asks.level_len(price),
+        }
+    }
+
+    /// the total quantity resting at `price` on `side`.
+    pub fn level_quantity(&self, side: OrderSide, price: NonZeroU32) -> u64 {
+        match side {
+            OrderSide::Buy => self.bids.level_quantity(price),
+            OrderSide::Sell => self.asks.level_quantity(price),
+        }
+    }
+
     /// remove an order from the orderbook, returns the order if it existed.
     #[inline]
     #[track_caller]
@@ -312,10 +574,73 @@ impl Orderbook {
         }
     }
 
-    /// get a mutable reference to an order in the orderbook, returns `None` if the order does not exist.
+    /// every resting order with its [`OrderIndex`], the bids from the highest price and then the
+    /// asks from the lowest, each price level in time priority.
+    ///
+    /// A removed order leaves nothing behind in its level, so only live orders are visited.
+    pub fn iter(&self) -> impl Iterator<Item = (OrderIndex, &Order)> + '_ {
+        fn order_index(side: OrderSide, order: &Order) -> OrderIndex {
+            OrderIndex {
+                side,
+                price: order.price,
+                memo: order.memo,
+            }
+        }
+
+        let bids = self.bids.iter_inner_rev().flat_map(PriceLevel::iter);
+        let asks = self.asks.iter_inner().flat_map(PriceLevel::iter);
+
+        bids.map(|order| (order_index(OrderSide::Buy, order), order))
+            .chain(asks.map(|order| (order_index(OrderSide::Sell, order), order)))
+    }
+
+    /// aggregate the resting orders of `side` by price level, best price first.
+    ///
+    /// This reads the running total of each level, it costs the same however many orders rest.
+    pub fn depth(&self, side: OrderSide) -> Vec<DepthLevel> {
+        fn to_depth_level(level: &PriceLevel) -> DepthLevel {
+            DepthLevel {
+                price: level.price,
+                quantity: level.quantity,
+                orders: level.inner.len(),
+            }
+        }
+
+        match side {
+            OrderSide::Buy => self.bids.iter_inner_rev().map(to_depth_level).collect(),
+            OrderSide::Sell => self.asks.iter_inner().map(to_depth_level).collect(),
+        }
+    }
+
+    /// `true` if the running total of every price level matches the sum of its orders.
+    pub fn depth_is_consistent(&self) -> bool {
+        self.bids
+            .iter_inner()
+            .chain(self.asks.iter_inner())
+            .all(|level| level.quantity == level.sum_quantity())
+    }
+
+    /// get a reference to an order in the orderbook, returns `None` if the order does not exist.
+    #[inline]
+    #[track_caller]
+    pub fn get(&self, order_index: OrderIndex) -> Option<&Order> {
+        let OrderIndex { side, price, memo } = order_index;
+
+        let levels = match side {
+            OrderSide::Buy => &self.bids,
+            OrderSide::Sell => &self.asks,
+        };
+
+        levels.get((price, memo))
+    }
+
+    /// take `by` off the quantity of a resting order, as a partial fill does.
+    ///
+    /// Returns the order as it is left, or `None` without changing anything if the order does
+    /// not exist or `by` is not less than its quantity.
     #[inline]
     #[track_caller]
-    pub fn get_mut(&mut self, order_index: OrderIndex) -> Option<&mut Order> {
+    pub fn reduce(&mut self, order_index: OrderIndex, by: u32) -> Option<Order> {
         let OrderIndex { side, price, memo } = order_index;
 
         let levels = match side {
@@ -323,6 +648,181 @@ impl Orderbook {
             OrderSide::Sell => &mut self.asks,
         };
 
-        levels.get_mut((price, memo))
+        levels.reduce_order((price, memo), by)
+    }
+
+    /// give back the storage left over by orders that have since been removed.
+    ///
+    /// Removing an order shifts the orders behind it down its level but a level that once
+    /// held many orders keeps the room for them. Compacting shrinks every level to the orders
+    /// it holds. Orders are found by price and memo rather than by position so no
+    /// [`OrderIndex`] changes. A book with nothing to give back is only walked, never
+    /// reallocated.
+    pub fn compact(&mut self) -> CompactionReport {
+        self.bids.compact() + self.asks.compact()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! nz {
+        ($e:literal) => {
+            ::std::num::NonZeroU32::new($e).unwrap()
+        };
+    }
+
+    fn order(price: NonZeroU32, quantity: NonZeroU32) -> Order {
+        Order {
+            memo: 0,
+            quantity,
+            price,
+            all_or_none: false,
+        }
+    }
+
+    #[test]
+    fn test_push_keeps_price_time_priority() {
+        let mut orderbook = Orderbook::new();
+
+        let ask_103 = orderbook.push_ask(order(nz!(103), nz!(1)));
+        let ask_101 = orderbook.push_ask(order(nz!(101), nz!(1)));
+        let ask_102 = orderbook.push_ask(order(nz!(102), nz!(1)));
+        let later_ask_101 = orderbook.push_ask(order(nz!(101), nz!(1)));
+
+        let asks = orderbook
+            .iter_rel(OrderSide::Sell)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        assert_eq!(asks, [ask_101, later_ask_101, ask_102, ask_103]);
+
+        let bid_97 = orderbook.push_bid(order(nz!(97), nz!(1)));
+        let bid_99 = orderbook.push_bid(order(nz!(99), nz!(1)));
+        let bid_98 = orderbook.push_bid(order(nz!(98), nz!(1)));
+
+        let bids = orderbook
+            .iter_rel(OrderSide::Buy)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        assert_eq!(bids, [bid_99, bid_98, bid_97]);
+    }
+
+    #[test]
+    fn test_stale_index_does_not_resolve_after_level_is_recreated() {
+        let mut orderbook = Orderbook::new();
+
+        // empty the level so it is dropped, then recreate it with a new order.
+        let stale = orderbook.push_bid(order(nz!(10), nz!(1)));
+        assert!(orderbook.remove(stale).is_some());
+        let current = orderbook.push_bid(order(nz!(10), nz!(2)));
+
+        assert_ne!(stale, current);
+        assert!(orderbook.get(stale).is_none());
+        assert!(orderbook.remove(stale).is_none());
+
+        let occupant = orderbook.get(current).unwrap();
+        assert_eq!(
+            occupant.quantity(),
+            nz!(2),
+            "the current occupant was changed"
+        );
+    }
+
+    #[test]
+    fn test_iter_visits_only_live_orders() {
+        let mut orderbook = Orderbook::new();
+
+        let mut live = vec![];
+        for price in [10, 12, 11, 10, 12] {
+            let price = NonZeroU32::new(price).unwrap();
+            live.push(orderbook.push_bid(order(price, nz!(1))));
+            live.push(orderbook.push_ask(order(price.saturating_add(10), nz!(1))));
+        }
+
+        for removed in [live.remove(0), live.remove(3), live.remove(6)] {
+            assert!(orderbook.remove(removed).is_some());
+        }
+
+        let visited = orderbook.iter().collect::<Vec<_>>();
+        assert_eq!(visited.len(), live.len());
+        for (index, order) in &visited {
+            assert!(live.contains(index));
+            assert_eq!(orderbook.get(*index), Some(*order));
+        }
+
+        let by_copy = orderbook
+            .iter_rel(OrderSide::Buy)
+            .chain(orderbook.iter_rel(OrderSide::Sell));
+        assert!(by_copy.eq(visited.into_iter().map(|(index, order)| (index, *order))));
+    }
+
+    #[test]
+    fn test_snapshot_restore_keeps_every_index() {
+        let mut orderbook = Orderbook::new();
+
+        let mut indices = vec![];
+        for (price, quantity) in [(10, 1), (10, 2), (11, 3), (12, 4), (10, 5)] {
+            indices.push(orderbook.push_bid(order(NonZeroU32::new(price).unwrap(), nz!(1))));
+            indices.push(orderbook.push_ask(order(
+                NonZeroU32::new(price + 10).unwrap(),
+                NonZeroU32::new(quantity).unwrap(),
+            )));
+        }
+
+        // leave holes in the middle of levels and drop a whole level.
+        for removed in [indices.remove(2), indices.remove(5), indices.remove(4)] {
+            assert!(orderbook.remove(removed).is_some());
+        }
+        orderbook.reduce(indices[2], 1).unwrap();
+
+        let snapshot = orderbook.snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored = Orderbook::restore(serde_json::from_str(&json).unwrap());
+
+        for index in &indices {
+            assert_eq!(restored.get(*index), orderbook.get(*index), "{index:?}");
+            assert!(restored.get(*index).is_some());
+        }
+        for side in [OrderSide::Buy, OrderSide::Sell] {
+            assert!(restored.iter_rel(side).eq(orderbook.iter_rel(side)));
+            assert_eq!(restored.depth(side), orderbook.depth(side));
+        }
+        assert!(restored.depth_is_consistent());
+        assert_eq!(restored.snapshot(), snapshot);
+
+        // the memo sequences carry on, the next orders get the same indices in both books.
+        let mut restored = restored;
+        let next = order(nz!(10), nz!(1));
+        assert_eq!(restored.push_bid(next), orderbook.push_bid(next));
+    }
+
+    #[test]
+    fn test_compact_gives_back_the_room_of_removed_orders() {
+        let mut orderbook = Orderbook::new();
+        let mut indices = (0..10_000)
+            .map(|_| orderbook.push_ask(order(nz!(100), nz!(1))))
+            .collect::<Vec<_>>();
+
+        for removed in indices.split_off(1_000) {
+            assert!(orderbook.remove(removed).is_some());
+        }
+
+        let capacity = |orderbook: &Orderbook| orderbook.asks.inner[0].inner.capacity();
+        let before = capacity(&orderbook);
+        assert!(before >= 10_000);
+
+        let report = orderbook.compact();
+        assert_eq!(capacity(&orderbook), 1_000);
+        assert_eq!(report.order_slots, before - 1_000);
+
+        for index in &indices {
+            assert!(orderbook.get(*index).is_some(), "{index:?}");
+        }
+        assert_eq!(orderbook.iter().count(), 1_000);
+        assert!(orderbook.depth_is_consistent());
+
+        // nothing left to give back.
+        assert_eq!(orderbook.compact(), CompactionReport::default());
     }
 }
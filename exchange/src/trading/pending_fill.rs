@@ -35,6 +35,25 @@ pub struct MakerFill {
     pub fill_amount: u32,
 }
 
+impl MakerFill {
+    /// as much of `maker` as a taker with `taker_rem_q` left can take.
+    pub(super) fn against(oix: OrderIndex, maker: Order, taker_rem_q: u32) -> Self {
+        let fill_amount = std::cmp::min(maker.quantity.get(), taker_rem_q);
+        let fill_type = if fill_amount == maker.quantity.get() {
+            FillType::Complete
+        } else {
+            FillType::Partial
+        };
+
+        Self {
+            oix,
+            maker,
+            fill_type,
+            fill_amount,
+        }
+    }
+}
+
 /// A pending fill operation on the [`Orderbook`].
 pub struct PendingFill<'a> {
     // capturing the orderbook by mutable reference enforces that the data in the pending-fill does not drift from the orderbook data.
@@ -49,6 +68,8 @@ pub struct PendingFill<'a> {
     pub(super) maker_fills: Vec<MakerFill>,
     /// The outcome of the fill operation for the taker's order.
     pub(super) taker_fill_outcome: FillType,
+    /// The maker orders taken off the taker without trading, see [`MakerScreen::Decrement`].
+    pub(super) decrements: Vec<MakerFill>,
 }
 
 impl<'a> PendingFill<'a> {
@@ -68,9 +89,24 @@ impl<'a> PendingFill<'a> {
             order_type,
             maker_fills,
             taker_fill_outcome,
+            decrements: Vec::new(),
         }
     }
 
+    /// take `decrements` off the taker as well as the fills.
+    pub fn with_decrements(mut self, decrements: Vec<MakerFill>) -> Self {
+        self.decrements = decrements;
+        self
+    }
+
+    /// The resting orders the taker was decremented against instead of trading with.
+    ///
+    /// committing takes the decremented quantity off the taker only, the resting orders are left
+    /// in the book for the caller to reduce or cancel.
+    pub fn decrements(&self) -> &[MakerFill] {
+        &self.decrements
+    }
+
     /// [`OrderSide::Buy`] or [`OrderSide::Sell`] if the taker's order is buy or sell respectively.
     pub fn taker_side(&self) -> OrderSide {
         self.side
@@ -93,10 +129,15 @@ impl<'a> PendingFill<'a> {
 
     /// Execute the pending fill operation.
     pub fn commit(self) -> Result<(FillType, Option<Order>), ExecutePendingFillError> {
-        let mut taker_order_remaining_quantity = self.taker.quantity.get();
+        let decremented = self
+            .decrements
+            .iter()
+            .map(|fill| fill.fill_amount)
+            .sum::<u32>();
+        let mut taker_order_remaining_quantity = self.taker.quantity.get() - decremented;
 
         for fill in &self.maker_fills {
-            if self.orderbook.get_mut(fill.oix).is_none() {
+            if self.orderbook.get(fill.oix).is_none() {
                 return Err(ExecutePendingFillError::InvalidOrderIndex(fill.oix));
             }
         }
@@ -105,7 +146,7 @@ impl<'a> PendingFill<'a> {
             oix,
             maker: order,
             fill_type,
-            ..
+            fill_amount,
         } in self.maker_fills
         {
             match fill_type {
@@ -119,17 +160,17 @@ impl<'a> PendingFill<'a> {
                     // if this also filled the taker order, then we wont loop again.
                     taker_order_remaining_quantity -= maker_order.quantity.get();
                 }
-                // partial fill for a maker order also means a complete fill for the taker order.
+                // partial fill for a maker order, the last fill under price-time priority but any
+                // number of the orders at a level may be partially filled when sharing pro-rata.
                 FillType::Partial => {
                     let maker_order = self
                         .orderbook
-                        .get_mut(oix)
+                        .get(oix)
                         .ok_or(ExecutePendingFillError::InvalidOrderIndex(oix))?; // this should never fail because we already checked that the order exists.
                     assert_eq!(*maker_order, order);
-                    assert!(taker_order_remaining_quantity < maker_order.quantity.get());
-                    maker_order.quantity =
-                    NonZeroU32::new(maker_order.quantity.get() - taker_order_remaining_quantity).expect("partial fills of maker orders will always have a quantity greater than zero");
-                    taker_order_remaining_quantity = 0;
+                    assert!(fill_amount < maker_order.quantity.get());
+                    self.orderbook.reduce(oix, fill_amount).expect("partial fills of maker orders will always have a quantity greater than zero");
+                    taker_order_remaining_quantity -= fill_amount;
                 }
                 FillType::None => unreachable!(),
             }
@@ -138,9 +179,12 @@ impl<'a> PendingFill<'a> {
         match self.taker_fill_outcome {
             FillType::Complete => assert_eq!(taker_order_remaining_quantity, 0),
             FillType::Partial => {
-                assert!(self.taker.quantity.get() > taker_order_remaining_quantity)
+                assert!(self.taker.quantity.get() - decremented > taker_order_remaining_quantity)
             }
-            FillType::None => assert_eq!(taker_order_remaining_quantity, self.taker.quantity.get()),
+            FillType::None => assert_eq!(
+                taker_order_remaining_quantity,
+                self.taker.quantity.get() - decremented
+            ),
         }
 
         let taker_order = if let Some(quantity) = NonZeroU32::new(taker_order_remaining_quantity) {
@@ -148,7 +192,7 @@ impl<'a> PendingFill<'a> {
             taker_order.quantity = quantity;
             Some(taker_order)
         } else {
-            // the taker order was completely filled.
+            // the taker order was completely filled, or decremented.
             None
         };
 
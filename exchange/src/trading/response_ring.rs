@@ -0,0 +1,384 @@
+//! A pre-allocated ring of response slots for the trading engine.
+//!
+//! Every [`TradeCmd`](super::TradeCmd) carries a way for the engine to reply to the
+//! caller. By default this is a fresh [`tokio::sync::oneshot`] channel per command,
+//! which means one heap allocation for every order placed or cancelled.
+//!
+//! When enabled via [`Configuration::te_response_ring_capacity`](crate::Configuration::te_response_ring_capacity)
+//! callers instead claim a slot in a [`ResponseRing`] that was allocated up front.
+//! The engine is the single writer of a claimed slot and the caller is the single
+//! reader, slots are handed back to the ring once the response has been read (or
+//! either side has gone away) and reused by index.
+//!
+//! If every slot is in use the caller falls back to a oneshot channel, so the ring
+//! only ever changes how a response is delivered and never whether it is delivered.
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{oneshot, Notify};
+
+/// the slot is not in use and can be claimed.
+const FREE: u8 = 0;
+/// the slot has been claimed and is waiting for the engine to respond.
+const CLAIMED: u8 = 1;
+/// the engine has written a response into the slot.
+const READY: u8 = 2;
+/// the engine dropped its half of the slot without responding.
+const CLOSED: u8 = 3;
+/// the caller dropped its half of the slot before a response was written.
+const ABANDONED: u8 = 4;
+
+struct Slot<T> {
+    state: AtomicU8,
+    value: UnsafeCell<Option<T>>,
+    notify: Notify,
+}
+
+/// A fixed-size ring of reusable response slots.
+pub struct ResponseRing<T> {
+    slots: Box<[Slot<T>]>,
+    cursor: AtomicUsize,
+}
+
+// SAFETY: access to `Slot::value` is serialized through `Slot::state`, only the
+// holder of the `RingTx` writes to it and only the holder of the `RingRx` reads
+// from it after observing `READY`.
+unsafe impl<T: Send> Sync for ResponseRing<T> {}
+unsafe impl<T: Send> Send for ResponseRing<T> {}
+
+impl<T> std::fmt::Debug for ResponseRing<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseRing")
+            .field("capacity", &self.slots.len())
+            .finish()
+    }
+}
+
+impl<T> ResponseRing<T> {
+    /// create a new ring with `capacity` pre-allocated slots.
+    pub fn new(capacity: usize) -> Arc<Self> {
+        let slots = (0..capacity)
+            .map(|_| Slot {
+                state: AtomicU8::new(FREE),
+                value: UnsafeCell::new(None),
+                notify: Notify::new(),
+            })
+            .collect();
+
+        Arc::new(Self {
+            slots,
+            cursor: AtomicUsize::new(0),
+        })
+    }
+
+    /// the number of slots in the ring.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// try to claim a free slot, returns `None` if every slot is in use.
+    pub fn try_claim(self: &Arc<Self>) -> Option<(RingTx<T>, RingRx<T>)> {
+        let len = self.slots.len();
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+
+        for offset in 0..len {
+            let index = start.wrapping_add(offset) % len;
+            let claimed = self.slots[index]
+                .state
+                .compare_exchange(FREE, CLAIMED, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok();
+
+            if claimed {
+                let tx = RingTx {
+                    ring: Arc::clone(self),
+                    index,
+                    armed: true,
+                };
+                let rx = RingRx {
+                    ring: Arc::clone(self),
+                    index,
+                    armed: true,
+                };
+                return Some((tx, rx));
+            }
+        }
+
+        None
+    }
+}
+
+/// The engine's half of a claimed slot.
+pub struct RingTx<T> {
+    ring: Arc<ResponseRing<T>>,
+    index: usize,
+    armed: bool,
+}
+
+impl<T> RingTx<T> {
+    /// write `value` into the slot and wake the reader, returns the value back if the reader has gone away.
+    pub fn send(mut self, value: T) -> Result<(), T> {
+        self.armed = false;
+        let slot = &self.ring.slots[self.index];
+
+        // SAFETY: the slot is CLAIMED or ABANDONED, neither of which the reader reads the value in.
+        unsafe { *slot.value.get() = Some(value) };
+
+        match slot
+            .state
+            .compare_exchange(CLAIMED, READY, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                slot.notify.notify_one();
+                Ok(())
+            }
+            Err(_abandoned) => {
+                // SAFETY: the reader is gone, we are the only one left touching this slot.
+                let value = unsafe { (*slot.value.get()).take() };
+                slot.state.store(FREE, Ordering::Release);
+                Err(value.expect("value was just written"))
+            }
+        }
+    }
+}
+
+impl<T> Drop for RingTx<T> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let slot = &self.ring.slots[self.index];
+        match slot
+            .state
+            .compare_exchange(CLAIMED, CLOSED, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => slot.notify.notify_one(),
+            Err(_abandoned) => slot.state.store(FREE, Ordering::Release),
+        }
+    }
+}
+
+/// The caller's half of a claimed slot.
+pub struct RingRx<T> {
+    ring: Arc<ResponseRing<T>>,
+    index: usize,
+    armed: bool,
+}
+
+impl<T> RingRx<T> {
+    /// wait for the engine to respond, returns `None` if the engine dropped the slot without responding.
+    pub async fn recv(mut self) -> Option<T> {
+        let ring = Arc::clone(&self.ring);
+        let slot = &ring.slots[self.index];
+
+        loop {
+            let notified = slot.notify.notified();
+
+            match slot.state.load(Ordering::Acquire) {
+                READY => {
+                    self.armed = false;
+                    // SAFETY: READY means the writer is done with the value.
+                    let value = unsafe { (*slot.value.get()).take() };
+                    slot.state.store(FREE, Ordering::Release);
+                    return value;
+                }
+                CLOSED => {
+                    self.armed = false;
+                    slot.state.store(FREE, Ordering::Release);
+                    return None;
+                }
+                // spurious wake-ups from a previous use of the slot just loop around.
+                _ => notified.await,
+            }
+        }
+    }
+}
+
+impl<T> Drop for RingRx<T> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let slot = &self.ring.slots[self.index];
+        match slot
+            .state
+            .compare_exchange(CLAIMED, ABANDONED, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => (),
+            Err(_ready_or_closed) => {
+                // SAFETY: the writer is done with the slot.
+                let _ = unsafe { (*slot.value.get()).take() };
+                slot.state.store(FREE, Ordering::Release);
+            }
+        }
+    }
+}
+
+/// The sending half of a trading engine response, either a oneshot or a ring slot.
+pub enum ResponseTx<T> {
+    /// a freshly allocated oneshot channel, the default.
+    Oneshot(oneshot::Sender<T>),
+    /// a slot claimed from a [`ResponseRing`].
+    Ring(RingTx<T>),
+}
+
+impl<T> ResponseTx<T> {
+    /// send the response, returns the value back if the receiver has gone away.
+    pub fn send(self, value: T) -> Result<(), T> {
+        match self {
+            Self::Oneshot(tx) => tx.send(value),
+            Self::Ring(tx) => tx.send(value),
+        }
+    }
+}
+
+impl<T> From<oneshot::Sender<T>> for ResponseTx<T> {
+    fn from(tx: oneshot::Sender<T>) -> Self {
+        Self::Oneshot(tx)
+    }
+}
+
+/// The receiving half of a trading engine response, either a oneshot or a ring slot.
+pub enum ResponseRx<T> {
+    /// a freshly allocated oneshot channel, the default.
+    Oneshot(oneshot::Receiver<T>),
+    /// a slot claimed from a [`ResponseRing`].
+    Ring(RingRx<T>),
+}
+
+impl<T> ResponseRx<T> {
+    /// wait for the response, returns `None` if the sender was dropped without responding.
+    pub async fn recv(self) -> Option<T> {
+        match self {
+            Self::Oneshot(rx) => rx.await.ok(),
+            Self::Ring(rx) => rx.recv().await,
+        }
+    }
+}
+
+impl<T> From<oneshot::Receiver<T>> for ResponseRx<T> {
+    fn from(rx: oneshot::Receiver<T>) -> Self {
+        Self::Oneshot(rx)
+    }
+}
+
+/// create a response channel, using a slot from `ring` if one is available otherwise a oneshot.
+pub fn response_channel<T>(ring: Option<&Arc<ResponseRing<T>>>) -> (ResponseTx<T>, ResponseRx<T>) {
+    if let Some((tx, rx)) = ring.and_then(|ring| ring.try_claim()) {
+        return (ResponseTx::Ring(tx), ResponseRx::Ring(rx));
+    }
+
+    let (tx, rx) = oneshot::channel();
+    (ResponseTx::Oneshot(tx), ResponseRx::Oneshot(rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ring_round_trip_reuses_slots() {
+        let ring = ResponseRing::new(2);
+
+        for i in 0..10u32 {
+            let (tx, rx) = response_channel(Some(&ring));
+            assert!(matches!(tx, ResponseTx::Ring(_)));
+            tx.send(i).unwrap();
+            assert_eq!(rx.recv().await, Some(i));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ring_exhausted_falls_back_to_oneshot() {
+        let ring = ResponseRing::<u32>::new(1);
+
+        let (held_tx, held_rx) = response_channel(Some(&ring));
+        assert!(matches!(held_tx, ResponseTx::Ring(_)));
+
+        let (tx, rx) = response_channel(Some(&ring));
+        assert!(matches!(tx, ResponseTx::Oneshot(_)));
+        tx.send(1).unwrap();
+        assert_eq!(rx.recv().await, Some(1));
+
+        held_tx.send(2).unwrap();
+        assert_eq!(held_rx.recv().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_tx_wakes_rx_with_none() {
+        let ring = ResponseRing::<u32>::new(1);
+        let (tx, rx) = response_channel(Some(&ring));
+
+        let waiter = tokio::spawn(rx.recv());
+        drop(tx);
+
+        assert_eq!(waiter.await.unwrap(), None);
+        assert!(ring.try_claim().is_some(), "slot should be free again");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_rx_frees_slot() {
+        let ring = ResponseRing::<u32>::new(1);
+        let (tx, rx) = response_channel(Some(&ring));
+
+        drop(rx);
+        assert_eq!(tx.send(7), Err(7));
+        assert!(ring.try_claim().is_some(), "slot should be free again");
+    }
+
+    /// round-trip `n` commands through a task standing in for the trading engine, returns the
+    /// time taken, the round trips completed and how many of those went over the ring.
+    async fn bench_round_trips(
+        n: u32,
+        ring: Option<Arc<ResponseRing<u32>>>,
+    ) -> (Duration, u32, u32) {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel::<(u32, ResponseTx<u32>)>(1024);
+
+        let engine = tokio::spawn(async move {
+            while let Some((value, response)) = cmd_rx.recv().await {
+                let _ = response.send(value);
+            }
+        });
+
+        let (mut completed, mut over_ring) = (0, 0);
+        let start = Instant::now();
+        for i in 0..n {
+            let (tx, rx) = response_channel(ring.as_ref());
+            over_ring += u32::from(matches!(tx, ResponseTx::Ring(_)));
+            cmd_tx.send((i, tx)).await.unwrap();
+            assert_eq!(rx.recv().await, Some(i));
+            completed += 1;
+        }
+        let elapsed = start.elapsed();
+
+        drop(cmd_tx);
+        engine.await.unwrap();
+        (elapsed, completed, over_ring)
+    }
+
+    #[tokio::test]
+    #[ignore = "benchmark, run with `cargo test -- --ignored --nocapture`"]
+    async fn bench_oneshot_vs_ring() {
+        const N: u32 = 100_000;
+
+        let (oneshot, completed, over_ring) = bench_round_trips(N, None).await;
+        assert_eq!((completed, over_ring), (N, 0));
+
+        let (ring, completed, over_ring) =
+            bench_round_trips(N, Some(ResponseRing::new(1024))).await;
+        assert_eq!((completed, over_ring), (N, N));
+
+        eprintln!(
+            "oneshot: {N} round trips in {oneshot:?} ({:?}/op)",
+            oneshot / N
+        );
+        eprintln!("ring:    {N} round trips in {ring:?} ({:?}/op)", ring / N);
+    }
+}
@@ -2,6 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use super::MakerScreen;
+
 /// The self-trade protection of an order.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SelfTradeProtection {
@@ -24,3 +26,49 @@ impl Default for SelfTradeProtection {
         Self::DecreaseCancel
     }
 }
+
+impl SelfTradeProtection {
+    /// what matching does with a resting order of the taker's own owner.
+    ///
+    /// - [`Self::DecreaseCancel`] trades nothing with it and takes the smaller quantity off both,
+    ///   the order left with nothing is cancelled and the taker carries on with what is left.
+    /// - [`Self::CancelOldest`] passes over it and cancels it once the taker is placed.
+    /// - [`Self::CancelNewest`] stops matching and cancels the rest of the taker.
+    /// - [`Self::CancelBoth`] stops matching and cancels the rest of the taker and the order.
+    pub fn screen(&self) -> MakerScreen {
+        match self {
+            Self::DecreaseCancel => MakerScreen::Decrement,
+            Self::CancelOldest => MakerScreen::Skip,
+            Self::CancelNewest | Self::CancelBoth => MakerScreen::Stop,
+        }
+    }
+
+    /// `true` if the resting order of the taker's owner is cancelled.
+    pub fn cancels_resting(&self) -> bool {
+        matches!(self, Self::CancelOldest | Self::CancelBoth)
+    }
+
+    /// the short code of the mode, as used in JSON and the `users.default_stp` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DecreaseCancel => "dc",
+            Self::CancelOldest => "co",
+            Self::CancelNewest => "cn",
+            Self::CancelBoth => "cb",
+        }
+    }
+}
+
+impl std::str::FromStr for SelfTradeProtection {
+    type Err = String;
+
+    fn from_str(st: &str) -> Result<Self, Self::Err> {
+        match st {
+            "dc" => Ok(Self::DecreaseCancel),
+            "co" => Ok(Self::CancelOldest),
+            "cn" => Ok(Self::CancelNewest),
+            "cb" => Ok(Self::CancelBoth),
+            _ => Err(format!("unknown self-trade protection {st:?}")),
+        }
+    }
+}
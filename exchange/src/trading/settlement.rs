@@ -0,0 +1,148 @@
+//! The moves of funds the engine's trades leave for the ledger.
+//!
+//! An order reserves what it gives up before the engine sees it, see `AppCx::place_order`, so
+//! the engine only has to say what became of each reserve: part of it paid for a fill, or part
+//! of it is no longer needed and goes back to its owner. The [`Settlement`]s of a command are
+//! written along with its journal entry and settled from there, none are lost with the request
+//! that placed the order.
+
+use std::num::NonZeroU32;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// An order whose reserve a [`Settlement`] draws on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SettlementOrder {
+    /// the order.
+    pub order_uuid: OrderUuid,
+    /// the owner of the order.
+    pub user_uuid: uuid::Uuid,
+    /// the price of the order, a buy reserved its notional at this price.
+    pub price: NonZeroU32,
+}
+
+/// What became of part of the reserve of one or two orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Settlement {
+    /// a taker traded with a maker, each side is paid out of the reserve of the other.
+    Fill {
+        /// the book the trade happened in.
+        asset: Asset,
+        /// the side of the incoming order.
+        taker_side: OrderSide,
+        /// the incoming order.
+        taker: SettlementOrder,
+        /// the resting order.
+        maker: SettlementOrder,
+        /// the quantity exchanged.
+        quantity: u32,
+        /// the price of the trade, the resting order's price.
+        price: NonZeroU32,
+    },
+    /// quantity of an order that will never fill, cancelled or reduced, its share of the reserve
+    /// goes back to the owner.
+    Release {
+        /// the book the order was placed in.
+        asset: Asset,
+        /// the side of the order.
+        side: OrderSide,
+        /// the order.
+        order: SettlementOrder,
+        /// the quantity that will never fill.
+        quantity: u32,
+    },
+}
+
+impl Settlement {
+    /// release the whole reserve of `place_order`, which the engine turned away or never got to.
+    pub fn release_rejected(place_order: &PlaceOrder) -> Self {
+        Self::Release {
+            asset: place_order.asset,
+            side: place_order.side,
+            order: SettlementOrder {
+                order_uuid: place_order.order_uuid,
+                user_uuid: place_order.user_uuid,
+                price: place_order.price,
+            },
+            quantity: place_order.quantity.get(),
+        }
+    }
+}
+
+impl Assets {
+    /// The settlements of `events`, the events of the last command, in the same order.
+    pub fn settlements(&self, events: &[MatchEvent]) -> Vec<Settlement> {
+        let order = |order_uuid: &OrderUuid| {
+            let record = self.orders.get(order_uuid)?;
+            Some((
+                record.side,
+                SettlementOrder {
+                    order_uuid: *order_uuid,
+                    user_uuid: record.user_uuid,
+                    price: record.price,
+                },
+            ))
+        };
+
+        let mut settlements = Vec::with_capacity(events.len());
+
+        for event in events {
+            match event {
+                MatchEvent::Fill {
+                    asset,
+                    maker,
+                    taker,
+                    quantity,
+                    price,
+                } => {
+                    let (Some((taker_side, taker)), Some((_, maker))) =
+                        (order(taker), maker.as_ref().and_then(order))
+                    else {
+                        tracing::error!(?event, "can not settle a fill of an unknown order");
+                        continue;
+                    };
+
+                    settlements.push(Settlement::Fill {
+                        asset: *asset,
+                        taker_side,
+                        taker,
+                        maker,
+                        quantity: *quantity,
+                        price: *price,
+                    });
+                }
+                MatchEvent::Cancel {
+                    asset,
+                    order_uuid,
+                    quantity,
+                }
+                | MatchEvent::Update {
+                    asset,
+                    order_uuid,
+                    quantity_reduced: quantity,
+                    ..
+                } if *quantity > 0 => {
+                    let Some((side, order)) = order(order_uuid) else {
+                        tracing::error!(?event, "can not release the reserve of an unknown order");
+                        continue;
+                    };
+
+                    settlements.push(Settlement::Release {
+                        asset: *asset,
+                        side,
+                        order,
+                        quantity: *quantity,
+                    });
+                }
+                MatchEvent::AggressorResting { .. }
+                | MatchEvent::Cancel { .. }
+                | MatchEvent::Update { .. } => {}
+            }
+        }
+
+        settlements
+    }
+}
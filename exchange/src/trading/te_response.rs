@@ -1,14 +1,13 @@
 #![allow(missing_docs)]
 
-use tokio::sync::oneshot;
-
+use super::response_ring::ResponseRx;
 use super::TradingEngineError;
 
 #[must_use]
-pub struct TeResponse<T, E = TradingEngineError>(pub oneshot::Receiver<Result<T, E>>);
+pub struct TeResponse<T, E = TradingEngineError>(pub ResponseRx<Result<T, E>>);
 
 impl<T, E> TeResponse<T, E> {
     pub async fn wait(self) -> Option<Result<T, E>> {
-        self.0.await.ok()
+        self.0.recv().await
     }
 }
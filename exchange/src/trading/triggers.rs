@@ -0,0 +1,212 @@
+//! Conditional orders held off the book until the market trades through a price.
+//!
+//! A stop and a market-if-touched order differ only in the direction the price has to move to
+//! release them:
+//!
+//! | side | [`TriggerKind::Stop`]          | [`TriggerKind::MarketIfTouched`] |
+//! |------|--------------------------------|----------------------------------|
+//! | buy  | price rises to the trigger     | price falls to the trigger       |
+//! | sell | price falls to the trigger     | price rises to the trigger       |
+//!
+//! A stop is released by the price moving against the order (cutting a loss, chasing a
+//! breakout), a market-if-touched by the price moving in its favour (taking a profit, buying a
+//! dip). Once released both are matched like any other order.
+//!
+//! The book's good-til-date orders are scheduled here too, by the time they lapse, and the
+//! engine cancels them once [`Triggers::poll_expired`] hands them back.
+
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+
+use serde::{Deserialize, Serialize};
+
+use super::{OrderSide, OrderUuid, PlaceOrder};
+
+/// Which way the price has to move to release a held order, see the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TriggerKind {
+    /// released once the price moves against the order.
+    #[serde(rename = "stop")]
+    Stop,
+    /// released once the price moves in favour of the order.
+    #[serde(rename = "market_if_touched")]
+    MarketIfTouched,
+}
+
+/// The condition a held order waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Trigger {
+    /// which way the price has to move.
+    pub kind: TriggerKind,
+    /// the price the last trade has to reach.
+    pub price: NonZeroU32,
+}
+
+impl Trigger {
+    /// `true` if a trade at `last_price` releases an order on `side` held by this trigger.
+    pub fn is_touched(&self, side: OrderSide, last_price: NonZeroU32) -> bool {
+        let on_rise = match (self.kind, side) {
+            (TriggerKind::Stop, OrderSide::Buy) => true,
+            (TriggerKind::Stop, OrderSide::Sell) => false,
+            (TriggerKind::MarketIfTouched, OrderSide::Buy) => false,
+            (TriggerKind::MarketIfTouched, OrderSide::Sell) => true,
+        };
+
+        if on_rise {
+            last_price >= self.price
+        } else {
+            last_price <= self.price
+        }
+    }
+}
+
+/// The orders of a book held until their trigger is touched, and the times its good-til-date
+/// orders lapse.
+#[derive(Debug, Default)]
+pub struct Triggers {
+    /// held orders in the order they were placed, each with its trigger set.
+    held: Vec<PlaceOrder>,
+    /// orders by when they expire, in milliseconds since the unix epoch.
+    expiries: BTreeMap<i64, Vec<OrderUuid>>,
+}
+
+impl Triggers {
+    /// hold `order` until its trigger is touched.
+    ///
+    /// # Panics
+    ///
+    /// if `order` has no trigger.
+    pub fn hold(&mut self, order: PlaceOrder) {
+        assert!(order.trigger.is_some(), "held orders must have a trigger");
+        self.held.push(order);
+    }
+
+    /// take out the orders released by a trade at `last_price`, oldest first, with their
+    /// triggers cleared.
+    pub fn release(&mut self, last_price: NonZeroU32) -> Vec<PlaceOrder> {
+        let (released, held) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition::<Vec<_>, _>(|order| {
+                order
+                    .trigger
+                    .is_some_and(|trigger| trigger.is_touched(order.side, last_price))
+            });
+
+        self.held = held;
+
+        released
+            .into_iter()
+            .map(|mut order| {
+                order.trigger = None;
+                order
+            })
+            .collect()
+    }
+
+    /// stop holding the order with `order_uuid`, returns it if it was held.
+    pub fn cancel(&mut self, order_uuid: OrderUuid) -> Option<PlaceOrder> {
+        let index = self
+            .held
+            .iter()
+            .position(|order| order.order_uuid == order_uuid)?;
+
+        Some(self.held.remove(index))
+    }
+
+    /// hand `order_uuid` back from [`Self::poll_expired`] once it is `expires_at`, in
+    /// milliseconds since the unix epoch.
+    ///
+    /// orders that close in the meantime are not unscheduled, the caller skips them when they
+    /// come back, they are gone by the maximum TTL anyway.
+    pub fn schedule_expiry(&mut self, order_uuid: OrderUuid, expires_at: i64) {
+        self.expiries
+            .entry(expires_at)
+            .or_default()
+            .push(order_uuid);
+    }
+
+    /// take out the orders that have expired by `now`, in milliseconds since the unix epoch,
+    /// the ones expiring first, first.
+    pub fn poll_expired(&mut self, now: i64) -> Vec<OrderUuid> {
+        // everything after `now` stays, an order expiring at `now` has expired.
+        let pending = self.expiries.split_off(&now.saturating_add(1));
+        std::mem::replace(&mut self.expiries, pending)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    /// the number of orders scheduled to expire.
+    pub fn expiring(&self) -> usize {
+        self.expiries.values().map(Vec::len).sum()
+    }
+
+    /// the number of orders held.
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    /// `true` if no orders are held.
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigger(kind: TriggerKind, price: u32) -> Trigger {
+        Trigger {
+            kind,
+            price: NonZeroU32::new(price).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_stop_and_market_if_touched_trigger_in_opposite_directions() {
+        let price = |price| NonZeroU32::new(price).unwrap();
+
+        let stop = trigger(TriggerKind::Stop, 100);
+        let mit = trigger(TriggerKind::MarketIfTouched, 100);
+
+        // a buy stop waits for the price to rise to 100, a buy MIT for it to fall to 100.
+        assert!(!stop.is_touched(OrderSide::Buy, price(99)));
+        assert!(stop.is_touched(OrderSide::Buy, price(100)));
+        assert!(stop.is_touched(OrderSide::Buy, price(101)));
+
+        assert!(mit.is_touched(OrderSide::Buy, price(99)));
+        assert!(mit.is_touched(OrderSide::Buy, price(100)));
+        assert!(!mit.is_touched(OrderSide::Buy, price(101)));
+
+        // and the other way around for sells.
+        assert!(stop.is_touched(OrderSide::Sell, price(99)));
+        assert!(!stop.is_touched(OrderSide::Sell, price(101)));
+        assert!(!mit.is_touched(OrderSide::Sell, price(99)));
+        assert!(mit.is_touched(OrderSide::Sell, price(101)));
+    }
+
+    #[test]
+    fn test_poll_expired_returns_only_the_lapsed_orders() {
+        let mut triggers = Triggers::default();
+        let soon = OrderUuid(uuid::Uuid::new_v4());
+        let later = OrderUuid(uuid::Uuid::new_v4());
+
+        let mut now = 1_000_000;
+        triggers.schedule_expiry(later, now + 60_000);
+        triggers.schedule_expiry(soon, now + 1_000);
+        assert!(triggers.poll_expired(now).is_empty());
+
+        now += 1_000;
+        assert_eq!(triggers.poll_expired(now), vec![soon]);
+        assert_eq!(triggers.expiring(), 1);
+
+        // handed back once only.
+        now += 1;
+        assert!(triggers.poll_expired(now).is_empty());
+
+        now += 60_000;
+        assert_eq!(triggers.poll_expired(now), vec![later]);
+        assert_eq!(triggers.expiring(), 0);
+    }
+}
@@ -1,14 +1,93 @@
 //! This module contains the [`try_fill_orders`] function, which attempts to fill a taker's order
 
-use std::convert::Infallible;
-
 use pending_fill::MakerFill;
 
 use super::*;
 
 /// An error that can occur when attempting to fill orders.
-#[derive(Debug, thiserror::Error)]
-pub enum TryFillOrdersError {}
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TryFillOrdersError {
+    /// the best bid is at or above the best ask, matching never leaves a book like this so it
+    /// has been corrupted.
+    #[error("the book is crossed, bid at {bid_price} is at or above ask at {ask_price}")]
+    CrossedBook {
+        /// the best bid.
+        bid: OrderIndex,
+        /// the price of the best bid.
+        bid_price: NonZeroU32,
+        /// the best ask.
+        ask: OrderIndex,
+        /// the price of the best ask.
+        ask_price: NonZeroU32,
+    },
+    /// a fill against a resting order is worth more than the ledger can hold.
+    #[error("the fill against the order at {price} is too large: {source}")]
+    NotionalOverflow {
+        /// the resting order the fill is against.
+        maker: OrderIndex,
+        /// the price the fill would execute at.
+        price: NonZeroU32,
+        /// the notional that overflowed.
+        #[source]
+        source: NotionalOverflow,
+    },
+    /// a market order found no resting orders to fill against.
+    #[error("there are no {side:?} orders resting to fill against")]
+    NoLiquidity {
+        /// the side of the book that is empty.
+        side: OrderSide,
+    },
+    /// matching stopped at a resting order the taker must not trade with.
+    #[error("self-trade prevented against the order at {price}")]
+    SelfTradePrevented {
+        /// the resting order the taker would have traded with.
+        maker: OrderIndex,
+        /// the price of the resting order.
+        price: NonZeroU32,
+    },
+}
+
+/// What the matcher does with a resting order the taker crosses, see [`try_fill_orders_skipping`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MakerScreen {
+    /// fill against the order.
+    Match,
+    /// pass over the order, it is left in the book untouched and keeps its place in the queue.
+    Skip,
+    /// stop matching with [`TryFillOrdersError::SelfTradePrevented`].
+    Abort,
+    /// stop matching before the order, the fills made so far stand.
+    Stop,
+    /// trade nothing with the order, take the smaller of its and the taker's quantity off both
+    /// and carry on matching with what is left of the taker, see [`PendingFill::decrements`].
+    Decrement,
+}
+
+/// what matching made of the taker under one of the [`MatchingPolicy`]s.
+struct Fills {
+    maker_fills: Vec<MakerFill>,
+    decrements: Vec<MakerFill>,
+    /// the quantity of the taker neither filled nor decremented.
+    taker_rem_q: u32,
+}
+
+/// How an incoming order is shared out among the resting orders at a price level.
+///
+/// Price levels are always taken best price first, the policy only decides who is filled
+/// within a level the taker can not clear.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchingPolicy {
+    /// first come first served, the oldest order at a price is filled first.
+    #[default]
+    PriceTime,
+    /// every order at a price is filled in proportion to its quantity.
+    ///
+    /// each order gets its share of the taker quantity rounded down, the units left over by the
+    /// rounding go one each to the orders in time priority. All-or-none orders only take part
+    /// when the taker clears the whole level.
+    ProRata,
+}
 
 /// Attempts to fill a taker's order against the current state of the order book.
 ///
@@ -21,50 +100,85 @@ pub fn try_fill_orders<'a>(
     taker: Order,
     side: OrderSide,
     order_type: OrderType,
-) -> Result<PendingFill<'a>, Infallible> {
-    let mut maker_fills = vec![];
-    let mut taker_fill_outcome = FillType::None;
-    let mut taker_rem_q = taker.quantity.get();
-
-    let maker_side = match side {
-        OrderSide::Buy => OrderSide::Sell,
-        OrderSide::Sell => OrderSide::Buy,
-    };
+) -> Result<PendingFill<'a>, TryFillOrdersError> {
+    try_fill_orders_skipping(
+        orderbook,
+        taker,
+        side,
+        order_type,
+        MatchingPolicy::PriceTime,
+        |_| MakerScreen::Match,
+    )
+}
 
-    for (oix, order) in orderbook.iter_rel(maker_side) {
-        if order_type == OrderType::Limit
-            && ((side == OrderSide::Buy && order.price > taker.price)
-                || (side == OrderSide::Sell && order.price < taker.price))
-        {
-            continue; // Skip orders that don't meet the price condition for limit orders
+/// Like [`try_fill_orders`] under `policy`, but every resting order the taker crosses is first
+/// put through `screen` which decides whether it is filled, passed over, or ends the match.
+pub fn try_fill_orders_skipping<'a>(
+    orderbook: &'a mut Orderbook,
+    taker: Order,
+    side: OrderSide,
+    order_type: OrderType,
+    policy: MatchingPolicy,
+    screen: impl Fn(OrderIndex) -> MakerScreen,
+) -> Result<PendingFill<'a>, TryFillOrdersError> {
+    let best_bid = orderbook.iter_rel(OrderSide::Buy).next();
+    let best_ask = orderbook.iter_rel(OrderSide::Sell).next();
+
+    if let (Some((bid, bid_order)), Some((ask, ask_order))) = (best_bid, best_ask) {
+        if bid_order.price >= ask_order.price {
+            return Err(TryFillOrdersError::CrossedBook {
+                bid,
+                bid_price: bid_order.price,
+                ask,
+                ask_price: ask_order.price,
+            });
         }
+    }
 
-        let fill_amount = std::cmp::min(order.quantity.get(), taker_rem_q);
-        let fill_type = if fill_amount == order.quantity.get() {
-            FillType::Complete
-        } else {
-            FillType::Partial
-        };
+    let resting = match side {
+        OrderSide::Buy => best_ask,
+        OrderSide::Sell => best_bid,
+    };
 
-        maker_fills.push(MakerFill {
-            oix,
-            maker: order,
-            fill_type,
-            fill_amount,
+    if order_type == OrderType::Market && resting.is_none() {
+        return Err(TryFillOrdersError::NoLiquidity {
+            side: side.opposite(),
         });
+    }
 
-        if taker_rem_q == fill_amount {
-            taker_fill_outcome = FillType::Complete;
-            taker_rem_q = 0;
-            break;
-        } else {
-            taker_fill_outcome = FillType::Partial;
-            taker_rem_q = taker_rem_q - fill_amount;
-        }
+    let Fills {
+        mut maker_fills,
+        mut decrements,
+        mut taker_rem_q,
+    } = match policy {
+        MatchingPolicy::PriceTime => price_time_fills(orderbook, taker, side, screen)?,
+        MatchingPolicy::ProRata => pro_rata_fills(orderbook, taker, side, screen)?,
+    };
+
+    if taker.all_or_none && taker_rem_q > 0 {
+        // an all-or-none taker that can not be completely filled does not fill at all.
+        maker_fills.clear();
+        decrements.clear();
+        taker_rem_q = taker.quantity.get();
     }
 
-    if taker_rem_q == taker.quantity.get() {
-        taker_fill_outcome = FillType::None;
+    let decremented = decrements.iter().map(|fill| fill.fill_amount).sum::<u32>();
+    let taker_fill_outcome = if taker_rem_q + decremented == taker.quantity.get() {
+        FillType::None
+    } else if taker_rem_q == 0 {
+        FillType::Complete
+    } else {
+        FillType::Partial
+    };
+
+    for fill in &maker_fills {
+        notional(fill.maker.price.get(), u64::from(fill.fill_amount)).map_err(|source| {
+            TryFillOrdersError::NotionalOverflow {
+                maker: fill.oix,
+                price: fill.maker.price,
+                source,
+            }
+        })?;
     }
 
     let pending_fill = PendingFill::new(
@@ -74,11 +188,181 @@ pub fn try_fill_orders<'a>(
         order_type,
         maker_fills,
         taker_fill_outcome,
-    );
+    )
+    .with_decrements(decrements);
 
     Ok(pending_fill)
 }
 
+/// the fills of `taker` in price-time priority and the quantity left unfilled.
+fn price_time_fills(
+    orderbook: &Orderbook,
+    taker: Order,
+    side: OrderSide,
+    screen: impl Fn(OrderIndex) -> MakerScreen,
+) -> Result<Fills, TryFillOrdersError> {
+    let mut maker_fills = vec![];
+    let mut decrements = vec![];
+    let mut taker_rem_q = taker.quantity.get();
+
+    // makers rest on the opposite side, best price first relative to the taker.
+    for (oix, order) in orderbook.iter_rel(side.opposite()) {
+        if !side.crosses(taker.price, order.price) {
+            continue; // Skip orders beyond the taker's price, the worst a market order accepts
+        }
+
+        if order.all_or_none && order.quantity.get() > taker_rem_q {
+            continue; // Skip all-or-none orders that the taker cannot fill in one go
+        }
+
+        let fills = match screen(oix) {
+            MakerScreen::Match => &mut maker_fills,
+            MakerScreen::Skip => continue,
+            MakerScreen::Abort => {
+                return Err(TryFillOrdersError::SelfTradePrevented {
+                    maker: oix,
+                    price: order.price,
+                })
+            }
+            MakerScreen::Stop => break,
+            MakerScreen::Decrement => &mut decrements,
+        };
+
+        let fill = MakerFill::against(oix, order, taker_rem_q);
+        taker_rem_q -= fill.fill_amount;
+        fills.push(fill);
+
+        if taker_rem_q == 0 {
+            break;
+        }
+    }
+
+    Ok(Fills {
+        maker_fills,
+        decrements,
+        taker_rem_q,
+    })
+}
+
+/// the fills of `taker` sharing each price level pro-rata and the quantity left unfilled, see
+/// [`MatchingPolicy::ProRata`].
+fn pro_rata_fills(
+    orderbook: &Orderbook,
+    taker: Order,
+    side: OrderSide,
+    screen: impl Fn(OrderIndex) -> MakerScreen,
+) -> Result<Fills, TryFillOrdersError> {
+    fn level_quantity(level: &[(OrderIndex, Order)]) -> u64 {
+        level
+            .iter()
+            .map(|(_, order)| u64::from(order.quantity.get()))
+            .sum()
+    }
+
+    let mut maker_fills = vec![];
+    let mut decrements = vec![];
+    let mut taker_rem_q = taker.quantity.get();
+    let mut stopped = false;
+
+    let mut makers = orderbook
+        .iter_rel(side.opposite())
+        .filter(|(_, order)| side.crosses(taker.price, order.price))
+        .peekable();
+
+    while taker_rem_q > 0 && !stopped {
+        let Some(&(_, best)) = makers.peek() else {
+            break;
+        };
+
+        // the resting orders at the best price left, in time priority.
+        let mut level = vec![];
+        while let Some((oix, order)) = makers.next_if(|(_, order)| order.price == best.price) {
+            match screen(oix) {
+                MakerScreen::Match => level.push((oix, order)),
+                MakerScreen::Skip => (),
+                MakerScreen::Abort => {
+                    return Err(TryFillOrdersError::SelfTradePrevented {
+                        maker: oix,
+                        price: order.price,
+                    })
+                }
+                // the orders of the level collected so far still share the taker.
+                MakerScreen::Stop => {
+                    stopped = true;
+                    break;
+                }
+                MakerScreen::Decrement if taker_rem_q > 0 => {
+                    let decrement = MakerFill::against(oix, order, taker_rem_q);
+                    taker_rem_q -= decrement.fill_amount;
+                    decrements.push(decrement);
+                }
+                MakerScreen::Decrement => (),
+            }
+        }
+
+        if level_quantity(&level) > u64::from(taker_rem_q) {
+            level.retain(|(_, order)| !order.all_or_none);
+        }
+
+        let level_q = level_quantity(&level);
+
+        // a level the taker clears is filled completely, whatever the policy.
+        if level_q <= u64::from(taker_rem_q) {
+            for (oix, order) in level {
+                maker_fills.push(MakerFill {
+                    oix,
+                    maker: order,
+                    fill_type: FillType::Complete,
+                    fill_amount: order.quantity.get(),
+                });
+            }
+            taker_rem_q -= level_q as u32;
+            continue;
+        }
+
+        // every share is rounded down and below the order's quantity, so the leftover units are
+        // fewer than the orders and each order can take one more.
+        let mut shares = level
+            .iter()
+            .map(|(_, order)| {
+                (u64::from(order.quantity.get()) * u64::from(taker_rem_q) / level_q) as u32
+            })
+            .collect::<Vec<_>>();
+        let leftover = taker_rem_q - shares.iter().sum::<u32>();
+        shares
+            .iter_mut()
+            .take(leftover as usize)
+            .for_each(|share| *share += 1);
+
+        for ((oix, order), fill_amount) in level.into_iter().zip(shares) {
+            if fill_amount == 0 {
+                continue;
+            }
+
+            let fill_type = if fill_amount == order.quantity.get() {
+                FillType::Complete
+            } else {
+                FillType::Partial
+            };
+
+            maker_fills.push(MakerFill {
+                oix,
+                maker: order,
+                fill_type,
+                fill_amount,
+            });
+        }
+
+        taker_rem_q = 0;
+    }
+
+    Ok(Fills {
+        maker_fills,
+        decrements,
+        taker_rem_q,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -96,12 +380,14 @@ mod tests {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            all_or_none: false,
         });
 
         let taker = Order {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            all_or_none: false,
         };
         let result =
             try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
@@ -117,11 +403,13 @@ mod tests {
             price: nz!(100),
             quantity: nz!(30),
             memo: 0,
+            all_or_none: false,
         });
         let taker = Order {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            all_or_none: false,
         };
 
         let result =
@@ -138,11 +426,13 @@ mod tests {
             price: nz!(150),
             quantity: nz!(50),
             memo: 0,
+            all_or_none: false,
         });
         let taker = Order {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            all_or_none: false,
         };
 
         let result =
@@ -158,6 +448,7 @@ mod tests {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            all_or_none: false,
         };
 
         let result =
@@ -173,11 +464,13 @@ mod tests {
             price: nz!(150),
             quantity: nz!(50),
             memo: 0,
+            all_or_none: false,
         });
         let taker = Order {
             price: nz!(100),
             quantity: nz!(50),
             memo: 0,
+            all_or_none: false,
         };
 
         let result =
@@ -195,22 +488,26 @@ mod tests {
             price: nz!(100),
             quantity: nz!(30),
             memo: 1,
+            all_or_none: false,
         });
         orderbook.push_ask(Order {
             price: nz!(105),
             quantity: nz!(20),
             memo: 2,
+            all_or_none: false,
         });
         orderbook.push_ask(Order {
             price: nz!(110),
             quantity: nz!(50),
             memo: 3,
+            all_or_none: false,
         });
 
         let taker = Order {
             price: nz!(110),   // Taker is willing to buy up to this price
             quantity: nz!(75), // Taker wants a total of 75 units
             memo: 4,
+            all_or_none: false,
         };
 
         let result =
@@ -256,4 +553,427 @@ mod tests {
         assert_eq!(result.order_type, OrderType::Limit);
         assert_eq!(result.taker_fill_outcome, FillType::Complete);
     }
+
+    #[test]
+    fn test_all_or_none_maker_skipped_by_smaller_taker() {
+        let mut orderbook = Orderbook::new();
+        orderbook.push_ask(Order {
+            price: nz!(100),
+            quantity: nz!(50),
+            memo: 0,
+            all_or_none: true,
+        });
+        orderbook.push_ask(Order {
+            price: nz!(105),
+            quantity: nz!(20),
+            memo: 0,
+            all_or_none: false,
+        });
+
+        let taker = Order {
+            price: nz!(110),
+            quantity: nz!(30),
+            memo: 0,
+            all_or_none: false,
+        };
+
+        let result =
+            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        assert_eq!(result.taker_fill_outcome, FillType::Partial);
+        assert_eq!(result.maker_fills.len(), 1);
+        assert_eq!(result.maker_fills[0].maker.price, nz!(105));
+
+        let (_, remaining) = result.commit().unwrap();
+        assert_eq!(remaining.unwrap().quantity, nz!(10));
+
+        let resting = orderbook.iter_rel(OrderSide::Sell).collect::<Vec<_>>();
+        assert_eq!(resting.len(), 1);
+        assert_eq!(
+            resting[0].1.quantity,
+            nz!(50),
+            "all-or-none maker untouched"
+        );
+    }
+
+    #[test]
+    fn test_all_or_none_maker_filled_by_larger_taker() {
+        let mut orderbook = Orderbook::new();
+        orderbook.push_ask(Order {
+            price: nz!(100),
+            quantity: nz!(50),
+            memo: 0,
+            all_or_none: true,
+        });
+
+        let taker = Order {
+            price: nz!(100),
+            quantity: nz!(60),
+            memo: 0,
+            all_or_none: false,
+        };
+
+        let result =
+            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        assert_eq!(result.taker_fill_outcome, FillType::Partial);
+        assert_eq!(result.maker_fills.len(), 1);
+        assert_eq!(result.maker_fills[0].fill_type, FillType::Complete);
+        assert_eq!(result.maker_fills[0].fill_amount, 50);
+
+        result.commit().unwrap();
+        assert_eq!(orderbook.iter_rel(OrderSide::Sell).count(), 0);
+    }
+
+    #[test]
+    fn test_all_or_none_taker_does_not_partially_fill() {
+        let mut orderbook = Orderbook::new();
+        orderbook.push_ask(Order {
+            price: nz!(100),
+            quantity: nz!(30),
+            memo: 0,
+            all_or_none: false,
+        });
+
+        let taker = Order {
+            price: nz!(100),
+            quantity: nz!(50),
+            memo: 0,
+            all_or_none: true,
+        };
+
+        let result =
+            try_fill_orders(&mut orderbook, taker, OrderSide::Buy, OrderType::Limit).unwrap();
+        assert_eq!(result.taker_fill_outcome, FillType::None);
+        assert_eq!(result.maker_fills.len(), 0);
+    }
+
+    fn order(price: u32, quantity: u32) -> Order {
+        Order {
+            price: std::num::NonZeroU32::new(price).unwrap(),
+            quantity: std::num::NonZeroU32::new(quantity).unwrap(),
+            memo: 0,
+            all_or_none: false,
+        }
+    }
+
+    /// match `side` against a book holding `levels` on the opposite side, prices are mirrored around 100 for sells.
+    fn fill_against_mirror(
+        side: OrderSide,
+        levels: &[(u32, u32)],
+        taker: (u32, u32),
+    ) -> Vec<(u32, u32, FillType)> {
+        let mirror = |price: u32| match side {
+            OrderSide::Buy => price,
+            OrderSide::Sell => 200 - price,
+        };
+
+        let mut orderbook = Orderbook::new();
+        for &(price, quantity) in levels {
+            orderbook.push(side.opposite(), order(mirror(price), quantity));
+        }
+
+        let taker = order(mirror(taker.0), taker.1);
+        let result = try_fill_orders(&mut orderbook, taker, side, OrderType::Limit).unwrap();
+
+        result
+            .maker_fills
+            .iter()
+            .map(|fill| {
+                (
+                    mirror(fill.maker.price.get()),
+                    fill.fill_amount,
+                    fill.fill_type,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_skipped_orders_are_passed_over() {
+        let mut orderbook = Orderbook::new();
+        let order = |price| Order {
+            price,
+            quantity: nz!(5),
+            memo: 0,
+            all_or_none: false,
+        };
+        let skipped = orderbook.push_ask(order(nz!(100)));
+        orderbook.push_ask(order(nz!(101)));
+
+        let taker = order(nz!(101));
+        let result = try_fill_orders_skipping(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            MatchingPolicy::PriceTime,
+            |oix| {
+                if oix == skipped {
+                    MakerScreen::Skip
+                } else {
+                    MakerScreen::Match
+                }
+            },
+        )
+        .unwrap();
+
+        assert_eq!(result.taker_fill_outcome, FillType::Complete);
+        assert_eq!(result.maker_fills.len(), 1);
+        assert_eq!(result.maker_fills[0].maker.price, nz!(101));
+    }
+
+    #[test]
+    fn test_buy_and_sell_are_symmetric() {
+        let levels = [(102, 5), (100, 5), (101, 5)];
+
+        for taker in [(101, 7), (100, 5), (99, 3), (105, 20)] {
+            let buy = fill_against_mirror(OrderSide::Buy, &levels, taker);
+            let sell = fill_against_mirror(OrderSide::Sell, &levels, taker);
+            assert_eq!(buy, sell, "taker {taker:?}");
+        }
+
+        assert_eq!(
+            fill_against_mirror(OrderSide::Sell, &levels, (101, 7)),
+            vec![(100, 5, FillType::Complete), (101, 2, FillType::Partial)]
+        );
+    }
+
+    /// the fill amount of each resting order when a buy of `quantity` up to 101 is matched
+    /// under `policy`, the fills are committed.
+    fn fill_shares(policy: MatchingPolicy, quantity: u32) -> (Vec<u32>, Vec<Option<u32>>) {
+        let mut orderbook = Orderbook::new();
+        let order = |price, quantity, all_or_none| Order {
+            price: NonZeroU32::new(price).unwrap(),
+            quantity: NonZeroU32::new(quantity).unwrap(),
+            memo: 0,
+            all_or_none,
+        };
+        let makers = [
+            orderbook.push_ask(order(100, 10, false)),
+            orderbook.push_ask(order(100, 30, false)),
+            orderbook.push_ask(order(100, 60, false)),
+            orderbook.push_ask(order(101, 50, false)),
+        ];
+
+        let taker = order(101, quantity, false);
+        let result = try_fill_orders_skipping(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            policy,
+            |_| MakerScreen::Match,
+        )
+        .unwrap();
+
+        let shares = makers
+            .iter()
+            .map(|oix| {
+                result
+                    .maker_fills
+                    .iter()
+                    .find(|fill| fill.oix == *oix)
+                    .map_or(0, |fill| fill.fill_amount)
+            })
+            .collect();
+
+        result.commit().unwrap();
+        let remaining = makers
+            .iter()
+            .map(|oix| orderbook.get(*oix).map(|order| order.quantity.get()))
+            .collect();
+
+        (shares, remaining)
+    }
+
+    #[test]
+    fn test_price_time_and_pro_rata_allocation() {
+        // price-time fills the oldest orders at the best price first.
+        let (shares, remaining) = fill_shares(MatchingPolicy::PriceTime, 45);
+        assert_eq!(shares, vec![10, 30, 5, 0]);
+        assert_eq!(remaining, vec![None, None, Some(55), Some(50)]);
+
+        // pro-rata shares the same 45 by size, 4.5, 13.5 and 27 rounded down leave one unit
+        // over which goes to the oldest order.
+        let (shares, remaining) = fill_shares(MatchingPolicy::ProRata, 45);
+        assert_eq!(shares, vec![5, 13, 27, 0]);
+        assert_eq!(remaining, vec![Some(5), Some(17), Some(33), Some(50)]);
+
+        // a level the taker clears is filled in full before the next one is shared.
+        for policy in [MatchingPolicy::PriceTime, MatchingPolicy::ProRata] {
+            let (shares, remaining) = fill_shares(policy, 120);
+            assert_eq!(shares, vec![10, 30, 60, 20], "{policy:?}");
+            assert_eq!(remaining, vec![None, None, None, Some(30)], "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_pro_rata_passes_over_all_or_none_makers() {
+        let mut orderbook = Orderbook::new();
+        let order = |quantity, all_or_none| Order {
+            price: nz!(100),
+            quantity: NonZeroU32::new(quantity).unwrap(),
+            memo: 0,
+            all_or_none,
+        };
+        orderbook.push_ask(order(40, true));
+        orderbook.push_ask(order(20, false));
+        orderbook.push_ask(order(20, false));
+
+        let taker = order(10, false);
+        let result = try_fill_orders_skipping(
+            &mut orderbook,
+            taker,
+            OrderSide::Buy,
+            OrderType::Limit,
+            MatchingPolicy::ProRata,
+            |_| MakerScreen::Match,
+        )
+        .unwrap();
+
+        let shares = result
+            .maker_fills
+            .iter()
+            .map(|fill| (fill.maker.quantity.get(), fill.fill_amount))
+            .collect::<Vec<_>>();
+        assert_eq!(shares, vec![(20, 5), (20, 5)]);
+        assert_eq!(result.taker_fill_outcome, FillType::Complete);
+    }
+
+    #[test]
+    fn test_crossed_book_is_refused() {
+        let mut orderbook = Orderbook::new();
+        let order = |price| Order {
+            price,
+            quantity: nz!(5),
+            memo: 0,
+            all_or_none: false,
+        };
+        let bid = orderbook.push_bid(order(nz!(101)));
+        let ask = orderbook.push_ask(order(nz!(100)));
+
+        let result = try_fill_orders(
+            &mut orderbook,
+            order(nz!(99)),
+            OrderSide::Sell,
+            OrderType::Limit,
+        );
+        assert_eq!(
+            result.err(),
+            Some(TryFillOrdersError::CrossedBook {
+                bid,
+                bid_price: nz!(101),
+                ask,
+                ask_price: nz!(100),
+            })
+        );
+    }
+
+    #[test]
+    fn test_fill_too_large_for_the_ledger_is_refused() {
+        let mut orderbook = Orderbook::new();
+        let order = Order {
+            price: nz!(4294967295),
+            quantity: nz!(4294967295),
+            memo: 0,
+            all_or_none: false,
+        };
+        let maker = orderbook.push_ask(order);
+
+        let result = try_fill_orders(&mut orderbook, order, OrderSide::Buy, OrderType::Limit);
+        assert!(matches!(
+            result.err(),
+            Some(TryFillOrdersError::NotionalOverflow {
+                maker: oix,
+                price,
+                ..
+            }) if oix == maker && price == nz!(4294967295)
+        ));
+
+        // the book is left as it was.
+        assert_eq!(orderbook.get(maker), Some(&order));
+    }
+
+    #[test]
+    fn test_market_order_against_an_empty_side_is_refused() {
+        let mut orderbook = Orderbook::new();
+        let order = Order {
+            price: nz!(100),
+            quantity: nz!(5),
+            memo: 0,
+            all_or_none: false,
+        };
+        orderbook.push_bid(order);
+
+        let result = try_fill_orders(&mut orderbook, order, OrderSide::Buy, OrderType::Market);
+        assert_eq!(
+            result.err(),
+            Some(TryFillOrdersError::NoLiquidity {
+                side: OrderSide::Sell
+            })
+        );
+
+        // a limit order rests instead.
+        let result =
+            try_fill_orders(&mut orderbook, order, OrderSide::Buy, OrderType::Limit).unwrap();
+        assert_eq!(result.taker_fill_outcome, FillType::None);
+    }
+
+    #[test]
+    fn test_screen_aborts_on_a_self_trade() {
+        let mut orderbook = Orderbook::new();
+        let order = |price| Order {
+            price,
+            quantity: nz!(5),
+            memo: 0,
+            all_or_none: false,
+        };
+        orderbook.push_ask(order(nz!(100)));
+        let own = orderbook.push_ask(order(nz!(101)));
+
+        for policy in [MatchingPolicy::PriceTime, MatchingPolicy::ProRata] {
+            let result = try_fill_orders_skipping(
+                &mut orderbook,
+                Order {
+                    quantity: nz!(10),
+                    ..order(nz!(101))
+                },
+                OrderSide::Buy,
+                OrderType::Limit,
+                policy,
+                |oix| {
+                    if oix == own {
+                        MakerScreen::Abort
+                    } else {
+                        MakerScreen::Match
+                    }
+                },
+            );
+            assert_eq!(
+                result.err(),
+                Some(TryFillOrdersError::SelfTradePrevented {
+                    maker: own,
+                    price: nz!(101),
+                }),
+                "{policy:?}"
+            );
+        }
+
+        // an order the taker does not cross never aborts the match.
+        let result = try_fill_orders_skipping(
+            &mut orderbook,
+            order(nz!(100)),
+            OrderSide::Buy,
+            OrderType::Limit,
+            MatchingPolicy::PriceTime,
+            |oix| {
+                if oix == own {
+                    MakerScreen::Abort
+                } else {
+                    MakerScreen::Match
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(result.taker_fill_outcome, FillType::Complete);
+    }
 }
@@ -0,0 +1,31 @@
+use axum::extract::{Json, State};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+
+/// The request body for the `admin_blocklist` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct Blocklist {
+    addresses: Vec<String>,
+}
+
+/// The response body for the `admin_blocklist` endpoint.
+#[derive(Debug, Serialize)]
+pub struct BlocklistReloaded {
+    entries: usize,
+}
+
+/// Replace the blocked addresses without a restart
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Json(Blocklist { addresses }): Json<Blocklist>,
+) -> Json<BlocklistReloaded> {
+    tracing::warn!(audit = "blocked_address", %user_uuid, "address blocklist replaced");
+
+    Json(BlocklistReloaded {
+        entries: state.reload_blocked_addresses(&addresses),
+    })
+}
@@ -0,0 +1,25 @@
+use axum::extract::{Json, State};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+
+/// The request and response body for the `admin_drain` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Drain {
+    enabled: bool,
+}
+
+/// Stop or resume taking new orders, cancels and triggered stops keep going through
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Json(Drain { enabled }): Json<Drain>,
+) -> Json<Drain> {
+    tracing::warn!(%user_uuid, enabled, "drain mode toggled");
+
+    Json(Drain {
+        enabled: state.set_draining(enabled),
+    })
+}
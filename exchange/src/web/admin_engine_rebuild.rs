@@ -0,0 +1,36 @@
+use axum::extract::{Json, Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+use super::InternalApiState;
+use crate::asset::ContainsAsset as _;
+use crate::Asset;
+
+/// Rebuild the book for `asset` from the journal and swap it in, responding with the number of
+/// resting orders before and after
+pub async fn f(State(state): State<InternalApiState>, Path(asset): Path<String>) -> Response {
+    let asset = match asset.as_str() {
+        "btc" | "BTC" => Asset::Bitcoin,
+        "eth" | "ETH" => Asset::Ether,
+        _ => {
+            tracing::warn!(?asset, "invalid asset");
+            return (StatusCode::NOT_FOUND, "invalid asset").into_response();
+        }
+    };
+
+    if !state
+        .assets
+        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
+    {
+        tracing::warn!(?asset, "asset not enabled");
+        return (StatusCode::NOT_FOUND, "asset not enabled").into_response();
+    }
+
+    match state.rebuild_book(asset).await {
+        Ok(report) => Json(report).into_response(),
+        Err(err) => {
+            tracing::error!(?err, ?asset, "failed to rebuild book");
+            super::internal_server_error("failed to rebuild the book")
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+
+use super::InternalApiState;
+
+/// Counters reported by the trading engine itself: resting and held orders per book, commands
+/// processed and the latency of the last one
+pub async fn f(State(state): State<InternalApiState>) -> Response {
+    match state.engine_stats().await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(err) => {
+            tracing::warn!(?err, "failed to fetch engine stats");
+            super::internal_server_error("trading engine is unresponsive")
+        }
+    }
+}
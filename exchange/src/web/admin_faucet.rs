@@ -0,0 +1,53 @@
+use std::num::NonZeroU64;
+
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::app_cx::FaucetError;
+
+/// The request body for the `admin_faucet` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Faucet {
+    /// The user to credit.
+    pub user_uuid: uuid::Uuid,
+    /// The currency to credit, e.g. `USD` or `BTC`.
+    pub currency: String,
+    /// The amount to credit, in the smallest unit of the currency.
+    #[serde(with = "crate::json_amount")]
+    pub amount: NonZeroU64,
+}
+
+/// Credit a user with test funds, only available when `faucet_enabled` is set
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(admin_uuid)): Extension<UserUuid>,
+    Json(body): Json<Faucet>,
+) -> Response {
+    let Faucet {
+        user_uuid,
+        currency,
+        amount,
+    } = body;
+
+    tracing::warn!(%admin_uuid, %user_uuid, ?currency, %amount, "faucet credit requested");
+
+    match state.credit_faucet(user_uuid, &currency, amount).await {
+        Ok(()) => axum::http::StatusCode::NO_CONTENT.into_response(),
+        Err(FaucetError::Disabled) => {
+            (axum::http::StatusCode::NOT_FOUND, "faucet is disabled").into_response()
+        }
+        Err(FaucetError::AmountTooLarge) => (
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            "amount is too large",
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::warn!(?err, "failed to credit faucet funds");
+            super::internal_server_error("failed to credit faucet funds")
+        }
+    }
+}
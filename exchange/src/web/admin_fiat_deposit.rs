@@ -0,0 +1,53 @@
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::app_cx::FiatDepositError;
+use crate::fiat::FiatProviderError;
+
+/// The request body for the `admin_fiat_deposit` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FiatDepositBody {
+    /// The reference the bank transfer was made with.
+    pub reference: String,
+}
+
+/// Credit a bank transfer once the fiat provider has received it
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(admin_uuid)): Extension<UserUuid>,
+    Json(FiatDepositBody { reference }): Json<FiatDepositBody>,
+) -> Response {
+    tracing::info!(%admin_uuid, ?reference, "fiat deposit credit requested");
+
+    match state.credit_fiat_deposit(&reference).await {
+        Ok(deposit) => Json(deposit).into_response(),
+        Err(FiatDepositError::Disabled) => (
+            axum::http::StatusCode::NOT_FOUND,
+            "fiat deposits are disabled",
+        )
+            .into_response(),
+        Err(FiatDepositError::Provider(FiatProviderError::UnknownReference)) => (
+            axum::http::StatusCode::NOT_FOUND,
+            "no transfer with this reference",
+        )
+            .into_response(),
+        Err(FiatDepositError::AlreadyCredited) => (
+            axum::http::StatusCode::CONFLICT,
+            "this transfer has already been credited",
+        )
+            .into_response(),
+        Err(err @ (FiatDepositError::UnsupportedCurrency | FiatDepositError::AmountTooLarge)) => (
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            err.to_string(),
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::warn!(?err, "failed to credit fiat deposit");
+            super::internal_server_error("failed to credit fiat deposit")
+        }
+    }
+}
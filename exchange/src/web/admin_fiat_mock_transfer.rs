@@ -0,0 +1,65 @@
+use std::num::NonZeroU64;
+
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::app_cx::FiatDepositError;
+use crate::fiat::FiatDeposit;
+
+/// The request body for the `admin_fiat_mock_transfer` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MockTransfer {
+    /// The reference the transfer is made with, credited with `POST /api/admin/fiat/deposits`.
+    pub reference: String,
+    /// The user the transfer is made for.
+    pub user_uuid: uuid::Uuid,
+    /// The ISO 4217 code of the transfer, only `USD` is credited.
+    pub currency: String,
+    /// The amount of the transfer, in the smallest unit of the currency.
+    #[serde(with = "crate::json_amount")]
+    pub amount: NonZeroU64,
+}
+
+/// Make up a bank transfer, only available when `fiat_provider` is `mock`
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(admin_uuid)): Extension<UserUuid>,
+    Json(body): Json<MockTransfer>,
+) -> Response {
+    let MockTransfer {
+        reference,
+        user_uuid,
+        currency,
+        amount,
+    } = body;
+
+    tracing::warn!(
+        %admin_uuid,
+        %user_uuid,
+        ?reference,
+        ?currency,
+        %amount,
+        "mock fiat transfer requested"
+    );
+
+    let deposit = FiatDeposit {
+        user_uuid,
+        currency,
+        amount,
+    };
+
+    match state.receive_mock_fiat_transfer(&reference, deposit) {
+        Ok(()) => axum::http::StatusCode::NO_CONTENT.into_response(),
+        Err(err @ (FiatDepositError::Disabled | FiatDepositError::NotMock)) => {
+            (axum::http::StatusCode::NOT_FOUND, err.to_string()).into_response()
+        }
+        Err(err) => {
+            tracing::warn!(?err, "failed to make up a mock fiat transfer");
+            super::internal_server_error("failed to make up a mock fiat transfer")
+        }
+    }
+}
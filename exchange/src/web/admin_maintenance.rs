@@ -0,0 +1,26 @@
+use axum::extract::{Json, State};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+
+/// The request and response body for the `admin_maintenance` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Maintenance {
+    enabled: bool,
+}
+
+/// Turn maintenance mode on or off without a restart
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Json(Maintenance { enabled }): Json<Maintenance>,
+) -> Json<Maintenance> {
+    tracing::warn!(%user_uuid, enabled, "maintenance mode toggled");
+    state.set_maintenance_mode(enabled);
+
+    Json(Maintenance {
+        enabled: state.maintenance_mode(),
+    })
+}
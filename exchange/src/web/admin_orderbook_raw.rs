@@ -0,0 +1,67 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+use super::InternalApiState;
+use crate::asset::ContainsAsset as _;
+use crate::Asset;
+
+/// the largest page the endpoint will return.
+const MAX_LIMIT: usize = 1000;
+
+fn default_limit() -> usize {
+    100
+}
+
+/// The query parameters for the `admin_orderbook_raw` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct PageParams {
+    #[serde(default)]
+    offset: usize,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+/// Dump a page of every order resting in the book for `asset`, in priority order
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Path(asset): Path<String>,
+    Query(PageParams { offset, limit }): Query<PageParams>,
+) -> Response {
+    let asset = match asset.as_str() {
+        "btc" | "BTC" => Asset::Bitcoin,
+        "eth" | "ETH" => Asset::Ether,
+        _ => {
+            tracing::warn!(?asset, "invalid asset");
+            return (StatusCode::NOT_FOUND, "invalid asset").into_response();
+        }
+    };
+
+    if !state
+        .assets
+        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
+    {
+        tracing::warn!(?asset, "asset not enabled");
+        return (StatusCode::NOT_FOUND, "asset not enabled").into_response();
+    }
+
+    let limit = limit.min(MAX_LIMIT);
+
+    let Ok(wait_response) = state.resting_orders(asset, offset, limit).await else {
+        tracing::warn!("failed to request resting orders, trade engine is suspended");
+        return super::internal_server_error("trading engine is suspended");
+    };
+
+    match wait_response.wait().await {
+        Some(Ok(page)) => Json(page).into_response(),
+        Some(Err(err)) => {
+            tracing::warn!(?err, "failed to list resting orders");
+            super::internal_server_error("failed to list resting orders")
+        }
+        None => {
+            tracing::warn!("wait_response did not return a result");
+            super::internal_server_error("trading engine is unresponsive")
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+
+use super::InternalApiState;
+use crate::asset::ContainsAsset as _;
+use crate::trading::{OrderSide, OrderUuid};
+use crate::Asset;
+
+/// The query parameters for the `admin_orderbook_top` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct TopParams {
+    side: OrderSide,
+}
+
+/// The response body for the `admin_orderbook_top` endpoint.
+#[derive(Debug, Serialize)]
+pub struct TopOfQueue {
+    order_uuid: OrderUuid,
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    price: u32,
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    quantity: u32,
+}
+
+/// The order on `side` of the book for `asset` that would match next, `null` if the side is empty
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Path(asset): Path<String>,
+    Query(TopParams { side }): Query<TopParams>,
+) -> Response {
+    let asset = match asset.as_str() {
+        "btc" | "BTC" => Asset::Bitcoin,
+        "eth" | "ETH" => Asset::Ether,
+        _ => {
+            tracing::warn!(?asset, "invalid asset");
+            return (StatusCode::NOT_FOUND, "invalid asset").into_response();
+        }
+    };
+
+    if !state
+        .assets
+        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
+    {
+        tracing::warn!(?asset, "asset not enabled");
+        return (StatusCode::NOT_FOUND, "asset not enabled").into_response();
+    }
+
+    match state.peek_top(asset, side).await {
+        Ok(top) => Json(top.map(|(price, quantity, order_uuid)| TopOfQueue {
+            order_uuid,
+            price: price.get(),
+            quantity: quantity.get(),
+        }))
+        .into_response(),
+        Err(err) => {
+            tracing::warn!(?err, "failed to peek at the top of the book");
+            super::internal_server_error("trading engine is unresponsive")
+        }
+    }
+}
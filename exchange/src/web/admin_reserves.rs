@@ -0,0 +1,9 @@
+use axum::extract::{Json, State};
+
+use super::InternalApiState;
+use crate::app_cx::ReserveMetricsSnapshot;
+
+/// Counters for reserved funds, including the amount still outstanding per currency
+pub async fn f(State(state): State<InternalApiState>) -> Json<ReserveMetricsSnapshot> {
+    Json(state.reserve_metrics().snapshot())
+}
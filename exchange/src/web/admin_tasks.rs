@@ -0,0 +1,9 @@
+use axum::extract::{Json, State};
+
+use super::InternalApiState;
+use crate::app_cx::TaskSnapshot;
+
+/// The registered background tasks, with those that missed their heartbeat flagged as stalled
+pub async fn f(State(state): State<InternalApiState>) -> Json<Vec<TaskSnapshot>> {
+    Json(state.tasks().snapshot())
+}
@@ -0,0 +1,165 @@
+//! Accepting webserver connections with the settings of [`WebserverConnection`].
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use socket2::{SockRef, TcpKeepalive};
+use tokio::net::{TcpListener, TcpStream};
+use tower::ServiceExt as _;
+
+use crate::config::WebserverConnection;
+
+/// apply the TCP settings to an accepted connection.
+pub(crate) fn configure_stream(
+    stream: &TcpStream,
+    settings: &WebserverConnection,
+) -> std::io::Result<()> {
+    stream.set_nodelay(settings.tcp_nodelay)?;
+
+    let socket = SockRef::from(stream);
+    match settings.tcp_keepalive_secs {
+        Some(secs) => {
+            let keepalive = TcpKeepalive::new()
+                .with_time(Duration::from_secs(secs))
+                .with_interval(Duration::from_secs(settings.tcp_keepalive_interval_secs));
+            socket.set_tcp_keepalive(&keepalive)
+        }
+        None => socket.set_keepalive(false),
+    }
+}
+
+/// serve `router` on every connection accepted by `lst`, each on its own task.
+///
+/// `axum::serve` doesn't expose socket options or the protocol selection, so this is the
+/// same accept loop with those applied. Handlers can still extract `ConnectInfo<SocketAddr>`.
+pub(crate) async fn serve_on(
+    lst: TcpListener,
+    router: Router,
+    settings: WebserverConnection,
+) -> std::io::Result<()> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    if !settings.http2 {
+        builder = builder.http1_only();
+    }
+
+    loop {
+        let (stream, remote) = match lst.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                // usually running out of file descriptors, back off instead of spinning.
+                tracing::warn!(?err, "failed to accept connection");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        if let Err(err) = configure_stream(&stream, &settings) {
+            tracing::warn!(?err, ?remote, "failed to apply socket options");
+        }
+
+        let service = router
+            .clone()
+            .map_request(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(remote));
+                request
+            });
+        let service = TowerToHyperService::new(service);
+        let builder = builder.clone();
+
+        tokio::spawn(async move {
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(err) = conn.await {
+                tracing::debug!(?err, ?remote, "connection closed with an error");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+
+    use super::*;
+
+    async fn connected_pair() -> (TcpStream, TcpStream) {
+        let lst = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(lst.local_addr().unwrap()).await.unwrap();
+        let (server, _) = lst.accept().await.unwrap();
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn test_socket_options_are_applied() {
+        let (_client, stream) = connected_pair().await;
+
+        let settings = WebserverConnection {
+            tcp_keepalive_secs: Some(30),
+            tcp_keepalive_interval_secs: 5,
+            ..Default::default()
+        };
+        configure_stream(&stream, &settings).unwrap();
+
+        let socket = SockRef::from(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(30));
+        assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(5));
+
+        let settings = WebserverConnection {
+            tcp_nodelay: false,
+            tcp_keepalive_secs: None,
+            ..Default::default()
+        };
+        configure_stream(&stream, &settings).unwrap();
+
+        assert!(!stream.nodelay().unwrap());
+        assert!(!socket.keepalive().unwrap());
+    }
+
+    async fn spawn_server(settings: WebserverConnection) -> SocketAddr {
+        let lst = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = lst.local_addr().unwrap();
+        let router =
+            Router::new().route(
+                "/",
+                get(|ConnectInfo(remote): ConnectInfo<SocketAddr>| async move {
+                    remote.ip().to_string()
+                }),
+            );
+
+        tokio::spawn(serve_on(lst, router, settings));
+        address
+    }
+
+    #[tokio::test]
+    async fn test_http2_prior_knowledge() {
+        let client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+
+        let address = spawn_server(WebserverConnection::default()).await;
+        let res = client
+            .get(format!("http://{address}/"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.version(), reqwest::Version::HTTP_2);
+        assert_eq!(res.text().await.unwrap(), "127.0.0.1");
+
+        let address = spawn_server(WebserverConnection {
+            http2: false,
+            ..Default::default()
+        })
+        .await;
+        let res = client.get(format!("http://{address}/")).send().await;
+        assert!(res.is_err(), "HTTP/2 was served while disabled");
+    }
+}
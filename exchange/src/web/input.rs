@@ -0,0 +1,100 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+/// Longest display name a user may register with, in characters.
+pub(crate) const MAX_NAME_LEN: usize = 64;
+
+/// Longest withdrawal address accepted, in characters.
+pub(crate) const MAX_ADDRESS_LEN: usize = 128;
+
+/// A user-provided string was rejected before reaching the database.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidInput {
+    #[error("`{field}` must not be empty")]
+    Empty { field: &'static str },
+    #[error("`{field}` must be at most {max} characters")]
+    TooLong { field: &'static str, max: usize },
+    #[error("`{field}` contains characters that are not allowed")]
+    Charset { field: &'static str },
+}
+
+impl IntoResponse for InvalidInput {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+    }
+}
+
+/// Trim `st` and check it is non-empty and at most `max` characters long.
+fn bounded<'a>(field: &'static str, st: &'a str, max: usize) -> Result<&'a str, InvalidInput> {
+    let st = st.trim();
+
+    if st.is_empty() {
+        return Err(InvalidInput::Empty { field });
+    }
+
+    if st.chars().count() > max {
+        return Err(InvalidInput::TooLong { field, max });
+    }
+
+    Ok(st)
+}
+
+/// A display name: any printable text, markup is escaped when rendered.
+pub(crate) fn display_name(field: &'static str, st: &str) -> Result<String, InvalidInput> {
+    let st = bounded(field, st, MAX_NAME_LEN)?;
+
+    if st.chars().any(char::is_control) {
+        return Err(InvalidInput::Charset { field });
+    }
+
+    Ok(st.to_owned())
+}
+
+/// A chain address: base58 and bech32 both fit within ASCII alphanumerics.
+pub(crate) fn address(field: &'static str, st: &str) -> Result<String, InvalidInput> {
+    let st = bounded(field, st, MAX_ADDRESS_LEN)?;
+
+    if !st.chars().all(|ch| ch.is_ascii_alphanumeric()) {
+        return Err(InvalidInput::Charset { field });
+    }
+
+    Ok(st.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_name() {
+        assert_eq!(display_name("name", "  alice "), Ok("alice".to_owned()));
+        assert_eq!(
+            display_name("name", &"a".repeat(MAX_NAME_LEN + 1)),
+            Err(InvalidInput::TooLong {
+                field: "name",
+                max: MAX_NAME_LEN
+            })
+        );
+        assert_eq!(
+            display_name("name", "   "),
+            Err(InvalidInput::Empty { field: "name" })
+        );
+        assert_eq!(
+            display_name("name", "bob\u{0}"),
+            Err(InvalidInput::Charset { field: "name" })
+        );
+        // markup is allowed through, templates escape it.
+        assert!(display_name("name", "<b>bob</b>").is_ok());
+    }
+
+    #[test]
+    fn test_address() {
+        assert!(address("address_text", "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").is_ok());
+        assert_eq!(
+            address("address_text", "<script>"),
+            Err(InvalidInput::Charset {
+                field: "address_text"
+            })
+        );
+    }
+}
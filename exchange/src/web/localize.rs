@@ -0,0 +1,145 @@
+//! Localized error messages for the browser-facing (HTMX) flows.
+//!
+//! Messages are jinja templates at `errors/<language>/<code>.txt.jinja`, picked by the
+//! `Accept-Language` header and falling back to English. JSON API responses keep their
+//! language-independent codes, only the human-readable message is localized.
+
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::http::HeaderMap;
+
+use crate::jinja::Jinja;
+
+/// The languages that have error message templates, English is the fallback.
+pub(crate) const SUPPORTED_LANGUAGES: &[&str] = &["en", "es"];
+
+/// The language every error message has a template in.
+const FALLBACK_LANGUAGE: &str = "en";
+
+/// the supported languages of the `Accept-Language` header, most preferred first, always ending
+/// with [`FALLBACK_LANGUAGE`].
+pub(crate) fn preferred_languages(headers: &HeaderMap) -> Vec<&'static str> {
+    let header = headers
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|hv| hv.to_str().ok())
+        .unwrap_or_default();
+
+    let mut ranges = header
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let tag = parts.next().filter(|tag| !tag.is_empty())?;
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+
+            // only the primary subtag matters, `es-MX` gets the `es` messages.
+            let primary = tag.split('-').next()?.to_ascii_lowercase();
+            let language = SUPPORTED_LANGUAGES
+                .iter()
+                .copied()
+                .find(|language| *language == primary)?;
+
+            (quality > 0.0).then_some((language, quality))
+        })
+        .collect::<Vec<_>>();
+
+    // stable, so equally preferred languages keep the order they were listed in.
+    ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    let mut languages = Vec::with_capacity(ranges.len() + 1);
+    for language in ranges
+        .into_iter()
+        .map(|(language, _)| language)
+        .chain([FALLBACK_LANGUAGE])
+    {
+        if !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+
+    languages
+}
+
+/// render the message of error `code` in the most preferred language of `headers` that has it.
+///
+/// Falls back to the code itself if no template renders, so the user still sees something.
+pub(crate) fn render_error(jinja: &Jinja, headers: &HeaderMap, code: &str) -> String {
+    let env = match jinja.acquire_env() {
+        Ok(env) => env,
+        Err(err) => {
+            tracing::warn!(?err, code, "failed to load error message templates");
+            return code.to_owned();
+        }
+    };
+
+    for language in preferred_languages(headers) {
+        let name = format!("errors/{language}/{code}.txt.jinja");
+
+        let Ok(template) = env.get_template(&name) else {
+            continue;
+        };
+
+        match template.render(minijinja::context! {}) {
+            Ok(message) => return message.trim().to_owned(),
+            Err(err) => tracing::warn!(?err, name, "failed to render error message"),
+        }
+    }
+
+    tracing::warn!(code, "no error message template");
+    code.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn accept_language(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static(value));
+        headers
+    }
+
+    fn test_jinja() -> Jinja {
+        let mut config = crate::Configuration::defaults_for_test();
+        config.jinja_template_dir =
+            Some(concat!(env!("CARGO_MANIFEST_DIR"), "/../frontend/templates").into());
+        crate::jinja::make_jinja_env(&config)
+    }
+
+    #[test]
+    fn test_preferred_languages() {
+        assert_eq!(preferred_languages(&HeaderMap::new()), vec!["en"]);
+        assert_eq!(
+            preferred_languages(&accept_language("fr-CH, fr;q=0.9, es-MX;q=0.8, en;q=0.7")),
+            vec!["es", "en"]
+        );
+        assert_eq!(
+            preferred_languages(&accept_language("en;q=0.5, es")),
+            vec!["es", "en"]
+        );
+        assert_eq!(preferred_languages(&accept_language("es;q=0")), vec!["en"]);
+    }
+
+    #[test]
+    fn test_error_messages_follow_accept_language() {
+        let jinja = test_jinja();
+        let es = accept_language("es");
+
+        assert_eq!(
+            render_error(&jinja, &es, "invalid_credentials"),
+            "Correo electrónico o contraseña incorrectos."
+        );
+        assert_eq!(
+            render_error(&jinja, &HeaderMap::new(), "invalid_credentials"),
+            "Incorrect email or password."
+        );
+
+        // no Spanish translation yet, English is used instead.
+        assert_eq!(
+            render_error(&jinja, &es, "email_taken"),
+            "That email has already been used."
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use axum::body::Body;
+use axum::http::header::ALLOW;
+use axum::http::{HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Answer `OPTIONS`, and unsupported methods, on known paths with an accurate `Allow` header.
+///
+/// The method routers already reject unsupported methods with a `405` and list the supported
+/// ones, but as `POST,DELETE` rather than the `POST, DELETE` form clients expect. An `OPTIONS`
+/// request to a route that doesn't handle it is answered with a `204` carrying the same header.
+///
+/// Routes must only authenticate the methods they handle (`MethodRouter::route_layer`) so
+/// discovery, and CORS preflights which never carry credentials, work without a session.
+///
+pub async fn allowed_methods(request: Request<Body>, next: Next) -> Response {
+    let is_options = request.method() == Method::OPTIONS;
+    let mut response = next.run(request).await;
+
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+
+    let allow = response
+        .headers()
+        .get(ALLOW)
+        .and_then(|allow| allow.to_str().ok())
+        .map(|allow| {
+            allow
+                .split(',')
+                .map(str::trim)
+                .filter(|method| !method.is_empty())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .and_then(|allow| HeaderValue::from_str(&allow).ok());
+
+    let Some(allow) = allow else {
+        return response;
+    };
+
+    if is_options {
+        return (StatusCode::NO_CONTENT, [(ALLOW, allow)]).into_response();
+    }
+
+    response.headers_mut().insert(ALLOW, allow);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::post;
+    use axum::Router;
+    use tower::ServiceExt as _;
+
+    use super::*;
+
+    fn router() -> Router {
+        let deny = axum::middleware::from_fn(|_: Request<Body>, _: Next| async {
+            StatusCode::UNAUTHORIZED.into_response()
+        });
+
+        Router::new()
+            .route(
+                "/order",
+                post(|| async {})
+                    .delete(|| async {})
+                    .put(|| async {})
+                    .route_layer(deny),
+            )
+            .layer(axum::middleware::from_fn(allowed_methods))
+    }
+
+    async fn request(method: Method) -> Response {
+        let request = Request::builder()
+            .method(method)
+            .uri("/order")
+            .body(Body::empty())
+            .unwrap();
+
+        router().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_method_lists_allowed_methods() {
+        let res = request(Method::PATCH).await;
+
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[ALLOW], "POST, DELETE, PUT");
+    }
+
+    #[tokio::test]
+    async fn test_options_lists_allowed_methods() {
+        let res = request(Method::OPTIONS).await;
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[ALLOW], "POST, DELETE, PUT");
+    }
+
+    #[tokio::test]
+    async fn test_supported_method_is_untouched() {
+        let res = request(Method::POST).await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().get(ALLOW).is_none());
+    }
+}
@@ -18,6 +18,13 @@ use crate::web::InternalApiState;
 #[derive(Debug, Clone)]
 pub struct UserUuid(pub uuid::Uuid);
 
+impl UserUuid {
+    /// record the user on the request span so every log line of the request carries it.
+    pub(crate) fn record_in_span(&self) {
+        tracing::Span::current().record("user_id", tracing::field::display(self.0));
+    }
+}
+
 /// Enforce that the request has a session-token cookie
 ///
 /// * A session-token cookie is a randomly generated 32-byte hex-encoded string.
@@ -33,6 +40,7 @@ pub async fn validate_session_token(
 ) -> axum::response::Response {
     match try_validate_session(state, request.headers()).await {
         Ok(user_uuid) => {
+            user_uuid.record_in_span();
             request.extensions_mut().insert(user_uuid);
             next.run(request).await
         }
@@ -40,6 +48,39 @@ pub async fn validate_session_token(
     }
 }
 
+/// Enforce that the user of an already validated session is an admin
+///
+/// Must be layered inside [`validate_session_token`], which provides the [`UserUuid`].
+///
+pub async fn validate_admin_role(
+    State(state): State<InternalApiState>,
+    request: Request<Body>,
+    next: Next,
+) -> axum::response::Response {
+    let Some(UserUuid(user_uuid)) = request.extensions().get::<UserUuid>().cloned() else {
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    };
+
+    let rec = sqlx::query!(
+        r#"SELECT role as "role: String" FROM users WHERE id = $1"#,
+        user_uuid
+    )
+    .fetch_optional(&state.db())
+    .await;
+
+    match rec {
+        Ok(Some(rec)) if rec.role == "admin" => next.run(request).await,
+        Ok(_) => {
+            tracing::warn!(?user_uuid, "non-admin user denied admin route");
+            (StatusCode::FORBIDDEN, "Forbidden").into_response()
+        }
+        Err(err) => {
+            tracing::error!(?err, "user role select failure");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Try again later").into_response()
+        }
+    }
+}
+
 pub async fn validate_session_token_or_redirect(
     State(state): State<InternalApiState>,
     mut request: Request<Body>,
@@ -47,6 +88,7 @@ pub async fn validate_session_token_or_redirect(
 ) -> axum::response::Response {
     match try_validate_session(state, request.headers()).await {
         Ok(user_uuid) => {
+            user_uuid.record_in_span();
             request.extensions_mut().insert(user_uuid);
             next.run(request).await
         }
@@ -70,8 +112,9 @@ pub async fn try_validate_session(
         ));
     };
 
+    // every use of the session marks it seen, see `AppCx::list_sessions`.
     let rec = match sqlx::query!(
-        "SELECT * FROM session_tokens WHERE token = $1",
+        "UPDATE session_tokens SET last_accessed_at = CURRENT_TIMESTAMP WHERE token = $1 RETURNING user_id, created_at, max_age",
         session_token.as_bytes()
     )
     .fetch_optional(&state.db())
@@ -0,0 +1,31 @@
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::header::RETRY_AFTER;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::web::InternalApiState;
+
+/// Reject deposit and withdrawal requests with a `503 Service Unavailable` while bitcoind is
+/// not connected.
+///
+/// Only happens when the exchange was allowed to start without bitcoind, see
+/// [`crate::config::BitcoindStartup`].
+///
+pub async fn require_bitcoind(
+    State(state): State<InternalApiState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if state.bitcoind_available() {
+        return next.run(request).await;
+    }
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(RETRY_AFTER, "30")],
+        "deposits and withdrawals are unavailable, try again later",
+    )
+        .into_response()
+}
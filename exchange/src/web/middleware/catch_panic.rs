@@ -0,0 +1,30 @@
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::response::Response;
+
+/// handler panics caught since the process started.
+static PANICS_CAUGHT: AtomicU64 = AtomicU64::new(0);
+
+/// The number of handler panics turned into a `500` since the process started.
+pub fn panics_caught() -> u64 {
+    PANICS_CAUGHT.load(Ordering::Relaxed)
+}
+
+/// Answer a request whose handler panicked with a `500` instead of dropping the connection.
+///
+/// For [`tower_http::catch_panic::CatchPanicLayer::custom`]. The response gets the request's
+/// `x-request-id` from the `PropagateRequestIdLayer` wrapping the catch, and the panic is logged
+/// inside the request span, so a client reporting the id can be matched to the panic.
+pub fn respond_to_panic(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("panic payload is not a string");
+
+    let panics_caught = PANICS_CAUGHT.fetch_add(1, Ordering::Relaxed) + 1;
+    tracing::error!(panic = message, panics_caught, "request handler panicked");
+
+    super::super::internal_server_error("internal server error")
+}
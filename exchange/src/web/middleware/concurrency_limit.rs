@@ -0,0 +1,95 @@
+use axum::error_handling::HandleErrorLayer;
+use axum::http::StatusCode;
+use axum::{BoxError, Router};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::ServiceBuilder;
+
+/// Limit the number of requests in flight across every route in `router` to `limit`.
+///
+/// The limit is global rather than per-client, once `limit` requests are in flight any
+/// further request is shed immediately with a `503 Service Unavailable` instead of queueing.
+/// A `limit` of `None` leaves the router untouched.
+///
+pub fn limit_concurrency(router: Router, limit: Option<usize>) -> Router {
+    let Some(limit) = limit else {
+        return router;
+    };
+
+    let middleware = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(|err: BoxError| async move {
+            tracing::warn!(
+                ?err,
+                "shedding request, route group is at its concurrency limit"
+            );
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "too many concurrent requests, try again later",
+            )
+        }))
+        .layer(LoadShedLayer::new())
+        .layer(GlobalConcurrencyLimitLayer::new(limit));
+
+    router.layer(middleware)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::get;
+    use tokio::sync::{mpsc, Semaphore};
+    use tower::ServiceExt as _;
+
+    use super::*;
+
+    fn request() -> Request<Body> {
+        Request::builder().uri("/slow").body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_excess_concurrent_request_gets_503() {
+        const N: usize = 2;
+
+        let release = Arc::new(Semaphore::new(0));
+        let (entered_tx, mut entered_rx) = mpsc::channel(N);
+
+        let slow = {
+            let release = release.clone();
+            move || {
+                let release = release.clone();
+                let entered_tx = entered_tx.clone();
+                async move {
+                    entered_tx.send(()).await.unwrap();
+                    release.acquire().await.unwrap().forget();
+                    StatusCode::OK
+                }
+            }
+        };
+
+        let router = limit_concurrency(Router::new().route("/slow", get(slow)), Some(N));
+
+        let in_flight = (0..N)
+            .map(|_| tokio::spawn(router.clone().oneshot(request())))
+            .collect::<Vec<_>>();
+
+        for _ in 0..N {
+            entered_rx.recv().await.unwrap();
+        }
+
+        let res = router.clone().oneshot(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        release.add_permits(N);
+        for handle in in_flight {
+            assert_eq!(handle.await.unwrap().unwrap().status(), StatusCode::OK);
+        }
+
+        // capacity is released once the in-flight requests finish.
+        release.add_permits(1);
+        let res = router.oneshot(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,43 @@
+use axum::async_trait;
+use axum::body::Body;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::web::REQUEST_TIMEOUT;
+
+/// When the request times out, [`REQUEST_TIMEOUT`] after it arrived
+///
+/// Work handed off for the request, e.g. a trade command, is pointless past this point, the
+/// client has been answered with a timeout by then.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestDeadline(pub tokio::time::Instant);
+
+impl RequestDeadline {
+    fn from_now() -> Self {
+        Self(tokio::time::Instant::now() + REQUEST_TIMEOUT)
+    }
+}
+
+/// Add a [`RequestDeadline`] extension to the request, taken as it arrives
+///
+pub async fn set_request_deadline(mut request: Request<Body>, next: Next) -> Response {
+    request.extensions_mut().insert(RequestDeadline::from_now());
+    next.run(request).await
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestDeadline {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // a router served without the http middleware, as in tests, starts the clock here.
+        Ok(parts
+            .extensions
+            .get::<Self>()
+            .copied()
+            .unwrap_or_else(Self::from_now))
+    }
+}
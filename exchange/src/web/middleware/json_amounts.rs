@@ -0,0 +1,30 @@
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::json_amount::JsonAmounts;
+use crate::web::InternalApiState;
+
+/// Handle the request with the [`JsonAmounts`] of the configuration in force
+///
+/// Amounts sent to the trade and public routes of an asset are held to the digit limits of that
+/// asset, see [`Configuration::json_amount_digits`](crate::Configuration::json_amount_digits).
+/// The settings are scoped to the request rather than set process-wide, so two exchanges in one
+/// process, as in tests, do not see each other's settings.
+///
+pub async fn scope_json_amounts(
+    State(state): State<InternalApiState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let config = state.config();
+    let asset = super::maintenance::path_asset(request.uri().path());
+    let amounts = JsonAmounts {
+        as_strings: config.json_amounts_as_strings,
+        digits: config.json_amount_digits(asset),
+    };
+
+    crate::json_amount::scope(amounts, next.run(request)).await
+}
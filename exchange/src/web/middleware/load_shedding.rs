@@ -0,0 +1,30 @@
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::header::RETRY_AFTER;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::web::InternalApiState;
+
+/// Reject new orders with a `503 Service Unavailable` while the trading engine is backed up.
+///
+/// Only layered on the routes that place orders, cancels always reach the engine so users can
+/// still pull their orders. See [`crate::config::LoadShedding`].
+///
+pub async fn shed_engine_load(
+    State(state): State<InternalApiState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if !state.shedding_load() {
+        return next.run(request).await;
+    }
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(RETRY_AFTER, "1")],
+        "the exchange is busy, try again shortly",
+    )
+        .into_response()
+}
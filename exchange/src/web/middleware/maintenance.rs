@@ -0,0 +1,76 @@
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::header::RETRY_AFTER;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::web::InternalApiState;
+use crate::Asset;
+
+/// Paths that stay up during maintenance, so orchestrators and operators can still reach the exchange.
+///
+/// These are the routes of [`health_routes`](crate::web::health_routes) and the switch itself,
+/// the exchange has no `/metrics` route to keep up.
+const ALWAYS_UP: &[&str] = &["/health", "/ready", "/api/admin/maintenance"];
+
+/// Reject requests with a `503 Service Unavailable` and a `Retry-After` during maintenance.
+///
+/// Every route except `/health` and `/ready` (and the admin routes, unless configured otherwise) is
+/// rejected while [`InternalApiState::maintenance_mode`] is on. The trade and public routes
+/// of assets listed in [`MaintenanceMode::assets`](crate::config::MaintenanceMode::assets)
+/// are rejected regardless.
+///
+pub async fn maintenance_mode(
+    State(state): State<InternalApiState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let config = &state.config().maintenance_mode;
+    let path = request.uri().path();
+
+    let in_maintenance = if ALWAYS_UP.contains(&path) {
+        false
+    } else if state.maintenance_mode() {
+        !(config.exempt_admin && path.starts_with("/api/admin/"))
+    } else {
+        path_asset(path).is_some_and(|asset| config.assets.contains(&asset))
+    };
+
+    if !in_maintenance {
+        return next.run(request).await;
+    }
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(RETRY_AFTER, config.retry_after_secs.to_string())],
+        "down for maintenance, try again later",
+    )
+        .into_response()
+}
+
+/// the asset of a `/api/trade/:asset/..` or `/api/public/:asset/..` path.
+pub(crate) fn path_asset(path: &str) -> Option<Asset> {
+    let rest = path
+        .strip_prefix("/api/trade/")
+        .or_else(|| path.strip_prefix("/api/public/"))?;
+
+    match rest.split('/').next()? {
+        "btc" | "BTC" => Some(Asset::Bitcoin),
+        "eth" | "ETH" => Some(Asset::Ether),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_asset() {
+        assert_eq!(path_asset("/api/trade/btc/order"), Some(Asset::Bitcoin));
+        assert_eq!(path_asset("/api/public/ETH/quote"), Some(Asset::Ether));
+        assert_eq!(path_asset("/api/public/time"), None);
+        assert_eq!(path_asset("/api/user/btc"), None);
+    }
+}
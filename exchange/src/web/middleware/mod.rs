@@ -1,8 +1,32 @@
 // pub mod msgpack;
 // pub use msgpack::Msgpack;
 
+pub mod allowed_methods;
+pub use allowed_methods::allowed_methods;
+
 pub mod auth;
-pub use auth::{validate_session_token, validate_session_token_or_redirect};
+pub use auth::{validate_admin_role, validate_session_token, validate_session_token_or_redirect};
+
+pub mod bitcoind;
+pub use bitcoind::require_bitcoind;
+
+pub mod catch_panic;
+pub use catch_panic::respond_to_panic;
+
+pub mod concurrency_limit;
+pub use concurrency_limit::limit_concurrency;
+
+pub mod deadline;
+pub use deadline::{set_request_deadline, RequestDeadline};
+
+pub mod json_amounts;
+pub use json_amounts::scope_json_amounts;
+
+pub mod load_shedding;
+pub use load_shedding::shed_engine_load;
+
+pub mod maintenance;
+pub use maintenance::maintenance_mode;
 
 pub mod ip_address {
     use std::net::IpAddr;
@@ -6,6 +6,7 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use axum::handler::Handler as _;
 use axum::http::{header, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::routing::{delete, get, post};
@@ -14,30 +15,39 @@ use axum::{Router, ServiceExt};
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 
+use tower_http::catch_panic::CatchPanicLayer;
 use tower_http::normalize_path::NormalizePathLayer;
 use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::services::ServeDir;
 use tower_http::timeout::TimeoutLayer;
-use tower_http::trace::{
-    DefaultMakeSpan, DefaultOnFailure, DefaultOnRequest, DefaultOnResponse, TraceLayer,
-};
+use tower_http::trace::{DefaultOnFailure, DefaultOnRequest, DefaultOnResponse, TraceLayer};
 use tower_http::{LatencyUnit, ServiceBuilderExt};
 
+mod connection;
+mod input;
+mod localize;
 mod middleware;
 
 mod trade_add_order;
 pub use trade_add_order::TradeAddOrder;
 mod trade_cancel_order;
 mod trade_edit_order;
+mod trade_get_order;
+mod trade_reduce_order;
 
 mod user_balance;
 mod user_create;
 mod user_delete;
 mod user_edit;
 mod user_get;
+mod user_statement;
+mod user_transactions;
 
+mod session_cookie;
 mod session_create;
 mod session_delete;
+mod session_list;
+mod session_revoke;
 
 mod deposit_create_addr;
 mod deposit_list_addrs;
@@ -49,7 +59,34 @@ mod withdraw_list_addrs;
 mod withdraw_status;
 mod withdraw_transfer;
 
+mod public_assets;
+mod public_book;
+mod public_quote;
+mod public_ticker;
 mod public_time;
+mod ready;
+
+mod ws_connect;
+mod ws_market_data;
+mod ws_ticket_create;
+
+/// How long a request may take before it is answered with a timeout.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+mod admin_blocklist;
+mod admin_drain;
+mod admin_engine_rebuild;
+mod admin_engine_stats;
+mod admin_faucet;
+mod admin_fiat_deposit;
+mod admin_fiat_mock_transfer;
+mod admin_maintenance;
+mod admin_orderbook_raw;
+mod admin_orderbook_top;
+mod admin_reserves;
+mod admin_tasks;
+
+mod health;
 
 mod html_home;
 mod html_index;
@@ -72,21 +109,47 @@ fn internal_server_error(message: &str) -> Response {
         .into_response()
 }
 
+/// respond to a failed database query, a `503` if postgres cancelled it for running too long otherwise a `500`.
+fn database_error(err: &sqlx::Error) -> Response {
+    if crate::app_cx::is_statement_timeout(err) {
+        return (
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            "database query timed out, try again later",
+        )
+            .into_response();
+    }
+
+    internal_server_error("database error")
+}
+
 type InternalApiState = crate::app_cx::AppCx;
 
 /// Router for the /trade path
 #[track_caller]
 pub fn trade_routes(state: InternalApiState) -> Router {
-    let trade_order = post(trade_add_order::f)
+    // authenticate per method so unsupported methods and `OPTIONS` still get a `405`/`Allow`.
+    let auth =
+        axum::middleware::from_fn_with_state(state.clone(), middleware::validate_session_token);
+
+    // cancels are never shed, they drain the book rather than add to it.
+    let shed = axum::middleware::from_fn_with_state(state.clone(), middleware::shed_engine_load);
+
+    let trade_order = post(trade_add_order::f.layer(shed.clone()))
         .delete(trade_cancel_order::f)
-        .put(trade_edit_order::f);
+        .put(trade_edit_order::f.layer(shed))
+        .route_layer(auth.clone());
 
     Router::new()
         .route("/trade/:asset/order", trade_order)
-        .route_layer(axum::middleware::from_fn_with_state(
-            state.clone(),
-            middleware::validate_session_token,
-        ))
+        .route(
+            "/trade/:asset/order/:uuid",
+            get(trade_get_order::f).route_layer(auth.clone()),
+        )
+        // like cancels, reduces are never shed.
+        .route(
+            "/trade/:asset/order/:uuid/reduce",
+            post(trade_reduce_order::f).route_layer(auth),
+        )
         .with_state(state)
 }
 
@@ -105,6 +168,20 @@ pub fn user_routes(state: InternalApiState) -> Router {
                     middleware::validate_session_token,
                 )),
         )
+        .route(
+            "/user/transactions",
+            get(user_transactions::f).route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::validate_session_token,
+            )),
+        )
+        .route(
+            "/user/statements/:date",
+            get(user_statement::f).route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::validate_session_token,
+            )),
+        )
         .route(
             "/user/:id/balance/:currency",
             get(user_balance::f).route_layer(axum::middleware::from_fn_with_state(
@@ -124,6 +201,10 @@ pub fn deposit_routes(state: InternalApiState) -> Router {
             get(deposit_list_addrs::f).post(deposit_create_addr::f),
         )
         .route("/deposit/status/{tx_id}", get(deposit_status::f))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::require_bitcoind,
+        ))
         .route_layer(axum::middleware::from_fn_with_state(
             state.clone(),
             middleware::validate_session_token,
@@ -146,6 +227,10 @@ pub fn withdrawal_routes(state: InternalApiState) -> Router {
         //     "/withdrawal/transfer",
         //     axum::routing::post(withdraw_transfer::withdraw_transfer),
         // )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::require_bitcoind,
+        ))
         .route_layer(axum::middleware::from_fn_with_state(
             state.clone(),
             middleware::validate_session_token,
@@ -157,24 +242,123 @@ pub fn withdrawal_routes(state: InternalApiState) -> Router {
 #[track_caller]
 pub fn session_routes(state: InternalApiState) -> Router {
     let session = post(session_create::f).delete(session_delete::f);
+    let validate =
+        axum::middleware::from_fn_with_state(state.clone(), middleware::validate_session_token);
+
+    Router::new()
+        .route("/session", session)
+        .route(
+            "/sessions",
+            get(session_list::f).route_layer(validate.clone()),
+        )
+        .route(
+            "/sessions/:id",
+            axum::routing::delete(session_revoke::f).route_layer(validate),
+        )
+        .with_state(state)
+}
 
-    Router::new().route("/session", session).with_state(state)
+/// Router for the /ws path
+#[track_caller]
+pub fn ws_routes(state: InternalApiState) -> Router {
+    let ticket = post(ws_ticket_create::f).route_layer(axum::middleware::from_fn_with_state(
+        state.clone(),
+        middleware::validate_session_token,
+    ));
+
+    Router::new()
+        .route("/ws/ticket", ticket)
+        // the ticket is checked by the handler, after the handshake.
+        .route("/ws", get(ws_connect::f))
+        // market data is public, like the rest of `/public`.
+        .route("/ws/:asset", get(ws_market_data::f))
+        .with_state(state)
+}
+
+/// Router for the /admin path, every route requires a session of a user with the admin role
+#[track_caller]
+pub fn admin_routes(state: InternalApiState) -> Router {
+    Router::new()
+        .route("/admin/orderbook/:asset/raw", get(admin_orderbook_raw::f))
+        .route("/admin/orderbook/:asset/top", get(admin_orderbook_top::f))
+        .route(
+            "/admin/maintenance",
+            axum::routing::put(admin_maintenance::f),
+        )
+        .route("/admin/drain", axum::routing::put(admin_drain::f))
+        .route("/admin/blocklist", axum::routing::put(admin_blocklist::f))
+        .route("/admin/faucet", axum::routing::post(admin_faucet::f))
+        .route(
+            "/admin/fiat/deposits",
+            axum::routing::post(admin_fiat_deposit::f),
+        )
+        .route(
+            "/admin/fiat/mock-transfers",
+            axum::routing::post(admin_fiat_mock_transfer::f),
+        )
+        .route("/admin/reserves", get(admin_reserves::f))
+        .route("/admin/tasks", get(admin_tasks::f))
+        .route("/admin/engine/stats", get(admin_engine_stats::f))
+        .route(
+            "/admin/engine/:asset/rebuild",
+            axum::routing::post(admin_engine_rebuild::f),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::validate_admin_role,
+        ))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::validate_session_token,
+        ))
+        .with_state(state)
 }
 
 /// Router for the /public path
-pub fn public_routes() -> Router {
-    Router::new().route("/public/time", get(public_time::f))
+pub fn public_routes(state: InternalApiState) -> Router {
+    Router::new()
+        .route("/public/time", get(public_time::f))
+        .route("/public/assets", get(public_assets::f))
+        .route("/public/book/:asset", get(public_book::f))
+        .route("/public/:asset/quote", get(public_quote::f))
+        .route("/public/:asset/ticker", get(public_ticker::f))
+        .with_state(state)
+}
+
+/// Router for the health and readiness checks, never authenticated nor put in maintenance
+pub fn health_routes(state: InternalApiState) -> Router {
+    Router::new()
+        .route("/health", get(health::f))
+        .route("/ready", get(ready::f))
+        .with_state(state)
 }
 
 fn api_router(state: InternalApiState) -> Router {
-    let router = trade_routes(state.clone())
-        .merge(user_routes(state.clone()))
-        .merge(session_routes(state.clone()))
-        .merge(withdrawal_routes(state.clone()))
-        .merge(deposit_routes(state.clone()))
-        .merge(public_routes());
+    use middleware::limit_concurrency;
+
+    let limits = state.config().route_concurrency_limits.clone();
+
+    let trade = limit_concurrency(trade_routes(state.clone()), limits.trade);
+    let user = limit_concurrency(user_routes(state.clone()), limits.user);
+    let session = limit_concurrency(session_routes(state.clone()), limits.session);
+    let withdrawal = limit_concurrency(withdrawal_routes(state.clone()), limits.withdrawal);
+    let deposit = limit_concurrency(deposit_routes(state.clone()), limits.deposit);
+    let public = limit_concurrency(public_routes(state.clone()), limits.public);
+    let ws = limit_concurrency(ws_routes(state.clone()), limits.ws);
+    let admin = limit_concurrency(admin_routes(state.clone()), limits.admin);
+
+    let router = trade
+        .merge(user)
+        .merge(session)
+        .merge(withdrawal)
+        .merge(deposit)
+        .merge(public)
+        .merge(ws)
+        .merge(admin);
 
-    Router::new().nest("/api", router)
+    Router::new()
+        .nest("/api", router)
+        .layer(axum::middleware::from_fn(middleware::allowed_methods))
 }
 
 fn html_router(state: InternalApiState) -> Router {
@@ -191,11 +375,62 @@ fn html_router(state: InternalApiState) -> Router {
         .with_state(state)
 }
 
+/// the span every request is handled in, carries the `x-request-id` so work done on behalf of
+/// the request (including in the trading engine) can be correlated with it. The `user_id` is
+/// recorded once the request is authenticated.
+pub(crate) fn make_request_span<B>(request: &axum::http::Request<B>) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        headers = ?request.headers(),
+        request_id,
+        user_id = tracing::field::Empty,
+    )
+}
+
 /// Using [`axum`], serve the internal API on the given address with the provided exchange implementation.
 pub fn serve(
     address: SocketAddr,
     state: InternalApiState,
 ) -> impl Future<Output = Result<(), ServeError>> {
+    let connection = state.config().webserver_connection.clone();
+
+    let router = api_router(state.clone())
+        .merge(health_routes(state.clone()))
+        .merge(html_router(state.clone()))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::maintenance_mode,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state,
+            middleware::scope_json_amounts,
+        ));
+
+    let router = with_http_middleware(router);
+
+    async move {
+        let lst = TcpListener::bind(&address).await?;
+        tracing::info!(?address, ?connection, "Serving webserver API");
+        let rval = connection::serve_on(lst, router, connection)
+            .await
+            .map_err(ServeError::Io);
+        tracing::warn!(?address, "Stopping webserver!");
+        rval
+    }
+}
+
+/// wrap `router` in the layers every request goes through: request ids, tracing, timeouts,
+/// path normalization, panic recovery and compression.
+fn with_http_middleware(router: Router) -> Router {
     let x_request_id = axum::http::HeaderName::from_static("x-request-id");
 
     let set_request_id_layer =
@@ -205,42 +440,952 @@ pub fn serve(
     let middleware = ServiceBuilder::new()
     // Mark the `Authorization` and `Cookie` headers as sensitive so it doesn't show in logs
     .sensitive_request_headers(sensitive_headers.clone())
+    // Set x-request-id before the request span is made so the span can carry it.
+    .layer(set_request_id_layer)
     // Add high level tracing/logging to all requests
     .layer(
         TraceLayer::new_for_http()
             .on_body_chunk(|chunk: &axum::body::Bytes, latency: Duration, _: &tracing::Span| {
                 tracing::trace!(size_bytes = chunk.len(), latency = ?latency, "sending body chunk")
             })
-            .make_span_with(DefaultMakeSpan::new().include_headers(true)).on_request(DefaultOnRequest::new())
+            .make_span_with(|request: &axum::http::Request<axum::body::Body>| make_request_span(request)).on_request(DefaultOnRequest::new())
             .on_response(DefaultOnResponse::new().latency_unit(LatencyUnit::Micros))
             .on_failure(DefaultOnFailure::new()),
     )
     .sensitive_response_headers(sensitive_headers)
+    // Note when the request times out, for the work it hands off to give up at the same time
+    .layer(axum::middleware::from_fn(middleware::set_request_deadline))
     // Set a timeout
-    .layer(TimeoutLayer::new(Duration::from_secs(10)))
-    // Set x-request-id for response headers.
-    .layer(set_request_id_layer)
+    .layer(TimeoutLayer::new(REQUEST_TIMEOUT))
     .layer(NormalizePathLayer::trim_trailing_slash())
     .layer(PropagateRequestIdLayer::new(x_request_id))
+    // Answer with a 500 when a handler panics, inside the propagation so it carries the x-request-id
+    .layer(CatchPanicLayer::custom(middleware::respond_to_panic))
     // Compress responses
     .compression();
 
-    let router = api_router(state.clone())
-        .merge(html_router(state))
-        .layer(middleware);
+    router.layer(middleware)
+}
 
-    async move {
-        let lst = TcpListener::bind(&address).await?;
-        let app = axum::serve(
-            lst,
-            router.into_make_service_with_connect_info::<SocketAddr>(),
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::header::ALLOW;
+    use axum::http::{Method, Request};
+    use tower::ServiceExt as _;
+
+    use super::*;
+    use crate::bitcoin::BitcoinRpcClient;
+    use crate::spawn_trading_engine::spawn_trading_engine;
+    use crate::Configuration;
+
+    /// the state of an exchange running `config`, with its trading engine and a mock bitcoind.
+    async fn make_state(db: sqlx::PgPool, config: Configuration) -> InternalApiState {
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        InternalApiState::new(
+            te_tx,
+            BitcoinRpcClient::new_mock(),
+            db,
+            crate::jinja::make_jinja_env(&config),
+            config,
+        )
+    }
+
+    async fn request(db: sqlx::PgPool, method: Method, uri: &str) -> Response {
+        let state = make_state(db, Configuration::defaults_for_test()).await;
+
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+
+        api_router(state).oneshot(request).await.unwrap()
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_maintenance_mode(db: sqlx::PgPool) {
+        let state = make_state(db, Configuration::defaults_for_test()).await;
+
+        let router = api_router(state.clone())
+            .merge(health_routes(state.clone()))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                middleware::maintenance_mode,
+            ));
+
+        let get = |uri: &str| {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            router.clone().oneshot(request)
+        };
+
+        let trade_order = format!("/api/trade/btc/order/{}", uuid::Uuid::new_v4());
+
+        state.set_maintenance_mode(true);
+
+        let res = get(&trade_order).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "300");
+        assert_eq!(get("/health").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(get("/ready").await.unwrap().status(), StatusCode::OK);
+
+        // back out of maintenance the request reaches the route, which wants a session.
+        state.set_maintenance_mode(false);
+
+        let res = get(&trade_order).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(get("/health").await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_public_assets_lists_allowed_order_types(db: sqlx::PgPool) {
+        let res = request(db, Method::GET, "/api/public/assets").await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let assets: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            assets,
+            serde_json::json!([
+                { "asset": "BTC", "order_types": ["limit", "market"] },
+                { "asset": "ETH", "order_types": ["limit", "market"] },
+            ])
         );
-        tracing::info!(?address, "Serving webserver API");
-        let rval = app
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_public_book_aggregates_price_levels(db: sqlx::PgPool) {
+        use std::num::NonZeroU32;
+
+        use crate::trading::{
+            response_channel, OrderSide, OrderType, PlaceOrder, SelfTradeProtection, TimeInForce,
+            TradeCmd, TradingEngineCmd,
+        };
+
+        let config = Configuration::defaults_for_test();
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
             .await
-            .map_err(axum::Error::new)
-            .map_err(ServeError::Axum);
-        tracing::warn!(?address, "Stopping webserver!");
-        rval
+            .unwrap();
+
+        // two bids at 100 make one level, the ask and the other bid a level each.
+        for (side, price, quantity) in [
+            (OrderSide::Buy, 100, 2),
+            (OrderSide::Buy, 100, 3),
+            (OrderSide::Buy, 99, 1),
+            (OrderSide::Sell, 105, 4),
+        ] {
+            let order = PlaceOrder::new(
+                crate::Asset::Bitcoin,
+                uuid::Uuid::new_v4(),
+                NonZeroU32::new(price).unwrap(),
+                NonZeroU32::new(quantity).unwrap(),
+                OrderType::Limit,
+                SelfTradeProtection::default(),
+                TimeInForce::GoodTilCanceled,
+                side,
+                false,
+                false,
+                None,
+            );
+            let (tx, rx) = response_channel(None);
+            te_tx
+                .send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((order, tx))))
+                .await
+                .unwrap();
+            rx.recv().await.unwrap().unwrap();
+        }
+
+        let state = InternalApiState::new(
+            te_tx,
+            BitcoinRpcClient::new_mock(),
+            db,
+            crate::jinja::make_jinja_env(&config),
+            config,
+        );
+        let get = |uri: &str| {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            api_router(state.clone()).oneshot(request)
+        };
+
+        // amounts may be written as strings, depending on the configuration.
+        let levels = |side: &serde_json::Value| {
+            let amount = |v: &serde_json::Value| v.as_u64().or_else(|| v.as_str()?.parse().ok());
+            side.as_array()
+                .unwrap()
+                .iter()
+                .map(|level| {
+                    (
+                        amount(&level["price"]).unwrap(),
+                        amount(&level["quantity"]).unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let res = get("/api/public/book/btc").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let book: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(levels(&book["bids"]), vec![(100, 5), (99, 1)]);
+        assert_eq!(levels(&book["asks"]), vec![(105, 4)]);
+
+        let res = get("/api/public/book/btc?depth=1").await.unwrap();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let book: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(levels(&book["bids"]), vec![(100, 5)]);
+        assert_eq!(levels(&book["asks"]), vec![(105, 4)]);
+
+        let res = get("/api/public/book/doge").await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_is_a_500_with_the_request_id() {
+        let panicking = get(|| async { panic!("deliberate panic") });
+        let router = with_http_middleware(Router::new().route("/panic", panicking));
+
+        let before = middleware::catch_panic::panics_caught();
+        let request = Request::builder()
+            .uri("/panic")
+            .header("x-request-id", "test-request-id")
+            .body(Body::empty())
+            .unwrap();
+        let res = router.oneshot(request).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.headers()["x-request-id"], "test-request-id");
+        assert!(middleware::catch_panic::panics_caught() > before);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_trade_order_unsupported_method(db: sqlx::PgPool) {
+        let res = request(db, Method::PATCH, "/api/trade/btc/order").await;
+
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[ALLOW], "POST, DELETE, PUT");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_trade_order_options(db: sqlx::PgPool) {
+        let res = request(db, Method::OPTIONS, "/api/trade/btc/order").await;
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[ALLOW], "POST, DELETE, PUT");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_htmx_login_error_follows_accept_language(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.jinja_template_dir =
+            Some(concat!(env!("CARGO_MANIFEST_DIR"), "/../frontend/templates").into());
+        let state = make_state(db, config).await;
+
+        let login = |hx: bool, language: &'static str| {
+            let mut request = Request::builder()
+                .method(Method::POST)
+                .uri("/session")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header(header::ACCEPT_LANGUAGE, language)
+                .extension(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                    [127, 0, 0, 1],
+                    0,
+                ))));
+            if hx {
+                request = request.header("hx-request", "true");
+            }
+            let request = request
+                .body(Body::from("email=nobody%40example.com&password=letmein"))
+                .unwrap();
+            session_routes(state.clone()).oneshot(request)
+        };
+
+        let body = |res: Response| async move {
+            let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        };
+
+        let res = login(true, "es-ES, en;q=0.5").await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            body(res).await,
+            "Correo electrónico o contraseña incorrectos."
+        );
+
+        let res = login(true, "de").await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(body(res).await, "Incorrect email or password.");
+
+        // API clients only get the status, whatever the language.
+        let res = login(false, "es").await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(body(res).await, "");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_sessions_on_several_devices(db: sqlx::PgPool) {
+        let config = Configuration::defaults_for_test();
+        let state = make_state(db, config).await;
+
+        let password_hash = crate::password::Password("letmein".into())
+            .argon2_hash_password()
+            .unwrap();
+        state
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+
+        let login = |device_label: &'static str| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("/session")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .extension(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                    [127, 0, 0, 1],
+                    0,
+                ))))
+                .body(Body::from(format!(
+                    "email=foo%40example.com&password=letmein&device_label={device_label}"
+                )))
+                .unwrap();
+            let router = session_routes(state.clone());
+            async move {
+                let res = router.oneshot(request).await.unwrap();
+                assert_eq!(res.status(), StatusCode::CREATED);
+                let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+                cookie.split(';').next().unwrap().to_owned()
+            }
+        };
+        let send = |method: Method, uri: String, cookie: &str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap();
+            session_routes(state.clone()).oneshot(request)
+        };
+        let send = &send;
+        let list = |cookie: String| async move {
+            let res = send(Method::GET, "/sessions".into(), &cookie)
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<Vec<serde_json::Value>>(&body).unwrap()
+        };
+
+        // logging in on the phone does not log the laptop out.
+        let laptop = login("laptop").await;
+        let phone = login("phone").await;
+        assert_ne!(laptop, phone);
+
+        let sessions = list(laptop.clone()).await;
+        let labels = sessions
+            .iter()
+            .map(|session| session["device_label"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["phone", "laptop"]);
+        assert_eq!(sessions[1]["current"], true);
+        assert_eq!(sessions[0]["current"], false);
+        assert!(sessions[1]["last_seen_at"].is_i64());
+
+        let phone_id = sessions[0]["id"].as_i64().unwrap();
+        let res = send(Method::DELETE, format!("/sessions/{phone_id}"), &laptop)
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let res = send(Method::GET, "/sessions".into(), &phone).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let sessions = list(laptop.clone()).await;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0]["device_label"], "laptop");
+
+        // another user's session, or one already revoked, is not found.
+        let res = send(Method::DELETE, format!("/sessions/{phone_id}"), &laptop)
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_admin_signs_up_with_the_admin_signup_token(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.admin_emails = vec!["Root@example.com".into(), "ops@example.com".into()];
+        config.admin_signup_token = Some("correct-horse-battery-staple".into());
+        let state = make_state(db.clone(), config).await;
+
+        let post = |uri: &'static str, body: String| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .extension(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+                    [127, 0, 0, 1],
+                    0,
+                ))))
+                .body(Body::from(body))
+                .unwrap();
+            api_router(state.clone()).oneshot(request)
+        };
+        let sign_up = |email: &'static str, admin_token: &'static str| {
+            post(
+                "/api/user",
+                format!("name=foo&email={email}&password=letmein&admin_token={admin_token}"),
+            )
+        };
+        let stats = |email: &'static str| {
+            let state = state.clone();
+            let login = post("/api/session", format!("email={email}&password=letmein"));
+            async move {
+                let res = login.await.unwrap();
+                assert_eq!(res.status(), StatusCode::CREATED);
+                let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+                let request = Request::builder()
+                    .uri("/api/admin/engine/stats")
+                    .header(header::COOKIE, cookie.split(';').next().unwrap())
+                    .body(Body::empty())
+                    .unwrap();
+                api_router(state).oneshot(request).await.unwrap().status()
+            }
+        };
+
+        // a wrong token, or the token with an email not on the list, creates nobody.
+        let res = sign_up("root%40example.com", "wrong").await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = sign_up("foo%40example.com", "correct-horse-battery-staple")
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // signing up with a listed email but no token grants nothing, at signup or reconcile.
+        let res = sign_up("ops%40example.com", "").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(state.reconcile_admin_roles().await.unwrap(), 0);
+        assert_eq!(stats("ops%40example.com").await, StatusCode::FORBIDDEN);
+
+        let res = sign_up("root%40example.com", "correct-horse-battery-staple")
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(stats("root%40example.com").await, StatusCode::OK);
+
+        // the next startup keeps the admin, and demotes one made by hand.
+        let res = sign_up("foo%40example.com", "").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let foo_uuid: uuid::Uuid = body["user_id"].as_str().unwrap().parse().unwrap();
+        sqlx::query!("UPDATE users SET role = 'admin' WHERE id = $1", foo_uuid)
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(state.reconcile_admin_roles().await.unwrap(), 1);
+
+        assert_eq!(stats("root%40example.com").await, StatusCode::OK);
+        assert_eq!(stats("foo%40example.com").await, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_trading_continues_while_bitcoind_is_unavailable(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.faucet_enabled = true;
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+        let (bitcoind_rpc, connection) = BitcoinRpcClient::new_deferred();
+        let state = InternalApiState::new(
+            te_tx,
+            bitcoind_rpc,
+            db,
+            crate::jinja::make_jinja_env(&config),
+            config,
+        );
+
+        let password_hash = crate::password::Password("letmein".into())
+            .argon2_hash_password()
+            .unwrap();
+        let user_uuid = state
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        state
+            .credit_faucet(user_uuid, "USD", std::num::NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+        let session_token = state
+            .create_session(user_uuid, None, None, None)
+            .await
+            .unwrap();
+
+        let send = |router: Router, method: Method, uri: &str, body: &'static str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::COOKIE, format!("session-token={session_token}"))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            router.oneshot(request)
+        };
+        let send = &send;
+        let readiness = |state: InternalApiState| async move {
+            let res = send(health_routes(state), Method::GET, "/ready", "")
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()["status"].clone()
+        };
+
+        let order = r#"{"side": "Buy", "order_type": "Limit", "quantity": 5, "price": 100}"#;
+        let res = send(
+            trade_routes(state.clone()),
+            Method::POST,
+            "/trade/btc/order",
+            order,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let deposits = || {
+            send(
+                deposit_routes(state.clone()),
+                Method::GET,
+                "/deposit/addresses",
+                "",
+            )
+        };
+        let res = deposits().await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(readiness(state.clone()).await, "degraded");
+
+        // bitcoind comes up, the reconnect loop completes the connection.
+        connection.connected(BitcoinRpcClient::new_mock());
+
+        let res = deposits().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(readiness(state.clone()).await, "ready");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_cancel_order_by_client_order_id(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.faucet_enabled = true;
+        let state = make_state(db, config).await;
+
+        let password_hash = crate::password::Password("letmein".into())
+            .argon2_hash_password()
+            .unwrap();
+        let user_uuid = state
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        state
+            .credit_faucet(user_uuid, "USD", std::num::NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+        let session_token = state
+            .create_session(user_uuid, None, None, None)
+            .await
+            .unwrap();
+
+        let send = |method: Method, uri: &str, body: Body| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::COOKIE, format!("session-token={session_token}"))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .unwrap();
+            trade_routes(state.clone()).oneshot(request)
+        };
+
+        let order = r#"{"side": "Buy", "order_type": "Limit", "quantity": 5, "price": 100, "client_order_id": "my-order"}"#;
+        let res = send(Method::POST, "/trade/btc/order", Body::from(order))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let cancel = "/trade/btc/order?client_order_id=my-order";
+        let res = send(Method::DELETE, cancel, Body::empty()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // the order is no longer open, so the id resolves to nothing.
+        let res = send(Method::DELETE, cancel, Body::empty()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let unknown = "/trade/btc/order?client_order_id=not-an-order";
+        let res = send(Method::DELETE, unknown, Body::empty()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_reduce_order_releases_its_reserve(db: sqlx::PgPool) {
+        let mut config = Configuration::defaults_for_test();
+        config.faucet_enabled = true;
+        let state = make_state(db, config).await;
+
+        let password_hash = crate::password::Password("letmein".into())
+            .argon2_hash_password()
+            .unwrap();
+        let user_uuid = state
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        state
+            .credit_faucet(user_uuid, "USD", std::num::NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+        let session_token = state
+            .create_session(user_uuid, None, None, None)
+            .await
+            .unwrap();
+
+        let send = |uri: &str, body: &str| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::COOKIE, format!("session-token={session_token}"))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_owned()))
+                .unwrap();
+            trade_routes(state.clone()).oneshot(request)
+        };
+        let json = |res: Response| async move {
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+        let usd = || {
+            let state = state.clone();
+            async move {
+                state.settle_pending().await.unwrap();
+                state
+                    .calculate_balance_from_accounting(user_uuid, "USD")
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let order = r#"{"side": "Buy", "order_type": "Limit", "quantity": 5, "price": 100}"#;
+        let res = send("/trade/btc/order", order).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let order_uuid = json(res).await["order_uuid"].as_str().unwrap().to_owned();
+        assert_eq!(usd().await, std::num::NonZeroU64::new(500));
+
+        // 2 of the 5 reserved at 100 come back.
+        let reduce = format!("/trade/btc/order/{order_uuid}/reduce");
+        let res = send(&reduce, r#"{"by": 2}"#).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json(res).await["quantity_remaining"], 3);
+        assert_eq!(usd().await, std::num::NonZeroU64::new(700));
+
+        let res = send(&reduce, r#"{"by": 4}"#).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // taking off all of what is left cancels the order and releases the rest.
+        let res = send(&reduce, r#"{"by": 3}"#).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json(res).await["quantity_remaining"], 0);
+        assert_eq!(usd().await, std::num::NonZeroU64::new(1000));
+
+        let res = send(&reduce, r#"{"by": 1}"#).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_market_data_feed_sends_a_snapshot_then_trades(db: sqlx::PgPool) {
+        use futures::StreamExt as _;
+        use tokio_tungstenite::tungstenite::Message;
+
+        use crate::trading::{
+            response_channel, OrderSide, OrderType, PlaceOrder, SelfTradeProtection, TimeInForce,
+            TradeCmd, TradingEngineCmd,
+        };
+
+        let mut config = Configuration::defaults_for_test();
+        config.faucet_enabled = true;
+        let (te_tx, _te_handle) = spawn_trading_engine(&config, db.clone())
+            .init_from_db(db.clone())
+            .await
+            .unwrap();
+
+        // an ask for the buyer to take, resting before anyone connects.
+        let ask = PlaceOrder::new(
+            crate::Asset::Bitcoin,
+            uuid::Uuid::new_v4(),
+            std::num::NonZeroU32::new(100).unwrap(),
+            std::num::NonZeroU32::new(5).unwrap(),
+            OrderType::Limit,
+            SelfTradeProtection::default(),
+            TimeInForce::GoodTilCanceled,
+            OrderSide::Sell,
+            false,
+            false,
+            None,
+        );
+        let (tx, rx) = response_channel(None);
+        te_tx
+            .send(TradingEngineCmd::trade(TradeCmd::PlaceOrder((ask, tx))))
+            .await
+            .unwrap();
+        rx.recv().await.unwrap().unwrap();
+
+        let state = InternalApiState::new(
+            te_tx,
+            BitcoinRpcClient::new_mock(),
+            db,
+            crate::jinja::make_jinja_env(&config),
+            config,
+        );
+
+        let lst = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = lst.local_addr().unwrap();
+        let router = api_router(state.clone());
+        tokio::spawn(async move { axum::serve(lst, router).await });
+
+        let (mut feed, _) = tokio_tungstenite::connect_async(format!("ws://{address}/api/ws/btc"))
+            .await
+            .unwrap();
+        async fn next_frame<S>(feed: &mut S) -> serde_json::Value
+        where
+            S: futures::Stream<Item = tokio_tungstenite::tungstenite::Result<Message>> + Unpin,
+        {
+            let message = tokio::time::timeout(Duration::from_secs(5), feed.next())
+                .await
+                .expect("no frame in time")
+                .unwrap()
+                .unwrap();
+            match message {
+                Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                message => panic!("unexpected message {message:?}"),
+            }
+        }
+        // amounts may be written as strings, depending on the configuration.
+        let amount = |v: &serde_json::Value| v.as_u64().or_else(|| v.as_str()?.parse().ok());
+
+        let snapshot = next_frame(&mut feed).await;
+        assert_eq!(snapshot["type"], "snapshot");
+        assert_eq!(amount(&snapshot["book"]["asks"][0]["price"]), Some(100));
+        assert_eq!(amount(&snapshot["book"]["asks"][0]["quantity"]), Some(5));
+
+        let password_hash = crate::password::Password("letmein".into())
+            .argon2_hash_password()
+            .unwrap();
+        let user_uuid = state
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        state
+            .credit_faucet(user_uuid, "USD", std::num::NonZeroU64::new(1000).unwrap())
+            .await
+            .unwrap();
+        let session_token = state
+            .create_session(user_uuid, None, None, None)
+            .await
+            .unwrap();
+
+        let order = r#"{"side": "Buy", "order_type": "Limit", "quantity": 2, "price": 100}"#;
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/trade/btc/order")
+            .header(header::COOKIE, format!("session-token={session_token}"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(order))
+            .unwrap();
+        let res = trade_routes(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let trade = next_frame(&mut feed).await;
+        assert_eq!(trade["type"], "trade");
+        assert_eq!(trade["taker_side"], "buy");
+        assert_eq!(amount(&trade["price"]), Some(100));
+        assert_eq!(amount(&trade["quantity"]), Some(2));
+
+        // followed by what is left of the level the trade took from.
+        let level = next_frame(&mut feed).await;
+        assert_eq!(level["type"], "level");
+        assert_eq!(level["side"], "sell");
+        assert_eq!(amount(&level["quantity"]), Some(3));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_ws_ticket_connects_once_and_expires(db: sqlx::PgPool) {
+        use futures::StreamExt as _;
+        use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+        // tickets of the second exchange have expired by the time they are used.
+        let mut config = Configuration::defaults_for_test();
+        let state = make_state(db.clone(), config.clone()).await;
+        config.ws_ticket_ttl_secs = 0;
+        let expiring = make_state(db, config).await;
+
+        let password_hash = crate::password::Password("letmein".into())
+            .argon2_hash_password()
+            .unwrap();
+
+        // serve `state` and take a ticket from its `POST /api/ws/ticket` for a new user.
+        let serve_and_issue = |state: InternalApiState, email: &'static str| {
+            let password_hash = password_hash.clone();
+            async move {
+                let user_uuid = state
+                    .create_user("foo", email, password_hash)
+                    .await
+                    .unwrap();
+                let session_token = state
+                    .create_session(user_uuid, None, None, None)
+                    .await
+                    .unwrap();
+
+                let request = Request::builder()
+                    .method(Method::POST)
+                    .uri("/api/ws/ticket")
+                    .header(header::COOKIE, format!("session-token={session_token}"))
+                    .body(Body::empty())
+                    .unwrap();
+                let res = api_router(state.clone()).oneshot(request).await.unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+                let lst = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let address = lst.local_addr().unwrap();
+                let router = api_router(state);
+                tokio::spawn(async move { axum::serve(lst, router).await });
+
+                let ticket = body["ticket"].as_str().unwrap().to_owned();
+                (address, user_uuid, ticket)
+            }
+        };
+        let connect = |address: std::net::SocketAddr, ticket: &str| {
+            tokio_tungstenite::connect_async(format!("ws://{address}/api/ws?ticket={ticket}"))
+        };
+        let rejected_status = |res: Result<_, WsError>| match res {
+            Err(WsError::Http(res)) => res.status().as_u16(),
+            Err(err) => panic!("unexpected error {err:?}"),
+            Ok(_) => panic!("the handshake should have been rejected"),
+        };
+
+        let (address, user_uuid, ticket) = serve_and_issue(state.clone(), "foo@example.com").await;
+
+        // a request that is not a websocket handshake is turned away without using the ticket.
+        let request = Request::builder()
+            .uri(format!("/api/ws?ticket={ticket}"))
+            .body(Body::empty())
+            .unwrap();
+        let res = api_router(state).oneshot(request).await.unwrap();
+        assert!(res.status().is_client_error());
+        assert_ne!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let (mut socket, _) = connect(address, &ticket).await.unwrap();
+        let hello = match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+            message => panic!("unexpected message {message:?}"),
+        };
+        assert_eq!(hello["type"], "hello");
+        assert_eq!(hello["user_uuid"], user_uuid.to_string());
+
+        // the ticket was used up by the first connection.
+        assert_eq!(rejected_status(connect(address, &ticket).await), 401);
+        assert_eq!(rejected_status(connect(address, "unknown").await), 401);
+
+        let (address, _, ticket) = serve_and_issue(expiring, "bar@example.com").await;
+        assert_eq!(rejected_status(connect(address, &ticket).await), 401);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_placements_are_shed_while_the_engine_is_backed_up(db: sqlx::PgPool) {
+        use crate::trading::{TradingEngineCmd, TradingEngineError};
+
+        // an engine that never drains its queue on its own, high water at 8 and low water at 4.
+        let (te_tx, mut te_rx) = tokio::sync::mpsc::channel(16);
+        let config = Configuration::defaults_for_test();
+        let state = InternalApiState::new(
+            te_tx.clone(),
+            BitcoinRpcClient::new_mock(),
+            db,
+            crate::jinja::make_jinja_env(&config),
+            config,
+        );
+
+        let password_hash = crate::password::Password("letmein".into())
+            .argon2_hash_password()
+            .unwrap();
+        let user_uuid = state
+            .create_user("foo", "foo@example.com", password_hash)
+            .await
+            .unwrap();
+        let session_token = state
+            .create_session(user_uuid, None, None, None)
+            .await
+            .unwrap();
+
+        let send = |method: Method, uri: &str, body: &'static str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::COOKIE, format!("session-token={session_token}"))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            trade_routes(state.clone()).oneshot(request)
+        };
+        let order = r#"{"side": "Buy", "order_type": "Limit", "quantity": 5, "price": 100}"#;
+
+        for _ in 0..10 {
+            te_tx.send(TradingEngineCmd::Resume).await.unwrap();
+        }
+        let res = send(Method::POST, "/trade/btc/order", order).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // between the marks the shedding carries on.
+        for _ in 0..4 {
+            te_rx.recv().await.unwrap();
+        }
+        let res = send(Method::POST, "/trade/btc/order", order).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // cancels still reach the engine, which answers once it catches up.
+        tokio::spawn(async move {
+            while let Some(cmd) = te_rx.recv().await {
+                cmd.consume_respond_with_error(TradingEngineError::ClientOrderIdNotFound(
+                    uuid::Uuid::nil(),
+                    String::new(),
+                ));
+            }
+        });
+        let cancel = "/trade/btc/order?client_order_id=my-order";
+        let res = send(Method::DELETE, cancel, "").await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // the queue drained below the low water mark, placements are back.
+        assert_eq!(state.engine_queue_depth(), 0);
+        let res = send(Method::POST, "/trade/btc/order", order).await.unwrap();
+        assert_ne!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
     }
 }
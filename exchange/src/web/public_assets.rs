@@ -0,0 +1,30 @@
+use axum::extract::{Json, State};
+use serde::Serialize;
+
+use super::InternalApiState;
+use crate::asset::AssetKey;
+use crate::trading::OrderType;
+
+/// An entry of the response body for the `public_assets` endpoint.
+#[derive(Debug, Serialize)]
+pub struct PublicAsset {
+    /// The ticker of the asset, e.g. `BTC`.
+    asset: String,
+    /// The order types accepted for the asset.
+    order_types: Vec<OrderType>,
+}
+
+/// List the enabled assets and the order types each accepts
+pub async fn f(State(state): State<InternalApiState>) -> Json<Vec<PublicAsset>> {
+    let assets = state
+        .assets
+        .iter()
+        .filter_map(|(key, asset)| matches!(key, AssetKey::ByValue(_)).then_some(*asset))
+        .map(|asset| PublicAsset {
+            asset: asset.to_string(),
+            order_types: state.config().allowed_order_types(asset).to_vec(),
+        })
+        .collect();
+
+    Json(assets)
+}
@@ -0,0 +1,59 @@
+use std::num::NonZeroU32;
+
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+use super::InternalApiState;
+use crate::asset::ContainsAsset as _;
+use crate::trading::OrderSide;
+use crate::Asset;
+
+/// The query parameters for the `public_quote` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct QuoteParams {
+    side: OrderSide,
+    quantity: NonZeroU32,
+}
+
+/// Estimate the average fill price of a market order of `quantity` for `asset`
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Path(asset): Path<String>,
+    Query(QuoteParams { side, quantity }): Query<QuoteParams>,
+) -> Response {
+    let asset = match asset.as_str() {
+        "btc" | "BTC" => Asset::Bitcoin,
+        "eth" | "ETH" => Asset::Ether,
+        _ => {
+            tracing::warn!(?asset, "invalid asset");
+            return (StatusCode::NOT_FOUND, "invalid asset").into_response();
+        }
+    };
+
+    if !state
+        .assets
+        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
+    {
+        tracing::warn!(?asset, "asset not enabled");
+        return (StatusCode::NOT_FOUND, "asset not enabled").into_response();
+    }
+
+    let Ok(wait_response) = state.depth_snapshot(asset).await else {
+        tracing::warn!("failed to request depth snapshot, trade engine is suspended");
+        return super::internal_server_error("trading engine is suspended");
+    };
+
+    match wait_response.wait().await {
+        Some(Ok(snapshot)) => Json(snapshot.quote(side, quantity)).into_response(),
+        Some(Err(err)) => {
+            tracing::warn!(?err, "failed to take depth snapshot");
+            super::internal_server_error("failed to take depth snapshot")
+        }
+        None => {
+            tracing::warn!("wait_response did not return a result");
+            super::internal_server_error("trading engine is unresponsive")
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+use super::InternalApiState;
+use crate::asset::ContainsAsset as _;
+use crate::Asset;
+
+/// The query parameters for the `public_ticker` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct TickerParams {
+    /// how many price levels of each side the microprice and imbalance are computed from.
+    #[serde(default = "default_levels")]
+    levels: usize,
+}
+
+fn default_levels() -> usize {
+    1
+}
+
+/// Top of book, microprice and imbalance of the book for `asset`
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Path(asset): Path<String>,
+    Query(TickerParams { levels }): Query<TickerParams>,
+) -> Response {
+    let asset = match asset.as_str() {
+        "btc" | "BTC" => Asset::Bitcoin,
+        "eth" | "ETH" => Asset::Ether,
+        _ => {
+            tracing::warn!(?asset, "invalid asset");
+            return (StatusCode::NOT_FOUND, "invalid asset").into_response();
+        }
+    };
+
+    if !state
+        .assets
+        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
+    {
+        tracing::warn!(?asset, "asset not enabled");
+        return (StatusCode::NOT_FOUND, "asset not enabled").into_response();
+    }
+
+    let Ok(wait_response) = state.depth_snapshot(asset).await else {
+        tracing::warn!("failed to request depth snapshot, trade engine is suspended");
+        return super::internal_server_error("trading engine is suspended");
+    };
+
+    match wait_response.wait().await {
+        Some(Ok(snapshot)) => {
+            let ticker = snapshot.ticker(levels.max(1));
+
+            tracing::info!(
+                ?asset,
+                levels,
+                microprice = ticker.microprice,
+                imbalance = ticker.imbalance,
+                one_sided = ticker.one_sided,
+                "book quality"
+            );
+
+            Json(ticker).into_response()
+        }
+        Some(Err(err)) => {
+            tracing::warn!(?err, "failed to take depth snapshot");
+            super::internal_server_error("failed to take depth snapshot")
+        }
+        None => {
+            tracing::warn!("wait_response did not return a result");
+            super::internal_server_error("trading engine is unresponsive")
+        }
+    }
+}
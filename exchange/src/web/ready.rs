@@ -0,0 +1,23 @@
+use axum::extract::{Json, State};
+use serde::Serialize;
+
+use super::InternalApiState;
+
+/// The body of the `ready` endpoint.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// `ready`, or `degraded` while a component is unavailable.
+    pub status: &'static str,
+    /// `false` while bitcoind is not connected, deposits and withdrawals are refused meanwhile.
+    pub bitcoind: bool,
+}
+
+/// Readiness check, the exchange takes traffic even when degraded so this always answers `200`
+pub async fn f(State(state): State<InternalApiState>) -> Json<Readiness> {
+    let bitcoind = state.bitcoind_available();
+
+    Json(Readiness {
+        status: if bitcoind { "ready" } else { "degraded" },
+        bitcoind,
+    })
+}
@@ -0,0 +1,73 @@
+use axum_extra::extract::cookie::{Cookie, SameSite};
+
+use crate::config::{CookieSameSite, SessionCookie};
+
+/// How long a browser keeps the session cookie.
+const SESSION_COOKIE_MAX_AGE: time::Duration = time::Duration::hours(1);
+
+/// Build the `Set-Cookie` value for a new session with the configured attributes.
+pub(crate) fn session_token_cookie(attrs: &SessionCookie, session_token: &str) -> String {
+    let same_site = match attrs.same_site {
+        CookieSameSite::Strict => SameSite::Strict,
+        CookieSameSite::Lax => SameSite::Lax,
+        CookieSameSite::None => SameSite::None,
+    };
+
+    let mut cookie = Cookie::build(("session-token", session_token))
+        .max_age(SESSION_COOKIE_MAX_AGE)
+        .path(attrs.path.as_str())
+        .secure(attrs.secure)
+        .http_only(attrs.http_only)
+        .same_site(same_site);
+
+    if let Some(domain) = &attrs.domain {
+        cookie = cookie.domain(domain.as_str());
+    }
+
+    cookie.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(set_cookie: &str) -> Vec<&str> {
+        set_cookie.split("; ").skip(1).collect()
+    }
+
+    #[test]
+    fn test_default_attributes() {
+        let set_cookie = session_token_cookie(&SessionCookie::default(), "abc");
+        let attrs = attributes(&set_cookie);
+
+        assert!(set_cookie.starts_with("session-token=abc;"), "{set_cookie}");
+        assert!(attrs.contains(&"Secure"), "{set_cookie}");
+        assert!(attrs.contains(&"HttpOnly"), "{set_cookie}");
+        assert!(attrs.contains(&"SameSite=Lax"), "{set_cookie}");
+        assert!(attrs.contains(&"Path=/"), "{set_cookie}");
+        assert!(
+            !attrs.iter().any(|attr| attr.starts_with("Domain=")),
+            "{set_cookie}"
+        );
+    }
+
+    #[test]
+    fn test_configured_attributes() {
+        let config = SessionCookie {
+            secure: false,
+            http_only: false,
+            same_site: CookieSameSite::Strict,
+            domain: Some("exchange.example".to_owned()),
+            path: "/api".to_owned(),
+        };
+
+        let set_cookie = session_token_cookie(&config, "abc");
+        let attrs = attributes(&set_cookie);
+
+        assert!(!attrs.contains(&"Secure"), "{set_cookie}");
+        assert!(!attrs.contains(&"HttpOnly"), "{set_cookie}");
+        assert!(attrs.contains(&"SameSite=Strict"), "{set_cookie}");
+        assert!(attrs.contains(&"Domain=exchange.example"), "{set_cookie}");
+        assert!(attrs.contains(&"Path=/api"), "{set_cookie}");
+    }
+}
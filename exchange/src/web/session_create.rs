@@ -8,7 +8,6 @@ use axum::http::header::{CONTENT_TYPE, SET_COOKIE, USER_AGENT};
 use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 use axum::response::{AppendHeaders, IntoResponse, IntoResponseParts, Response};
 use axum::{Form, Json};
-use axum_extra::extract::cookie::{self, Cookie};
 use axum_extra::extract::CookieJar;
 use axum_htmx::HxRequest;
 use email_address::EmailAddress;
@@ -26,6 +25,9 @@ pub struct SessionCreate {
     email: EmailAddress,
     #[serde(deserialize_with = "de_password_from_str")]
     password: Password,
+    /// tells the user's sessions apart when they are listed, e.g. "work laptop".
+    #[serde(default)]
+    device_label: Option<String>,
 }
 
 pub async fn f(
@@ -39,6 +41,18 @@ pub async fn f(
     use VerifyLoginDetailsError as V;
     tracing::trace!(?body, "session_create");
 
+    let device_label = body.device_label.filter(|label| !label.trim().is_empty());
+    if device_label
+        .as_ref()
+        .is_some_and(|label| label.len() > crate::app_cx::MAX_DEVICE_LABEL_LEN)
+    {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "device labels can be at most 64 bytes long",
+        )
+            .into_response();
+    }
+
     let ip_address = rightmost_ip_address(&headers).unwrap_or(connect_info.ip());
     let user_agent = headers
         .get(USER_AGENT)
@@ -50,6 +64,11 @@ pub async fn f(
         .await
     {
         Ok(user_uuid) => user_uuid,
+        Err(V::Unauthorized) if hx => {
+            let message =
+                super::localize::render_error(state.jinja(), &headers, "invalid_credentials");
+            return (StatusCode::UNAUTHORIZED, message).into_response();
+        }
         Err(V::Unauthorized) => return StatusCode::UNAUTHORIZED.into_response(),
         Err(V::Other(_)) => {
             return StatusCode::INTERNAL_SERVER_ERROR.into_response();
@@ -57,7 +76,7 @@ pub async fn f(
     };
 
     let session_token = match state
-        .create_session(user_uuid, Some(ip_address), user_agent)
+        .create_session(user_uuid, Some(ip_address), user_agent, device_label)
         .await
     {
         Ok(st) => st,
@@ -69,10 +88,8 @@ pub async fn f(
 
     tracing::info!(?session_token, "session created");
 
-    let session_token_cookie = Cookie::build(("session-token", session_token.as_str()))
-        .max_age(time::Duration::hours(1))
-        .path("/")
-        .to_string();
+    let session_token_cookie =
+        super::session_cookie::session_token_cookie(&state.config().session_cookie, &session_token);
 
     (
         AppendHeaders([
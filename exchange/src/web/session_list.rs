@@ -0,0 +1,24 @@
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use axum_extra::extract::CookieJar;
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+
+/// The current user's active sessions, one per device they are logged in on
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    jar: CookieJar,
+) -> Response {
+    let current_token = jar
+        .get("session-token")
+        .map(|cookie| cookie.value_trimmed())
+        .unwrap_or_default();
+
+    match state.list_sessions(user_uuid, current_token).await {
+        Ok(sessions) => Json(sessions).into_response(),
+        Err(err) => super::database_error(&err),
+    }
+}
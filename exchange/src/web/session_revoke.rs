@@ -0,0 +1,23 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+
+/// Log the current user out of one of their sessions, the others stay valid
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Path(id): Path<i32>,
+) -> Response {
+    match state.revoke_session(user_uuid, id).await {
+        Ok(true) => {
+            tracing::info!(%user_uuid, id, "session revoked");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "session not found").into_response(),
+        Err(err) => super::database_error(&err),
+    }
+}
@@ -6,11 +6,12 @@ use axum::Extension;
 use serde::{Deserialize, Serialize};
 
 use super::middleware::auth::UserUuid;
+use super::middleware::RequestDeadline;
 use super::InternalApiState;
 use crate::asset::ContainsAsset as _;
 use crate::trading::{
-    OrderSide, OrderType, PlaceOrderResult, SelfTradeProtection, TimeInForce,
-    TradingEngineError as TErr,
+    Execution, OrderSide, OrderType, PlaceOrderError, PlaceOrderResult, SelfTradeProtection,
+    TimeInForce, TradingEngineError as TErr, Trigger, TryFillOrdersError,
 };
 use crate::Asset;
 
@@ -22,27 +23,60 @@ pub struct TradeAddOrder {
     /// The type of the order.
     pub order_type: OrderType,
     /// The quantity of the order.
+    #[serde(with = "crate::json_amount")]
     pub quantity: NonZeroU32,
-    /// The price of the order.
+    /// The price of the order, the worst price a market order is filled at.
+    #[serde(with = "crate::json_amount")]
     pub price: NonZeroU32,
     /// The time in force of the order.
     #[serde(default)]
     pub time_in_force: TimeInForce,
-    /// The self-trade protection of the order.
+    /// The self-trade protection of the order, the user's default if unset.
     #[serde(default)]
-    pub stp: SelfTradeProtection,
+    pub stp: Option<SelfTradeProtection>,
+    /// Cancel any unfilled remainder instead of resting it on the book.
+    #[serde(default)]
+    pub reduce_only: bool,
+    /// Only ever fill the order in its entirety, never partially.
+    #[serde(default)]
+    pub all_or_none: bool,
+    /// When a good-til-date order expires, in milliseconds since the unix epoch.
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// When a good-til-date order expires, in milliseconds after it is placed.
+    ///
+    /// Measured from the server's clock when the order is received, so it is unaffected by the
+    /// client's clock. Mutually exclusive with `expires_at` and capped by the same maximum TTL.
+    #[serde(default)]
+    pub expires_in_ms: Option<u64>,
+    /// Opt into nonce sequencing, must be greater than the nonce of the user's previous order.
+    #[serde(default)]
+    pub nonce: Option<u64>,
+    /// Hold the order off the book until the last trade reaches a price, as a stop or a market-if-touched order.
+    #[serde(default)]
+    pub trigger: Option<Trigger>,
+    /// The client's own id for the order, unique among the user's open orders and usable to cancel it.
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    /// Let the maker reject matches against the order while it rests, if the exchange enables it.
+    #[serde(default)]
+    pub last_look: bool,
 }
 
 /// The response body for the `trade_add_order` endpoint.
 #[derive(Debug, Serialize)]
 pub struct TradeAddOrderResponse {
     order_uuid: uuid::Uuid,
+    executions: Vec<Execution>,
+    #[serde(serialize_with = "crate::json_amount::serialize")]
+    price_improvement: i64,
 }
 
 /// Place an order for `asset`
 pub async fn f(
     State(state): State<InternalApiState>,
     Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    RequestDeadline(deadline): RequestDeadline,
     Path(asset): Path<String>,
     Json(body): Json<TradeAddOrder>,
 ) -> Response {
@@ -65,28 +99,57 @@ pub async fn f(
         tracing::info!(?asset, "placing order for asset");
     }
 
-    let (response, reserved_funds) = match state.place_order(asset, user_uuid, body).await {
+    // once placed, what the order fills or releases of its reserve is settled with what the engine journals.
+    let response = match state
+        .place_order_before(asset, user_uuid, body, deadline)
+        .await
+    {
         Ok(r) => r,
+        Err(crate::app_cx::PlaceOrderError::InvalidExpiry(err)) => {
+            return (
+                axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                err.to_string(),
+            )
+                .into_response();
+        }
+        Err(
+            err @ (crate::app_cx::PlaceOrderError::InvalidClientOrderId
+            | crate::app_cx::PlaceOrderError::LastLookDisabled
+            | crate::app_cx::PlaceOrderError::NotionalTooLarge(_)),
+        ) => {
+            return (
+                axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                err.to_string(),
+            )
+                .into_response();
+        }
+        Err(
+            err @ (crate::app_cx::PlaceOrderError::ReduceOnly
+            | crate::app_cx::PlaceOrderError::Draining),
+        ) => {
+            return (axum::http::StatusCode::SERVICE_UNAVAILABLE, err.to_string()).into_response();
+        }
         Err(err) => {
             tracing::warn!(?err, "failed to place order");
             return super::internal_server_error("failed to place order");
         }
     };
 
-    let _deferred_revert =
-        reserved_funds.defer_revert(tokio::runtime::Handle::current(), state.db());
-
     let order_uuid = response.wait().await;
 
-    if matches!(order_uuid, Some(Ok(_))) {
-        _deferred_revert.cancel();
-    }
-
     match order_uuid {
-        Some(Ok(PlaceOrderResult { order_uuid, .. })) => {
-            tracing::info!(?order_uuid, "order placed");
+        Some(Ok(PlaceOrderResult {
+            order_uuid,
+            executions,
+            price_improvement,
+            ..
+        })) => {
+            tracing::info!(?order_uuid, executions = executions.len(), "order placed");
+
             Json(TradeAddOrderResponse {
                 order_uuid: order_uuid.0,
+                executions,
+                price_improvement,
             })
             .into_response()
         }
@@ -94,6 +157,47 @@ pub async fn f(
             TErr::UnserializableInput => super::internal_server_error(
                 "this input was considered problematic and could not be processed",
             ),
+            TErr::PlaceOrder(err @ PlaceOrderError::OrderTypeNotAllowed { .. }) => (
+                axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                err.to_string(),
+            )
+                .into_response(),
+            TErr::PlaceOrder(PlaceOrderError::Match(
+                err @ TryFillOrdersError::CrossedBook { .. },
+            )) => {
+                tracing::error!(?err, "refusing to match against a corrupt book");
+                (
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE,
+                    "the order book is unavailable",
+                )
+                    .into_response()
+            }
+            TErr::PlaceOrder(PlaceOrderError::Match(
+                err @ TryFillOrdersError::NotionalOverflow { .. },
+            )) => (
+                axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                err.to_string(),
+            )
+                .into_response(),
+            TErr::PlaceOrder(PlaceOrderError::Match(
+                err @ (TryFillOrdersError::NoLiquidity { .. }
+                | TryFillOrdersError::SelfTradePrevented { .. }),
+            )) => (axum::http::StatusCode::CONFLICT, err.to_string()).into_response(),
+            TErr::PlaceOrder(PlaceOrderError::PriceLevelFull) => (
+                axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                "too many orders resting at this price",
+            )
+                .into_response(),
+            TErr::PlaceOrder(
+                err @ (PlaceOrderError::StaleNonce { .. }
+                | PlaceOrderError::NonceRequired
+                | PlaceOrderError::DuplicateClientOrderId),
+            ) => (axum::http::StatusCode::CONFLICT, err.to_string()).into_response(),
+            TErr::DeadlineExceeded => (
+                axum::http::StatusCode::GATEWAY_TIMEOUT,
+                "the order was not processed in time",
+            )
+                .into_response(),
             err => {
                 tracing::warn!(?err, "failed to place order");
                 super::internal_server_error("failed to place order")
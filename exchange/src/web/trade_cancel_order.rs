@@ -1,11 +1,13 @@
-use axum::extract::{Json, Path, State};
+use axum::extract::{Json, Path, Query, State};
 use axum::response::{IntoResponse, Response};
 use axum::Extension;
 use serde::{Deserialize, Serialize};
 
 use super::middleware::auth::UserUuid;
+use super::middleware::RequestDeadline;
 use super::InternalApiState;
 use crate::asset::ContainsAsset as _;
+use crate::trading::TradingEngineError as TErr;
 use crate::Asset;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +15,12 @@ pub struct TradeCancelOrder {
     pub order_uuid: uuid::Uuid,
 }
 
+/// Cancel by the client order id the order was placed with instead of the body's `order_uuid`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TradeCancelOrderQuery {
+    pub client_order_id: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct TradeCancelOrderResponse {}
 
@@ -20,8 +28,10 @@ pub struct TradeCancelOrderResponse {}
 pub async fn f(
     State(state): State<InternalApiState>,
     Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    RequestDeadline(deadline): RequestDeadline,
     Path(asset): Path<String>,
-    Json(body): Json<TradeCancelOrder>,
+    Query(query): Query<TradeCancelOrderQuery>,
+    body: Option<Json<TradeCancelOrder>>,
 ) -> Response {
     let asset = match asset.as_str() {
         "btc" | "BTC" => Asset::Bitcoin,
@@ -42,7 +52,27 @@ pub async fn f(
         tracing::info!(?asset, "placing order for asset");
     }
 
-    let Ok(wait_response) = state.cancel_order(user_uuid, body.order_uuid).await else {
+    let cancelled = match (query.client_order_id, body) {
+        (Some(client_order_id), _) => {
+            state
+                .cancel_order_by_client_id(user_uuid, client_order_id, deadline)
+                .await
+        }
+        (None, Some(Json(body))) => {
+            state
+                .cancel_order_before(user_uuid, body.order_uuid, deadline)
+                .await
+        }
+        (None, None) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                "expected an order_uuid or a client_order_id",
+            )
+                .into_response();
+        }
+    };
+
+    let Ok(wait_response) = cancelled else {
         tracing::warn!("failed to cancel order, trade engine is suspended");
         return super::internal_server_error("trading engine is suspended");
     };
@@ -57,6 +87,15 @@ pub async fn f(
             tracing::info!("order cancelled");
             (axum::http::StatusCode::OK, "order cancelled").into_response()
         }
+        Err(err @ (TErr::OrderNotFound(..) | TErr::ClientOrderIdNotFound(..))) => {
+            tracing::info!(?err, "no order to cancel");
+            (axum::http::StatusCode::NOT_FOUND, "order not found").into_response()
+        }
+        Err(TErr::DeadlineExceeded) => (
+            axum::http::StatusCode::GATEWAY_TIMEOUT,
+            "the cancel was not processed in time",
+        )
+            .into_response(),
         Err(err) => {
             tracing::warn!(?err, "failed to cancel order");
             super::internal_server_error("failed to cancel order")
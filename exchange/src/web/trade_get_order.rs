@@ -0,0 +1,94 @@
+use axum::extract::{Json, Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use chrono::TimeZone as _;
+use serde::Serialize;
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::app_cx::FetchOrderError;
+use crate::asset::ContainsAsset as _;
+use crate::trading::{OrderSide, OrderStatus, OrderType, OrderUuid};
+use crate::Asset;
+
+#[derive(Debug, Serialize)]
+pub struct TradeGetOrderResponse {
+    pub order_uuid: uuid::Uuid,
+    pub status: OrderStatus,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    #[serde(with = "crate::json_amount")]
+    pub price: u32,
+    #[serde(with = "crate::json_amount")]
+    pub quantity: u32,
+    #[serde(with = "crate::json_amount")]
+    pub quantity_filled: u32,
+    #[serde(with = "crate::json_amount")]
+    pub quantity_remaining: u32,
+    pub created_at: Option<String>,
+}
+
+/// Get the current state of a single order for `asset`
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Path((asset, order_uuid)): Path<(String, uuid::Uuid)>,
+) -> Response {
+    let asset = match asset.as_str() {
+        "btc" | "BTC" => Asset::Bitcoin,
+        "eth" | "ETH" => Asset::Ether,
+        _ => {
+            tracing::warn!(?asset, "invalid asset");
+            return (StatusCode::NOT_FOUND, "invalid asset").into_response();
+        }
+    };
+
+    if !state
+        .assets
+        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
+    {
+        tracing::warn!(?asset, "asset not enabled");
+        return (StatusCode::NOT_FOUND, "asset not enabled").into_response();
+    }
+
+    let record = match state
+        .fetch_order(user_uuid, asset, OrderUuid(order_uuid))
+        .await
+    {
+        Ok(record) => record,
+        Err(FetchOrderError::NotFound) => {
+            return (StatusCode::NOT_FOUND, "order not found").into_response();
+        }
+        Err(FetchOrderError::Forbidden) => {
+            tracing::warn!(?user_uuid, ?order_uuid, "order belongs to another user");
+            return (StatusCode::FORBIDDEN, "forbidden").into_response();
+        }
+        Err(FetchOrderError::TradingEngineUnresponsive) => {
+            tracing::warn!("failed to fetch order, trade engine is unresponsive");
+            return super::internal_server_error("trading engine is unresponsive");
+        }
+        Err(FetchOrderError::Database(err)) => {
+            tracing::warn!(?err, "failed to fetch archived order");
+            return super::internal_server_error("failed to fetch order");
+        }
+    };
+
+    let created_at = chrono::Utc
+        .timestamp_millis_opt(record.created_at)
+        .single()
+        .map(|created_at| created_at.to_rfc3339());
+
+    Json(TradeGetOrderResponse {
+        order_uuid,
+        status: record.status,
+        side: record.side,
+        order_type: record.order_type,
+        price: record.price.get(),
+        quantity: record.quantity.get(),
+        quantity_filled: record.quantity_filled,
+        quantity_remaining: record.quantity_remaining(),
+        created_at,
+    })
+    .into_response()
+}
@@ -0,0 +1,92 @@
+use std::num::NonZeroU32;
+
+use axum::extract::{Json, Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::auth::UserUuid;
+use super::middleware::RequestDeadline;
+use super::InternalApiState;
+use crate::asset::ContainsAsset as _;
+use crate::trading::TradingEngineError as TErr;
+use crate::Asset;
+
+/// The request body for the `trade_reduce_order` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeReduceOrder {
+    /// The quantity to take off the order, at most what it has resting.
+    #[serde(with = "crate::json_amount")]
+    pub by: NonZeroU32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TradeReduceOrderResponse {
+    /// The quantity left resting, zero if the reduce cancelled the order.
+    #[serde(with = "crate::json_amount")]
+    pub quantity_remaining: u32,
+}
+
+/// Take quantity off a resting order for `asset`, it keeps its place in the queue
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    RequestDeadline(deadline): RequestDeadline,
+    Path((asset, order_uuid)): Path<(String, uuid::Uuid)>,
+    Json(body): Json<TradeReduceOrder>,
+) -> Response {
+    let asset = match asset.as_str() {
+        "btc" | "BTC" => Asset::Bitcoin,
+        "eth" | "ETH" => Asset::Ether,
+        _ => {
+            tracing::warn!(?asset, "invalid asset");
+            return (StatusCode::NOT_FOUND, "invalid asset").into_response();
+        }
+    };
+
+    if !state
+        .assets
+        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
+    {
+        tracing::warn!(?asset, "asset not enabled");
+        return (StatusCode::NOT_FOUND, "asset not enabled").into_response();
+    }
+
+    // the reserve of what is taken off is released with what the engine journals for it.
+    let Ok(wait_response) = state
+        .reduce_order(user_uuid, order_uuid, body.by, deadline)
+        .await
+    else {
+        tracing::warn!("failed to reduce order, trade engine is suspended");
+        return super::internal_server_error("trading engine is suspended");
+    };
+
+    let Some(res) = wait_response.wait().await else {
+        tracing::warn!("wait_response did not return a result");
+        return super::internal_server_error("trading engine is unresponsive");
+    };
+
+    match res {
+        Ok(quantity_remaining) => {
+            tracing::info!(quantity_remaining, "order reduced");
+            Json(TradeReduceOrderResponse { quantity_remaining }).into_response()
+        }
+        Err(err @ TErr::OrderNotFound(..)) => {
+            tracing::info!(?err, "no order to reduce");
+            (StatusCode::NOT_FOUND, "order not found").into_response()
+        }
+        Err(err @ TErr::ReduceExceedsQuantity { .. }) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response()
+        }
+        Err(TErr::DeadlineExceeded) => (
+            StatusCode::GATEWAY_TIMEOUT,
+            "the reduce was not processed in time",
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::warn!(?err, "failed to reduce order");
+            super::internal_server_error("failed to reduce order")
+        }
+    }
+}
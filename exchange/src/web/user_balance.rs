@@ -32,12 +32,14 @@ pub async fn f(
         return StatusCode::BAD_REQUEST.into_response()
     }
 
-    state.update_user_accounts(user_id).await;
+    if let Err(err) = state.update_user_accounts(user_id).await {
+        tracing::warn!(?err, %user_id, "failed to reconcile deposits");
+    }
 
     let st = if currency == "*" {
         let details = match state.user_balance(user_id).await {
             Ok(t) => t,
-            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            Err(err) => return super::database_error(&err),
         };
 
         details
@@ -50,7 +52,7 @@ pub async fn f(
 
         let balance = match state.calculate_balance_from_accounting(user_id, &currency).await {
             Ok(t) => t.map(|b| b.get()).unwrap_or(0),
-            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            Err(err) => return super::database_error(&err),
         };
         format!("<div id='balance-{currency}'>{balance}</div>")
     };
@@ -14,7 +14,6 @@ use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::{AppendHeaders, IntoResponse, Response};
 use axum::{Form, Json};
 
-use axum_extra::extract::cookie::Cookie;
 use axum_htmx::{HxRequest, HX_REDIRECT};
 use email_address::EmailAddress;
 use serde::Deserialize;
@@ -25,6 +24,9 @@ pub struct UserCreate {
     email: EmailAddress,
     #[serde(deserialize_with = "de_password_from_str")]
     password: Password,
+    /// the configured `admin_signup_token`, verifies an email on `admin_emails` is the user's.
+    #[serde(default)]
+    admin_token: Option<String>,
 }
 
 impl IntoResponse for CreateUserError {
@@ -46,15 +48,44 @@ pub async fn f(
     headers: HeaderMap,
     Form(body): Form<UserCreate>,
 ) -> Result<Response, CreateUserError> {
+    let name = match super::input::display_name("name", &body.name) {
+        Ok(name) => name,
+        Err(err) => return Ok(err.into_response()),
+    };
+
+    // a wrong token is refused before the user is created, rather than leaving a plain user.
+    let admin_token = body.admin_token.filter(|token| !token.is_empty());
+    if let Some(token) = &admin_token {
+        if !state.is_admin_signup(body.email.as_str(), token) {
+            tracing::warn!("admin signup refused");
+            return Ok((StatusCode::FORBIDDEN, "invalid admin signup token").into_response());
+        }
+    }
+
     let password_hash =
         tokio::task::spawn_blocking({ move || body.password.argon2_hash_password() })
             .await
             .map_err(|_| CreateUserError::PasswordHashError)?
             .map_err(|_| CreateUserError::PasswordHashError)?; // TODO: use a more specific error on one of these branches
 
-    let user_uuid = state
-        .create_user(body.name.as_str(), body.email.as_str(), password_hash)
-        .await?;
+    let user_uuid = match state
+        .create_user(name.as_str(), body.email.as_str(), password_hash)
+        .await
+    {
+        Ok(user_uuid) => user_uuid,
+        Err(CreateUserError::EmailUniqueViolation(_)) if hx => {
+            let message = super::localize::render_error(state.jinja(), &headers, "email_taken");
+            return Ok((StatusCode::CONFLICT, message).into_response());
+        }
+        Err(err) => return Err(err),
+    };
+
+    if admin_token.is_some() {
+        if let Err(err) = state.verify_admin_signup(user_uuid).await {
+            tracing::error!(?err, "could not verify admin signup");
+            return Err(CreateUserError::Sqlx(err));
+        }
+    }
 
     let ip_address = rightmost_ip_address(&headers).unwrap_or(connect_info.ip());
     let user_agent = headers
@@ -63,7 +94,7 @@ pub async fn f(
         .map(|st| st.to_owned());
 
     let session_token = match state
-        .create_session(user_uuid, Some(ip_address), user_agent)
+        .create_session(user_uuid, Some(ip_address), user_agent, None)
         .await
     {
         Ok(st) => st,
@@ -75,10 +106,8 @@ pub async fn f(
 
     tracing::info!(?session_token, "session created");
 
-    let session_token_cookie = Cookie::build(("session-token", session_token.as_str()))
-        .max_age(time::Duration::hours(1))
-        .path("/")
-        .to_string();
+    let session_token_cookie =
+        super::session_cookie::session_token_cookie(&state.config().session_cookie, &session_token);
 
     let user_uuid = Json(serde_json::json!({
         "user_id": user_uuid.to_string(),
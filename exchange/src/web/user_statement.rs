@@ -0,0 +1,26 @@
+use axum::extract::{Json, Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+
+/// The digested statement of the current user's account movements on a UTC day, `YYYY-MM-DD`
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Path(date): Path<String>,
+) -> Response {
+    let Ok(date) = chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d") else {
+        return (
+            axum::http::StatusCode::BAD_REQUEST,
+            "expected a date as YYYY-MM-DD",
+        )
+            .into_response();
+    };
+
+    match state.user_statement(user_uuid, date).await {
+        Ok(statement) => Json(statement).into_response(),
+        Err(err) => super::database_error(&err),
+    }
+}
@@ -0,0 +1,38 @@
+use axum::extract::{Json, Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::Deserialize;
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+
+/// The query parameters for the `user_transactions` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct ActivityParams {
+    /// the `next_cursor` of the previous page, the newest entries if unset.
+    cursor: Option<i32>,
+    /// the maximum number of entries to return.
+    #[serde(default = "default_limit")]
+    limit: u32,
+}
+
+fn default_limit() -> u32 {
+    50
+}
+
+/// The most entries a single page may hold.
+const MAX_LIMIT: u32 = 500;
+
+/// List the deposits, withdrawals, reserves and trades of the current user, newest first
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+    Query(ActivityParams { cursor, limit }): Query<ActivityParams>,
+) -> Response {
+    let limit = limit.clamp(1, MAX_LIMIT);
+
+    match state.list_activity(user_uuid, cursor, limit).await {
+        Ok(page) => Json(page).into_response(),
+        Err(err) => super::database_error(&err),
+    }
+}
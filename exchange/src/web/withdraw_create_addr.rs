@@ -19,6 +19,10 @@ pub enum CreateWithdrawalAddressError {
     InvalidAsset,
     #[error("A withdrawal address for the specified asset already exists")]
     AlreadyExists,
+    #[error("{0}")]
+    InvalidInput(#[from] super::input::InvalidInput),
+    #[error("{0}")]
+    Blocked(#[from] crate::app_cx::BlockedAddressError),
     #[error("sqlx: {0}")]
     Sqlx(#[from] sqlx::Error),
 }
@@ -29,6 +33,10 @@ impl IntoResponse for CreateWithdrawalAddressError {
             Self::InvalidAsset => {
                 (StatusCode::BAD_REQUEST, "Invalid asset specified").into_response()
             }
+            Self::InvalidInput(err) => err.into_response(),
+            Self::Blocked(err) => {
+                (StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS, err.to_string()).into_response()
+            }
             Self::Sqlx(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
             Self::AlreadyExists => (
                 StatusCode::CONFLICT,
@@ -61,10 +69,13 @@ pub async fn f(
         }
     };
 
+    let address_text = super::input::address("address_text", &params.address_text)?;
+    state.screen_withdrawal_address(user_id, &address_text)?;
+
     let addrs = state.list_withdrawal_addrs(user_id).await?;
     if addrs
         .iter()
-        .any(|(text, asset)| asset.as_str() == params.asset && text.as_str() == params.address_text)
+        .any(|(text, asset)| asset.as_str() == params.asset && text.as_str() == address_text)
     {
         return Err(CreateWithdrawalAddressError::AlreadyExists);
     }
@@ -76,7 +87,7 @@ pub async fn f(
         RETURNING id
         "#,
         user_id,
-        params.address_text,
+        address_text,
         asset.to_string(),
     )
     .fetch_one(&db)
@@ -85,10 +96,7 @@ pub async fn f(
 
     Ok((
         [(HX_TRIGGER, "updateWithdrawalAddrs")],
-        Html(format!(
-            "<p>{address_text}</p>",
-            address_text = params.address_text
-        )),
+        Html(format!("<p>{address_text}</p>")),
     )
         .into_response())
 }
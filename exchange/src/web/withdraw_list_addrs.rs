@@ -1,5 +1,4 @@
 use axum::extract::State;
-use axum::http::StatusCode;
 use axum::response::{IntoResponse as _, Response};
 use axum::{Extension, Json};
 
@@ -14,7 +13,7 @@ pub async fn f(
         Ok(v_rec) => v_rec,
         Err(err) => {
             tracing::error!(?err, "selecting withdrawal addresses for user");
-            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            return super::database_error(&err);
         }
     };
 
@@ -40,6 +40,11 @@ pub async fn withdraw_transfer(
         }
     };
 
+    // the address may have been blocked since it was registered.
+    if let Err(err) = state.screen_withdrawal_address(user_id, &address_text) {
+        return (StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS, err.to_string()).into_response();
+    }
+
     // verify user has necessary amount for transfer
     let user_amount = match sqlx::query!("").fetch_one(&db).await {
         Ok(_) => todo!(),
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+use crate::trading::{ExecutionReport, ExecutionReportFormat, LastLookRequest};
+
+/// A message sent by the client.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// the maker's answer to a last look, see [`crate::trading::last_look`].
+    LastLookDecision {
+        request_id: uuid::Uuid,
+        accept: bool,
+    },
+    /// start receiving the execution reports of the user's orders, in `format`.
+    SubscribeExecutions {
+        #[serde(default)]
+        format: ExecutionReportFormat,
+    },
+}
+
+/// The query parameters for the `ws_connect` endpoint.
+#[derive(Debug, Deserialize)]
+pub struct ConnectParams {
+    /// a ticket issued by `POST /api/ws/ticket`.
+    #[serde(default)]
+    ticket: String,
+}
+
+/// Open an authenticated websocket, the user is identified by the ticket it was opened with
+///
+/// Tickets are single-use, the ticket is only redeemed once the handshake has been checked to
+/// be a websocket upgrade so a handshake that fails leaves it usable for another attempt.
+pub async fn f(
+    State(state): State<InternalApiState>,
+    ws: WebSocketUpgrade,
+    Query(ConnectParams { ticket }): Query<ConnectParams>,
+) -> Response {
+    let user_uuid = match state.ws_tickets().redeem(&ticket) {
+        Ok(user_uuid) => user_uuid,
+        Err(err) => {
+            tracing::warn!(?err, "websocket ticket rejected");
+            return (
+                StatusCode::UNAUTHORIZED,
+                "Unauthorized: invalid websocket ticket",
+            )
+                .into_response();
+        }
+    };
+    UserUuid(user_uuid).record_in_span();
+
+    // the socket outlives the request, it carries the request's amount settings along.
+    let amounts = crate::json_amount::current();
+    ws.on_upgrade(move |socket| {
+        crate::json_amount::scope(amounts, handle_socket(socket, state, user_uuid))
+    })
+}
+
+async fn handle_socket(mut socket: WebSocket, state: InternalApiState, user_uuid: uuid::Uuid) {
+    tracing::info!(?user_uuid, "websocket connected");
+
+    let hello = serde_json::json!({ "type": "hello", "user_uuid": user_uuid });
+    if socket.send(Message::Text(hello.to_string())).await.is_err() {
+        return;
+    }
+
+    // makers only hear about last looks while connected, without a subscription matches go ahead.
+    let mut last_looks = state.subscribe_last_look(user_uuid).await.ok();
+    let mut pending: HashMap<uuid::Uuid, oneshot::Sender<bool>> = HashMap::new();
+    let mut executions = None;
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => {
+                let Some(Ok(message)) = message else {
+                    break;
+                };
+
+                match message {
+                    Message::Ping(payload) => {
+                        if socket.send(Message::Pong(payload)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Message::Text(text) => match serde_json::from_str(&text) {
+                        Ok(ClientMessage::LastLookDecision { request_id, accept }) => {
+                            if let Some(decision) = pending.remove(&request_id) {
+                                let _ = decision.send(accept);
+                            }
+                        }
+                        Ok(ClientMessage::SubscribeExecutions { format }) => {
+                            // subscribing again only changes the format.
+                            let subscription = match executions.take() {
+                                Some((reports, _)) => Ok(reports),
+                                None => state.subscribe_execution_reports().await,
+                            };
+
+                            let reply = match subscription {
+                                Ok(reports) => {
+                                    executions = Some((reports, format));
+                                    serde_json::json!({
+                                        "type": "subscribed",
+                                        "channel": "executions",
+                                        "format": format,
+                                    })
+                                }
+                                Err(err) => {
+                                    tracing::warn!(?err, "failed to subscribe to execution reports");
+                                    serde_json::json!({
+                                        "type": "error",
+                                        "channel": "executions",
+                                        "message": err.to_string(),
+                                    })
+                                }
+                            };
+                            if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(err) => tracing::debug!(?err, "ignoring unrecognised message"),
+                    },
+                    Message::Close(_) => break,
+                    _ => (),
+                }
+            }
+            Some(request) = recv_last_look(&mut last_looks) => {
+                let LastLookRequest { quote, decision } = request;
+
+                // the engine stops waiting once the window closes, forget those requests.
+                pending.retain(|_, decision| !decision.is_closed());
+                pending.insert(quote.request_id, decision);
+
+                let message = serde_json::json!({ "type": "last_look", "quote": quote });
+                if socket.send(Message::Text(message.to_string())).await.is_err() {
+                    break;
+                }
+            }
+            report = recv_execution_report(&mut executions) => {
+                let report = match report {
+                    Ok(report) => report,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!(?user_uuid, missed, "missed execution reports, lagging behind");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        executions = None;
+                        continue;
+                    }
+                };
+
+                // every order's reports go to every subscriber, only pass on the user's own.
+                let Some((_, format)) = &executions else {
+                    continue;
+                };
+                if report.user_uuid != user_uuid {
+                    continue;
+                }
+
+                let report = format.to_json(&report);
+                let message = serde_json::json!({ "type": "execution_report", "report": report });
+                if socket.send(Message::Text(message.to_string())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    tracing::info!(?user_uuid, "websocket disconnected");
+}
+
+/// the next last look for the maker, never resolves without a subscription.
+async fn recv_last_look(
+    last_looks: &mut Option<mpsc::Receiver<LastLookRequest>>,
+) -> Option<LastLookRequest> {
+    match last_looks {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// the next execution report of any order, never resolves without a subscription.
+async fn recv_execution_report(
+    executions: &mut Option<(broadcast::Receiver<ExecutionReport>, ExecutionReportFormat)>,
+) -> Result<ExecutionReport, broadcast::error::RecvError> {
+    match executions {
+        Some((rx, _)) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,102 @@
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use tokio::sync::broadcast::error::RecvError;
+
+use super::InternalApiState;
+use crate::asset::ContainsAsset as _;
+use crate::Asset;
+
+/// Stream the trades and price level changes of the book for `asset`, after a snapshot of it
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Path(asset): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let asset = match asset.as_str() {
+        "btc" | "BTC" => Asset::Bitcoin,
+        "eth" | "ETH" => Asset::Ether,
+        _ => {
+            tracing::warn!(?asset, "invalid asset");
+            return (StatusCode::NOT_FOUND, "invalid asset").into_response();
+        }
+    };
+
+    if !state
+        .assets
+        .contains_asset(&crate::asset::AssetKey::ByValue(asset))
+    {
+        tracing::warn!(?asset, "asset not enabled");
+        return (StatusCode::NOT_FOUND, "asset not enabled").into_response();
+    }
+
+    // the socket outlives the request, it carries the request's amount settings along.
+    let amounts = crate::json_amount::current();
+    ws.on_upgrade(move |socket| {
+        crate::json_amount::scope(amounts, handle_socket(socket, state, asset))
+    })
+}
+
+async fn handle_socket(mut socket: WebSocket, state: InternalApiState, asset: Asset) {
+    let (snapshot, mut feed) = match state.subscribe_market_data(asset).await {
+        Ok(subscription) => subscription,
+        Err(err) => {
+            tracing::warn!(?err, "failed to subscribe to market data");
+            let _ = socket
+                .send(Message::Close(Some(CloseFrame {
+                    code: close_code::ERROR,
+                    reason: "trading engine is unresponsive".into(),
+                })))
+                .await;
+            return;
+        }
+    };
+
+    let snapshot = serde_json::json!({ "type": "snapshot", "book": snapshot });
+    if socket
+        .send(Message::Text(snapshot.to_string()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Ping(payload))) => {
+                    if socket.send(Message::Pong(payload)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => (),
+            },
+            frame = feed.recv() => match frame {
+                Ok(frame) if frame.asset() == asset => {
+                    let Ok(text) = serde_json::to_string(&frame) else {
+                        continue;
+                    };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => (),
+                // the engine never waits on a subscriber, one that can't keep up is dropped. its
+                // book is missing changes by now, it has to reconnect for a new snapshot.
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(?asset, missed, "dropping market data subscriber that fell behind");
+                    let _ = socket
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::AGAIN,
+                            reason: "fell behind the feed, reconnect for a new snapshot".into(),
+                        })))
+                        .await;
+                    break;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use serde::Serialize;
+
+use super::middleware::auth::UserUuid;
+use super::InternalApiState;
+
+/// The response body for the `ws_ticket_create` endpoint.
+#[derive(Debug, Serialize)]
+pub struct WsTicketCreateResponse {
+    ticket: String,
+    expires_in_secs: u64,
+}
+
+/// Issue a single-use ticket for opening an authenticated websocket
+pub async fn f(
+    State(state): State<InternalApiState>,
+    Extension(UserUuid(user_uuid)): Extension<UserUuid>,
+) -> Response {
+    let tickets = state.ws_tickets();
+    let ticket = tickets.issue(user_uuid);
+
+    Json(WsTicketCreateResponse {
+        ticket,
+        expires_in_secs: tickets.ttl().as_secs(),
+    })
+    .into_response()
+}
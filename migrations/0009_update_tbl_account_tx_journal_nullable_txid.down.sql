@@ -0,0 +1,2 @@
+ALTER TABLE account_tx_journal
+ALTER COLUMN txid SET NOT NULL;
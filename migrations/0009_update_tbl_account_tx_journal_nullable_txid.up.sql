@@ -0,0 +1,4 @@
+-- only chain deposits and withdrawals have a txid, internal transfers like
+-- reserving funds for an order do not.
+ALTER TABLE account_tx_journal
+ALTER COLUMN txid DROP NOT NULL;
@@ -0,0 +1,2 @@
+-- Drop the orders_archive table
+DROP TABLE IF EXISTS orders_archive;
@@ -0,0 +1,10 @@
+-- closed orders moved out of the trading engine, see `AppCx::archive_closed_orders`
+CREATE TABLE IF NOT EXISTS orders_archive (
+    order_uuid UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    closed_at TIMESTAMPTZ NOT NULL,
+    record JSONB NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS orders_archive_user_id_closed_at ON orders_archive (user_id, closed_at);
@@ -0,0 +1,4 @@
+-- the self-trade protection applied to a user's orders that do not set one,
+-- NULL uses the exchange default.
+ALTER TABLE users
+ADD COLUMN default_stp TEXT CHECK (default_stp IN ('dc', 'co', 'cn', 'cb'));
@@ -0,0 +1 @@
+DROP INDEX IF EXISTS account_tx_journal_fiat_deposit_txid;
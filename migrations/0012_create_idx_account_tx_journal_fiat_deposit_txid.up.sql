@@ -0,0 +1,3 @@
+-- a bank transfer is credited once, its reference is kept as the txid of the deposit.
+CREATE UNIQUE INDEX IF NOT EXISTS account_tx_journal_fiat_deposit_txid
+ON account_tx_journal (txid) WHERE transaction_type = 'FIAT.DEPOSIT';
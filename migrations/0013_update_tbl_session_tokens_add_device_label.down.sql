@@ -0,0 +1,2 @@
+ALTER TABLE session_tokens
+DROP COLUMN IF EXISTS device_label;
@@ -0,0 +1,3 @@
+-- a user may be logged in on several devices at once, the label tells their sessions apart.
+ALTER TABLE session_tokens
+ADD COLUMN device_label TEXT;
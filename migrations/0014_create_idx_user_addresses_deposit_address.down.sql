@@ -0,0 +1 @@
+DROP INDEX IF EXISTS user_addresses_deposit_address;
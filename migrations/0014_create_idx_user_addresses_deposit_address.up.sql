@@ -0,0 +1,3 @@
+-- a transaction seen on the wallet is mapped back to the user owning the address it paid.
+CREATE INDEX IF NOT EXISTS user_addresses_deposit_address
+ON user_addresses (address_text) WHERE kind = 'deposit';
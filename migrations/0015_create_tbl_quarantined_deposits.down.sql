@@ -0,0 +1,2 @@
+-- Drop the quarantined_deposits table
+DROP TABLE IF EXISTS quarantined_deposits;
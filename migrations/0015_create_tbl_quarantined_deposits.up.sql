@@ -0,0 +1,12 @@
+-- deposits paying a blocked address are held here instead of credited, see `AppCx::quarantine_deposit`
+CREATE TABLE IF NOT EXISTS quarantined_deposits (
+    id BIGSERIAL PRIMARY KEY,
+    txid TEXT NOT NULL,
+    address_text TEXT NOT NULL,
+    user_id UUID REFERENCES users(id),
+    currency TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    blocked_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(txid, address_text)
+);
@@ -0,0 +1,2 @@
+-- Drop the trade_settlements table
+DROP TABLE IF EXISTS trade_settlements;
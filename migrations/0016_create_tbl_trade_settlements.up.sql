@@ -0,0 +1,10 @@
+-- the ledger moves of the trades the trading engine journalled, see `AppCx::settle_pending`
+CREATE TABLE IF NOT EXISTS trade_settlements (
+    id BIGSERIAL PRIMARY KEY,
+    jstr JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    settled_at TIMESTAMPTZ,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS trade_settlements_pending ON trade_settlements (id) WHERE settled_at IS NULL AND error IS NULL;
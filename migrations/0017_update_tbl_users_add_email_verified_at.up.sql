@@ -0,0 +1,5 @@
+-- when the owner of the email was confirmed, set by an operator. only verified
+-- users on `admin_emails` are promoted, so signing up with an admin's email first
+-- grants nothing.
+ALTER TABLE users
+ADD COLUMN email_verified_at TIMESTAMPTZ;